@@ -1,24 +1,225 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::str::FromStr;
+use crate::csr;
+use crate::encoding;
+use crate::fpu::RoundingMode;
 use crate::instruction::Instruction;
+use crate::isa::{Extension, Isa, IsaError};
+use crate::vector::{vtype, Sew};
 
+#[derive(Debug, Clone)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub labels: HashMap<String, usize>,
+    /// Whether each instruction is encoded as a 16-bit parcel.
+    pub compressed: Vec<bool>,
+}
+
+/// Code-size effect of [`Program::compress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionReport {
+    pub instructions: usize,
+    pub compressed: usize,
+    pub uncompressed_bytes: u32,
+    pub bytes: u32,
+}
+
+impl CompressionReport {
+    pub fn saved_bytes(&self) -> u32 {
+        self.uncompressed_bytes - self.bytes
+    }
+}
+
+impl fmt::Display for CompressionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = if self.uncompressed_bytes == 0 {
+            0.0
+        } else {
+            100.0 * self.saved_bytes() as f64 / self.uncompressed_bytes as f64
+        };
+        write!(
+            f,
+            "compressed {}/{} instructions: {} -> {} bytes ({:.1}% smaller)",
+            self.compressed, self.instructions, self.uncompressed_bytes, self.bytes, percent
+        )
+    }
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        let compressed = vec![false; instructions.len()];
+        Program { instructions, labels: HashMap::new(), compressed }
+    }
+
+    /// Byte address of every instruction, plus the end of the code.
+    pub fn addresses(&self) -> Vec<u32> {
+        layout(&self.compressed)
+    }
+
+    pub fn code_size(&self) -> u32 {
+        *self.addresses().last().unwrap_or(&0)
+    }
+
+    /// The instruction at `idx` with its label offset converted to bytes.
+    fn byte_form(&self, idx: usize, addrs: &[u32]) -> Instruction {
+        let mut inst = self.instructions[idx];
+        if let Some(offset) = inst.label_offset() {
+            let target = (idx as i64 + offset as i64).clamp(0, self.instructions.len() as i64) as usize;
+            inst.patch_label(addrs[target] as i32 - addrs[idx] as i32);
+        }
+        inst
+    }
+
+    /// Drops compression from every instruction that does not fit in 16 bits
+    /// at `xlen` under the resulting layout, until the layout stops changing.
+    fn relax(&self, mut compressed: Vec<bool>, xlen: u32) -> Vec<bool> {
+        loop {
+            let addrs = layout(&compressed);
+            let next: Vec<bool> = (0..self.instructions.len())
+                .map(|i| compressed[i] && encoding::compress(&self.byte_form(i, &addrs), xlen).is_some())
+                .collect();
+            if next == compressed {
+                return compressed;
+            }
+            compressed = next;
+        }
+    }
+
+    /// Uses the 16-bit form for every instruction that has one on an
+    /// `xlen`-bit hart.
+    pub fn compress(&mut self, xlen: u32) -> CompressionReport {
+        self.compressed = self.relax(vec![true; self.instructions.len()], xlen);
+        CompressionReport {
+            instructions: self.instructions.len(),
+            compressed: self.compressed.iter().filter(|c| **c).count(),
+            uncompressed_bytes: 4 * self.instructions.len() as u32,
+            bytes: self.code_size(),
+        }
+    }
+
+    /// Assembles the program into a little-endian code image for an
+    /// `xlen`-bit hart.
+    pub fn encode(&self, xlen: u32) -> Option<Vec<u8>> {
+        let addrs = self.addresses();
+        let mut bytes = Vec::with_capacity(self.code_size() as usize);
+        for (idx, compressed) in self.compressed.iter().enumerate() {
+            let inst = self.byte_form(idx, &addrs);
+            if *compressed {
+                bytes.extend(encoding::compress(&inst, xlen)?.to_le_bytes());
+            } else {
+                bytes.extend(encoding::encode(&inst)?.to_le_bytes());
+            }
+        }
+        Some(bytes)
+    }
+
+    /// Reads a code image back, keeping track of which parcels were 16-bit.
+    pub fn decode(bytes: &[u8], xlen: u32) -> Option<Program> {
+        let mut decoded = Vec::new();
+        let mut addrs = Vec::new();
+        let mut addr = 0;
+        while (addr as usize) < bytes.len() {
+            let d = encoding::decode_at(&bytes[addr as usize..], xlen)?;
+            addrs.push(addr);
+            decoded.push(d);
+            addr += d.size();
+        }
+        addrs.push(addr);
+
+        let mut program = Program::new(decoded.iter().map(|d| d.inst).collect());
+        program.compressed = decoded.iter().map(|d| d.compressed).collect();
+        for (idx, inst) in program.instructions.iter_mut().enumerate() {
+            if let Some(offset) = inst.label_offset() {
+                let target = (addrs[idx] as i64 + offset as i64) as u32;
+                let target = addrs.binary_search(&target).ok()?;
+                inst.patch_label(target as i32 - idx as i32);
+            }
+        }
+        Some(program)
+    }
+}
+
+fn layout(compressed: &[bool]) -> Vec<u32> {
+    let mut addrs = Vec::with_capacity(compressed.len() + 1);
+    let mut addr = 0;
+    addrs.push(addr);
+    for c in compressed {
+        addr += if *c { 2 } else { 4 };
+        addrs.push(addr);
+    }
+    addrs
 }
 
 fn parse_reg(reg: &str) -> Option<usize> {
     let reg = reg.trim();
-    if reg.starts_with('x') {
-        reg[1..].parse::<usize>().ok()
-    } else {
-        None
+    reg.strip_prefix('x')?.parse::<usize>().ok()
+}
+
+/// Accepts `f0`-`f31` and the ABI names `ft0`-`ft11`, `fs0`-`fs11`, `fa0`-`fa7`.
+pub fn parse_freg(reg: &str) -> Option<usize> {
+    let reg = reg.trim();
+    let (bank, n) = reg.split_at_checked(2).filter(|(b, _)| b.starts_with('f'))?;
+    let n = n.parse::<usize>().ok();
+    let idx = match (bank, n) {
+        ("ft", Some(n @ 0..=7)) => n,
+        ("ft", Some(n @ 8..=11)) => n + 20,
+        ("fs", Some(n @ 0..=1)) => n + 8,
+        ("fs", Some(n @ 2..=11)) => n + 16,
+        ("fa", Some(n @ 0..=7)) => n + 10,
+        _ => reg[1..].parse::<usize>().ok()?,
+    };
+    (idx < 32).then_some(idx)
+}
+
+fn parse_vreg(reg: &str) -> Option<usize> {
+    reg.trim().strip_prefix('v')?.parse::<usize>().ok().filter(|&r| r < 32)
+}
+
+/// The `vtype` operands of `vsetvli`, such as `e32, m2, ta, ma`. LMUL
+/// defaults to `m1` and both policies to undisturbed.
+fn parse_vtype(fields: &[&str]) -> Option<u16> {
+    let (sew, rest) = fields.split_first()?;
+    let sew = Some(*sew).filter(|s| s.starts_with('e')).and_then(Sew::from_name)?;
+    let (mut lmul, mut ta, mut ma) = (0, false, false);
+    for field in rest {
+        match *field {
+            "ta" | "tu" => ta = *field == "ta",
+            "ma" | "mu" => ma = *field == "ma",
+            "m1" => lmul = 0,
+            "m2" => lmul = 1,
+            "m4" => lmul = 2,
+            "m8" => lmul = 3,
+            "mf2" => lmul = -1,
+            "mf4" => lmul = -2,
+            "mf8" => lmul = -3,
+            _ => return None,
+        }
+    }
+    Some(vtype(sew, lmul, ta, ma))
+}
+
+fn parse_csr(csr: &str) -> Option<u16> {
+    let csr = csr.trim();
+    csr::from_name(csr).or_else(|| parse_imm(csr).and_then(|v| u16::try_from(v).ok()).filter(|v| *v < 0x1000))
+}
+
+/// The CSR behind the `fr*`/`fs*` pseudo-instructions.
+fn fp_csr_alias(mnemonic: &str) -> u16 {
+    match &mnemonic[2..] {
+        "rm" => csr::FRM,
+        "flags" => csr::FFLAGS,
+        _ => csr::FCSR,
     }
 }
 
+fn parse_uimm5(imm: &str) -> Option<i32> {
+    parse_imm(imm).filter(|v| (0..32).contains(v))
+}
+
 fn parse_imm(imm: &str) -> Option<i32> {
     let imm = imm.trim();
     if imm.starts_with("0x") || imm.starts_with("0X") {
@@ -30,6 +231,19 @@ fn parse_imm(imm: &str) -> Option<i32> {
     }
 }
 
+/// A `fence` operand such as `rw`: letters from `iorw`, in that order.
+fn parse_fence_set(set: &str) -> Option<u8> {
+    let mut rest = set.trim();
+    let mut bits = 0;
+    for (letter, bit) in [('i', 8), ('o', 4), ('r', 2), ('w', 1)] {
+        if let Some(tail) = rest.strip_prefix(letter) {
+            bits |= bit;
+            rest = tail;
+        }
+    }
+    (rest.is_empty() && bits != 0).then_some(bits)
+}
+
 fn parse_mem_operand(mem: &str) -> Option<(i32, usize)> {
     let mem = mem.trim();
     let start = mem.find('(')?;
@@ -39,6 +253,84 @@ fn parse_mem_operand(mem: &str) -> Option<(i32, usize)> {
     Some((imm, rs))
 }
 
+/// Atomics only take a bare `(rs1)` address; a zero offset is tolerated.
+fn parse_amo_addr(mem: &str) -> Option<usize> {
+    match parse_mem_operand(mem)? {
+        (0, rs1) => Some(rs1),
+        _ => None,
+    }
+}
+
+/// Splits `amoadd.w.aqrl` into `amoadd.w` and its acquire/release bits.
+fn split_ordering(mnemonic: &str) -> (&str, bool, bool) {
+    if let Some(base) = mnemonic.strip_suffix(".aqrl") {
+        (base, true, true)
+    } else if let Some(base) = mnemonic.strip_suffix(".aq") {
+        (base, true, false)
+    } else if let Some(base) = mnemonic.strip_suffix(".rl") {
+        (base, false, true)
+    } else {
+        (mnemonic, false, false)
+    }
+}
+
+// Operand readers for the mixed integer/FP formats of the F and D extensions.
+fn xreg<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<usize> {
+    parse_reg(tokens.next()?)
+}
+
+fn freg<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<usize> {
+    parse_freg(tokens.next()?)
+}
+
+/// The rounding mode is an optional trailing operand; it defaults to `dyn`.
+fn rm<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<RoundingMode> {
+    match tokens.next() {
+        Some(rm) => RoundingMode::from_name(&rm.to_lowercase()),
+        None => Some(RoundingMode::Dyn),
+    }
+}
+
+macro_rules! parse_fp {
+    ($tokens:ident, $variant:ident { $($field:ident: $reader:ident),* }) => {
+        Some(Instruction::$variant { $($field: $reader(&mut $tokens)?),* })
+    };
+}
+
+macro_rules! parse_fp_load {
+    ($tokens:ident, $variant:ident) => {{
+        let rd = parse_freg($tokens.next()?)?;
+        let (imm, rs1) = parse_mem_operand($tokens.next()?)?;
+        Some(Instruction::$variant { rd, rs1, imm })
+    }};
+}
+
+macro_rules! parse_fp_store {
+    ($tokens:ident, $variant:ident) => {{
+        let rs2 = parse_freg($tokens.next()?)?;
+        let (imm, rs1) = parse_mem_operand($tokens.next()?)?;
+        Some(Instruction::$variant { rs1, rs2, imm })
+    }};
+}
+
+macro_rules! parse_csr_type {
+    ($tokens:ident, $variant:ident) => {{
+        let rd = parse_reg($tokens.next()?)?;
+        let csr = parse_csr($tokens.next()?)?;
+        let rs1 = parse_reg($tokens.next()?)?;
+        Some(Instruction::$variant { rd, rs1, csr })
+    }};
+}
+
+macro_rules! parse_csr_imm_type {
+    ($tokens:ident, $variant:ident) => {{
+        let rd = parse_reg($tokens.next()?)?;
+        let csr = parse_csr($tokens.next()?)?;
+        let imm = parse_uimm5($tokens.next()?)?;
+        Some(Instruction::$variant { rd, imm, csr })
+    }};
+}
+
 macro_rules! parse_r_type {
     ($tokens:ident, $variant:ident) => {
         Some(Instruction::$variant {
@@ -59,6 +351,15 @@ macro_rules! parse_i_type {
     };
 }
 
+macro_rules! parse_unary {
+    ($tokens:ident, $variant:ident) => {
+        Some(Instruction::$variant {
+            rd: parse_reg($tokens.next()?)?,
+            rs1: parse_reg($tokens.next()?)?,
+        })
+    };
+}
+
 macro_rules! parse_load {
     ($tokens:ident, $variant:ident) => {{
         let rd = parse_reg($tokens.next()?)?;
@@ -75,6 +376,15 @@ macro_rules! parse_store {
     }};
 }
 
+macro_rules! parse_amo {
+    ($tokens:ident, $variant:ident, $aq:ident, $rl:ident) => {{
+        let rd = parse_reg($tokens.next()?)?;
+        let rs2 = parse_reg($tokens.next()?)?;
+        let rs1 = parse_amo_addr($tokens.next()?)?;
+        Some(Instruction::$variant { rd, rs1, rs2, aq: $aq, rl: $rl })
+    }};
+}
+
 macro_rules! parse_b_type {
     ($tokens:ident, $variant:ident) => {
         Some(Instruction::$variant {
@@ -103,6 +413,174 @@ macro_rules! parse_j_type {
     };
 }
 
+/// Parses a V-extension instruction. Masked forms end in a `v0.t` operand.
+fn parse_vector<'a>(mnemonic: &str, tokens: impl Iterator<Item = &'a str>) -> Option<Instruction> {
+    let mut ops = tokens.collect::<Vec<_>>();
+    let vm = ops.last() != Some(&"v0.t");
+    if !vm {
+        ops.pop();
+    }
+    let v = |i: usize| ops.get(i).and_then(|r| parse_vreg(r));
+    let x = |i: usize| ops.get(i).and_then(|r| parse_reg(r));
+    let imm = |i: usize| ops.get(i).and_then(|r| parse_imm(r));
+
+    // vle32.v, vse8.v, vlse16.v, vsse64.v
+    if let Some((kind, width)) = mnemonic
+        .strip_suffix(".v")
+        .and_then(|m| ["vlse", "vsse", "vle", "vse"].into_iter().find_map(|p| Some((p, m.strip_prefix(p)?))))
+    {
+        let eew = Sew::from_name(width)?;
+        let rs1 = parse_amo_addr(ops.get(1)?)?;
+        return match kind {
+            "vle" => Some(Instruction::VleV { vd: v(0)?, rs1, eew, vm }),
+            "vse" => Some(Instruction::VseV { vs3: v(0)?, rs1, eew, vm }),
+            "vlse" => Some(Instruction::VlseV { vd: v(0)?, rs1, rs2: x(2)?, eew, vm }),
+            _ => Some(Instruction::VsseV { vs3: v(0)?, rs1, rs2: x(2)?, eew, vm }),
+        };
+    }
+
+    let inst = match mnemonic {
+        "vsetvli" => Instruction::Vsetvli { rd: x(0)?, rs1: x(1)?, vtypei: parse_vtype(ops.get(2..)?)? },
+        "vsetivli" => {
+            let uimm = parse_uimm5(ops.get(1)?)? as u8;
+            Instruction::Vsetivli { rd: x(0)?, uimm, vtypei: parse_vtype(ops.get(2..)?)? }
+        }
+
+        "vadd.vv" => Instruction::VaddVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vadd.vx" => Instruction::VaddVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vadd.vi" => Instruction::VaddVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+        "vsub.vv" => Instruction::VsubVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vsub.vx" => Instruction::VsubVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vmul.vv" => Instruction::VmulVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vmul.vx" => Instruction::VmulVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vand.vv" => Instruction::VandVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vand.vx" => Instruction::VandVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vand.vi" => Instruction::VandVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+        "vor.vv" => Instruction::VorVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vor.vx" => Instruction::VorVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vor.vi" => Instruction::VorVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+        "vxor.vv" => Instruction::VxorVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vxor.vx" => Instruction::VxorVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vxor.vi" => Instruction::VxorVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+
+        "vmv.v.v" if vm => Instruction::VmvVv { vd: v(0)?, vs1: v(1)? },
+        "vmv.v.x" if vm => Instruction::VmvVx { vd: v(0)?, rs1: x(1)? },
+        "vmv.v.i" if vm => Instruction::VmvVi { vd: v(0)?, imm: imm(1)? },
+        "vmv.x.s" if vm => Instruction::VmvXS { rd: x(0)?, vs2: v(1)? },
+        "vmv.s.x" if vm => Instruction::VmvSX { vd: v(0)?, rs1: x(1)? },
+
+        "vredsum.vs" => Instruction::VredsumVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vredand.vs" => Instruction::VredandVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vredor.vs" => Instruction::VredorVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vredxor.vs" => Instruction::VredxorVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vredmin.vs" => Instruction::VredminVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vredmax.vs" => Instruction::VredmaxVs { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+
+        "vmseq.vv" => Instruction::VmseqVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vmseq.vx" => Instruction::VmseqVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vmseq.vi" => Instruction::VmseqVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+        "vmsne.vv" => Instruction::VmsneVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vmsne.vx" => Instruction::VmsneVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vmsne.vi" => Instruction::VmsneVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+        "vmslt.vv" => Instruction::VmsltVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vmslt.vx" => Instruction::VmsltVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vmsle.vv" => Instruction::VmsleVv { vd: v(0)?, vs2: v(1)?, vs1: v(2)?, vm },
+        "vmsle.vx" => Instruction::VmsleVx { vd: v(0)?, vs2: v(1)?, rs1: x(2)?, vm },
+        "vmsle.vi" => Instruction::VmsleVi { vd: v(0)?, vs2: v(1)?, imm: imm(2)?, vm },
+
+        "vmand.mm" if vm => Instruction::VmandMm { vd: v(0)?, vs2: v(1)?, vs1: v(2)? },
+        "vmandn.mm" if vm => Instruction::VmandnMm { vd: v(0)?, vs2: v(1)?, vs1: v(2)? },
+        "vmor.mm" if vm => Instruction::VmorMm { vd: v(0)?, vs2: v(1)?, vs1: v(2)? },
+        "vmxor.mm" if vm => Instruction::VmxorMm { vd: v(0)?, vs2: v(1)?, vs1: v(2)? },
+        "vcpop.m" => Instruction::VcpopM { rd: x(0)?, vs2: v(1)?, vm },
+        "vfirst.m" => Instruction::VfirstM { rd: x(0)?, vs2: v(1)?, vm },
+        _ => return None,
+    };
+    Some(inst)
+}
+
+/// Parses the operands of a `c.*` mnemonic into the instruction it expands to.
+fn parse_compressed<'a>(mnemonic: &str, mut tokens: impl Iterator<Item = &'a str>) -> Option<Instruction> {
+    let inst = match mnemonic {
+        "c.nop" => Instruction::Addi { rd: 0, rs1: 0, imm: 0 },
+        "c.addi" | "c.andi" | "c.slli" | "c.srli" | "c.srai" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let imm = parse_imm(tokens.next()?)?;
+            match mnemonic {
+                "c.addi" => Instruction::Addi { rd, rs1: rd, imm },
+                "c.andi" => Instruction::Andi { rd, rs1: rd, imm },
+                "c.slli" => Instruction::Slli { rd, rs1: rd, imm },
+                "c.srli" => Instruction::Srli { rd, rs1: rd, imm },
+                _ => Instruction::Srai { rd, rs1: rd, imm },
+            }
+        }
+        "c.li" => {
+            let rd = parse_reg(tokens.next()?)?;
+            Instruction::Addi { rd, rs1: 0, imm: parse_imm(tokens.next()?)? }
+        }
+        "c.lui" => parse_u_type!(tokens, Lui)?,
+        "c.addi16sp" => {
+            let _sp = parse_reg(tokens.next()?).filter(|r| *r == 2)?;
+            Instruction::Addi { rd: 2, rs1: 2, imm: parse_imm(tokens.next()?)? }
+        }
+        "c.addi4spn" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let _sp = parse_reg(tokens.next()?).filter(|r| *r == 2)?;
+            Instruction::Addi { rd, rs1: 2, imm: parse_imm(tokens.next()?)? }
+        }
+        "c.mv" | "c.add" | "c.sub" | "c.xor" | "c.or" | "c.and" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let rs2 = parse_reg(tokens.next()?)?;
+            match mnemonic {
+                "c.mv" => Instruction::Add { rd, rs1: 0, rs2 },
+                "c.add" => Instruction::Add { rd, rs1: rd, rs2 },
+                "c.sub" => Instruction::Sub { rd, rs1: rd, rs2 },
+                "c.xor" => Instruction::Xor { rd, rs1: rd, rs2 },
+                "c.or" => Instruction::Or { rd, rs1: rd, rs2 },
+                _ => Instruction::And { rd, rs1: rd, rs2 },
+            }
+        }
+        "c.addiw" => {
+            let rd = parse_reg(tokens.next()?)?;
+            Instruction::Addiw { rd, rs1: rd, imm: parse_imm(tokens.next()?)? }
+        }
+        "c.addw" | "c.subw" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let rs2 = parse_reg(tokens.next()?)?;
+            match mnemonic {
+                "c.addw" => Instruction::Addw { rd, rs1: rd, rs2 },
+                _ => Instruction::Subw { rd, rs1: rd, rs2 },
+            }
+        }
+        "c.lw" | "c.lwsp" => parse_load!(tokens, Lw)?,
+        "c.sw" | "c.swsp" => parse_store!(tokens, Sw)?,
+        "c.ld" | "c.ldsp" => parse_load!(tokens, Ld)?,
+        "c.sd" | "c.sdsp" => parse_store!(tokens, Sd)?,
+        "c.flw" | "c.flwsp" => parse_fp_load!(tokens, Flw)?,
+        "c.fsw" | "c.fswsp" => parse_fp_store!(tokens, Fsw)?,
+        "c.fld" | "c.fldsp" => parse_fp_load!(tokens, Fld)?,
+        "c.fsd" | "c.fsdsp" => parse_fp_store!(tokens, Fsd)?,
+        "c.j" => Instruction::Jal { rd: 0, offset: 0 },
+        "c.jal" => Instruction::Jal { rd: 1, offset: 0 },
+        "c.jr" => Instruction::Jalr { rd: 0, rs1: parse_reg(tokens.next()?)?, imm: 0 },
+        "c.jalr" => Instruction::Jalr { rd: 1, rs1: parse_reg(tokens.next()?)?, imm: 0 },
+        "c.beqz" => Instruction::Beq { rs1: parse_reg(tokens.next()?)?, rs2: 0, offset: 0 },
+        "c.bnez" => Instruction::Bne { rs1: parse_reg(tokens.next()?)?, rs2: 0, offset: 0 },
+        _ => return None,
+    };
+    let sp_form = mnemonic.ends_with("sp") && mnemonic != "c.addi16sp";
+    let uses_sp = matches!(inst,
+        Instruction::Lw { rs1: 2, .. } | Instruction::Sw { rs1: 2, .. } |
+        Instruction::Ld { rs1: 2, .. } | Instruction::Sd { rs1: 2, .. } |
+        Instruction::Flw { rs1: 2, .. } | Instruction::Fsw { rs1: 2, .. } |
+        Instruction::Fld { rs1: 2, .. } | Instruction::Fsd { rs1: 2, .. });
+    if sp_form != uses_sp && mnemonic != "c.addi4spn" {
+        return None;
+    }
+    // Whether the form exists at the target XLEN is checked on loading.
+    [32, 64].into_iter().any(|xlen| encoding::compress(&inst, xlen).is_some()).then_some(inst)
+}
+
 pub fn parse_instruction(line: &str) -> Option<Instruction> {
     let line = line.split('#').next()?.trim();
     if line.is_empty() {
@@ -110,13 +588,24 @@ pub fn parse_instruction(line: &str) -> Option<Instruction> {
     }
 
     let mut tokens = line
-        .split(|c| c == ',' || c == ' ' || c == '\t')
+        .split([',', ' ', '\t'])
         .map(|s| s.trim())
         .filter(|s| !s.is_empty());
 
     let mnemonic = tokens.next()?.to_lowercase();
+    if mnemonic.starts_with("c.") {
+        return parse_compressed(&mnemonic, tokens);
+    }
+    if mnemonic.starts_with('v') {
+        return parse_vector(&mnemonic, tokens);
+    }
+    let (mnemonic, aq, rl) = split_ordering(&mnemonic);
+
+    if (aq || rl) && !mnemonic.ends_with(".w") {
+        return None;
+    }
 
-    match mnemonic.as_str() {
+    match mnemonic {
         // R-Format
         "add" => parse_r_type!(tokens, Add),
         "sub" => parse_r_type!(tokens, Sub),
@@ -161,6 +650,77 @@ pub fn parse_instruction(line: &str) -> Option<Instruction> {
         "sh" => parse_store!(tokens, Sh),
         "sw" => parse_store!(tokens, Sw),
 
+        // RV64I/RV64M
+        "ld" => parse_load!(tokens, Ld),
+        "lwu" => parse_load!(tokens, Lwu),
+        "sd" => parse_store!(tokens, Sd),
+        "addiw" => parse_i_type!(tokens, Addiw),
+        "slliw" => parse_i_type!(tokens, Slliw),
+        "srliw" => parse_i_type!(tokens, Srliw),
+        "sraiw" => parse_i_type!(tokens, Sraiw),
+        "addw" => parse_r_type!(tokens, Addw),
+        "subw" => parse_r_type!(tokens, Subw),
+        "sllw" => parse_r_type!(tokens, Sllw),
+        "srlw" => parse_r_type!(tokens, Srlw),
+        "sraw" => parse_r_type!(tokens, Sraw),
+        "mulw" => parse_r_type!(tokens, Mulw),
+        "divw" => parse_r_type!(tokens, Divw),
+        "divuw" => parse_r_type!(tokens, Divuw),
+        "remw" => parse_r_type!(tokens, Remw),
+        "remuw" => parse_r_type!(tokens, Remuw),
+
+        // Zba
+        "sh1add" => parse_r_type!(tokens, Sh1add),
+        "sh2add" => parse_r_type!(tokens, Sh2add),
+        "sh3add" => parse_r_type!(tokens, Sh3add),
+        "add.uw" => parse_r_type!(tokens, AddUw),
+        "sh1add.uw" => parse_r_type!(tokens, Sh1addUw),
+        "sh2add.uw" => parse_r_type!(tokens, Sh2addUw),
+        "sh3add.uw" => parse_r_type!(tokens, Sh3addUw),
+        "slli.uw" => parse_i_type!(tokens, SlliUw),
+        "zext.w" => Some(Instruction::AddUw { rd: xreg(&mut tokens)?, rs1: xreg(&mut tokens)?, rs2: 0 }),
+
+        // Zbb
+        "andn" => parse_r_type!(tokens, Andn),
+        "orn" => parse_r_type!(tokens, Orn),
+        "xnor" => parse_r_type!(tokens, Xnor),
+        "clz" => parse_unary!(tokens, Clz),
+        "ctz" => parse_unary!(tokens, Ctz),
+        "cpop" => parse_unary!(tokens, Cpop),
+        "clzw" => parse_unary!(tokens, Clzw),
+        "ctzw" => parse_unary!(tokens, Ctzw),
+        "cpopw" => parse_unary!(tokens, Cpopw),
+        "max" => parse_r_type!(tokens, Max),
+        "maxu" => parse_r_type!(tokens, Maxu),
+        "min" => parse_r_type!(tokens, Min),
+        "minu" => parse_r_type!(tokens, Minu),
+        "sext.b" => parse_unary!(tokens, SextB),
+        "sext.h" => parse_unary!(tokens, SextH),
+        "zext.h" => parse_unary!(tokens, ZextH),
+        "rol" => parse_r_type!(tokens, Rol),
+        "ror" => parse_r_type!(tokens, Ror),
+        "rori" => parse_i_type!(tokens, Rori),
+        "rolw" => parse_r_type!(tokens, Rolw),
+        "rorw" => parse_r_type!(tokens, Rorw),
+        "roriw" => parse_i_type!(tokens, Roriw),
+        "orc.b" => parse_unary!(tokens, OrcB),
+        "rev8" => parse_unary!(tokens, Rev8),
+
+        // Zbc
+        "clmul" => parse_r_type!(tokens, Clmul),
+        "clmulh" => parse_r_type!(tokens, Clmulh),
+        "clmulr" => parse_r_type!(tokens, Clmulr),
+
+        // Zbs
+        "bclr" => parse_r_type!(tokens, Bclr),
+        "bclri" => parse_i_type!(tokens, Bclri),
+        "bext" => parse_r_type!(tokens, Bext),
+        "bexti" => parse_i_type!(tokens, Bexti),
+        "binv" => parse_r_type!(tokens, Binv),
+        "binvi" => parse_i_type!(tokens, Binvi),
+        "bset" => parse_r_type!(tokens, Bset),
+        "bseti" => parse_i_type!(tokens, Bseti),
+
         // B-Format
         "beq" => parse_b_type!(tokens, Beq),
         "bne" => parse_b_type!(tokens, Bne),
@@ -176,6 +736,156 @@ pub fn parse_instruction(line: &str) -> Option<Instruction> {
         "lui" => parse_u_type!(tokens, Lui),
         "auipc" => parse_u_type!(tokens, Auipc),
 
+        // A-Extension
+        "lr.w" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let rs1 = parse_amo_addr(tokens.next()?)?;
+            Some(Instruction::LrW { rd, rs1, aq, rl })
+        }
+        "sc.w" => parse_amo!(tokens, ScW, aq, rl),
+        "amoswap.w" => parse_amo!(tokens, AmoswapW, aq, rl),
+        "amoadd.w" => parse_amo!(tokens, AmoaddW, aq, rl),
+        "amoxor.w" => parse_amo!(tokens, AmoxorW, aq, rl),
+        "amoand.w" => parse_amo!(tokens, AmoandW, aq, rl),
+        "amoor.w" => parse_amo!(tokens, AmoorW, aq, rl),
+        "amomin.w" => parse_amo!(tokens, AmominW, aq, rl),
+        "amomax.w" => parse_amo!(tokens, AmomaxW, aq, rl),
+        "amominu.w" => parse_amo!(tokens, AmominuW, aq, rl),
+        "amomaxu.w" => parse_amo!(tokens, AmomaxuW, aq, rl),
+
+        // Zicsr
+        "csrrw" => parse_csr_type!(tokens, Csrrw),
+        "csrrs" => parse_csr_type!(tokens, Csrrs),
+        "csrrc" => parse_csr_type!(tokens, Csrrc),
+        "csrrwi" => parse_csr_imm_type!(tokens, Csrrwi),
+        "csrrsi" => parse_csr_imm_type!(tokens, Csrrsi),
+        "csrrci" => parse_csr_imm_type!(tokens, Csrrci),
+        "csrr" => {
+            let rd = parse_reg(tokens.next()?)?;
+            let csr = parse_csr(tokens.next()?)?;
+            Some(Instruction::Csrrs { rd, rs1: 0, csr })
+        }
+        "csrw" => {
+            let csr = parse_csr(tokens.next()?)?;
+            let rs1 = parse_reg(tokens.next()?)?;
+            Some(Instruction::Csrrw { rd: 0, rs1, csr })
+        }
+        "frcsr" | "frrm" | "frflags" => {
+            let csr = fp_csr_alias(mnemonic);
+            Some(Instruction::Csrrs { rd: parse_reg(tokens.next()?)?, rs1: 0, csr })
+        }
+        "fscsr" | "fsrm" | "fsflags" => {
+            let csr = fp_csr_alias(mnemonic);
+            let first = parse_reg(tokens.next()?)?;
+            match tokens.next() {
+                Some(rs1) => Some(Instruction::Csrrw { rd: first, rs1: parse_reg(rs1)?, csr }),
+                None => Some(Instruction::Csrrw { rd: 0, rs1: first, csr }),
+            }
+        }
+
+        // Memory ordering
+        "fence" => {
+            let pred = tokens.next().map_or(Some(0xF), parse_fence_set)?;
+            let succ = tokens.next().map_or(Some(0xF), parse_fence_set)?;
+            Some(Instruction::Fence { pred, succ })
+        }
+
+        // Environment calls and privileged instructions
+        "ecall" => Some(Instruction::Ecall),
+        "ebreak" => Some(Instruction::Ebreak),
+        "mret" => Some(Instruction::Mret),
+        "sret" => Some(Instruction::Sret),
+        "wfi" => Some(Instruction::Wfi),
+        "sfence.vma" => {
+            let rs1 = tokens.next().map_or(Some(0), parse_reg)?;
+            let rs2 = tokens.next().map_or(Some(0), parse_reg)?;
+            Some(Instruction::SfenceVma { rs1, rs2 })
+        }
+
+        // F/D-Extension
+        "flw" => parse_fp_load!(tokens, Flw),
+        "fld" => parse_fp_load!(tokens, Fld),
+        "fsw" => parse_fp_store!(tokens, Fsw),
+        "fsd" => parse_fp_store!(tokens, Fsd),
+        "fmv.x.w" => parse_fp!(tokens, FmvXW { rd: xreg, rs1: freg }),
+        "fmv.w.x" => parse_fp!(tokens, FmvWX { rd: freg, rs1: xreg }),
+        "fcvt.s.d" => parse_fp!(tokens, FcvtSD { rd: freg, rs1: freg, rm: rm }),
+        "fcvt.d.s" => parse_fp!(tokens, FcvtDS { rd: freg, rs1: freg, rm: rm }),
+        "fadd.s" => parse_fp!(tokens, FaddS { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fsub.s" => parse_fp!(tokens, FsubS { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fmul.s" => parse_fp!(tokens, FmulS { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fdiv.s" => parse_fp!(tokens, FdivS { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fsqrt.s" => parse_fp!(tokens, FsqrtS { rd: freg, rs1: freg, rm: rm }),
+        "fmin.s" => parse_fp!(tokens, FminS { rd: freg, rs1: freg, rs2: freg }),
+        "fmax.s" => parse_fp!(tokens, FmaxS { rd: freg, rs1: freg, rs2: freg }),
+        "fmadd.s" => parse_fp!(tokens, FmaddS { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fmsub.s" => parse_fp!(tokens, FmsubS { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fnmsub.s" => parse_fp!(tokens, FnmsubS { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fnmadd.s" => parse_fp!(tokens, FnmaddS { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fsgnj.s" => parse_fp!(tokens, FsgnjS { rd: freg, rs1: freg, rs2: freg }),
+        "fsgnjn.s" => parse_fp!(tokens, FsgnjnS { rd: freg, rs1: freg, rs2: freg }),
+        "fsgnjx.s" => parse_fp!(tokens, FsgnjxS { rd: freg, rs1: freg, rs2: freg }),
+        "fmv.s" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjS { rd, rs1: rs, rs2: rs })
+        }
+        "fneg.s" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjnS { rd, rs1: rs, rs2: rs })
+        }
+        "fabs.s" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjxS { rd, rs1: rs, rs2: rs })
+        }
+        "feq.s" => parse_fp!(tokens, FeqS { rd: xreg, rs1: freg, rs2: freg }),
+        "flt.s" => parse_fp!(tokens, FltS { rd: xreg, rs1: freg, rs2: freg }),
+        "fle.s" => parse_fp!(tokens, FleS { rd: xreg, rs1: freg, rs2: freg }),
+        "fclass.s" => parse_fp!(tokens, FclassS { rd: xreg, rs1: freg }),
+        "fcvt.w.s" => parse_fp!(tokens, FcvtWS { rd: xreg, rs1: freg, rm: rm }),
+        "fcvt.wu.s" => parse_fp!(tokens, FcvtWuS { rd: xreg, rs1: freg, rm: rm }),
+        "fcvt.s.w" => parse_fp!(tokens, FcvtSW { rd: freg, rs1: xreg, rm: rm }),
+        "fcvt.s.wu" => parse_fp!(tokens, FcvtSWu { rd: freg, rs1: xreg, rm: rm }),
+        "fadd.d" => parse_fp!(tokens, FaddD { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fsub.d" => parse_fp!(tokens, FsubD { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fmul.d" => parse_fp!(tokens, FmulD { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fdiv.d" => parse_fp!(tokens, FdivD { rd: freg, rs1: freg, rs2: freg, rm: rm }),
+        "fsqrt.d" => parse_fp!(tokens, FsqrtD { rd: freg, rs1: freg, rm: rm }),
+        "fmin.d" => parse_fp!(tokens, FminD { rd: freg, rs1: freg, rs2: freg }),
+        "fmax.d" => parse_fp!(tokens, FmaxD { rd: freg, rs1: freg, rs2: freg }),
+        "fmadd.d" => parse_fp!(tokens, FmaddD { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fmsub.d" => parse_fp!(tokens, FmsubD { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fnmsub.d" => parse_fp!(tokens, FnmsubD { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fnmadd.d" => parse_fp!(tokens, FnmaddD { rd: freg, rs1: freg, rs2: freg, rs3: freg, rm: rm }),
+        "fsgnj.d" => parse_fp!(tokens, FsgnjD { rd: freg, rs1: freg, rs2: freg }),
+        "fsgnjn.d" => parse_fp!(tokens, FsgnjnD { rd: freg, rs1: freg, rs2: freg }),
+        "fsgnjx.d" => parse_fp!(tokens, FsgnjxD { rd: freg, rs1: freg, rs2: freg }),
+        "fmv.d" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjD { rd, rs1: rs, rs2: rs })
+        }
+        "fneg.d" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjnD { rd, rs1: rs, rs2: rs })
+        }
+        "fabs.d" => {
+            let rd = parse_freg(tokens.next()?)?;
+            let rs = parse_freg(tokens.next()?)?;
+            Some(Instruction::FsgnjxD { rd, rs1: rs, rs2: rs })
+        }
+        "feq.d" => parse_fp!(tokens, FeqD { rd: xreg, rs1: freg, rs2: freg }),
+        "flt.d" => parse_fp!(tokens, FltD { rd: xreg, rs1: freg, rs2: freg }),
+        "fle.d" => parse_fp!(tokens, FleD { rd: xreg, rs1: freg, rs2: freg }),
+        "fclass.d" => parse_fp!(tokens, FclassD { rd: xreg, rs1: freg }),
+        "fcvt.w.d" => parse_fp!(tokens, FcvtWD { rd: xreg, rs1: freg, rm: rm }),
+        "fcvt.wu.d" => parse_fp!(tokens, FcvtWuD { rd: xreg, rs1: freg, rm: rm }),
+        "fcvt.d.w" => parse_fp!(tokens, FcvtDW { rd: freg, rs1: xreg, rm: rm }),
+        "fcvt.d.wu" => parse_fp!(tokens, FcvtDWu { rd: freg, rs1: xreg, rm: rm }),
+
         // Debug
         "print" => Some(Instruction::Print {
             rs: parse_reg(tokens.next()?)?,
@@ -186,14 +896,21 @@ pub fn parse_instruction(line: &str) -> Option<Instruction> {
 }
 
 pub fn load_asm(path: &str)->io::Result<Program> {
+    load_asm_for(path, &Isa::default())
+}
+
+/// Like [`load_asm`], but rejects every instruction `isa` does not enable,
+/// naming the offending line.
+pub fn load_asm_for(path: &str, isa: &Isa) -> io::Result<Program> {
     let file = File::open(path)?;
     let reader = io::BufReader::new(file);
 
     let mut instructions:Vec<Instruction> = Vec::new();
+    let mut compressed: Vec<bool> = Vec::new();
     let mut labels: HashMap<String,usize>= HashMap::new();
     let mut patch_list: HashMap<String,Vec<usize>> = HashMap::new();
 
-    for line_res in reader.lines() {
+    for (line_no, line_res) in reader.lines().enumerate() {
         let line = line_res?;
         let trimmed = line.split('#').next().unwrap_or("").trim();
         if trimmed.is_empty() {
@@ -218,11 +935,25 @@ pub fn load_asm(path: &str)->io::Result<Program> {
             Some(inst) => inst,
             None => continue,
         };
+        let is_compressed = trimmed.to_lowercase().starts_with("c.");
+        let allowed = if is_compressed && !isa.has(Extension::C) {
+            Err(IsaError::Disabled { ext: Extension::C, isa: *isa })
+        } else {
+            isa.check(&inst).and_then(|()| match encoding::compress(&inst, isa.xlen) {
+                None if is_compressed => Err(IsaError::NoCompressedForm(*isa)),
+                _ => Ok(()),
+            })
+        };
+        if let Err(e) = allowed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{path}:{}: `{trimmed}` {e}", line_no + 1),
+            ));
+        }
 
         let last_token = trimmed
             .split(|c: char| c.is_whitespace() || c == ',')
-            .filter(|s| !s.is_empty())
-            .last();
+            .rfind(|s| !s.is_empty());
 
         if let Some(label_ref) = last_token {
             if labels.contains_key(label_ref) {
@@ -238,7 +969,16 @@ pub fn load_asm(path: &str)->io::Result<Program> {
             }
         }
         instructions.push(inst);
+        compressed.push(is_compressed);
     }
 
-    Ok(Program{instructions, labels})
+    let program = Program { instructions, labels, compressed };
+    let fits = program.relax(program.compressed.clone(), isa.xlen);
+    if let Some(idx) = (0..fits.len()).find(|i| fits[*i] != program.compressed[*i]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("compressed instruction {idx} does not fit in 16 bits: {:?}", program.instructions[idx]),
+        ));
+    }
+    Ok(program)
 }
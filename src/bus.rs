@@ -0,0 +1,251 @@
+use std::fmt;
+use thiserror::Error;
+use crate::memory::{Memory, MemoryError};
+
+/// A memory-mapped peripheral. Offsets are relative to the base address the
+/// device is attached at; `size` is the access width in bytes (1, 2, 4 or 8).
+pub trait Device {
+    fn name(&self) -> &str;
+
+    /// Length of the device's register window in bytes.
+    fn size(&self) -> u64;
+
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, MemoryError>;
+
+    fn write(&mut self, offset: u64, size: usize, val: u64) -> Result<(), MemoryError>;
+
+    /// Advances the device by one CPU step.
+    fn tick(&mut self) {}
+
+    /// Whether the device's interrupt line is raised.
+    fn interrupt(&self) -> bool {
+        false
+    }
+
+    /// Receives the lines of every interrupt source after each step, as
+    /// [`Bus::interrupts`] reports them. Interrupt controllers use this.
+    fn interrupt_lines(&mut self, _lines: u64) {}
+
+    /// The `mip` bits the device drives directly for `hart`, like the
+    /// timer and software interrupts of a CLINT.
+    fn hart_interrupts(&self, _hart: usize) -> u64 {
+        0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BusError {
+    #[error("{name} at 0x{base:08x}..0x{end:08x} overlaps {other}")]
+    Overlap { name: String, base: u64, end: u64, other: String },
+}
+
+struct Mapping {
+    base: u64,
+    irq: Option<u32>,
+    device: Box<dyn Device>,
+}
+
+impl Mapping {
+    fn end(&self) -> u64 {
+        self.base + self.device.size()
+    }
+}
+
+/// One entry of the memory map, for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub base: u64,
+    pub end: u64,
+    pub irq: Option<u32>,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:08x}..0x{:08x} {}", self.base, self.end, self.name)?;
+        if let Some(irq) = self.irq {
+            write!(f, " (irq {irq})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Routes physical addresses to RAM or to attached devices. Accesses must be
+/// naturally aligned; unmapped addresses are out of bounds.
+pub struct Bus {
+    ram: Memory,
+    ram_base: u64,
+    devices: Vec<Mapping>,
+}
+
+impl Bus {
+    /// A bus with `ram_size` bytes of RAM at address 0 and no devices.
+    pub fn new(ram_size: usize) -> Self {
+        Self::with_ram(0, ram_size)
+    }
+
+    pub fn with_ram(ram_base: u64, ram_size: usize) -> Self {
+        Bus { ram: Memory::new(ram_size), ram_base, devices: Vec::new() }
+    }
+
+    pub fn ram(&self) -> &Memory {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut Memory {
+        &mut self.ram
+    }
+
+    pub fn ram_base(&self) -> u64 {
+        self.ram_base
+    }
+
+    /// One past the last RAM address, where the stack starts.
+    pub fn ram_end(&self) -> u64 {
+        self.ram_base + self.ram.size() as u64
+    }
+
+    /// Maps `device` at `base`, optionally wired to interrupt source `irq`.
+    pub fn attach(&mut self, base: u64, irq: Option<u32>, device: Box<dyn Device>) -> Result<(), BusError> {
+        let end = base + device.size();
+        let overlaps = |start: u64, stop: u64| base < stop && start < end;
+        let other = if overlaps(self.ram_base, self.ram_end()) {
+            Some("RAM".to_string())
+        } else {
+            self.devices.iter().find(|m| overlaps(m.base, m.end())).map(|m| m.device.name().to_string())
+        };
+        if let Some(other) = other {
+            return Err(BusError::Overlap { name: device.name().to_string(), base, end, other });
+        }
+        self.devices.push(Mapping { base, irq, device });
+        Ok(())
+    }
+
+    /// The memory map, sorted by address.
+    pub fn regions(&self) -> Vec<Region> {
+        let ram = Region { name: "RAM".to_string(), base: self.ram_base, end: self.ram_end(), irq: None };
+        let mut regions = vec![ram];
+        regions.extend(self.devices.iter().map(|m| Region {
+            name: m.device.name().to_string(),
+            base: m.base,
+            end: m.end(),
+            irq: m.irq,
+        }));
+        regions.sort_by_key(|r| r.base);
+        regions
+    }
+
+    /// Advances every device, then hands the resulting interrupt lines to
+    /// them.
+    pub fn tick(&mut self) {
+        for mapping in &mut self.devices {
+            mapping.device.tick();
+        }
+        let lines = self.interrupts();
+        for mapping in &mut self.devices {
+            mapping.device.interrupt_lines(lines);
+        }
+    }
+
+    /// Bit `n` is set while the device wired to interrupt source `n` raises
+    /// its line.
+    pub fn interrupts(&self) -> u64 {
+        self.devices
+            .iter()
+            .filter(|m| m.device.interrupt())
+            .filter_map(|m| m.irq)
+            .fold(0, |lines, irq| lines | 1 << irq)
+    }
+
+    pub fn hart_interrupts(&self, hart: usize) -> u64 {
+        self.devices.iter().fold(0, |mip, m| mip | m.device.hart_interrupts(hart))
+    }
+
+    fn ram_offset(&self, addr: u64, size: usize) -> Option<u64> {
+        let offset = addr.checked_sub(self.ram_base)?;
+        (offset.checked_add(size as u64)? <= self.ram.size() as u64).then_some(offset)
+    }
+
+    fn device_at(&mut self, addr: u64, size: usize) -> Option<(&mut Box<dyn Device>, u64)> {
+        self.devices
+            .iter_mut()
+            .find(|m| addr >= m.base && addr.saturating_add(size as u64) <= m.end())
+            .map(|m| (&mut m.device, addr - m.base))
+    }
+
+    fn check_aligned(addr: u64, size: usize) -> Result<(), MemoryError> {
+        if addr.is_multiple_of(size as u64) { Ok(()) } else { Err(MemoryError::MisalignedAccess(addr)) }
+    }
+
+    /// Reads `size` bytes, zero-extended.
+    pub fn read(&mut self, addr: u64, size: usize) -> Result<u64, MemoryError> {
+        Self::check_aligned(addr, size)?;
+        if let Some(offset) = self.ram_offset(addr, size) {
+            return Ok(match size {
+                1 => self.ram.read_byte(offset)? as u8 as u64,
+                2 => self.ram.read_halfword(offset)? as u16 as u64,
+                4 => self.ram.read_word(offset)? as u32 as u64,
+                _ => self.ram.read_doubleword(offset)? as u64,
+            });
+        }
+        match self.device_at(addr, size) {
+            Some((device, offset)) => device.read(offset, size),
+            None => Err(MemoryError::OutOfBounds(addr)),
+        }
+    }
+
+    pub fn write(&mut self, addr: u64, size: usize, val: u64) -> Result<(), MemoryError> {
+        Self::check_aligned(addr, size)?;
+        if let Some(offset) = self.ram_offset(addr, size) {
+            return match size {
+                1 => self.ram.write_byte(offset, val as u8),
+                2 => self.ram.write_halfword(offset, val as u16),
+                4 => self.ram.write_word(offset, val as i32),
+                _ => self.ram.write_doubleword(offset, val as i64),
+            };
+        }
+        match self.device_at(addr, size) {
+            Some((device, offset)) => device.write(offset, size, val),
+            None => Err(MemoryError::OutOfBounds(addr)),
+        }
+    }
+
+    /// Reads a RAM word without side effects; devices are not visible.
+    pub fn peek_word(&self, addr: u64) -> Result<i32, MemoryError> {
+        Self::check_aligned(addr, 4)?;
+        let offset = self.ram_offset(addr, 4).ok_or(MemoryError::OutOfBounds(addr))?;
+        self.ram.read_word(offset)
+    }
+
+    pub fn read_byte(&mut self, addr: u64) -> Result<i8, MemoryError> {
+        Ok(self.read(addr, 1)? as i8)
+    }
+
+    pub fn write_byte(&mut self, addr: u64, val: u8) -> Result<(), MemoryError> {
+        self.write(addr, 1, val as u64)
+    }
+
+    pub fn read_halfword(&mut self, addr: u64) -> Result<i16, MemoryError> {
+        Ok(self.read(addr, 2)? as i16)
+    }
+
+    pub fn write_halfword(&mut self, addr: u64, val: u16) -> Result<(), MemoryError> {
+        self.write(addr, 2, val as u64)
+    }
+
+    pub fn read_word(&mut self, addr: u64) -> Result<i32, MemoryError> {
+        Ok(self.read(addr, 4)? as i32)
+    }
+
+    pub fn write_word(&mut self, addr: u64, val: i32) -> Result<(), MemoryError> {
+        self.write(addr, 4, val as u32 as u64)
+    }
+
+    pub fn read_doubleword(&mut self, addr: u64) -> Result<i64, MemoryError> {
+        Ok(self.read(addr, 8)? as i64)
+    }
+
+    pub fn write_doubleword(&mut self, addr: u64, val: i64) -> Result<(), MemoryError> {
+        self.write(addr, 8, val as u64)
+    }
+}
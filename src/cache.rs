@@ -0,0 +1,408 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Which line of a full set a miss replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    /// The least recently used.
+    Lru,
+    /// The one filled first.
+    Fifo,
+    Random,
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Replacement::Lru => write!(f, "LRU"),
+            Replacement::Fifo => write!(f, "FIFO"),
+            Replacement::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// When a store reaches the level below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// When the dirty line is evicted.
+    WriteBack,
+    /// On every store.
+    WriteThrough,
+}
+
+impl fmt::Display for WritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WritePolicy::WriteBack => write!(f, "write-back"),
+            WritePolicy::WriteThrough => write!(f, "write-through"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CacheError {
+    #[error("bad cache number `{0}`")]
+    BadNumber(String),
+    #[error("cache {what} must be a power of two, got {value}")]
+    NotPowerOfTwo { what: &'static str, value: usize },
+    #[error("a {size}-byte cache cannot hold {ways} way(s) of {line}-byte lines")]
+    TooSmall { size: usize, line: usize, ways: usize },
+    #[error("unknown cache option `{0}`")]
+    UnknownOption(String),
+}
+
+/// The geometry and policies of one cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Capacity in bytes.
+    pub size: usize,
+    /// Line size in bytes.
+    pub line: usize,
+    /// Associativity; `size / line` ways make the cache fully associative.
+    pub ways: usize,
+    pub replacement: Replacement,
+    pub write: WritePolicy,
+    /// Whether a store miss fills the line before writing it.
+    pub write_allocate: bool,
+}
+
+impl CacheConfig {
+    /// A write-back, write-allocate LRU cache.
+    pub fn new(size: usize, line: usize, ways: usize) -> Result<Self, CacheError> {
+        for (what, value) in [("size", size), ("line size", line), ("associativity", ways)] {
+            if !value.is_power_of_two() {
+                return Err(CacheError::NotPowerOfTwo { what, value });
+            }
+        }
+        if size < line * ways {
+            return Err(CacheError::TooSmall { size, line, ways });
+        }
+        Ok(CacheConfig { size, line, ways, replacement: Replacement::Lru, write: WritePolicy::WriteBack, write_allocate: true })
+    }
+
+    pub fn sets(&self) -> usize {
+        self.size / (self.line * self.ways)
+    }
+}
+
+/// Parses `<size>:<line>:<ways>` followed by any of `lru`, `fifo`,
+/// `random`, `wb`, `wt`, `wa` and `nwa`, each after a `:`.
+impl FromStr for CacheConfig {
+    type Err = CacheError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mut number = || {
+            let part = parts.next().unwrap_or("");
+            part.parse::<usize>().map_err(|_| CacheError::BadNumber(part.to_string()))
+        };
+        let mut config = CacheConfig::new(number()?, number()?, number()?)?;
+        for option in s.split(':').skip(3) {
+            match option {
+                "lru" => config.replacement = Replacement::Lru,
+                "fifo" => config.replacement = Replacement::Fifo,
+                "random" => config.replacement = Replacement::Random,
+                "wb" => config.write = WritePolicy::WriteBack,
+                "wt" => config.write = WritePolicy::WriteThrough,
+                "wa" => config.write_allocate = true,
+                "nwa" => config.write_allocate = false,
+                _ => return Err(CacheError::UnknownOption(option.to_string())),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for CacheConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} B, {} B lines, {}-way, {} sets, {}, {}, {}",
+            self.size,
+            self.line,
+            self.ways,
+            self.sets(),
+            self.replacement,
+            self.write,
+            if self.write_allocate { "write-allocate" } else { "no-write-allocate" }
+        )
+    }
+}
+
+/// One way of a set. The model keeps tags only; the data stays in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Line {
+    pub valid: bool,
+    pub dirty: bool,
+    pub tag: u64,
+    /// When the line was last used and when it was filled, in accesses.
+    last_used: u64,
+    filled: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Valid lines replaced to make room.
+    pub evictions: u64,
+    /// Dirty lines written to the level below on eviction.
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        if self.accesses() == 0 { 0.0 } else { self.hits as f64 / self.accesses() as f64 }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses, {} evictions, {} writebacks (hit rate {:.1}%)",
+            self.hits,
+            self.misses,
+            self.evictions,
+            self.writebacks,
+            100.0 * self.hit_rate()
+        )
+    }
+}
+
+/// The result of one access: where it landed and what it cost below.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub set: usize,
+    /// The way holding the line afterwards; `None` for a store miss that
+    /// did not allocate.
+    pub way: Option<usize>,
+    pub hit: bool,
+    /// Address of the line evicted to make room.
+    pub evicted: Option<u64>,
+    /// Accesses the level below must serve, as `(line address, write)`.
+    pub below: Vec<(u64, bool)>,
+}
+
+/// A set-associative cache of physical addresses.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    name: &'static str,
+    config: CacheConfig,
+    sets: Vec<Vec<Line>>,
+    stats: CacheStats,
+    clock: u64,
+    /// xorshift64 state for random replacement.
+    seed: u64,
+    last: Option<Lookup>,
+}
+
+impl Cache {
+    pub fn new(name: &'static str, config: CacheConfig) -> Self {
+        let sets = vec![vec![Line::default(); config.ways]; config.sets()];
+        Cache { name, config, sets, stats: CacheStats::default(), clock: 0, seed: 0x9E37_79B9_7F4A_7C15, last: None }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    pub fn sets(&self) -> &[Vec<Line>] {
+        &self.sets
+    }
+
+    /// The most recent access.
+    pub fn last(&self) -> Option<&Lookup> {
+        self.last.as_ref()
+    }
+
+    /// Which set `addr` maps to, and its tag.
+    pub fn locate(&self, addr: u64) -> (usize, u64) {
+        let line = addr / self.config.line as u64;
+        let sets = self.config.sets() as u64;
+        ((line % sets) as usize, line / sets)
+    }
+
+    /// The address of the first byte of `tag`'s line in `set`.
+    pub fn line_address(&self, set: usize, tag: u64) -> u64 {
+        (tag * self.config.sets() as u64 + set as u64) * self.config.line as u64
+    }
+
+    /// Reads or writes the byte at `addr`.
+    pub fn access(&mut self, addr: u64, write: bool) -> Lookup {
+        self.clock += 1;
+        let (set, tag) = self.locate(addr);
+        let line_addr = self.line_address(set, tag);
+        let write_through = write && self.config.write == WritePolicy::WriteThrough;
+        let mut below = vec![];
+
+        if let Some(way) = self.sets[set].iter().position(|l| l.valid && l.tag == tag) {
+            self.stats.hits += 1;
+            let line = &mut self.sets[set][way];
+            line.last_used = self.clock;
+            line.dirty |= write && !write_through;
+            if write_through {
+                below.push((line_addr, true));
+            }
+            return self.record(Lookup { set, way: Some(way), hit: true, evicted: None, below });
+        }
+
+        self.stats.misses += 1;
+        if write && !self.config.write_allocate {
+            below.push((line_addr, true));
+            return self.record(Lookup { set, way: None, hit: false, evicted: None, below });
+        }
+        let way = self.victim(set);
+        let old = self.sets[set][way];
+        let mut evicted = None;
+        if old.valid {
+            self.stats.evictions += 1;
+            let old_addr = self.line_address(set, old.tag);
+            evicted = Some(old_addr);
+            if old.dirty {
+                self.stats.writebacks += 1;
+                below.push((old_addr, true));
+            }
+        }
+        below.push((line_addr, false));
+        if write_through {
+            below.push((line_addr, true));
+        }
+        let dirty = write && !write_through;
+        self.sets[set][way] = Line { valid: true, dirty, tag, last_used: self.clock, filled: self.clock };
+        self.record(Lookup { set, way: Some(way), hit: false, evicted, below })
+    }
+
+    fn record(&mut self, lookup: Lookup) -> Lookup {
+        self.last = Some(lookup.clone());
+        lookup
+    }
+
+    /// The way a miss in `set` fills: an invalid one if any, else the one
+    /// the replacement policy picks.
+    fn victim(&mut self, set: usize) -> usize {
+        let lines = &self.sets[set];
+        if let Some(way) = lines.iter().position(|l| !l.valid) {
+            return way;
+        }
+        let oldest = |key: fn(&Line) -> u64| (0..lines.len()).min_by_key(|&w| key(&lines[w])).unwrap_or(0);
+        match self.config.replacement {
+            Replacement::Lru => oldest(|l| l.last_used),
+            Replacement::Fifo => oldest(|l| l.filled),
+            Replacement::Random => {
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 7;
+                self.seed ^= self.seed << 17;
+                (self.seed % self.config.ways as u64) as usize
+            }
+        }
+    }
+}
+
+/// One line per set, marking the set and way of the last access with `>`
+/// and `*`, then the counters.
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.name, self.config)?;
+        let last = self.last.as_ref();
+        for (set, lines) in self.sets.iter().enumerate() {
+            let touched = last.filter(|l| l.set == set);
+            write!(f, "{} set {set:>3}:", if touched.is_some() { '>' } else { ' ' })?;
+            for (way, line) in lines.iter().enumerate() {
+                if !line.valid {
+                    write!(f, " [{:^13}]", "-")?;
+                    continue;
+                }
+                let dirty = if line.dirty { 'D' } else { ' ' };
+                let star = if touched.is_some_and(|l| l.way == Some(way)) { '*' } else { ' ' };
+                write!(f, " [0x{:08x} {dirty}{star}]", self.line_address(set, line.tag))?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "  {}", self.stats)
+    }
+}
+
+/// The caches in front of one hart's memory: split L1 instruction and data
+/// caches and an optional unified L2. Any of them may be left out.
+#[derive(Debug, Clone, Default)]
+pub struct Caches {
+    pub icache: Option<Cache>,
+    pub dcache: Option<Cache>,
+    pub l2: Option<Cache>,
+    /// Line reads and writes that reached memory.
+    pub memory_reads: u64,
+    pub memory_writes: u64,
+}
+
+impl Caches {
+    pub fn new(icache: Option<CacheConfig>, dcache: Option<CacheConfig>, l2: Option<CacheConfig>) -> Self {
+        Caches {
+            icache: icache.map(|c| Cache::new("I-cache", c)),
+            dcache: dcache.map(|c| Cache::new("D-cache", c)),
+            l2: l2.map(|c| Cache::new("L2", c)),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.icache.is_none() && self.dcache.is_none() && self.l2.is_none()
+    }
+
+    /// An instruction fetch from `addr`.
+    pub fn fetch(&mut self, addr: u64) {
+        let below = match &mut self.icache {
+            Some(cache) => cache.access(addr, false).below,
+            None => vec![(addr, false)],
+        };
+        self.serve(below);
+    }
+
+    /// A data access to the physical address `addr`.
+    pub fn data(&mut self, addr: u64, write: bool) {
+        let below = match &mut self.dcache {
+            Some(cache) => cache.access(addr, write).below,
+            None => vec![(addr, write)],
+        };
+        self.serve(below);
+    }
+
+    /// Passes L1 requests through the L2 to memory.
+    fn serve(&mut self, requests: Vec<(u64, bool)>) {
+        for (addr, write) in requests {
+            let below = match &mut self.l2 {
+                Some(l2) => l2.access(addr, write).below,
+                None => vec![(addr, write)],
+            };
+            for (_, write) in below {
+                if write {
+                    self.memory_writes += 1;
+                } else {
+                    self.memory_reads += 1;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Caches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cache in [&self.icache, &self.dcache, &self.l2].into_iter().flatten() {
+            writeln!(f, "{cache}")?;
+        }
+        write!(f, "memory: {} reads, {} writes", self.memory_reads, self.memory_writes)
+    }
+}
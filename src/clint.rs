@@ -0,0 +1,112 @@
+use crate::bus::Device;
+use crate::memory::MemoryError;
+use crate::privilege::{MSIP, MTIP};
+
+/// Where QEMU's `virt` machine puts its CLINT.
+pub const VIRT_CLINT_BASE: u64 = 0x0200_0000;
+
+const MSIP_BASE: u64 = 0x0000;
+const MTIMECMP_BASE: u64 = 0x4000;
+const MTIME: u64 = 0xBFF8;
+
+/// A CLINT-compatible core-local interruptor: one `msip` word and one
+/// `mtimecmp` doubleword per hart, and a shared `mtime` that advances once
+/// every `ratio` steps. Registers may be accessed in any naturally aligned
+/// width, so RV32 code can reach the 64-bit ones a word at a time.
+pub struct Clint {
+    msip: Vec<bool>,
+    mtimecmp: Vec<u64>,
+    mtime: u64,
+    ratio: u64,
+    ticks: u64,
+}
+
+impl Clint {
+    pub fn new(harts: usize, ratio: u64) -> Self {
+        Clint { msip: vec![false; harts], mtimecmp: vec![u64::MAX; harts], mtime: 0, ratio: ratio.max(1), ticks: 0 }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    /// The register containing `offset`, as (start offset, width in bytes).
+    fn register(&self, offset: u64) -> Option<(u64, u64)> {
+        let harts = self.msip.len() as u64;
+        if (MTIME..MTIME + 8).contains(&offset) {
+            Some((MTIME, 8))
+        } else if (MTIMECMP_BASE..MTIMECMP_BASE + 8 * harts).contains(&offset) {
+            Some((offset & !7, 8))
+        } else if offset < MSIP_BASE + 4 * harts {
+            Some((offset & !3, 4))
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, reg: u64) -> u64 {
+        match reg {
+            MTIME => self.mtime,
+            r if r >= MTIMECMP_BASE => self.mtimecmp[((r - MTIMECMP_BASE) / 8) as usize],
+            r => self.msip[(r / 4) as usize] as u64,
+        }
+    }
+
+    fn set(&mut self, reg: u64, val: u64) {
+        match reg {
+            MTIME => self.mtime = val,
+            r if r >= MTIMECMP_BASE => self.mtimecmp[((r - MTIMECMP_BASE) / 8) as usize] = val,
+            r => self.msip[(r / 4) as usize] = val & 1 != 0,
+        }
+    }
+}
+
+fn mask(size: usize) -> u64 {
+    if size >= 8 { u64::MAX } else { (1 << (8 * size)) - 1 }
+}
+
+impl Device for Clint {
+    fn name(&self) -> &str {
+        "clint"
+    }
+
+    fn size(&self) -> u64 {
+        0x10000
+    }
+
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, MemoryError> {
+        let (reg, width) = self.register(offset).ok_or(MemoryError::OutOfBounds(offset))?;
+        if offset + size as u64 > reg + width {
+            return Err(MemoryError::OutOfBounds(offset));
+        }
+        Ok((self.get(reg) >> (8 * (offset - reg))) & mask(size))
+    }
+
+    fn write(&mut self, offset: u64, size: usize, val: u64) -> Result<(), MemoryError> {
+        let (reg, width) = self.register(offset).ok_or(MemoryError::OutOfBounds(offset))?;
+        if offset + size as u64 > reg + width {
+            return Err(MemoryError::OutOfBounds(offset));
+        }
+        let shift = 8 * (offset - reg);
+        let field = mask(size) << shift;
+        self.set(reg, (self.get(reg) & !field) | ((val << shift) & field));
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+        if self.ticks == self.ratio {
+            self.ticks = 0;
+            self.mtime = self.mtime.wrapping_add(1);
+        }
+    }
+
+    fn hart_interrupts(&self, hart: usize) -> u64 {
+        let (Some(&msip), Some(&mtimecmp)) = (self.msip.get(hart), self.mtimecmp.get(hart)) else {
+            return 0;
+        };
+        let software = if msip { MSIP } else { 0 };
+        let timer = if self.mtime >= mtimecmp { MTIP } else { 0 };
+        software | timer
+    }
+}
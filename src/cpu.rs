@@ -1,19 +1,81 @@
-use crate::instruction::Instruction;
+use std::collections::BTreeMap;
+use crate::instruction::{Instruction, Reg};
+use crate::bus::Bus;
+use crate::cache::Caches;
 use crate::memory::{Memory, MemoryError};
 use thiserror::Error;
 use crate::asm_parser::Program;
+use crate::csr;
+use crate::fpu::{self, Float, RoundingMode};
+use crate::isa::{Extension, Extensions, Isa};
+use crate::encoding;
+use crate::mmu::{Access, Fault, Mmu, Translation};
+use crate::predictor::BranchPredictor;
+use crate::stats::{Counters, ExecStats};
+use crate::trace::{self, MemAccess, Recording, RegRead, RegWrite, StepRecord, TrapRecord};
+use crate::privilege::{self, Exception, Interrupt, Privilege, Trap, TrapCsrs};
+use crate::vector::{Operand, Sew, VectorUnit};
+use crate::xlen::{Rv32, Xlen};
 
 #[derive(Debug, Error)]
 pub enum CpuError {
     #[error(transparent)]
     MemoryError(#[from] MemoryError),
+    #[error("Unknown CSR: 0x{0:03x}")]
+    UnknownCsr(u16),
+    #[error("Invalid rounding mode in frm: {0}")]
+    InvalidRoundingMode(u8),
+    #[error("Illegal instruction: {0:?}")]
+    IllegalInstruction(Instruction),
+    #[error("CSR 0x{0:03x} is not accessible from {1:?} mode")]
+    CsrPrivilege(u16, Privilege),
+    #[error("CSR 0x{0:03x} is read-only")]
+    ReadOnlyCsr(u16),
+    #[error("Environment call from {0:?} mode")]
+    EnvironmentCall(Privilege),
+    #[error("Breakpoint")]
+    Breakpoint,
+    #[error("Page fault on {1} at 0x{0:08x}: {2}")]
+    PageFault(u64, Access, Fault),
+    #[error("Jump to 0x{0:08x}, which is not the start of an instruction")]
+    MisalignedJump(u64),
 }
 
-pub struct Cpu {
-    pub regs: [i32; 32],
-    memory: Memory,
+pub struct Cpu<X: Xlen = Rv32> {
+    pub regs: [X::Reg; 32],
+    pub fregs: [u64; 32],
+    fflags: u8,
+    frm: u8,
+    bus: Bus,
+    /// The index of the next instruction in the program, which engines and
+    /// views step through. Programs only ever see its byte address,
+    /// [`pc_address`](Self::pc_address): links, `auipc`, `xepc`, `xtvec`
+    /// and jump targets are all byte addresses.
     pub pc: usize,
     program: Vec<Instruction>,
+    compressed: Vec<bool>,
+    /// Byte address of each instruction, plus the end of the code.
+    addresses: Vec<u32>,
+    /// The program's labels by instruction index.
+    labels: BTreeMap<usize, String>,
+    reservation: Option<u64>,
+    /// Physical addresses written by the last step.
+    stores: Vec<u64>,
+    hartid: usize,
+    extensions: Extensions,
+    privilege: Privilege,
+    trap: TrapCsrs,
+    mmu: Mmu,
+    vector: VectorUnit,
+    caches: Caches,
+    predictors: Vec<BranchPredictor>,
+    counters: Counters,
+    /// Instructions retired: steps that neither trapped nor were interrupted.
+    instret: u64,
+    /// Whether each step leaves a [`StepRecord`].
+    tracing: bool,
+    recording: Option<Recording>,
+    last_record: Option<StepRecord>,
 }
 impl Default for Cpu {
     fn default() -> Self {
@@ -23,204 +85,1377 @@ impl Default for Cpu {
 
 impl Cpu {
     pub fn new(mem_size: usize) -> Self {
+        Self::with_memory(mem_size)
+    }
+}
+
+impl<X: Xlen> Cpu<X> {
+    /// Like [`Cpu::new`], for any register width: `Cpu::<Rv64>::with_memory(1024)`.
+    pub fn with_memory(mem_size: usize) -> Self {
+        Self::with_bus(Bus::new(mem_size))
+    }
+
+    /// A CPU on a configured memory map; `sp` starts at the end of RAM.
+    pub fn with_bus(bus: Bus) -> Self {
         let mut cpu = Cpu {
-            regs: [0; 32],
-            memory: Memory::new(mem_size),
+            regs: [X::Reg::default(); 32],
+            fregs: [0; 32],
+            fflags: 0,
+            frm: 0,
+            bus,
             pc: 0,
             program: vec![],
+            compressed: vec![],
+            addresses: vec![0],
+            labels: BTreeMap::new(),
+            reservation: None,
+            stores: vec![],
+            hartid: 0,
+            extensions: Extensions::all(),
+            privilege: Privilege::Machine,
+            trap: TrapCsrs::default(),
+            mmu: Mmu::default(),
+            vector: VectorUnit::default(),
+            caches: Caches::default(),
+            predictors: vec![],
+            counters: Counters::default(),
+            instret: 0,
+            tracing: false,
+            recording: None,
+            last_record: None,
         };
-        cpu.regs[2] = cpu.memory.size() as i32;
+        cpu.set(2, cpu.bus.ram_end() as i64);
         cpu
     }
+
+    fn x(&self, r: usize) -> i64 {
+        X::to_i64(self.regs[r])
+    }
+
+    fn xu(&self, r: usize) -> u64 {
+        X::to_u64(self.regs[r])
+    }
+
+    fn set(&mut self, r: usize, v: i64) {
+        self.regs[r] = X::from_i64(v);
+    }
+
+    /// Effective address `rs1 + imm`, wrapped to XLEN bits.
+    fn addr(&self, rs1: usize, imm: i32) -> u64 {
+        X::to_u64(X::from_i64(self.x(rs1).wrapping_add(imm as i64)))
+    }
+
+    /// Rejects shift immediates that do not fit in `bits` bits.
+    fn shamt(inst: Instruction, imm: i32, bits: u32) -> Result<u32, CpuError> {
+        match u32::try_from(imm) {
+            Ok(sh) if sh < bits => Ok(sh),
+            _ => Err(CpuError::IllegalInstruction(inst)),
+        }
+    }
+
+    pub fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    /// Instructions from extensions outside `extensions` raise
+    /// [`CpuError::IllegalInstruction`].
+    pub fn set_extensions(&mut self, extensions: Extensions) {
+        self.extensions = extensions;
+    }
+
+    pub fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    pub fn set_privilege(&mut self, privilege: Privilege) {
+        self.privilege = privilege;
+    }
+
+    /// The value of `mhartid`.
+    pub fn hartid(&self) -> usize {
+        self.hartid
+    }
+
+    pub fn set_hartid(&mut self, hartid: usize) {
+        self.hartid = hartid;
+    }
+
+    pub fn vector(&self) -> &VectorUnit {
+        &self.vector
+    }
+
+    /// Replaces the vector register file with one of `vlen` bits per
+    /// register, which also clears it.
+    pub fn set_vlen(&mut self, vlen: usize) {
+        self.vector = VectorUnit::new(vlen);
+    }
+
+    pub fn trap_csrs(&self) -> &TrapCsrs {
+        &self.trap
+    }
+
+    /// The RAM behind the bus, indexed from its base address.
+    pub fn memory(&self) -> &Memory {
+        self.bus.ram()
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        self.bus.ram_mut()
+    }
+
+    /// Physical memory and devices, bypassing translation.
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn mmu(&self) -> &Mmu {
+        &self.mmu
+    }
+
+    pub fn set_tlb_capacity(&mut self, capacity: usize) {
+        self.mmu.set_tlb_capacity(capacity);
+    }
+
+    /// The caches the hart's fetches and RAM accesses go through.
+    pub fn caches(&self) -> &Caches {
+        &self.caches
+    }
+
+    pub fn set_caches(&mut self, caches: Caches) {
+        self.caches = caches;
+    }
+
+    /// The predictors scored against every conditional branch.
+    pub fn predictors(&self) -> &[BranchPredictor] {
+        &self.predictors
+    }
+
+    pub fn add_predictor(&mut self, predictor: BranchPredictor) {
+        self.predictors.push(predictor);
+    }
+
+    /// How often each instruction has executed since the program loaded.
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.counters.clear();
+    }
+
+    /// How many instructions have retired; a step that traps or takes an
+    /// interrupt leaves this unchanged.
+    pub fn instret(&self) -> u64 {
+        self.instret
+    }
+
+    /// The execution report, listing the `top` hottest instructions and
+    /// labels.
+    pub fn stats(&self, top: usize) -> ExecStats {
+        ExecStats::collect(self, top)
+    }
+
+    /// Translates a data address at the current effective privilege, and
+    /// runs RAM accesses through the data cache.
+    fn translate(&mut self, vaddr: u64, access: Access) -> Result<u64, CpuError> {
+        let privilege = self.trap.data_privilege(self.privilege);
+        let paddr = self
+            .mmu
+            .translate(&mut self.bus, vaddr, access, privilege, self.trap.mstatus)
+            .map_err(|fault| match fault {
+                Fault::PteOutOfBounds(addr) => CpuError::from(MemoryError::OutOfBounds(addr)),
+                fault => CpuError::PageFault(vaddr, access, fault),
+            })?;
+        let in_ram = (self.bus.ram_base()..self.bus.ram_end()).contains(&paddr);
+        if in_ram && !self.caches.is_empty() {
+            self.caches.data(paddr, access == Access::Store);
+        }
+        if let Some(recording) = &mut self.recording {
+            let size = trace::access_size(&self.program[self.pc]);
+            let old = if in_ram { self.bus.read(paddr, size).ok() } else { None };
+            recording.memory.push(MemAccess { vaddr, paddr, size, access, old, new: None });
+        }
+        Ok(paddr)
+    }
+
+    /// How a data access to `vaddr` would translate right now.
+    pub fn explain_translation(&self, vaddr: u64, access: Access) -> Translation {
+        let privilege = self.trap.data_privilege(self.privilege);
+        self.mmu.explain(&self.bus, vaddr, access, privilege, self.trap.mstatus)
+    }
+
+    pub fn isa(&self) -> Isa {
+        Isa { xlen: X::BITS, extensions: self.extensions }
+    }
+
     pub fn load_instructions(&mut self, program: Vec<Instruction>) {
+        self.compressed = vec![false; program.len()];
+        self.addresses = (0..=program.len() as u32).map(|idx| 4 * idx).collect();
         self.program = program;
+        self.labels.clear();
+        self.counters.clear();
     }
     pub fn load_program(&mut self, program:Program){
+        self.addresses = program.addresses();
         self.program = program.instructions;
+        self.compressed = program.compressed;
         self.pc = *program.labels.get("_start").unwrap_or(&0);
+        self.labels.clear();
+        self.counters.clear();
+        for (label, idx) in program.labels {
+            match self.labels.get(&idx) {
+                Some(other) if *other <= label => {}
+                _ => {
+                    self.labels.insert(idx, label);
+                }
+            }
+        }
+    }
+
+    /// The program's labels by instruction index; where several mark the
+    /// same instruction, the first by name.
+    pub fn labels(&self) -> &BTreeMap<usize, String> {
+        &self.labels
+    }
+
+    /// The closest label at or before instruction `idx`.
+    pub fn label_for(&self, idx: usize) -> Option<&str> {
+        self.labels.range(..=idx).next_back().map(|(_, label)| label.as_str())
+    }
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
     }
+
     pub fn add_instruction(&mut self, inst: Instruction) {
         self.program.push(inst);
+        self.compressed.push(false);
+        let end = *self.addresses.last().unwrap_or(&0);
+        self.addresses.push(end + 4);
+    }
+
+    /// Byte address of instruction `idx`: each instruction before it takes
+    /// 2 bytes if compressed and 4 otherwise, and 4 past the end.
+    pub fn address_of(&self, idx: usize) -> u32 {
+        match self.addresses.get(idx) {
+            Some(&addr) => addr,
+            None => {
+                let end = self.addresses.len() - 1;
+                self.addresses[end] + 4 * (idx - end) as u32
+            }
+        }
+    }
+
+    /// The program counter as a byte address.
+    pub fn pc_address(&self) -> u32 {
+        self.address_of(self.pc)
+    }
+
+    /// The index of the instruction at byte address `addr`, the inverse of
+    /// [`address_of`](Self::address_of). `None` when `addr` falls inside an
+    /// instruction.
+    pub fn index_of(&self, addr: u64) -> Option<usize> {
+        let end = self.addresses.len() - 1;
+        let end_addr = self.addresses[end] as u64;
+        if addr >= end_addr {
+            let past = addr - end_addr;
+            return past.is_multiple_of(4).then(|| end + (past / 4) as usize);
+        }
+        self.addresses.binary_search(&(addr as u32)).ok()
+    }
+
+    /// The instruction index a jump to `addr` continues at.
+    fn jump_target(&self, addr: u64) -> Result<usize, CpuError> {
+        self.index_of(addr).ok_or(CpuError::MisalignedJump(addr))
     }
 
     pub fn print_instructions(&self) {
         println!("\nInstructions:");
         for (idx, inst) in self.program.iter().enumerate() {
             let marker = if idx == self.pc { " -> " } else { "    " };
-            println!("{}{}: {:?}", marker, idx, inst);
+            let size = if self.compressed[idx] { "c" } else { " " };
+            println!("{}{}: 0x{:04x} {} {:?}", marker, idx, self.address_of(idx), size, inst);
         }
         println!();
     }
 
+    /// The floating-point control and status register: `frm` in bits 7:5,
+    /// the accrued exception flags in bits 4:0.
+    pub fn fcsr(&self) -> u32 {
+        ((self.frm as u32) << 5) | self.fflags as u32
+    }
+
+    pub fn read_csr(&self, csr: u16) -> Result<i64, CpuError> {
+        match csr {
+            csr::FFLAGS => Ok(self.fflags as i64),
+            csr::FRM => Ok(self.frm as i64),
+            csr::FCSR => Ok(self.fcsr() as i64),
+            csr::MISA => Ok(self.isa().misa() as i64),
+            csr::MHARTID => Ok(self.hartid as i64),
+            csr::SATP => Ok(self.mmu.satp() as i64),
+            csr::VSTART => Ok(0),
+            csr::VL => Ok(self.vector.vl() as i64),
+            csr::VTYPE => Ok(X::to_i64(X::from_i64(self.vector.vtype(X::BITS) as i64))),
+            csr::VLENB => Ok(self.vector.vlenb() as i64),
+            _ => self.trap.read(csr).map(|v| v as i64).ok_or(CpuError::UnknownCsr(csr)),
+        }
+    }
+
+    pub fn write_csr(&mut self, csr: u16, val: i64) -> Result<(), CpuError> {
+        match csr {
+            csr::FFLAGS => self.fflags = (val & 0x1F) as u8,
+            csr::FRM => self.frm = (val & 0x7) as u8,
+            csr::FCSR => {
+                self.fflags = (val & 0x1F) as u8;
+                self.frm = ((val >> 5) & 0x7) as u8;
+            }
+            // The extensions are fixed at configuration time.
+            csr::MISA => {}
+            // Only Sv32 is implemented, so RV64 is limited to Bare mode.
+            csr::SATP if X::BITS == 32 => self.mmu.set_satp(val as u64),
+            csr::SATP => {}
+            // Vector instructions always run to completion.
+            csr::VSTART => {}
+            _ => {
+                if !self.trap.write(csr, X::to_u64(X::from_i64(val))) {
+                    return Err(CpuError::UnknownCsr(csr));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shared by all six Zicsr instructions: `write` maps the old value to the
+    /// new one, or is `None` when the instruction must not write at all.
+    fn csr_op(&mut self, rd: usize, csr: u16, write: Option<i64>, op: impl Fn(i64, i64) -> i64) -> Result<(), CpuError> {
+        self.check_csr_access(csr, write.is_some())?;
+        let old = self.read_csr(csr)?;
+        if let Some(val) = write {
+            self.write_csr(csr, op(old, val))?;
+        }
+        self.set(rd, old);
+        Ok(())
+    }
+
+    /// `mstatus.TVM` keeps S-mode away from `satp` and `sfence.vma`.
+    fn tvm_trapped(&self) -> bool {
+        self.privilege == Privilege::Supervisor && self.trap.mstatus & privilege::TVM != 0
+    }
+
+    fn check_csr_access(&self, csr: u16, write: bool) -> Result<(), CpuError> {
+        if csr::min_privilege(csr) > self.privilege.bits() {
+            return Err(CpuError::CsrPrivilege(csr, self.privilege));
+        }
+        if csr == csr::SATP && self.tvm_trapped() {
+            return Err(CpuError::CsrPrivilege(csr, self.privilege));
+        }
+        if write && csr::is_read_only(csr) {
+            return Err(CpuError::ReadOnlyCsr(csr));
+        }
+        Ok(())
+    }
+
+    /// The exception an execution error raises, and the value for `xtval`.
+    fn exception_for(inst: &Instruction, err: &CpuError) -> (Exception, u64) {
+        let store = inst.writes_memory();
+        match err {
+            CpuError::MemoryError(MemoryError::MisalignedAccess(addr)) if store => (Exception::StoreAddressMisaligned, *addr),
+            CpuError::MemoryError(MemoryError::MisalignedAccess(addr)) => (Exception::LoadAddressMisaligned, *addr),
+            CpuError::MemoryError(MemoryError::OutOfBounds(addr)) if store => (Exception::StoreAccessFault, *addr),
+            CpuError::MemoryError(MemoryError::OutOfBounds(addr)) => (Exception::LoadAccessFault, *addr),
+            CpuError::EnvironmentCall(privilege) => (Exception::ecall_from(*privilege), 0),
+            CpuError::Breakpoint => (Exception::Breakpoint, 0),
+            CpuError::MisalignedJump(addr) => (Exception::InstructionAddressMisaligned, *addr),
+            CpuError::PageFault(addr, access, _) => {
+                let cause = match access {
+                    Access::Load => Exception::LoadPageFault,
+                    Access::Store => Exception::StorePageFault,
+                    Access::Fetch => Exception::InstructionPageFault,
+                };
+                (cause, *addr)
+            }
+            _ => (Exception::IllegalInstruction, encoding::encode(inst).unwrap_or(0) as u64),
+        }
+    }
+
+    /// The interrupt to take before the next instruction, if any. M-mode
+    /// interrupts are enabled below M-mode or by `mstatus.MIE`; those
+    /// delegated through `mideleg` below S-mode or by `mstatus.SIE`.
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.trap.pending_interrupts() & self.trap.mie;
+        let machine = self.privilege < Privilege::Machine || self.trap.mstatus & privilege::MIE != 0;
+        let supervisor = self.privilege < Privilege::Supervisor
+            || (self.privilege == Privilege::Supervisor && self.trap.mstatus & privilege::SIE != 0);
+        let enabled = match (machine, supervisor) {
+            (true, true) => pending,
+            (true, false) => pending & !self.trap.mideleg,
+            (false, _) => 0,
+        };
+        Interrupt::PRIORITY.into_iter().find(|i| enabled & i.bit() != 0)
+    }
+
+    /// Enters the trap handler: S-mode if delegated by `medeleg`/`mideleg`
+    /// and not raised in M-mode, M-mode otherwise. Returns `false` when that
+    /// mode has no trap vector yet.
+    fn take_trap(&mut self, trap: Trap, tval: u64) -> bool {
+        let (code, delegated, cause) = match trap {
+            Trap::Exception(e) => (e.code(), self.trap.medeleg, e.code()),
+            Trap::Interrupt(i) => (i.code(), self.trap.mideleg, i.code() | 1 << (X::BITS - 1)),
+        };
+        let status = self.trap.mstatus;
+        // The low two bits of xtvec select the mode; only direct is modelled.
+        let handler = |vector: Option<u64>| vector.and_then(|v| self.index_of(v & !3));
+        let epc = self.pc_address() as u64;
+        if self.privilege < Privilege::Machine && (delegated >> code) & 1 != 0 {
+            let Some(handler) = handler(self.trap.stvec) else { return false };
+            self.trap.sepc = epc;
+            self.trap.scause = cause;
+            self.trap.stval = tval;
+            let spp = if self.privilege == Privilege::Supervisor { privilege::SPP } else { 0 };
+            let spie = if status & privilege::SIE != 0 { privilege::SPIE } else { 0 };
+            self.trap.mstatus = (status & !(privilege::SPP | privilege::SPIE | privilege::SIE)) | spp | spie;
+            self.privilege = Privilege::Supervisor;
+            self.pc = handler;
+        } else {
+            let Some(handler) = handler(self.trap.mtvec) else { return false };
+            self.trap.mepc = epc;
+            self.trap.mcause = cause;
+            self.trap.mtval = tval;
+            let mpp = self.privilege.bits() << privilege::MPP_SHIFT;
+            let mpie = if status & privilege::MIE != 0 { privilege::MPIE } else { 0 };
+            self.trap.mstatus = (status & !(privilege::MPP | privilege::MPIE | privilege::MIE)) | mpp | mpie;
+            self.privilege = Privilege::Machine;
+            self.pc = handler;
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.trap = Some(TrapRecord { trap, tval, privilege: self.privilege });
+        }
+        true
+    }
+
+    pub fn freg<T: Float>(&self, r: usize) -> T {
+        T::unbox(self.fregs[r])
+    }
+
+    pub fn set_freg<T: Float>(&mut self, r: usize, v: T) {
+        self.fregs[r] = v.boxed();
+    }
+
+    fn rounding_mode(&self, rm: RoundingMode) -> Result<RoundingMode, CpuError> {
+        if rm != RoundingMode::Dyn {
+            return Ok(rm);
+        }
+        match RoundingMode::from_bits(self.frm) {
+            Some(RoundingMode::Dyn) | None => Err(CpuError::InvalidRoundingMode(self.frm)),
+            Some(rm) => Ok(rm),
+        }
+    }
+
+    fn fp_unary<T: Float>(&mut self, rd: usize, rs1: usize, rm: RoundingMode, op: fn(T, RoundingMode, &mut u8) -> T) -> Result<(), CpuError> {
+        let rm = self.rounding_mode(rm)?;
+        let v = op(self.freg(rs1), rm, &mut self.fflags);
+        self.set_freg(rd, v);
+        Ok(())
+    }
+
+    fn fp_binary<T: Float>(&mut self, rd: usize, rs1: usize, rs2: usize, rm: RoundingMode, op: fn(T, T, RoundingMode, &mut u8) -> T) -> Result<(), CpuError> {
+        let rm = self.rounding_mode(rm)?;
+        let v = op(self.freg(rs1), self.freg(rs2), rm, &mut self.fflags);
+        self.set_freg(rd, v);
+        Ok(())
+    }
+
+    fn fp_fused<T: Float>(&mut self, (rd, rs1, rs2, rs3): (usize, usize, usize, usize), rm: RoundingMode, negate_product: bool, negate_addend: bool) -> Result<(), CpuError> {
+        let rm = self.rounding_mode(rm)?;
+        let v = fpu::fma::<T>(self.freg(rs1), self.freg(rs2), self.freg(rs3), negate_product, negate_addend, rm, &mut self.fflags);
+        self.set_freg(rd, v);
+        Ok(())
+    }
+
+    fn fp_min_max<T: Float>(&mut self, rd: usize, rs1: usize, rs2: usize, op: fn(T, T, &mut u8) -> T) {
+        let v = op(self.freg(rs1), self.freg(rs2), &mut self.fflags);
+        self.set_freg(rd, v);
+    }
+
+    fn fp_compare<T: Float>(&mut self, rd: usize, rs1: usize, rs2: usize, op: fn(T, T, &mut u8) -> bool) {
+        let v = op(self.freg(rs1), self.freg(rs2), &mut self.fflags);
+        self.set(rd, v as i64);
+    }
+
+    fn fp_sign_inject<T: Float>(&mut self, rd: usize, rs1: usize, rs2: usize, negate: bool, xor: bool) {
+        let v = fpu::sign_inject::<T>(self.freg(rs1), self.freg(rs2), negate, xor);
+        self.set_freg(rd, v);
+    }
+
+    fn fp_to_int<T: Float>(&mut self, rd: usize, rs1: usize, rm: RoundingMode, signed: bool) -> Result<(), CpuError> {
+        let rm = self.rounding_mode(rm)?;
+        let x: T = self.freg(rs1);
+        let v = if signed {
+            fpu::to_i32(x, rm, &mut self.fflags)
+        } else {
+            fpu::to_u32(x, rm, &mut self.fflags) as i32
+        };
+        self.set(rd, v as i64);
+        Ok(())
+    }
+
+    fn int_to_fp<T: Float>(&mut self, rd: usize, rs1: usize, rm: RoundingMode, signed: bool) -> Result<(), CpuError> {
+        let rm = self.rounding_mode(rm)?;
+        let v = if signed { self.x(rs1) as i32 as i64 } else { self.x(rs1) as u32 as i64 };
+        let v: T = fpu::from_int(v, rm, &mut self.fflags);
+        self.set_freg(rd, v);
+        Ok(())
+    }
+
+    /// Rotates the low XLEN bits of `v`.
+    fn rotate(v: u64, sh: u32, left: bool) -> i64 {
+        let sh = sh & X::shamt_mask();
+        let rotated = if X::BITS == 64 {
+            if left { v.rotate_left(sh) } else { v.rotate_right(sh) }
+        } else if left {
+            (v as u32).rotate_left(sh) as u64
+        } else {
+            (v as u32).rotate_right(sh) as u64
+        };
+        rotated as i64
+    }
+
+    /// The full 2*XLEN-bit carry-less product.
+    fn clmul(a: u64, b: u64) -> u128 {
+        (0..X::BITS)
+            .filter(|i| (b >> i) & 1 != 0)
+            .fold(0, |acc, i| acc ^ ((a as u128) << i))
+    }
+
+    /// Applies `f` to each byte of the low XLEN bits of `v`.
+    fn map_bytes(v: u64, f: impl Fn(u8) -> u8) -> i64 {
+        (0..X::BITS / 8).fold(0u64, |acc, i| acc | (f((v >> (8 * i)) as u8) as u64) << (8 * i)) as i64
+    }
+
+    /// Address of the word reserved by the last `lr.w`, if still valid.
+    pub fn reservation(&self) -> Option<u64> {
+        self.reservation
+    }
+
+    /// Takes a conditional branch by `offset` instructions if `taken`,
+    /// scoring each predictor on it.
+    fn branch(&mut self, next_pc: &mut usize, offset: i32, taken: bool) {
+        let target = (self.pc as i32 + offset) as usize;
+        for predictor in &mut self.predictors {
+            predictor.resolve(self.pc, target, taken);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.branch = Some(taken);
+        }
+        if taken {
+            self.counters.record_taken(self.pc);
+            *next_pc = target;
+        }
+    }
+
+    /// Records a store to `addr`, which also breaks this hart's reservation.
+    fn invalidate_reservation(&mut self, addr: u64) {
+        self.stores.push(addr);
+        self.snoop_store(addr);
+    }
+
+    /// Drops the reservation if it covers `addr`; called for stores made by
+    /// other harts.
+    pub fn snoop_store(&mut self, addr: u64) {
+        if self.reservation == Some(addr & !3) {
+            self.reservation = None;
+        }
+    }
+
+    /// Physical addresses the last step stored to.
+    pub fn last_stores(&self) -> &[u64] {
+        &self.stores
+    }
+
+    fn amo(&mut self, rd: usize, rs1: usize, rs2: usize, op: impl Fn(i32, i32) -> i32) -> Result<(), CpuError> {
+        let addr = self.translate(self.xu(rs1), Access::Store)?;
+        let old = self.bus.read_word(addr)?;
+        self.bus.write_word(addr, op(old, self.x(rs2) as i32))?;
+        self.invalidate_reservation(addr);
+        self.set(rd, old as i64);
+        Ok(())
+    }
+
+    /// A vector operation, which `None` marks as illegal in the current
+    /// configuration.
+    fn vop(&mut self, inst: Instruction, op: impl FnOnce(&mut VectorUnit) -> Option<()>) -> Result<(), CpuError> {
+        op(&mut self.vector).ok_or(CpuError::IllegalInstruction(inst))
+    }
+
+    /// The `.vx` operand: `x[rs1]`, sign-extended to 64 bits.
+    fn vx(&self, rs1: usize) -> Operand {
+        Operand::Scalar(self.x(rs1) as u64)
+    }
+
+    fn vi(imm: i32) -> Operand {
+        Operand::Scalar(imm as i64 as u64)
+    }
+
+    /// Unit-stride when `stride` is `None`, else strided by `x[rs2]` bytes.
+    fn vector_load(&mut self, inst: Instruction, vd: usize, rs1: usize, stride: Option<usize>, eew: Sew, vm: bool) -> Result<(), CpuError> {
+        self.vector.check_access(vd, eew).ok_or(CpuError::IllegalInstruction(inst))?;
+        let stride = stride.map_or(eew.bytes() as i64, |rs2| self.x(rs2));
+        for i in self.vector.active_elements(vm) {
+            let vaddr = self.x(rs1).wrapping_add(stride.wrapping_mul(i as i64));
+            let addr = self.translate(X::to_u64(X::from_i64(vaddr)), Access::Load)?;
+            let val = self.bus.read(addr, eew.bytes())?;
+            self.vector.set_element(vd, i, eew, val);
+        }
+        Ok(())
+    }
+
+    fn vector_store(&mut self, inst: Instruction, vs3: usize, rs1: usize, stride: Option<usize>, eew: Sew, vm: bool) -> Result<(), CpuError> {
+        self.vector.check_access(vs3, eew).ok_or(CpuError::IllegalInstruction(inst))?;
+        let stride = stride.map_or(eew.bytes() as i64, |rs2| self.x(rs2));
+        for i in self.vector.active_elements(vm) {
+            let vaddr = self.x(rs1).wrapping_add(stride.wrapping_mul(i as i64));
+            let addr = self.translate(X::to_u64(X::from_i64(vaddr)), Access::Store)?;
+            self.bus.write(addr, eew.bytes(), self.vector.element(vs3, i, eew))?;
+            self.invalidate_reservation(addr);
+        }
+        Ok(())
+    }
+
+    /// Makes every step leave a [`StepRecord`] in
+    /// [`last_record`](Self::last_record).
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+        self.last_record = None;
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.tracing
+    }
+
+    /// What the last step did, while tracing.
+    pub fn last_record(&self) -> Option<&StepRecord> {
+        self.last_record.as_ref()
+    }
+
+    /// [`execute_next`](Self::execute_next), returning what the step did,
+    /// or `None` when there was nothing to execute.
+    pub fn execute_traced(&mut self) -> Result<Option<StepRecord>, CpuError> {
+        let tracing = self.tracing;
+        self.tracing = true;
+        let result = self.execute_next();
+        self.tracing = tracing;
+        let record = if tracing { self.last_record.clone() } else { self.last_record.take() };
+        result.map(|stepped| record.filter(|_| stepped))
+    }
+
+    /// A scalar register's value, FP registers as raw bits.
+    fn reg_value(&self, reg: Reg) -> Option<i64> {
+        match reg {
+            Reg::X(r) => Some(self.x(r)),
+            Reg::F(r) => Some(self.fregs[r] as i64),
+            Reg::V(_) => None,
+        }
+    }
+
+    /// Executes one instruction, or enters the handler of a pending enabled
+    /// interrupt instead. Exceptions are delivered to the trap handler of the
+    /// responsible mode when one is installed, and returned otherwise.
     pub fn execute_next(&mut self) -> Result<bool, CpuError> {
+        if !self.tracing {
+            return self.step();
+        }
+        self.last_record = None;
+        let pc = self.pc;
+        let inst = self.program.get(pc).copied();
+        let reads = inst.map_or(vec![], |inst| {
+            let sources = inst.sources().into_iter();
+            sources.filter_map(|reg| Some(RegRead { reg, value: self.reg_value(reg)? })).collect()
+        });
+        let dest = inst.and_then(|inst| inst.destination()).and_then(|reg| Some((reg, self.reg_value(reg)?)));
+        self.recording = Some(Recording::default());
+        let result = self.step();
+        let recording = self.recording.take().unwrap_or_default();
+        if let Ok(true) = result {
+            let interrupted = matches!(recording.trap, Some(TrapRecord { trap: Trap::Interrupt(_), .. }));
+            let mut memory = recording.memory;
+            for access in &mut memory {
+                if access.old.is_some() {
+                    access.new = self.bus.read(access.paddr, access.size).ok();
+                }
+            }
+            self.last_record = Some(StepRecord {
+                pc,
+                next_pc: self.pc,
+                inst: if interrupted { None } else { inst },
+                reads: if interrupted { vec![] } else { reads },
+                write: match (dest, recording.trap) {
+                    (Some((reg, old)), None) => {
+                        Some(RegWrite { reg, old, new: self.reg_value(reg).expect("a scalar register") })
+                    }
+                    _ => None,
+                },
+                memory,
+                branch: recording.branch,
+                trap: recording.trap,
+            });
+        }
+        result
+    }
+
+    fn step(&mut self) -> Result<bool, CpuError> {
         if self.pc >= self.program.len() {
             return Ok(false);
         }
-        let inst = &self.program[self.pc];
+        self.stores.clear();
+        self.trap.interrupt_lines = self.bus.hart_interrupts(self.hartid);
+        if let Some(interrupt) = self.pending_interrupt()
+            && self.take_trap(Trap::Interrupt(interrupt), 0)
+        {
+            self.bus.tick();
+            return Ok(true);
+        }
+        let inst = self.program[self.pc];
+        self.counters.record(self.pc);
+        if !self.caches.is_empty() {
+            self.caches.fetch(self.pc_address() as u64);
+        }
+        let result = self.execute(inst);
+        self.bus.tick();
+        match result {
+            Ok(()) => {
+                self.instret += 1;
+                Ok(true)
+            }
+            Err(err) => {
+                let (cause, tval) = Self::exception_for(&inst, &err);
+                if self.take_trap(Trap::Exception(cause), tval) { Ok(true) } else { Err(err) }
+            }
+        }
+    }
+
+    fn execute(&mut self, inst: Instruction) -> Result<(), CpuError> {
         let mut next_pc = self.pc + 1;
 
-        match inst {
-            Instruction::Add { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] + self.regs[*rs2],
-            Instruction::Sub { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] - self.regs[*rs2],
-            Instruction::Mul { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] * self.regs[*rs2],
+        let parcel_ok = !self.compressed[self.pc] || self.extensions.contains(Extension::C);
+        if self.isa().check(&inst).is_err() || !parcel_ok {
+            return Err(CpuError::IllegalInstruction(inst));
+        }
+
+        match &inst {
+            Instruction::Add { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1).wrapping_add(self.x(*rs2))),
+            Instruction::Sub { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1).wrapping_sub(self.x(*rs2))),
+            Instruction::Mul { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1).wrapping_mul(self.x(*rs2))),
             Instruction::Mulh { rd, rs1, rs2 } => {
-                let result = self.regs[*rs1] as i64 * self.regs[*rs2] as i64;
-                self.regs[*rd] = ( result >> 32 )as i32;
+                let result = self.x(*rs1) as i128 * self.x(*rs2) as i128;
+                self.set(*rd, (result >> X::BITS) as i64);
             }
             Instruction::Mulhsu { rd, rs1, rs2 } => {
-                let result = self.regs[*rs1] as i64 * self.regs[*rs2] as u32 as i64;
-                self.regs[*rd] = ( result >> 32 )as i32;
+                let result = self.x(*rs1) as i128 * self.xu(*rs2) as i128;
+                self.set(*rd, (result >> X::BITS) as i64);
             }
             Instruction::Mulhu { rd, rs1, rs2 } => {
-                let result = self.regs[*rs1] as u32 as  u64 * self.regs[*rs2] as u32 as u64;
-                self.regs[*rd] = ( result >> 32 )as i32;
+                let result = self.xu(*rs1) as u128 * self.xu(*rs2) as u128;
+                self.set(*rd, (result >> X::BITS) as i64);
             }
             Instruction::Div { rd, rs1, rs2 } => {
-                self.regs[*rd] = if self.regs[*rs2] != 0 {
-                    self.regs[*rs1] / self.regs[*rs2]
+                let v = if self.x(*rs2) != 0 {
+                    self.x(*rs1).wrapping_div(self.x(*rs2))
                 } else {
                     -1
-                }
+                };
+                self.set(*rd, v);
             }
             Instruction::Divu { rd, rs1, rs2 } => {
-                self.regs[*rd] = if self.regs[*rs2] != 0 {
-                    ((self.regs[*rs1] as u32) / (self.regs[*rs2] as u32)) as i32
+                let v = if self.xu(*rs2) != 0 {
+                    (self.xu(*rs1) / self.xu(*rs2)) as i64
                 } else {
                     -1
-                }
+                };
+                self.set(*rd, v);
             }
             Instruction::Rem {rd, rs1, rs2} =>{
-                self.regs[*rd] = if self.regs[*rs2] == 0 {
-                    self.regs[*rs1]
+                let v = if self.x(*rs2) == 0 {
+                    self.x(*rs1)
                 }else{
-                    self.regs[*rs1] % self.regs[*rs2]
+                    self.x(*rs1).wrapping_rem(self.x(*rs2))
                 };
+                self.set(*rd, v);
             }
             Instruction::Remu {rd, rs1, rs2} =>{
-                self.regs[*rd] = if self.regs[*rs2] == 0 {
-                    self.regs[*rs1]
+                let v = if self.xu(*rs2) == 0 {
+                    self.x(*rs1)
                 }else{
-                    ((self.regs[*rs1] as u32) % (self.regs[*rs2] as u32)) as i32
+                    (self.xu(*rs1) % self.xu(*rs2)) as i64
                 };
+                self.set(*rd, v);
             }
-            Instruction::And { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] & self.regs[*rs2],
-            Instruction::Or { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] | self.regs[*rs2],
-            Instruction::Xor { rd, rs1, rs2 } => self.regs[*rd] = self.regs[*rs1] ^ self.regs[*rs2],
+            Instruction::And { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1) & self.x(*rs2)),
+            Instruction::Or { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1) | self.x(*rs2)),
+            Instruction::Xor { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1) ^ self.x(*rs2)),
             Instruction::Sll { rd, rs1, rs2 } => {
-                self.regs[*rd] = self.regs[*rs1] << (self.regs[*rs2] & 0x1F)
+                self.set(*rd, self.x(*rs1) << (self.xu(*rs2) as u32 & X::shamt_mask()))
             }
             Instruction::Srl { rd, rs1, rs2 } => {
-                self.regs[*rd] = ((self.regs[*rs1] as u32) >> (self.regs[*rs2] & 0x1F)) as i32
+                self.set(*rd, (self.xu(*rs1) >> (self.xu(*rs2) as u32 & X::shamt_mask())) as i64)
             }
             Instruction::Sra { rd, rs1, rs2 } => {
-                self.regs[*rd] = self.regs[*rs1] >> (self.regs[*rs2] & 0x1F)
+                self.set(*rd, self.x(*rs1) >> (self.xu(*rs2) as u32 & X::shamt_mask()))
             }
             Instruction::Slt { rd, rs1, rs2 } => {
-                self.regs[*rd] = if self.regs[*rs1] < self.regs[*rs2] { 1 }else { 0 }
+                self.set(*rd, if self.x(*rs1) < self.x(*rs2) { 1 }else { 0 })
             }
             Instruction::Sltu { rd, rs1, rs2 } => {
-                self.regs[*rd] = if (self.regs[*rs1] as u32 )< (self.regs[*rs2] as u32) { 1 }else { 0 }
+                self.set(*rd, if self.xu(*rs1) < self.xu(*rs2) { 1 }else { 0 })
+            }
+            Instruction::Addi { rd, rs1, imm } => self.set(*rd, self.x(*rs1).wrapping_add(*imm as i64)),
+            Instruction::Andi { rd, rs1, imm } => self.set(*rd, self.x(*rs1) & *imm as i64),
+            Instruction::Ori { rd, rs1, imm } => self.set(*rd, self.x(*rs1) | *imm as i64),
+            Instruction::Xori { rd, rs1, imm } => self.set(*rd, self.x(*rs1) ^ *imm as i64),
+            Instruction::Slli { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, self.x(*rs1) << sh)
             }
-            Instruction::Addi { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] + imm,
-            Instruction::Andi { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] & imm,
-            Instruction::Ori { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] | imm,
-            Instruction::Xori { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] ^ imm,
-            Instruction::Slli { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] << imm,
             Instruction::Srli { rd, rs1, imm } => {
-                self.regs[*rd] = ((self.regs[*rs1] as u32) >> imm) as i32
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, (self.xu(*rs1) >> sh) as i64)
+            }
+            Instruction::Srai { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, self.x(*rs1) >> sh)
             }
-            Instruction::Srai { rd, rs1, imm } => self.regs[*rd] = self.regs[*rs1] >> imm,
             Instruction::Slti { rd, rs1, imm } => {
-                self.regs[*rd] = if self.regs[*rs1] < *imm { 1 } else { 0 };
+                self.set(*rd, if self.x(*rs1) < *imm as i64 { 1 } else { 0 });
             }
             Instruction::Sltiu { rd, rs1, imm } => {
-                self.regs[*rd] = if (self.regs[*rs1] as u32 )< (*imm as u32) { 1 }else { 0 }
+                self.set(*rd, if self.xu(*rs1) < X::to_u64(X::from_i64(*imm as i64)) { 1 }else { 0 })
             }
 
             Instruction::Sb { rs1, rs2, imm } => {
-                let addr = (self.regs[*rs2] + imm) as u32;
-                self.memory
-                    .write_byte(addr, (self.regs[*rs1] & 0xFF) as u8)?
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus
+                    .write_byte(addr, (self.x(*rs2) & 0xFF) as u8)?;
+                self.invalidate_reservation(addr);
             }
             Instruction::Sh { rs1, rs2, imm } => {
-                let addr = (self.regs[*rs2] + imm) as u32;
-                self.memory
-                    .write_halfword(addr, (self.regs[*rs1] & 0xFFFF) as u16)?
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus
+                    .write_halfword(addr, (self.x(*rs2) & 0xFFFF) as u16)?;
+                self.invalidate_reservation(addr);
             }
             Instruction::Sw { rs1, rs2, imm } => {
-                let addr = (self.regs[*rs2] + imm) as u32;
-                self.memory.write_word(addr, self.regs[*rs1])?
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus.write_word(addr, self.x(*rs2) as i32)?;
+                self.invalidate_reservation(addr);
             }
 
             Instruction::Lb { rd, rs1, imm } => {
-                let addr = (self.regs[*rs1] + imm) as u32;
-                self.regs[*rd] = self.memory.read_byte(addr)? as i32; // sign-extend i8 -> i32
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_byte(addr)? as i64; // sign-extend
+                self.set(*rd, v);
             }
             Instruction::Lbu { rd, rs1, imm } => {
-                let addr = (self.regs[*rs1] + imm) as u32;
-                self.regs[*rd] = self.memory.read_byte(addr)? as u8 as i32; // zero-extend
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_byte(addr)? as u8 as i64; // zero-extend
+                self.set(*rd, v);
             }
             Instruction::Lh { rd, rs1, imm } => {
-                let addr = (self.regs[*rs1] + imm) as u32;
-                self.regs[*rd] = self.memory.read_halfword(addr)? as i32; // sign-extend i16 -> i32
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_halfword(addr)? as i64; // sign-extend
+                self.set(*rd, v);
             }
             Instruction::Lhu { rd, rs1, imm } => {
-                let addr = (self.regs[*rs1] + imm) as u32;
-                self.regs[*rd] = self.memory.read_halfword(addr)? as u16 as i32; // zero-extend
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_halfword(addr)? as u16 as i64; // zero-extend
+                self.set(*rd, v);
             }
             Instruction::Lw { rd, rs1, imm } => {
-                let addr = (self.regs[*rs1] + imm) as u32;
-                self.regs[*rd] = self.memory.read_word(addr)?
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_word(addr)? as i64; // sign-extend
+                self.set(*rd, v);
             }
 
-            Instruction::Beq { rs1, rs2, offset } => {
-                if self.regs[*rs1] == self.regs[*rs2] {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::Beq { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.x(*rs1) == self.x(*rs2)),
+            Instruction::Bne { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.x(*rs1) != self.x(*rs2)),
+            Instruction::Blt { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.x(*rs1) < self.x(*rs2)),
+            Instruction::Bltu { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.xu(*rs1) < self.xu(*rs2)),
+            Instruction::Bge { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.x(*rs1) >= self.x(*rs2)),
+            Instruction::Bgeu { rs1, rs2, offset } => self.branch(&mut next_pc, *offset, self.xu(*rs1) >= self.xu(*rs2)),
+            Instruction::Jal { rd, offset } => {
+                self.set(*rd, self.address_of(self.pc + 1) as i64);
+                next_pc = (self.pc as i32 + offset) as usize;
+            }
+            Instruction::Jalr { rd, rs1, imm } => {
+                next_pc = self.jump_target(self.addr(*rs1, *imm) & !1)?;
+                self.set(*rd, self.address_of(self.pc + 1) as i64);
+            }
+            Instruction::Lui { rd, imm } => {
+                self.set(*rd, (imm << 12) as i64);
+            }
+            Instruction::Auipc { rd, imm } => {
+                self.set(*rd, (self.pc_address() as i64).wrapping_add((imm << 12) as i64));
+            }
+
+            Instruction::Ld { rd, rs1, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_doubleword(addr)?;
+                self.set(*rd, v);
+            }
+            Instruction::Lwu { rd, rs1, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                let v = self.bus.read_word(addr)? as u32 as i64; // zero-extend
+                self.set(*rd, v);
+            }
+            Instruction::Sd { rs1, rs2, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus.write_doubleword(addr, self.x(*rs2))?;
+                self.invalidate_reservation(addr);
+                self.invalidate_reservation(addr + 4);
+            }
+            Instruction::Addiw { rd, rs1, imm } => self.set(*rd, (self.x(*rs1) as i32).wrapping_add(*imm) as i64),
+            Instruction::Slliw { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, 32)?;
+                self.set(*rd, ((self.x(*rs1) as i32) << sh) as i64)
+            }
+            Instruction::Srliw { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, 32)?;
+                self.set(*rd, ((self.x(*rs1) as u32) >> sh) as i32 as i64)
+            }
+            Instruction::Sraiw { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, 32)?;
+                self.set(*rd, ((self.x(*rs1) as i32) >> sh) as i64)
+            }
+            Instruction::Addw { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) as i32).wrapping_add(self.x(*rs2) as i32) as i64),
+            Instruction::Subw { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) as i32).wrapping_sub(self.x(*rs2) as i32) as i64),
+            Instruction::Sllw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as i32) << (self.x(*rs2) & 0x1F)) as i64)
+            }
+            Instruction::Srlw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as u32) >> (self.x(*rs2) & 0x1F)) as i32 as i64)
+            }
+            Instruction::Sraw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as i32) >> (self.x(*rs2) & 0x1F)) as i64)
+            }
+            Instruction::Mulw { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) as i32).wrapping_mul(self.x(*rs2) as i32) as i64),
+            Instruction::Divw { rd, rs1, rs2 } => {
+                let (a, b) = (self.x(*rs1) as i32, self.x(*rs2) as i32);
+                self.set(*rd, if b != 0 { a.wrapping_div(b) } else { -1 } as i64);
+            }
+            Instruction::Divuw { rd, rs1, rs2 } => {
+                let (a, b) = (self.x(*rs1) as u32, self.x(*rs2) as u32);
+                self.set(*rd, a.checked_div(b).map_or(-1, |q| q as i32) as i64);
+            }
+            Instruction::Remw { rd, rs1, rs2 } => {
+                let (a, b) = (self.x(*rs1) as i32, self.x(*rs2) as i32);
+                self.set(*rd, if b == 0 { a } else { a.wrapping_rem(b) } as i64);
+            }
+            Instruction::Remuw { rd, rs1, rs2 } => {
+                let (a, b) = (self.x(*rs1) as u32, self.x(*rs2) as u32);
+                self.set(*rd, if b == 0 { a } else { a % b } as i32 as i64);
+            }
+
+            Instruction::Sh1add { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) << 1).wrapping_add(self.x(*rs2))),
+            Instruction::Sh2add { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) << 2).wrapping_add(self.x(*rs2))),
+            Instruction::Sh3add { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) << 3).wrapping_add(self.x(*rs2))),
+            Instruction::AddUw { rd, rs1, rs2 } => self.set(*rd, (self.x(*rs1) as u32 as i64).wrapping_add(self.x(*rs2))),
+            Instruction::Sh1addUw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as u32 as i64) << 1).wrapping_add(self.x(*rs2)))
+            }
+            Instruction::Sh2addUw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as u32 as i64) << 2).wrapping_add(self.x(*rs2)))
+            }
+            Instruction::Sh3addUw { rd, rs1, rs2 } => {
+                self.set(*rd, ((self.x(*rs1) as u32 as i64) << 3).wrapping_add(self.x(*rs2)))
+            }
+            Instruction::SlliUw { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, 64)?;
+                self.set(*rd, ((self.x(*rs1) as u32 as u64) << sh) as i64)
+            }
+
+            Instruction::Andn { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1) & !self.x(*rs2)),
+            Instruction::Orn { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1) | !self.x(*rs2)),
+            Instruction::Xnor { rd, rs1, rs2 } => self.set(*rd, !(self.x(*rs1) ^ self.x(*rs2))),
+            Instruction::Clz { rd, rs1 } => self.set(*rd, (self.xu(*rs1).leading_zeros() - (64 - X::BITS)) as i64),
+            Instruction::Ctz { rd, rs1 } => self.set(*rd, self.xu(*rs1).trailing_zeros().min(X::BITS) as i64),
+            Instruction::Cpop { rd, rs1 } => self.set(*rd, self.xu(*rs1).count_ones() as i64),
+            Instruction::Clzw { rd, rs1 } => self.set(*rd, (self.x(*rs1) as u32).leading_zeros() as i64),
+            Instruction::Ctzw { rd, rs1 } => self.set(*rd, (self.x(*rs1) as u32).trailing_zeros() as i64),
+            Instruction::Cpopw { rd, rs1 } => self.set(*rd, (self.x(*rs1) as u32).count_ones() as i64),
+            Instruction::Max { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1).max(self.x(*rs2))),
+            Instruction::Maxu { rd, rs1, rs2 } => self.set(*rd, self.xu(*rs1).max(self.xu(*rs2)) as i64),
+            Instruction::Min { rd, rs1, rs2 } => self.set(*rd, self.x(*rs1).min(self.x(*rs2))),
+            Instruction::Minu { rd, rs1, rs2 } => self.set(*rd, self.xu(*rs1).min(self.xu(*rs2)) as i64),
+            Instruction::SextB { rd, rs1 } => self.set(*rd, self.x(*rs1) as i8 as i64),
+            Instruction::SextH { rd, rs1 } => self.set(*rd, self.x(*rs1) as i16 as i64),
+            Instruction::ZextH { rd, rs1 } => self.set(*rd, self.x(*rs1) as u16 as i64),
+            Instruction::Rol { rd, rs1, rs2 } => self.set(*rd, Self::rotate(self.xu(*rs1), self.xu(*rs2) as u32, true)),
+            Instruction::Ror { rd, rs1, rs2 } => self.set(*rd, Self::rotate(self.xu(*rs1), self.xu(*rs2) as u32, false)),
+            Instruction::Rori { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, Self::rotate(self.xu(*rs1), sh, false))
+            }
+            Instruction::Rolw { rd, rs1, rs2 } => {
+                self.set(*rd, (self.x(*rs1) as u32).rotate_left(self.x(*rs2) as u32 & 0x1F) as i32 as i64)
+            }
+            Instruction::Rorw { rd, rs1, rs2 } => {
+                self.set(*rd, (self.x(*rs1) as u32).rotate_right(self.x(*rs2) as u32 & 0x1F) as i32 as i64)
+            }
+            Instruction::Roriw { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, 32)?;
+                self.set(*rd, (self.x(*rs1) as u32).rotate_right(sh) as i32 as i64)
+            }
+            Instruction::OrcB { rd, rs1 } => {
+                self.set(*rd, Self::map_bytes(self.xu(*rs1), |b| if b != 0 { 0xFF } else { 0 }))
+            }
+            Instruction::Rev8 { rd, rs1 } => self.set(*rd, (self.xu(*rs1).swap_bytes() >> (64 - X::BITS)) as i64),
+
+            Instruction::Clmul { rd, rs1, rs2 } => self.set(*rd, Self::clmul(self.xu(*rs1), self.xu(*rs2)) as i64),
+            Instruction::Clmulh { rd, rs1, rs2 } => {
+                self.set(*rd, (Self::clmul(self.xu(*rs1), self.xu(*rs2)) >> X::BITS) as i64)
+            }
+            Instruction::Clmulr { rd, rs1, rs2 } => {
+                self.set(*rd, (Self::clmul(self.xu(*rs1), self.xu(*rs2)) >> (X::BITS - 1)) as i64)
+            }
+
+            Instruction::Bclr { rd, rs1, rs2 } => {
+                self.set(*rd, self.x(*rs1) & !(1 << (self.xu(*rs2) as u32 & X::shamt_mask())))
+            }
+            Instruction::Bext { rd, rs1, rs2 } => {
+                self.set(*rd, (self.xu(*rs1) >> (self.xu(*rs2) as u32 & X::shamt_mask())) as i64 & 1)
+            }
+            Instruction::Binv { rd, rs1, rs2 } => {
+                self.set(*rd, self.x(*rs1) ^ (1 << (self.xu(*rs2) as u32 & X::shamt_mask())))
+            }
+            Instruction::Bset { rd, rs1, rs2 } => {
+                self.set(*rd, self.x(*rs1) | (1 << (self.xu(*rs2) as u32 & X::shamt_mask())))
+            }
+            Instruction::Bclri { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, self.x(*rs1) & !(1 << sh))
+            }
+            Instruction::Bexti { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, (self.xu(*rs1) >> sh) as i64 & 1)
+            }
+            Instruction::Binvi { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, self.x(*rs1) ^ (1 << sh))
+            }
+            Instruction::Bseti { rd, rs1, imm } => {
+                let sh = Self::shamt(inst, *imm, X::BITS)?;
+                self.set(*rd, self.x(*rs1) | (1 << sh))
+            }
+
+            Instruction::LrW { rd, rs1, .. } => {
+                let addr = self.translate(self.xu(*rs1), Access::Load)?;
+                let v = self.bus.read_word(addr)? as i64;
+                self.set(*rd, v);
+                self.reservation = Some(addr);
+            }
+            Instruction::ScW { rd, rs1, rs2, .. } => {
+                let addr = self.translate(self.xu(*rs1), Access::Store)?;
+                if self.reservation == Some(addr) {
+                    self.bus.write_word(addr, self.x(*rs2) as i32)?;
+                    self.stores.push(addr);
+                    self.set(*rd, 0);
+                } else {
+                    self.set(*rd, 1);
+                }
+                self.reservation = None;
+            }
+            Instruction::AmoswapW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, |_, b| b)?,
+            Instruction::AmoaddW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, i32::wrapping_add)?,
+            Instruction::AmoxorW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, |a, b| a ^ b)?,
+            Instruction::AmoandW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, |a, b| a & b)?,
+            Instruction::AmoorW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, |a, b| a | b)?,
+            Instruction::AmominW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, i32::min)?,
+            Instruction::AmomaxW { rd, rs1, rs2, .. } => self.amo(*rd, *rs1, *rs2, i32::max)?,
+            Instruction::AmominuW { rd, rs1, rs2, .. } => {
+                self.amo(*rd, *rs1, *rs2, |a, b| (a as u32).min(b as u32) as i32)?
+            }
+            Instruction::AmomaxuW { rd, rs1, rs2, .. } => {
+                self.amo(*rd, *rs1, *rs2, |a, b| (a as u32).max(b as u32) as i32)?
+            }
+            Instruction::Csrrw { rd, rs1, csr } => {
+                let val = self.x(*rs1);
+                if *rd == 0 {
+                    self.check_csr_access(*csr, true)?;
+                    self.write_csr(*csr, val)?;
+                } else {
+                    self.csr_op(*rd, *csr, Some(val), |_, new| new)?;
+                }
+            }
+            Instruction::Csrrs { rd, rs1, csr } => {
+                let write = (*rs1 != 0).then_some(self.x(*rs1));
+                self.csr_op(*rd, *csr, write, |old, mask| old | mask)?
+            }
+            Instruction::Csrrc { rd, rs1, csr } => {
+                let write = (*rs1 != 0).then_some(self.x(*rs1));
+                self.csr_op(*rd, *csr, write, |old, mask| old & !mask)?
+            }
+            Instruction::Csrrwi { rd, imm, csr } => {
+                if *rd == 0 {
+                    self.check_csr_access(*csr, true)?;
+                    self.write_csr(*csr, *imm as i64)?;
+                } else {
+                    self.csr_op(*rd, *csr, Some(*imm as i64), |_, new| new)?;
                 }
             }
-            Instruction::Bne { rs1, rs2, offset } => {
-                if self.regs[*rs1] != self.regs[*rs2] {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::Csrrsi { rd, imm, csr } => {
+                self.csr_op(*rd, *csr, (*imm != 0).then_some(*imm as i64), |old, mask| old | mask)?
+            }
+            Instruction::Csrrci { rd, imm, csr } => {
+                self.csr_op(*rd, *csr, (*imm != 0).then_some(*imm as i64), |old, mask| old & !mask)?
+            }
+
+            Instruction::Ecall => return Err(CpuError::EnvironmentCall(self.privilege)),
+            Instruction::Ebreak => return Err(CpuError::Breakpoint),
+            Instruction::Mret => {
+                if self.privilege < Privilege::Machine {
+                    return Err(CpuError::IllegalInstruction(inst));
+                }
+                next_pc = self.jump_target(self.trap.mepc)?;
+                let status = self.trap.mstatus;
+                let mie = if status & privilege::MPIE != 0 { privilege::MIE } else { 0 };
+                self.privilege = self.trap.mpp();
+                self.trap.mstatus = (status & !(privilege::MIE | privilege::MPP | privilege::MPRV)) | mie | privilege::MPIE;
+                if self.privilege == Privilege::Machine {
+                    self.trap.mstatus |= status & privilege::MPRV;
                 }
             }
-            Instruction::Blt { rs1, rs2, offset } => {
-                if self.regs[*rs1] < self.regs[*rs2] {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::Sret => {
+                let trapped = self.privilege == Privilege::Supervisor && self.trap.mstatus & privilege::TSR != 0;
+                if self.privilege < Privilege::Supervisor || trapped {
+                    return Err(CpuError::IllegalInstruction(inst));
                 }
+                next_pc = self.jump_target(self.trap.sepc)?;
+                let status = self.trap.mstatus;
+                let sie = if status & privilege::SPIE != 0 { privilege::SIE } else { 0 };
+                self.privilege = self.trap.spp();
+                self.trap.mstatus = (status & !(privilege::SIE | privilege::SPP | privilege::MPRV)) | sie | privilege::SPIE;
             }
-            Instruction::Bltu { rs1, rs2, offset } => {
-                if (self.regs[*rs1] as u32) < (self.regs[*rs2] as u32) {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            // Harts take turns one instruction at a time, so memory is
+            // sequentially consistent and there is nothing to order.
+            Instruction::Fence { .. } => {}
+            // Interrupts are checked between instructions anyway, so waiting
+            // for one is a no-op.
+            Instruction::Wfi => {
+                let trapped = self.privilege == Privilege::Supervisor && self.trap.mstatus & privilege::TW != 0;
+                if self.privilege == Privilege::User || trapped {
+                    return Err(CpuError::IllegalInstruction(inst));
                 }
             }
-            Instruction::Bge { rs1, rs2, offset } => {
-                if self.regs[*rs1] >= self.regs[*rs2] {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::SfenceVma { rs1, rs2 } => {
+                if self.privilege == Privilege::User || self.tvm_trapped() {
+                    return Err(CpuError::IllegalInstruction(inst));
                 }
+                let vaddr = (*rs1 != 0).then(|| self.xu(*rs1));
+                let asid = (*rs2 != 0).then(|| self.xu(*rs2));
+                self.mmu.fence(vaddr, asid);
+            }
+
+            Instruction::Flw { rd, rs1, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                self.fregs[*rd] = 0xFFFF_FFFF_0000_0000 | self.bus.read_word(addr)? as u32 as u64;
+            }
+            Instruction::Fld { rd, rs1, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Load)?;
+                self.fregs[*rd] = self.bus.read_doubleword(addr)? as u64;
+            }
+            Instruction::Fsw { rs1, rs2, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus.write_word(addr, self.fregs[*rs2] as i32)?;
+                self.invalidate_reservation(addr);
+            }
+            Instruction::Fsd { rs1, rs2, imm } => {
+                let addr = self.translate(self.addr(*rs1, *imm), Access::Store)?;
+                self.bus.write_doubleword(addr, self.fregs[*rs2] as i64)?;
+                self.invalidate_reservation(addr);
+                self.invalidate_reservation(addr + 4);
+            }
+            Instruction::FmvXW { rd, rs1 } => self.set(*rd, self.fregs[*rs1] as i32 as i64),
+            Instruction::FmvWX { rd, rs1 } => self.fregs[*rd] = 0xFFFF_FFFF_0000_0000 | self.x(*rs1) as u32 as u64,
+            Instruction::FcvtSD { rd, rs1, rm } => {
+                let rm = self.rounding_mode(*rm)?;
+                let v = fpu::narrow(self.freg(*rs1), rm, &mut self.fflags);
+                self.set_freg(*rd, v);
+            }
+            Instruction::FcvtDS { rd, rs1, .. } => {
+                let v = fpu::widen(self.freg(*rs1), &mut self.fflags);
+                self.set_freg(*rd, v);
+            }
+
+            Instruction::FaddS { rd, rs1, rs2, rm } => self.fp_binary::<f32>(*rd, *rs1, *rs2, *rm, fpu::add)?,
+            Instruction::FsubS { rd, rs1, rs2, rm } => self.fp_binary::<f32>(*rd, *rs1, *rs2, *rm, fpu::sub)?,
+            Instruction::FmulS { rd, rs1, rs2, rm } => self.fp_binary::<f32>(*rd, *rs1, *rs2, *rm, fpu::mul)?,
+            Instruction::FdivS { rd, rs1, rs2, rm } => self.fp_binary::<f32>(*rd, *rs1, *rs2, *rm, fpu::div)?,
+            Instruction::FsqrtS { rd, rs1, rm } => self.fp_unary::<f32>(*rd, *rs1, *rm, fpu::sqrt)?,
+            Instruction::FminS { rd, rs1, rs2 } => self.fp_min_max::<f32>(*rd, *rs1, *rs2, fpu::min),
+            Instruction::FmaxS { rd, rs1, rs2 } => self.fp_min_max::<f32>(*rd, *rs1, *rs2, fpu::max),
+            Instruction::FmaddS { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f32>((*rd, *rs1, *rs2, *rs3), *rm, false, false)?,
+            Instruction::FmsubS { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f32>((*rd, *rs1, *rs2, *rs3), *rm, false, true)?,
+            Instruction::FnmsubS { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f32>((*rd, *rs1, *rs2, *rs3), *rm, true, false)?,
+            Instruction::FnmaddS { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f32>((*rd, *rs1, *rs2, *rs3), *rm, true, true)?,
+            Instruction::FsgnjS { rd, rs1, rs2 } => self.fp_sign_inject::<f32>(*rd, *rs1, *rs2, false, false),
+            Instruction::FsgnjnS { rd, rs1, rs2 } => self.fp_sign_inject::<f32>(*rd, *rs1, *rs2, true, false),
+            Instruction::FsgnjxS { rd, rs1, rs2 } => self.fp_sign_inject::<f32>(*rd, *rs1, *rs2, false, true),
+            Instruction::FeqS { rd, rs1, rs2 } => self.fp_compare::<f32>(*rd, *rs1, *rs2, fpu::eq),
+            Instruction::FltS { rd, rs1, rs2 } => self.fp_compare::<f32>(*rd, *rs1, *rs2, fpu::lt),
+            Instruction::FleS { rd, rs1, rs2 } => self.fp_compare::<f32>(*rd, *rs1, *rs2, fpu::le),
+            Instruction::FclassS { rd, rs1 } => self.set(*rd, fpu::classify(self.freg::<f32>(*rs1)) as i64),
+            Instruction::FcvtWS { rd, rs1, rm } => self.fp_to_int::<f32>(*rd, *rs1, *rm, true)?,
+            Instruction::FcvtWuS { rd, rs1, rm } => self.fp_to_int::<f32>(*rd, *rs1, *rm, false)?,
+            Instruction::FcvtSW { rd, rs1, rm } => self.int_to_fp::<f32>(*rd, *rs1, *rm, true)?,
+            Instruction::FcvtSWu { rd, rs1, rm } => self.int_to_fp::<f32>(*rd, *rs1, *rm, false)?,
+
+            Instruction::FaddD { rd, rs1, rs2, rm } => self.fp_binary::<f64>(*rd, *rs1, *rs2, *rm, fpu::add)?,
+            Instruction::FsubD { rd, rs1, rs2, rm } => self.fp_binary::<f64>(*rd, *rs1, *rs2, *rm, fpu::sub)?,
+            Instruction::FmulD { rd, rs1, rs2, rm } => self.fp_binary::<f64>(*rd, *rs1, *rs2, *rm, fpu::mul)?,
+            Instruction::FdivD { rd, rs1, rs2, rm } => self.fp_binary::<f64>(*rd, *rs1, *rs2, *rm, fpu::div)?,
+            Instruction::FsqrtD { rd, rs1, rm } => self.fp_unary::<f64>(*rd, *rs1, *rm, fpu::sqrt)?,
+            Instruction::FminD { rd, rs1, rs2 } => self.fp_min_max::<f64>(*rd, *rs1, *rs2, fpu::min),
+            Instruction::FmaxD { rd, rs1, rs2 } => self.fp_min_max::<f64>(*rd, *rs1, *rs2, fpu::max),
+            Instruction::FmaddD { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f64>((*rd, *rs1, *rs2, *rs3), *rm, false, false)?,
+            Instruction::FmsubD { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f64>((*rd, *rs1, *rs2, *rs3), *rm, false, true)?,
+            Instruction::FnmsubD { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f64>((*rd, *rs1, *rs2, *rs3), *rm, true, false)?,
+            Instruction::FnmaddD { rd, rs1, rs2, rs3, rm } => self.fp_fused::<f64>((*rd, *rs1, *rs2, *rs3), *rm, true, true)?,
+            Instruction::FsgnjD { rd, rs1, rs2 } => self.fp_sign_inject::<f64>(*rd, *rs1, *rs2, false, false),
+            Instruction::FsgnjnD { rd, rs1, rs2 } => self.fp_sign_inject::<f64>(*rd, *rs1, *rs2, true, false),
+            Instruction::FsgnjxD { rd, rs1, rs2 } => self.fp_sign_inject::<f64>(*rd, *rs1, *rs2, false, true),
+            Instruction::FeqD { rd, rs1, rs2 } => self.fp_compare::<f64>(*rd, *rs1, *rs2, fpu::eq),
+            Instruction::FltD { rd, rs1, rs2 } => self.fp_compare::<f64>(*rd, *rs1, *rs2, fpu::lt),
+            Instruction::FleD { rd, rs1, rs2 } => self.fp_compare::<f64>(*rd, *rs1, *rs2, fpu::le),
+            Instruction::FclassD { rd, rs1 } => self.set(*rd, fpu::classify(self.freg::<f64>(*rs1)) as i64),
+            Instruction::FcvtWD { rd, rs1, rm } => self.fp_to_int::<f64>(*rd, *rs1, *rm, true)?,
+            Instruction::FcvtWuD { rd, rs1, rm } => self.fp_to_int::<f64>(*rd, *rs1, *rm, false)?,
+            Instruction::FcvtDW { rd, rs1, rm } => self.int_to_fp::<f64>(*rd, *rs1, *rm, true)?,
+            Instruction::FcvtDWu { rd, rs1, rm } => self.int_to_fp::<f64>(*rd, *rs1, *rm, false)?,
+
+            Instruction::Vsetvli { rd, rs1, vtypei } => {
+                // rs1 = x0 asks for VLMAX, or keeps vl when rd is x0 too.
+                let avl = match (*rs1, *rd) {
+                    (0, 0) => self.vector.vl(),
+                    (0, _) => u64::MAX,
+                    _ => self.xu(*rs1),
+                };
+                let vl = self.vector.configure(avl, *vtypei as u64);
+                self.set(*rd, vl as i64);
+            }
+            Instruction::Vsetivli { rd, uimm, vtypei } => {
+                let vl = self.vector.configure(*uimm as u64, *vtypei as u64);
+                self.set(*rd, vl as i64);
+            }
+            Instruction::VleV { vd, rs1, eew, vm } => self.vector_load(inst, *vd, *rs1, None, *eew, *vm)?,
+            Instruction::VseV { vs3, rs1, eew, vm } => self.vector_store(inst, *vs3, *rs1, None, *eew, *vm)?,
+            Instruction::VlseV { vd, rs1, rs2, eew, vm } => self.vector_load(inst, *vd, *rs1, Some(*rs2), *eew, *vm)?,
+            Instruction::VsseV { vs3, rs1, rs2, eew, vm } => self.vector_store(inst, *vs3, *rs1, Some(*rs2), *eew, *vm)?,
+
+            Instruction::VaddVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, u64::wrapping_add))?,
+            Instruction::VaddVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, u64::wrapping_add))?
             }
-            Instruction::Bgeu { rs1, rs2, offset } => {
-                if (self.regs[*rs1] as u32) >= (self.regs[*rs2] as u32) {
-                    next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::VaddVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Self::vi(*imm), *vm, u64::wrapping_add))?,
+            Instruction::VsubVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, u64::wrapping_sub))?,
+            Instruction::VsubVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, u64::wrapping_sub))?
+            }
+            Instruction::VmulVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, u64::wrapping_mul))?,
+            Instruction::VmulVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, u64::wrapping_mul))?
+            }
+            Instruction::VandVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a & b))?,
+            Instruction::VandVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, |a, b| a & b))?
+            }
+            Instruction::VandVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a & b))?,
+            Instruction::VorVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a | b))?,
+            Instruction::VorVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, |a, b| a | b))?
+            }
+            Instruction::VorVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a | b))?,
+            Instruction::VxorVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a ^ b))?,
+            Instruction::VxorVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.binop(*vd, *vs2, src, *vm, |a, b| a ^ b))?
+            }
+            Instruction::VxorVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.binop(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a ^ b))?,
+            Instruction::VmvVv { vd, vs1 } => self.vop(inst, |v| v.splat(*vd, Operand::Vector(*vs1)))?,
+            Instruction::VmvVx { vd, rs1 } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.splat(*vd, src))?
+            }
+            Instruction::VmvVi { vd, imm } => self.vop(inst, |v| v.splat(*vd, Self::vi(*imm)))?,
+            Instruction::VmvXS { rd, vs2 } => {
+                if self.vector.vill() {
+                    return Err(CpuError::IllegalInstruction(inst));
                 }
+                let sew = self.vector.sew();
+                self.set(*rd, sew.sign_extend(self.vector.element(*vs2, 0, sew)));
             }
-            Instruction::Jal { rd, offset } => {
-                self.regs[*rd] = (self.pc + 1) as i32;
-                next_pc = (self.pc as i32 + offset) as usize;
+            Instruction::VmvSX { vd, rs1 } => {
+                if self.vector.vill() {
+                    return Err(CpuError::IllegalInstruction(inst));
+                }
+                if self.vector.vl() > 0 {
+                    let sew = self.vector.sew();
+                    self.vector.set_element(*vd, 0, sew, sew.truncate(self.x(*rs1) as u64));
+                }
             }
-            Instruction::Jalr { rd, rs1, imm } => {
-                self.regs[*rd] = (self.pc + 1) as i32;
-                next_pc = (self.regs[*rs1] + imm) as usize;
+
+            Instruction::VredsumVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, i64::wrapping_add))?,
+            Instruction::VredandVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, |a, b| a & b))?,
+            Instruction::VredorVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, |a, b| a | b))?,
+            Instruction::VredxorVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, |a, b| a ^ b))?,
+            Instruction::VredminVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, i64::min))?,
+            Instruction::VredmaxVs { vd, vs2, vs1, vm } => self.vop(inst, |v| v.reduce(*vd, *vs2, *vs1, *vm, i64::max))?,
+
+            Instruction::VmseqVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a == b))?,
+            Instruction::VmseqVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.compare(*vd, *vs2, src, *vm, |a, b| a == b))?
             }
-            Instruction::Lui { rd, imm } => {
-                self.regs[*rd] = imm << 12 ;
+            Instruction::VmseqVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a == b))?,
+            Instruction::VmsneVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a != b))?,
+            Instruction::VmsneVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.compare(*vd, *vs2, src, *vm, |a, b| a != b))?
             }
-            Instruction::Auipc { rd, imm } => {
-                self.regs[*rd] = (self.pc as i32).wrapping_add(imm << 12);
+            Instruction::VmsneVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a != b))?,
+            Instruction::VmsltVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a < b))?,
+            Instruction::VmsltVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.compare(*vd, *vs2, src, *vm, |a, b| a < b))?
             }
-            Instruction::Print { rs } => print!("x{}: {}\n", rs, self.regs[*rs]),
+            Instruction::VmsleVv { vd, vs2, vs1, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Operand::Vector(*vs1), *vm, |a, b| a <= b))?,
+            Instruction::VmsleVx { vd, vs2, rs1, vm } => {
+                let src = self.vx(*rs1);
+                self.vop(inst, |v| v.compare(*vd, *vs2, src, *vm, |a, b| a <= b))?
+            }
+            Instruction::VmsleVi { vd, vs2, imm, vm } => self.vop(inst, |v| v.compare(*vd, *vs2, Self::vi(*imm), *vm, |a, b| a <= b))?,
+            Instruction::VmandMm { vd, vs2, vs1 } => self.vop(inst, |v| v.mask_op(*vd, *vs2, *vs1, |a, b| a && b))?,
+            Instruction::VmandnMm { vd, vs2, vs1 } => self.vop(inst, |v| v.mask_op(*vd, *vs2, *vs1, |a, b| a && !b))?,
+            Instruction::VmorMm { vd, vs2, vs1 } => self.vop(inst, |v| v.mask_op(*vd, *vs2, *vs1, |a, b| a || b))?,
+            Instruction::VmxorMm { vd, vs2, vs1 } => self.vop(inst, |v| v.mask_op(*vd, *vs2, *vs1, |a, b| a != b))?,
+            Instruction::VcpopM { rd, vs2, vm } => {
+                let set = self.vector.mask_set(*vs2, *vm).ok_or(CpuError::IllegalInstruction(inst))?;
+                self.set(*rd, set.len() as i64);
+            }
+            Instruction::VfirstM { rd, vs2, vm } => {
+                let set = self.vector.mask_set(*vs2, *vm).ok_or(CpuError::IllegalInstruction(inst))?;
+                self.set(*rd, set.first().map_or(-1, |&i| i as i64));
+            }
+
+            Instruction::Print { rs } => println!("x{}: {}", rs, self.regs[*rs]),
         }
-        self.regs[0] = 0;
+        self.regs[0] = X::Reg::default();
         self.pc = next_pc;
-        Ok(true)
+        Ok(())
     }
 }
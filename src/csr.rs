@@ -0,0 +1,83 @@
+// Floating-point CSRs
+pub const FFLAGS: u16 = 0x001;
+pub const FRM: u16 = 0x002;
+pub const FCSR: u16 = 0x003;
+
+// Vector CSRs
+pub const VSTART: u16 = 0x008;
+pub const VL: u16 = 0xC20;
+pub const VTYPE: u16 = 0xC21;
+pub const VLENB: u16 = 0xC22;
+
+// Supervisor trap setup and handling
+pub const SSTATUS: u16 = 0x100;
+pub const SIE: u16 = 0x104;
+pub const STVEC: u16 = 0x105;
+pub const SSCRATCH: u16 = 0x140;
+pub const SEPC: u16 = 0x141;
+pub const SCAUSE: u16 = 0x142;
+pub const STVAL: u16 = 0x143;
+pub const SIP: u16 = 0x144;
+
+// Supervisor protection and translation
+pub const SATP: u16 = 0x180;
+
+// Machine information
+pub const MISA: u16 = 0x301;
+pub const MHARTID: u16 = 0xF14;
+
+// Machine trap setup and handling
+pub const MSTATUS: u16 = 0x300;
+pub const MEDELEG: u16 = 0x302;
+pub const MIDELEG: u16 = 0x303;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MSCRATCH: u16 = 0x340;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+
+pub fn from_name(name: &str) -> Option<u16> {
+    match name {
+        "fflags" => Some(FFLAGS),
+        "frm" => Some(FRM),
+        "fcsr" => Some(FCSR),
+        "vstart" => Some(VSTART),
+        "vl" => Some(VL),
+        "vtype" => Some(VTYPE),
+        "vlenb" => Some(VLENB),
+        "sstatus" => Some(SSTATUS),
+        "sie" => Some(SIE),
+        "stvec" => Some(STVEC),
+        "sscratch" => Some(SSCRATCH),
+        "sepc" => Some(SEPC),
+        "scause" => Some(SCAUSE),
+        "stval" => Some(STVAL),
+        "sip" => Some(SIP),
+        "satp" => Some(SATP),
+        "misa" => Some(MISA),
+        "mhartid" => Some(MHARTID),
+        "mstatus" => Some(MSTATUS),
+        "medeleg" => Some(MEDELEG),
+        "mideleg" => Some(MIDELEG),
+        "mie" => Some(MIE),
+        "mtvec" => Some(MTVEC),
+        "mscratch" => Some(MSCRATCH),
+        "mepc" => Some(MEPC),
+        "mcause" => Some(MCAUSE),
+        "mtval" => Some(MTVAL),
+        "mip" => Some(MIP),
+        _ => None,
+    }
+}
+
+/// The lowest privilege level that may access `csr` (bits 9:8).
+pub fn min_privilege(csr: u16) -> u64 {
+    ((csr >> 8) & 3) as u64
+}
+
+/// CSRs with bits 11:10 set are read-only.
+pub fn is_read_only(csr: u16) -> bool {
+    (csr >> 10) & 3 == 3
+}
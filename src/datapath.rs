@@ -0,0 +1,399 @@
+use std::fmt;
+use crate::cpu::{Cpu, CpuError};
+use crate::instruction::{Instruction, Reg};
+use crate::isa::Extension;
+use crate::xlen::Xlen;
+
+/// What the ALU does, as the 2-bit ALUOp the main control unit sends to
+/// the ALU control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    /// 00: address arithmetic for loads, stores and jumps.
+    Add = 0b00,
+    /// 01: compare the two registers of a branch.
+    Branch = 0b01,
+    /// 10: the operation is chosen by funct3/funct7.
+    Funct = 0b10,
+}
+
+impl fmt::Display for AluOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02b}", *self as u8)
+    }
+}
+
+/// Which immediate format the immediate generator decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImmSel {
+    None,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+impl fmt::Display for ImmSel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImmSel::None => write!(f, "-"),
+            imm => write!(f, "{imm:?}"),
+        }
+    }
+}
+
+/// What the write-back multiplexer sends to the register file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemToReg {
+    Alu,
+    Mem,
+    /// The return address of `jal`/`jalr`.
+    PcNext,
+}
+
+impl fmt::Display for MemToReg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemToReg::Alu => write!(f, "ALU"),
+            MemToReg::Mem => write!(f, "Mem"),
+            MemToReg::PcNext => write!(f, "PC+4"),
+        }
+    }
+}
+
+/// The main control unit's outputs for one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlSignals {
+    pub reg_write: bool,
+    /// The ALU's second operand is the immediate rather than rs2.
+    pub alu_src: bool,
+    pub mem_read: bool,
+    pub mem_write: bool,
+    pub mem_to_reg: MemToReg,
+    pub branch: bool,
+    pub jump: bool,
+    pub alu_op: AluOp,
+    pub imm_sel: ImmSel,
+}
+
+impl ControlSignals {
+    /// The signals of an integer instruction on the classic single-cycle
+    /// datapath; `None` for instructions it has no path for, such as CSR
+    /// accesses, atomics, floating point and vectors.
+    pub fn for_instruction(inst: &Instruction) -> Option<ControlSignals> {
+        use Instruction::*;
+        let alu = |alu_src, imm_sel| ControlSignals {
+            reg_write: true,
+            alu_src,
+            mem_read: false,
+            mem_write: false,
+            mem_to_reg: MemToReg::Alu,
+            branch: false,
+            jump: false,
+            alu_op: AluOp::Funct,
+            imm_sel,
+        };
+        let signals = match inst {
+            Lb { .. } | Lh { .. } | Lw { .. } | Ld { .. } | Lbu { .. } | Lhu { .. } | Lwu { .. } => ControlSignals {
+                mem_read: true,
+                mem_to_reg: MemToReg::Mem,
+                alu_op: AluOp::Add,
+                ..alu(true, ImmSel::I)
+            },
+            Sb { .. } | Sh { .. } | Sw { .. } | Sd { .. } => ControlSignals {
+                reg_write: false,
+                mem_write: true,
+                alu_op: AluOp::Add,
+                ..alu(true, ImmSel::S)
+            },
+            Beq { .. } | Bne { .. } | Blt { .. } | Bltu { .. } | Bge { .. } | Bgeu { .. } => ControlSignals {
+                reg_write: false,
+                branch: true,
+                alu_op: AluOp::Branch,
+                ..alu(false, ImmSel::B)
+            },
+            Jal { .. } => ControlSignals {
+                mem_to_reg: MemToReg::PcNext,
+                jump: true,
+                alu_op: AluOp::Add,
+                ..alu(true, ImmSel::J)
+            },
+            Jalr { .. } => ControlSignals {
+                mem_to_reg: MemToReg::PcNext,
+                jump: true,
+                alu_op: AluOp::Add,
+                ..alu(true, ImmSel::I)
+            },
+            Lui { .. } | Auipc { .. } => ControlSignals { alu_op: AluOp::Add, ..alu(true, ImmSel::U) },
+
+            Addi { .. } | Andi { .. } | Ori { .. } | Xori { .. } | Slli { .. } | Srli { .. } | Srai { .. }
+            | Slti { .. } | Sltiu { .. } | Addiw { .. } | Slliw { .. } | Srliw { .. } | Sraiw { .. }
+            | SlliUw { .. } | Rori { .. } | Roriw { .. } | Bclri { .. } | Bexti { .. } | Binvi { .. }
+            | Bseti { .. } => alu(true, ImmSel::I),
+
+            Fence { .. } | Ecall | Ebreak | Mret | Sret | Wfi | SfenceVma { .. } => return None,
+            // Register-register and unary operations of the integer extensions.
+            _ if matches!(
+                inst.extension(),
+                Extension::I | Extension::M | Extension::Zba | Extension::Zbb | Extension::Zbc | Extension::Zbs
+            ) =>
+            {
+                alu(false, ImmSel::None)
+            }
+            _ => return None,
+        };
+        Some(signals)
+    }
+}
+
+impl fmt::Display for ControlSignals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RegWrite={} ALUSrc={} MemRead={} MemWrite={} MemtoReg={} Branch={} Jump={} ALUOp={} ImmSel={}",
+            self.reg_write as u8,
+            self.alu_src as u8,
+            self.mem_read as u8,
+            self.mem_write as u8,
+            self.mem_to_reg,
+            self.branch as u8,
+            self.jump as u8,
+            self.alu_op,
+            self.imm_sel
+        )
+    }
+}
+
+/// The immediate an instruction carries: U-type values are already
+/// shifted, branch and jump offsets still count instructions.
+fn immediate(inst: &Instruction) -> Option<i64> {
+    use Instruction::*;
+    match *inst {
+        Lui { imm, .. } | Auipc { imm, .. } => Some(((imm as i64) << 12) as i32 as i64),
+        Beq { offset, .. } | Bne { offset, .. } | Blt { offset, .. } | Bltu { offset, .. } | Bge { offset, .. }
+        | Bgeu { offset, .. } | Jal { offset, .. } => Some(offset as i64),
+        Addi { imm, .. } | Andi { imm, .. } | Ori { imm, .. } | Xori { imm, .. } | Slli { imm, .. }
+        | Srli { imm, .. } | Srai { imm, .. } | Slti { imm, .. } | Sltiu { imm, .. } | Lb { imm, .. }
+        | Lh { imm, .. } | Lw { imm, .. } | Ld { imm, .. } | Lbu { imm, .. } | Lhu { imm, .. } | Lwu { imm, .. }
+        | Sb { imm, .. } | Sh { imm, .. } | Sw { imm, .. } | Sd { imm, .. } | Jalr { imm, .. }
+        | Addiw { imm, .. } | Slliw { imm, .. } | Srliw { imm, .. } | Sraiw { imm, .. } | SlliUw { imm, .. }
+        | Rori { imm, .. } | Roriw { imm, .. } | Bclri { imm, .. } | Bexti { imm, .. } | Binvi { imm, .. }
+        | Bseti { imm, .. } => Some(imm as i64),
+        _ => None,
+    }
+}
+
+/// The register-file read ports: rs1 and rs2 as encoded.
+pub(crate) fn read_ports(inst: &Instruction) -> (Option<usize>, Option<usize>) {
+    let mut xs = inst.operands().into_iter().filter_map(|r| if let Reg::X(r) = r { Some(r) } else { None });
+    (xs.next(), xs.next())
+}
+
+/// The datapath's view of one executed instruction: the control signals
+/// and the values on the main buses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatapathTrace {
+    /// The instruction's index in the program.
+    pub pc: usize,
+    /// The byte address on the PC bus.
+    pub address: u64,
+    pub inst: Instruction,
+    pub signals: ControlSignals,
+    /// Branch and jump offsets in bytes, as the immediate generator puts
+    /// them on the bus.
+    pub imm: Option<i64>,
+    /// Register-file read ports as `(register, value)`.
+    pub rs1: Option<(usize, i64)>,
+    pub rs2: Option<(usize, i64)>,
+    pub alu_a: i64,
+    pub alu_b: i64,
+    pub alu_out: i64,
+    pub mem_addr: Option<u64>,
+    /// The value stored, or the value loaded.
+    pub mem_data: Option<i64>,
+    /// The register-file write port as `(rd, value)`.
+    pub write_back: Option<(usize, i64)>,
+    pub next_pc: usize,
+    /// The byte address of `next_pc`.
+    pub next_address: u64,
+}
+
+/// The datapath inputs of the instruction at `cpu.pc`, captured before it
+/// executes.
+pub struct Fetched {
+    pc: usize,
+    /// The byte address `auipc`, `jal` and branch targets add to.
+    address: i64,
+    imm: Option<i64>,
+    inst: Instruction,
+    signals: ControlSignals,
+    rs1: Option<(usize, i64)>,
+    rs2: Option<(usize, i64)>,
+}
+
+impl Fetched {
+    /// `None` past the end of the program or for instructions off the
+    /// single-cycle datapath.
+    pub fn new<X: Xlen>(cpu: &Cpu<X>) -> Option<Fetched> {
+        let inst = *cpu.program().get(cpu.pc)?;
+        let signals = ControlSignals::for_instruction(&inst)?;
+        let (rs1, rs2) = read_ports(&inst);
+        let read = |r: usize| (r, X::to_i64(cpu.regs[r]));
+        let address = cpu.pc_address() as i64;
+        // Offsets count instructions, which need not all be 4 bytes long, so
+        // the byte offset comes from the target's address.
+        let imm = immediate(&inst).map(|offset| match inst {
+            Instruction::Jal { .. } => byte_offset(cpu, address, offset),
+            _ if inst.is_branch() => byte_offset(cpu, address, offset),
+            _ => offset,
+        });
+        Some(Fetched { pc: cpu.pc, address, imm, inst, signals, rs1: rs1.map(read), rs2: rs2.map(read) })
+    }
+
+    /// Fills in the results from `cpu` once the instruction has executed.
+    pub fn complete<X: Xlen>(self, cpu: &Cpu<X>) -> DatapathTrace {
+        let Fetched { pc, address, imm, inst, signals, rs1, rs2 } = self;
+        let value = |port: Option<(usize, i64)>| port.map_or(0, |(_, v)| v);
+        let alu_a = match inst {
+            Instruction::Lui { .. } => 0,
+            Instruction::Auipc { .. } | Instruction::Jal { .. } => address,
+            _ => value(rs1),
+        };
+        let alu_b = if signals.alu_src { imm.unwrap_or(0) } else { value(rs2) };
+        let xlen = |v: i64| X::to_i64(X::from_i64(v));
+        let rd = inst.destination().and_then(|r| if let Reg::X(r) = r { Some(r) } else { None });
+        let written = rd.map(|r| X::to_i64(cpu.regs[r]));
+        // An ALU result is only visible through rd, so writes to x0 show 0.
+        let alu_out = match signals.alu_op {
+            AluOp::Funct => written.unwrap_or(0),
+            AluOp::Branch => xlen(alu_a.wrapping_sub(alu_b)),
+            AluOp::Add => xlen(alu_a.wrapping_add(alu_b)),
+        };
+        let mem_addr = (signals.mem_read || signals.mem_write).then_some(X::to_u64(X::from_i64(alu_out)));
+        let mem_data = if signals.mem_write { Some(value(rs2)) } else if signals.mem_read { written } else { None };
+        let write_back = if signals.reg_write { Some((rd.unwrap_or(0), written.unwrap_or(0))) } else { None };
+        DatapathTrace {
+            pc,
+            address: address as u64,
+            inst,
+            signals,
+            imm,
+            rs1,
+            rs2,
+            alu_a,
+            alu_b,
+            alu_out,
+            mem_addr,
+            mem_data,
+            write_back,
+            next_pc: cpu.pc,
+            next_address: cpu.pc_address() as u64,
+        }
+    }
+}
+
+/// The byte distance from `address`, the instruction at `cpu.pc`, to the
+/// instruction `offset` instructions away.
+fn byte_offset<X: Xlen>(cpu: &Cpu<X>, address: i64, offset: i64) -> i64 {
+    match usize::try_from(cpu.pc as i64 + offset) {
+        Ok(target) => cpu.address_of(target) as i64 - address,
+        Err(_) => 4 * offset,
+    }
+}
+
+/// Executes the next instruction and returns its datapath trace, or `None`
+/// when it has no single-cycle datapath view (it still executes).
+pub fn step<X: Xlen>(cpu: &mut Cpu<X>) -> Result<Option<DatapathTrace>, CpuError> {
+    let fetched = Fetched::new(cpu);
+    cpu.execute_next()?;
+    Ok(fetched.map(|f| f.complete(cpu)))
+}
+
+impl DatapathTrace {
+    /// Whether a branch or jump replaced the next instruction.
+    pub fn taken(&self) -> bool {
+        self.next_pc != self.pc + 1
+    }
+}
+
+/// A row of boxes joined by arrows on their first line:
+///
+/// ```text
+/// +----+   +------+
+/// | PC |-->| IMem |
+/// | 3  |   | ...  |
+/// +----+   +------+
+/// ```
+fn boxes(columns: &[(&str, Vec<String>)]) -> String {
+    let widths = columns
+        .iter()
+        .map(|(title, lines)| lines.iter().map(String::len).chain([title.len()]).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    let height = columns.iter().map(|(_, lines)| lines.len()).max().unwrap_or(0);
+    let border = widths.iter().map(|w| format!("+{}+", "-".repeat(w + 2))).collect::<Vec<_>>().join("   ");
+    let mut out = format!("{border}\n");
+    let title = columns.iter().zip(&widths).map(|((t, _), w)| format!("| {t:^w$} |")).collect::<Vec<_>>();
+    out.push_str(&title.join("-->"));
+    out.push('\n');
+    for i in 0..height {
+        let row = columns
+            .iter()
+            .zip(&widths)
+            .map(|((_, lines), w)| format!("| {:<w$} |", lines.get(i).map_or("", String::as_str)))
+            .collect::<Vec<_>>();
+        out.push_str(&row.join("   "));
+        out.push('\n');
+    }
+    out.push_str(&border);
+    out
+}
+
+fn opt<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+/// The instruction, the datapath as boxes from PC to write-back with the
+/// values on their inputs and outputs, then the control signals.
+impl fmt::Display for DatapathTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = &self.signals;
+        let port = |p: Option<(usize, i64)>| p.map_or("-".to_string(), |(r, v)| format!("x{r} = {v}"));
+        let next = if s.branch || s.jump {
+            format!("next {} ({})", self.next_address, if self.taken() { "taken" } else { "not taken" })
+        } else {
+            format!("next {}", self.next_address)
+        };
+        let columns = [
+            ("PC", vec![self.address.to_string(), next]),
+            ("Imm gen", vec![format!("ImmSel {}", s.imm_sel), format!("imm {}", opt(self.imm))]),
+            ("Registers", vec![format!("rs1 {}", port(self.rs1)), format!("rs2 {}", port(self.rs2))]),
+            (
+                "ALU",
+                vec![
+                    format!("A {}", self.alu_a),
+                    format!("B {} ({})", self.alu_b, if s.alu_src { "imm" } else { "rs2" }),
+                    format!("ALUOp {}", s.alu_op),
+                    format!("out {}", self.alu_out),
+                ],
+            ),
+            (
+                "Data memory",
+                vec![
+                    format!("read {} write {}", s.mem_read as u8, s.mem_write as u8),
+                    format!("addr {}", opt(self.mem_addr.map(|a| format!("{a:#x}")))),
+                    format!("data {}", opt(self.mem_data)),
+                ],
+            ),
+            (
+                "Write back",
+                vec![
+                    format!("MemtoReg {}", s.mem_to_reg),
+                    self.write_back.map_or("-".to_string(), |(rd, v)| format!("x{rd} <- {v}")),
+                ],
+            ),
+        ];
+        writeln!(f, "{}: {:?}", self.pc, self.inst)?;
+        writeln!(f, "{}", boxes(&columns))?;
+        write!(f, "{s}")
+    }
+}
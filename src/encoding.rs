@@ -0,0 +1,1037 @@
+use crate::fpu::RoundingMode;
+use crate::instruction::Instruction;
+use crate::vector::Sew;
+
+// Major opcodes
+const LOAD: u32 = 0b0000011;
+const LOAD_FP: u32 = 0b0000111;
+const CUSTOM_0: u32 = 0b0001011;
+const MISC_MEM: u32 = 0b0001111;
+const OP_IMM: u32 = 0b0010011;
+const AUIPC: u32 = 0b0010111;
+const OP_IMM_32: u32 = 0b0011011;
+const STORE: u32 = 0b0100011;
+const STORE_FP: u32 = 0b0100111;
+const AMO: u32 = 0b0101111;
+const OP: u32 = 0b0110011;
+const LUI: u32 = 0b0110111;
+const OP_32: u32 = 0b0111011;
+const MADD: u32 = 0b1000011;
+const MSUB: u32 = 0b1000111;
+const NMSUB: u32 = 0b1001011;
+const NMADD: u32 = 0b1001111;
+const OP_FP: u32 = 0b1010011;
+const OP_V: u32 = 0b1010111;
+const BRANCH: u32 = 0b1100011;
+const JALR: u32 = 0b1100111;
+const JAL: u32 = 0b1101111;
+const SYSTEM: u32 = 0b1110011;
+
+/// The base instruction formats. R4 counts as R, and the vector
+/// arithmetic formats as R too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Format {
+    R,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+impl Format {
+    pub const ALL: [Format; 6] = [Format::R, Format::I, Format::S, Format::B, Format::U, Format::J];
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// The format `inst` is encoded in, by its major opcode. `None` when it
+/// does not encode.
+pub fn format(inst: &Instruction) -> Option<Format> {
+    // Offsets may still be in instructions rather than bytes.
+    if inst.is_branch() {
+        return Some(Format::B);
+    }
+    if let Instruction::Jal { .. } = inst {
+        return Some(Format::J);
+    }
+    let format = match encode(inst)? & 0x7F {
+        OP | OP_32 | AMO | OP_FP | OP_V | MADD | MSUB | NMSUB | NMADD => Format::R,
+        STORE | STORE_FP => Format::S,
+        BRANCH => Format::B,
+        LUI | AUIPC => Format::U,
+        JAL => Format::J,
+        _ => Format::I,
+    };
+    Some(format)
+}
+
+/// An instruction read back from a code image, and whether it came from a
+/// 16-bit parcel.
+#[derive(Debug, Clone, Copy)]
+pub struct Decoded {
+    pub inst: Instruction,
+    pub compressed: bool,
+}
+
+impl Decoded {
+    pub fn size(&self) -> u32 {
+        if self.compressed { 2 } else { 4 }
+    }
+}
+
+fn bits(v: u32, hi: u32, lo: u32) -> u32 {
+    (v >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+fn reg(r: usize) -> Option<u32> {
+    (r < 32).then_some(r as u32)
+}
+
+fn fits(imm: i32, width: u32) -> bool {
+    let min = -(1 << (width - 1));
+    let max = (1 << (width - 1)) - 1;
+    (min..=max).contains(&imm)
+}
+
+fn r_type(opcode: u32, f7: u32, f3: u32, rd: usize, rs1: usize, rs2: usize) -> Option<u32> {
+    Some(f7 << 25 | reg(rs2)? << 20 | reg(rs1)? << 15 | f3 << 12 | reg(rd)? << 7 | opcode)
+}
+
+fn i_type(opcode: u32, f3: u32, rd: usize, rs1: usize, imm: i32) -> Option<u32> {
+    if !fits(imm, 12) {
+        return None;
+    }
+    Some((imm as u32 & 0xFFF) << 20 | reg(rs1)? << 15 | f3 << 12 | reg(rd)? << 7 | opcode)
+}
+
+/// Shift-immediate format; shamt[5] (RV64 only) lives in the low funct7 bit.
+fn shift_type(opcode: u32, f7: u32, f3: u32, rd: usize, rs1: usize, shamt: i32, width: i32) -> Option<u32> {
+    if !(0..width).contains(&shamt) {
+        return None;
+    }
+    r_type(opcode, f7 | (shamt as u32 >> 5), f3, rd, rs1, shamt as usize & 0x1F)
+}
+
+fn s_type(opcode: u32, f3: u32, rs1: usize, rs2: usize, imm: i32) -> Option<u32> {
+    if !fits(imm, 12) {
+        return None;
+    }
+    let imm = imm as u32;
+    Some(bits(imm, 11, 5) << 25 | reg(rs2)? << 20 | reg(rs1)? << 15 | f3 << 12 | bits(imm, 4, 0) << 7 | opcode)
+}
+
+fn b_type(f3: u32, rs1: usize, rs2: usize, offset: i32) -> Option<u32> {
+    if offset % 2 != 0 || !fits(offset, 13) {
+        return None;
+    }
+    let o = offset as u32;
+    Some(
+        bits(o, 12, 12) << 31
+            | bits(o, 10, 5) << 25
+            | reg(rs2)? << 20
+            | reg(rs1)? << 15
+            | f3 << 12
+            | bits(o, 4, 1) << 8
+            | bits(o, 11, 11) << 7
+            | BRANCH,
+    )
+}
+
+fn u_type(opcode: u32, rd: usize, imm: i32) -> Option<u32> {
+    if !(-0x80000..=0xFFFFF).contains(&imm) {
+        return None;
+    }
+    Some((imm as u32 & 0xFFFFF) << 12 | reg(rd)? << 7 | opcode)
+}
+
+fn j_type(rd: usize, offset: i32) -> Option<u32> {
+    if offset % 2 != 0 || !fits(offset, 21) {
+        return None;
+    }
+    let o = offset as u32;
+    Some(
+        bits(o, 20, 20) << 31
+            | bits(o, 10, 1) << 21
+            | bits(o, 11, 11) << 20
+            | bits(o, 19, 12) << 12
+            | reg(rd)? << 7
+            | JAL,
+    )
+}
+
+fn amo_type(f5: u32, rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool) -> Option<u32> {
+    r_type(AMO, f5 << 2 | (aq as u32) << 1 | rl as u32, 2, rd, rs1, rs2)
+}
+
+fn csr_type(f3: u32, rd: usize, src: usize, csr: u16) -> Option<u32> {
+    if csr >= 0x1000 {
+        return None;
+    }
+    Some((csr as u32) << 20 | reg(src)? << 15 | f3 << 12 | reg(rd)? << 7 | SYSTEM)
+}
+
+fn uimm5(imm: i32) -> Option<usize> {
+    (0..32).contains(&imm).then_some(imm as usize)
+}
+
+fn fp_type(f7: u32, rm: RoundingMode, rd: usize, rs1: usize, rs2: usize) -> Option<u32> {
+    r_type(OP_FP, f7, rm.bits() as u32, rd, rs1, rs2)
+}
+
+fn fma_type(opcode: u32, fmt: u32, rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode) -> Option<u32> {
+    r_type(opcode, reg(rs3)? << 2 | fmt, rm.bits() as u32, rd, rs1, rs2)
+}
+
+// Each table below lists one instruction group once; the macro derives both
+// the encoder and the decoder from it so the two can never disagree.
+
+macro_rules! r_table {
+    ($encode:ident, $decode:ident, $opcode:expr, $($variant:ident = $f7:literal, $f3:literal;)*) => {
+        fn $encode(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rd, rs1, rs2 } => r_type($opcode, $f7, $f3, rd, rs1, rs2),)*
+                _ => None,
+            }
+        }
+        fn $decode(f7: u32, f3: u32, rd: usize, rs1: usize, rs2: usize) -> Option<Instruction> {
+            match (f7, f3) {
+                $(($f7, $f3) => Some(Instruction::$variant { rd, rs1, rs2 }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+r_table! { encode_op, decode_op, OP,
+    Add = 0x00, 0; Sub = 0x20, 0; Sll = 0x00, 1; Slt = 0x00, 2; Sltu = 0x00, 3;
+    Xor = 0x00, 4; Srl = 0x00, 5; Sra = 0x20, 5; Or = 0x00, 6; And = 0x00, 7;
+    Mul = 0x01, 0; Mulh = 0x01, 1; Mulhsu = 0x01, 2; Mulhu = 0x01, 3;
+    Div = 0x01, 4; Divu = 0x01, 5; Rem = 0x01, 6; Remu = 0x01, 7;
+    Sh1add = 0x10, 2; Sh2add = 0x10, 4; Sh3add = 0x10, 6;
+    Andn = 0x20, 7; Orn = 0x20, 6; Xnor = 0x20, 4; Rol = 0x30, 1; Ror = 0x30, 5;
+    Min = 0x05, 4; Minu = 0x05, 5; Max = 0x05, 6; Maxu = 0x05, 7;
+    Clmul = 0x05, 1; Clmulr = 0x05, 2; Clmulh = 0x05, 3;
+    Bclr = 0x24, 1; Bext = 0x24, 5; Binv = 0x34, 1; Bset = 0x14, 1;
+}
+r_table! { encode_op_32, decode_op_32, OP_32,
+    Addw = 0x00, 0; Subw = 0x20, 0; Sllw = 0x00, 1; Srlw = 0x00, 5; Sraw = 0x20, 5;
+    Mulw = 0x01, 0; Divw = 0x01, 4; Divuw = 0x01, 5; Remw = 0x01, 6; Remuw = 0x01, 7;
+    AddUw = 0x04, 0; Sh1addUw = 0x10, 2; Sh2addUw = 0x10, 4; Sh3addUw = 0x10, 6; Rolw = 0x30, 1; Rorw = 0x30, 5;
+}
+
+/// Single-operand instructions encoded as OP-IMM(-32) with a fixed immediate,
+/// given as (funct7, funct3, rs2 field).
+macro_rules! unary_table {
+    ($($variant:ident = $opcode:ident, $f7:literal, $f3:literal, $rs2:literal;)*) => {
+        fn encode_unary(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rd, rs1 } => r_type($opcode, $f7, $f3, rd, rs1, $rs2),)*
+                _ => None,
+            }
+        }
+        fn decode_unary(opcode: u32, f7: u32, f3: u32, rd: usize, rs1: usize, rs2: usize) -> Option<Instruction> {
+            $(if (opcode, f7, f3, rs2) == ($opcode, $f7, $f3, $rs2) {
+                return Some(Instruction::$variant { rd, rs1 });
+            })*
+            None
+        }
+    };
+}
+
+// `zext.h` and `rev8` have XLEN-specific encodings; the RV32 ones are
+// emitted, and the RV64 ones are accepted by the decoder below.
+unary_table! {
+    Clz = OP_IMM, 0x30, 1, 0; Ctz = OP_IMM, 0x30, 1, 1; Cpop = OP_IMM, 0x30, 1, 2;
+    SextB = OP_IMM, 0x30, 1, 4; SextH = OP_IMM, 0x30, 1, 5;
+    Clzw = OP_IMM_32, 0x30, 1, 0; Ctzw = OP_IMM_32, 0x30, 1, 1; Cpopw = OP_IMM_32, 0x30, 1, 2;
+    OrcB = OP_IMM, 0x14, 5, 7; Rev8 = OP_IMM, 0x34, 5, 0x18; ZextH = OP, 0x04, 4, 0;
+}
+
+macro_rules! i_table {
+    ($encode:ident, $decode:ident, $opcode:expr, $($variant:ident = $f3:literal;)*) => {
+        fn $encode(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rd, rs1, imm } => i_type($opcode, $f3, rd, rs1, imm),)*
+                _ => None,
+            }
+        }
+        fn $decode(f3: u32, rd: usize, rs1: usize, imm: i32) -> Option<Instruction> {
+            match f3 {
+                $($f3 => Some(Instruction::$variant { rd, rs1, imm }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+i_table! { encode_op_imm, decode_op_imm, OP_IMM,
+    Addi = 0; Slti = 2; Sltiu = 3; Xori = 4; Ori = 6; Andi = 7;
+}
+i_table! { encode_load, decode_load, LOAD,
+    Lb = 0; Lh = 1; Lw = 2; Ld = 3; Lbu = 4; Lhu = 5; Lwu = 6;
+}
+i_table! { encode_fp_load, decode_fp_load, LOAD_FP,
+    Flw = 2; Fld = 3;
+}
+
+macro_rules! s_table {
+    ($encode:ident, $decode:ident, $opcode:expr, $($variant:ident = $f3:literal;)*) => {
+        fn $encode(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rs1, rs2, imm } => s_type($opcode, $f3, rs1, rs2, imm),)*
+                _ => None,
+            }
+        }
+        fn $decode(f3: u32, rs1: usize, rs2: usize, imm: i32) -> Option<Instruction> {
+            match f3 {
+                $($f3 => Some(Instruction::$variant { rs1, rs2, imm }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+s_table! { encode_store, decode_store, STORE,
+    Sb = 0; Sh = 1; Sw = 2; Sd = 3;
+}
+s_table! { encode_fp_store, decode_fp_store, STORE_FP,
+    Fsw = 2; Fsd = 3;
+}
+
+macro_rules! b_table {
+    ($($variant:ident = $f3:literal;)*) => {
+        fn encode_branch(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rs1, rs2, offset } => b_type($f3, rs1, rs2, offset),)*
+                _ => None,
+            }
+        }
+        fn decode_branch(f3: u32, rs1: usize, rs2: usize, offset: i32) -> Option<Instruction> {
+            match f3 {
+                $($f3 => Some(Instruction::$variant { rs1, rs2, offset }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+b_table! {
+    Beq = 0; Bne = 1; Blt = 4; Bge = 5; Bltu = 6; Bgeu = 7;
+}
+
+macro_rules! amo_table {
+    ($($variant:ident = $f5:literal;)*) => {
+        fn encode_amo(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                Instruction::LrW { rd, rs1, aq, rl } => amo_type(0b00010, rd, rs1, 0, aq, rl),
+                $(Instruction::$variant { rd, rs1, rs2, aq, rl } => amo_type($f5, rd, rs1, rs2, aq, rl),)*
+                _ => None,
+            }
+        }
+        fn decode_amo(f5: u32, rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool) -> Option<Instruction> {
+            match f5 {
+                0b00010 if rs2 == 0 => Some(Instruction::LrW { rd, rs1, aq, rl }),
+                $($f5 => Some(Instruction::$variant { rd, rs1, rs2, aq, rl }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+amo_table! {
+    ScW = 0b00011; AmoswapW = 0b00001; AmoaddW = 0b00000; AmoxorW = 0b00100; AmoandW = 0b01100;
+    AmoorW = 0b01000; AmominW = 0b10000; AmomaxW = 0b10100; AmominuW = 0b11000; AmomaxuW = 0b11100;
+}
+
+macro_rules! csr_table {
+    ($($variant:ident = $f3:literal;)* | $($imm_variant:ident = $imm_f3:literal;)*) => {
+        fn encode_csr(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rd, rs1, csr } => csr_type($f3, rd, rs1, csr),)*
+                $(Instruction::$imm_variant { rd, imm, csr } => csr_type($imm_f3, rd, uimm5(imm)?, csr),)*
+                _ => None,
+            }
+        }
+        fn decode_csr(f3: u32, rd: usize, src: usize, csr: u16) -> Option<Instruction> {
+            match f3 {
+                $($f3 => Some(Instruction::$variant { rd, rs1: src, csr }),)*
+                $($imm_f3 => Some(Instruction::$imm_variant { rd, imm: src as i32, csr }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+csr_table! {
+    Csrrw = 1; Csrrs = 2; Csrrc = 3; | Csrrwi = 5; Csrrsi = 6; Csrrci = 7;
+}
+
+/// OP-FP instructions. `rounded` entries carry a rounding mode in funct3,
+/// `unary` entries are selected by funct7 and the rs2 field, and `fixed`
+/// entries by funct7 and funct3.
+macro_rules! fp_table {
+    (
+        rounded { $($rv:ident = $rf7:literal;)* }
+        unary { $($uv:ident = $uf7:literal, $urs2:literal;)* }
+        fixed { $($fv:ident = $ff7:literal, $ff3:literal;)* }
+        moves { $($mv:ident = $mf7:literal, $mf3:literal;)* }
+    ) => {
+        fn encode_fp(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$rv { rd, rs1, rs2, rm } => fp_type($rf7, rm, rd, rs1, rs2),)*
+                $(Instruction::$uv { rd, rs1, rm } => fp_type($uf7, rm, rd, rs1, $urs2),)*
+                $(Instruction::$fv { rd, rs1, rs2 } => r_type(OP_FP, $ff7, $ff3, rd, rs1, rs2),)*
+                $(Instruction::$mv { rd, rs1 } => r_type(OP_FP, $mf7, $mf3, rd, rs1, 0),)*
+                _ => None,
+            }
+        }
+        fn decode_fp(f7: u32, f3: u32, rd: usize, rs1: usize, rs2: usize) -> Option<Instruction> {
+            let rm = RoundingMode::from_bits(f3 as u8);
+            match (f7, f3, rs2) {
+                $(($rf7, _, _) => Some(Instruction::$rv { rd, rs1, rs2, rm: rm? }),)*
+                $(($uf7, _, $urs2) => Some(Instruction::$uv { rd, rs1, rm: rm? }),)*
+                $(($ff7, $ff3, _) => Some(Instruction::$fv { rd, rs1, rs2 }),)*
+                $(($mf7, $mf3, 0) => Some(Instruction::$mv { rd, rs1 }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+fp_table! {
+    rounded {
+        FaddS = 0b0000000; FsubS = 0b0000100; FmulS = 0b0001000; FdivS = 0b0001100;
+        FaddD = 0b0000001; FsubD = 0b0000101; FmulD = 0b0001001; FdivD = 0b0001101;
+    }
+    unary {
+        FsqrtS = 0b0101100, 0; FsqrtD = 0b0101101, 0;
+        FcvtSD = 0b0100000, 1; FcvtDS = 0b0100001, 0;
+        FcvtWS = 0b1100000, 0; FcvtWuS = 0b1100000, 1; FcvtSW = 0b1101000, 0; FcvtSWu = 0b1101000, 1;
+        FcvtWD = 0b1100001, 0; FcvtWuD = 0b1100001, 1; FcvtDW = 0b1101001, 0; FcvtDWu = 0b1101001, 1;
+    }
+    fixed {
+        FsgnjS = 0b0010000, 0; FsgnjnS = 0b0010000, 1; FsgnjxS = 0b0010000, 2;
+        FsgnjD = 0b0010001, 0; FsgnjnD = 0b0010001, 1; FsgnjxD = 0b0010001, 2;
+        FminS = 0b0010100, 0; FmaxS = 0b0010100, 1; FminD = 0b0010101, 0; FmaxD = 0b0010101, 1;
+        FleS = 0b1010000, 0; FltS = 0b1010000, 1; FeqS = 0b1010000, 2;
+        FleD = 0b1010001, 0; FltD = 0b1010001, 1; FeqD = 0b1010001, 2;
+    }
+    moves {
+        FmvXW = 0b1110000, 0; FclassS = 0b1110000, 1; FclassD = 0b1110001, 1; FmvWX = 0b1111000, 0;
+    }
+}
+
+macro_rules! fma_table {
+    ($($variant:ident = $opcode:ident, $fmt:literal;)*) => {
+        fn encode_fma(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { rd, rs1, rs2, rs3, rm } => fma_type($opcode, $fmt, rd, rs1, rs2, rs3, rm),)*
+                _ => None,
+            }
+        }
+        fn decode_fma(opcode: u32, fmt: u32, rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode) -> Option<Instruction> {
+            $(if (opcode, fmt) == ($opcode, $fmt) {
+                return Some(Instruction::$variant { rd, rs1, rs2, rs3, rm });
+            })*
+            None
+        }
+    };
+}
+
+fma_table! {
+    FmaddS = MADD, 0; FmsubS = MSUB, 0; FnmsubS = NMSUB, 0; FnmaddS = NMADD, 0;
+    FmaddD = MADD, 1; FmsubD = MSUB, 1; FnmsubD = NMSUB, 1; FnmaddD = NMADD, 1;
+}
+
+// OP-V funct3
+const OPIVV: u32 = 0b000;
+const OPMVV: u32 = 0b010;
+const OPIVI: u32 = 0b011;
+const OPIVX: u32 = 0b100;
+const OPMVX: u32 = 0b110;
+const OPCFG: u32 = 0b111;
+
+fn v_type(f6: u32, vm: bool, vs2: usize, src: u32, f3: u32, vd: usize) -> Option<u32> {
+    Some(f6 << 26 | (vm as u32) << 25 | reg(vs2)? << 20 | src << 15 | f3 << 12 | reg(vd)? << 7 | OP_V)
+}
+
+fn simm5(imm: i32) -> Option<u32> {
+    fits(imm, 5).then_some(imm as u32 & 0x1F)
+}
+
+fn simm5_from(src: u32) -> i32 {
+    ((src as i32) << 27) >> 27
+}
+
+fn reg_from(src: u32) -> usize {
+    src as usize
+}
+
+/// The width field of vector loads and stores, which shares LOAD-FP and
+/// STORE-FP with the scalar floating-point accesses.
+fn vector_width(eew: Sew) -> u32 {
+    match eew {
+        Sew::E8 => 0b000,
+        Sew::E16 => 0b101,
+        Sew::E32 => 0b110,
+        Sew::E64 => 0b111,
+    }
+}
+
+fn vector_eew(width: u32) -> Option<Sew> {
+    match width {
+        0b000 => Some(Sew::E8),
+        0b101 => Some(Sew::E16),
+        0b110 => Some(Sew::E32),
+        0b111 => Some(Sew::E64),
+        _ => None,
+    }
+}
+
+/// `mop` is 0 for unit-stride accesses and 2 for strided ones, whose stride
+/// register sits in the rs2 field.
+fn vmem_type(opcode: u32, mop: u32, vm: bool, rs2: usize, rs1: usize, eew: Sew, vd: usize) -> Option<u32> {
+    Some(mop << 26 | (vm as u32) << 25 | reg(rs2)? << 20 | reg(rs1)? << 15 | vector_width(eew) << 12 | reg(vd)? << 7 | opcode)
+}
+
+fn decode_vmem(word: u32, load: bool) -> Option<Instruction> {
+    let eew = vector_eew(bits(word, 14, 12))?;
+    let (vd, rs1, rs2) = (bits(word, 11, 7) as usize, bits(word, 19, 15) as usize, bits(word, 24, 20) as usize);
+    let vm = bits(word, 25, 25) != 0;
+    // nf and mew must be zero; unit-stride accesses have lumop = 0.
+    match (bits(word, 31, 26), load) {
+        (0b00, true) if rs2 == 0 => Some(Instruction::VleV { vd, rs1, eew, vm }),
+        (0b00, false) if rs2 == 0 => Some(Instruction::VseV { vs3: vd, rs1, eew, vm }),
+        (0b10, true) => Some(Instruction::VlseV { vd, rs1, rs2, eew, vm }),
+        (0b10, false) => Some(Instruction::VsseV { vs3: vd, rs1, rs2, eew, vm }),
+        _ => None,
+    }
+}
+
+/// OP-V instructions of the form `op vd, vs2, <src>[, v0.t]`, where the
+/// source field holds `$field`.
+macro_rules! v_table {
+    ($encode:ident, $decode:ident, $f3:expr, $field:ident, $to:expr, $from:expr, $($variant:ident = $f6:literal;)*) => {
+        fn $encode(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { vd, vs2, $field, vm } => v_type($f6, vm, vs2, $to($field)?, $f3, vd),)*
+                _ => None,
+            }
+        }
+        fn $decode(f6: u32, vm: bool, vd: usize, vs2: usize, src: u32) -> Option<Instruction> {
+            let $field = $from(src);
+            match f6 {
+                $($f6 => Some(Instruction::$variant { vd, vs2, $field, vm }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+v_table! { encode_opivv, decode_opivv, OPIVV, vs1, reg, reg_from,
+    VaddVv = 0b000000; VsubVv = 0b000010; VandVv = 0b001001; VorVv = 0b001010; VxorVv = 0b001011;
+    VmseqVv = 0b011000; VmsneVv = 0b011001; VmsltVv = 0b011011; VmsleVv = 0b011101;
+}
+v_table! { encode_opivx, decode_opivx, OPIVX, rs1, reg, reg_from,
+    VaddVx = 0b000000; VsubVx = 0b000010; VandVx = 0b001001; VorVx = 0b001010; VxorVx = 0b001011;
+    VmseqVx = 0b011000; VmsneVx = 0b011001; VmsltVx = 0b011011; VmsleVx = 0b011101;
+}
+v_table! { encode_opivi, decode_opivi, OPIVI, imm, simm5, simm5_from,
+    VaddVi = 0b000000; VandVi = 0b001001; VorVi = 0b001010; VxorVi = 0b001011;
+    VmseqVi = 0b011000; VmsneVi = 0b011001; VmsleVi = 0b011101;
+}
+v_table! { encode_opmvv, decode_opmvv, OPMVV, vs1, reg, reg_from,
+    VredsumVs = 0b000000; VredandVs = 0b000001; VredorVs = 0b000010; VredxorVs = 0b000011;
+    VredminVs = 0b000101; VredmaxVs = 0b000111; VmulVv = 0b100101;
+}
+v_table! { encode_opmvx, decode_opmvx, OPMVX, rs1, reg, reg_from,
+    VmulVx = 0b100101;
+}
+
+// funct6 of the instructions outside the tables
+const VMV: u32 = 0b010111;
+const VWXUNARY0: u32 = 0b010000;
+const VRXUNARY0: u32 = 0b010000;
+const VCPOP: u32 = 0b10000;
+const VFIRST: u32 = 0b10001;
+
+macro_rules! vmask_table {
+    ($($variant:ident = $f6:literal;)*) => {
+        fn encode_vmask(inst: &Instruction) -> Option<u32> {
+            match *inst {
+                $(Instruction::$variant { vd, vs2, vs1 } => v_type($f6, true, vs2, reg(vs1)?, OPMVV, vd),)*
+                _ => None,
+            }
+        }
+        fn decode_vmask(f6: u32, vd: usize, vs2: usize, vs1: usize) -> Option<Instruction> {
+            match f6 {
+                $($f6 => Some(Instruction::$variant { vd, vs2, vs1 }),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+vmask_table! {
+    VmandnMm = 0b011000; VmandMm = 0b011001; VmorMm = 0b011010; VmxorMm = 0b011011;
+}
+
+fn encode_vector(inst: &Instruction) -> Option<u32> {
+    match *inst {
+        Instruction::Vsetvli { rd, rs1, vtypei } if vtypei < 1 << 11 => {
+            Some((vtypei as u32) << 20 | reg(rs1)? << 15 | OPCFG << 12 | reg(rd)? << 7 | OP_V)
+        }
+        Instruction::Vsetivli { rd, uimm, vtypei } if vtypei < 1 << 10 && uimm < 32 => {
+            Some(0b11 << 30 | (vtypei as u32) << 20 | (uimm as u32) << 15 | OPCFG << 12 | reg(rd)? << 7 | OP_V)
+        }
+        Instruction::VleV { vd, rs1, eew, vm } => vmem_type(LOAD_FP, 0b00, vm, 0, rs1, eew, vd),
+        Instruction::VseV { vs3, rs1, eew, vm } => vmem_type(STORE_FP, 0b00, vm, 0, rs1, eew, vs3),
+        Instruction::VlseV { vd, rs1, rs2, eew, vm } => vmem_type(LOAD_FP, 0b10, vm, rs2, rs1, eew, vd),
+        Instruction::VsseV { vs3, rs1, rs2, eew, vm } => vmem_type(STORE_FP, 0b10, vm, rs2, rs1, eew, vs3),
+        Instruction::VmvVv { vd, vs1 } => v_type(VMV, true, 0, reg(vs1)?, OPIVV, vd),
+        Instruction::VmvVx { vd, rs1 } => v_type(VMV, true, 0, reg(rs1)?, OPIVX, vd),
+        Instruction::VmvVi { vd, imm } => v_type(VMV, true, 0, simm5(imm)?, OPIVI, vd),
+        Instruction::VmvXS { rd, vs2 } => v_type(VWXUNARY0, true, vs2, 0, OPMVV, rd),
+        Instruction::VmvSX { vd, rs1 } => v_type(VRXUNARY0, true, 0, reg(rs1)?, OPMVX, vd),
+        Instruction::VcpopM { rd, vs2, vm } => v_type(VWXUNARY0, vm, vs2, VCPOP, OPMVV, rd),
+        Instruction::VfirstM { rd, vs2, vm } => v_type(VWXUNARY0, vm, vs2, VFIRST, OPMVV, rd),
+        _ => encode_opivv(inst)
+            .or_else(|| encode_opivx(inst))
+            .or_else(|| encode_opivi(inst))
+            .or_else(|| encode_opmvv(inst))
+            .or_else(|| encode_opmvx(inst))
+            .or_else(|| encode_vmask(inst)),
+    }
+}
+
+fn decode_vector(word: u32) -> Option<Instruction> {
+    let rd = bits(word, 11, 7) as usize;
+    let f3 = bits(word, 14, 12);
+    let src = bits(word, 19, 15);
+    let vs2 = bits(word, 24, 20) as usize;
+    let vm = bits(word, 25, 25) != 0;
+    let f6 = bits(word, 31, 26);
+    match (f3, f6) {
+        (OPCFG, _) if word >> 31 == 0 => Some(Instruction::Vsetvli { rd, rs1: src as usize, vtypei: bits(word, 30, 20) as u16 }),
+        (OPCFG, _) if word >> 30 == 0b11 => {
+            Some(Instruction::Vsetivli { rd, uimm: src as u8, vtypei: bits(word, 29, 20) as u16 })
+        }
+        (OPCFG, _) => None,
+        (OPIVV, VMV) if vm && vs2 == 0 => Some(Instruction::VmvVv { vd: rd, vs1: src as usize }),
+        (OPIVX, VMV) if vm && vs2 == 0 => Some(Instruction::VmvVx { vd: rd, rs1: src as usize }),
+        (OPIVI, VMV) if vm && vs2 == 0 => Some(Instruction::VmvVi { vd: rd, imm: simm5_from(src) }),
+        (OPMVV, VWXUNARY0) => match src {
+            0 if vm => Some(Instruction::VmvXS { rd, vs2 }),
+            VCPOP => Some(Instruction::VcpopM { rd, vs2, vm }),
+            VFIRST => Some(Instruction::VfirstM { rd, vs2, vm }),
+            _ => None,
+        },
+        (OPMVX, VRXUNARY0) if vm && vs2 == 0 => Some(Instruction::VmvSX { vd: rd, rs1: src as usize }),
+        (OPMVV, 0b011000..=0b011011) if vm => decode_vmask(f6, rd, vs2, src as usize),
+        (OPIVV, _) => decode_opivv(f6, vm, rd, vs2, src),
+        (OPIVX, _) => decode_opivx(f6, vm, rd, vs2, src),
+        (OPIVI, _) => decode_opivi(f6, vm, rd, vs2, src),
+        (OPMVV, _) => decode_opmvv(f6, vm, rd, vs2, src),
+        (OPMVX, _) => decode_opmvx(f6, vm, rd, vs2, src),
+        _ => None,
+    }
+}
+
+/// Encodes a 32-bit instruction. Branch and jump offsets must already be in
+/// bytes. Returns `None` when an operand does not fit the format.
+pub fn encode(inst: &Instruction) -> Option<u32> {
+    match *inst {
+        Instruction::Slli { rd, rs1, imm } => shift_type(OP_IMM, 0x00, 1, rd, rs1, imm, 64),
+        Instruction::Srli { rd, rs1, imm } => shift_type(OP_IMM, 0x00, 5, rd, rs1, imm, 64),
+        Instruction::Srai { rd, rs1, imm } => shift_type(OP_IMM, 0x20, 5, rd, rs1, imm, 64),
+        Instruction::Rori { rd, rs1, imm } => shift_type(OP_IMM, 0x30, 5, rd, rs1, imm, 64),
+        Instruction::Bclri { rd, rs1, imm } => shift_type(OP_IMM, 0x24, 1, rd, rs1, imm, 64),
+        Instruction::Bexti { rd, rs1, imm } => shift_type(OP_IMM, 0x24, 5, rd, rs1, imm, 64),
+        Instruction::Binvi { rd, rs1, imm } => shift_type(OP_IMM, 0x34, 1, rd, rs1, imm, 64),
+        Instruction::Bseti { rd, rs1, imm } => shift_type(OP_IMM, 0x14, 1, rd, rs1, imm, 64),
+        Instruction::Addiw { rd, rs1, imm } => i_type(OP_IMM_32, 0, rd, rs1, imm),
+        Instruction::Slliw { rd, rs1, imm } => shift_type(OP_IMM_32, 0x00, 1, rd, rs1, imm, 32),
+        Instruction::Srliw { rd, rs1, imm } => shift_type(OP_IMM_32, 0x00, 5, rd, rs1, imm, 32),
+        Instruction::Sraiw { rd, rs1, imm } => shift_type(OP_IMM_32, 0x20, 5, rd, rs1, imm, 32),
+        Instruction::Roriw { rd, rs1, imm } => shift_type(OP_IMM_32, 0x30, 5, rd, rs1, imm, 32),
+        Instruction::SlliUw { rd, rs1, imm } => shift_type(OP_IMM_32, 0x04, 1, rd, rs1, imm, 64),
+        Instruction::Jalr { rd, rs1, imm } => i_type(JALR, 0, rd, rs1, imm),
+        Instruction::Jal { rd, offset } => j_type(rd, offset),
+        Instruction::Lui { rd, imm } => u_type(LUI, rd, imm),
+        Instruction::Auipc { rd, imm } => u_type(AUIPC, rd, imm),
+        Instruction::Print { rs } => i_type(CUSTOM_0, 0, 0, rs, 0),
+        Instruction::Fence { pred, succ } => Some((pred as u32 & 0xF) << 24 | (succ as u32 & 0xF) << 20 | MISC_MEM),
+        Instruction::Ecall => Some(0x0000_0073),
+        Instruction::Ebreak => Some(0x0010_0073),
+        Instruction::Sret => Some(0x1020_0073),
+        Instruction::Wfi => Some(0x1050_0073),
+        Instruction::Mret => Some(0x3020_0073),
+        Instruction::SfenceVma { rs1, rs2 } => r_type(SYSTEM, 0b0001001, 0, 0, rs1, rs2),
+        _ => encode_op(inst)
+            .or_else(|| encode_op_32(inst))
+            .or_else(|| encode_unary(inst))
+            .or_else(|| encode_op_imm(inst))
+            .or_else(|| encode_load(inst))
+            .or_else(|| encode_fp_load(inst))
+            .or_else(|| encode_store(inst))
+            .or_else(|| encode_fp_store(inst))
+            .or_else(|| encode_branch(inst))
+            .or_else(|| encode_amo(inst))
+            .or_else(|| encode_csr(inst))
+            .or_else(|| encode_fp(inst))
+            .or_else(|| encode_fma(inst))
+            .or_else(|| encode_vector(inst)),
+    }
+}
+
+/// Decodes a 32-bit instruction; offsets come back in bytes.
+pub fn decode(word: u32) -> Option<Instruction> {
+    let opcode = bits(word, 6, 0);
+    let rd = bits(word, 11, 7) as usize;
+    let f3 = bits(word, 14, 12);
+    let rs1 = bits(word, 19, 15) as usize;
+    let rs2 = bits(word, 24, 20) as usize;
+    let f7 = bits(word, 31, 25);
+    let i_imm = (word as i32) >> 20;
+    let s_imm = ((word as i32) >> 25) << 5 | bits(word, 11, 7) as i32;
+
+    if let Some(inst) = decode_unary(opcode, f7, f3, rd, rs1, rs2) {
+        return Some(inst);
+    }
+
+    match opcode {
+        OP => decode_op(f7, f3, rd, rs1, rs2),
+        OP_32 if (f7, f3, rs2) == (0x04, 4, 0) => Some(Instruction::ZextH { rd, rs1 }),
+        OP_32 => decode_op_32(f7, f3, rd, rs1, rs2),
+        OP_IMM => {
+            let imm = ((f7 & 1) << 5 | rs2 as u32) as i32;
+            match (f3, f7 & !1) {
+                (1, 0x00) => Some(Instruction::Slli { rd, rs1, imm }),
+                (5, 0x00) => Some(Instruction::Srli { rd, rs1, imm }),
+                (5, 0x20) => Some(Instruction::Srai { rd, rs1, imm }),
+                (5, 0x30) => Some(Instruction::Rori { rd, rs1, imm }),
+                (1, 0x24) => Some(Instruction::Bclri { rd, rs1, imm }),
+                (5, 0x24) => Some(Instruction::Bexti { rd, rs1, imm }),
+                (1, 0x34) => Some(Instruction::Binvi { rd, rs1, imm }),
+                (1, 0x14) => Some(Instruction::Bseti { rd, rs1, imm }),
+                (5, 0x34) if rs2 == 0x18 => Some(Instruction::Rev8 { rd, rs1 }),
+                (1 | 5, _) => None,
+                _ => decode_op_imm(f3, rd, rs1, i_imm),
+            }
+        }
+        OP_IMM_32 => match (f3, f7) {
+            (0, _) => Some(Instruction::Addiw { rd, rs1, imm: i_imm }),
+            (1, 0x00) => Some(Instruction::Slliw { rd, rs1, imm: rs2 as i32 }),
+            (5, 0x00) => Some(Instruction::Srliw { rd, rs1, imm: rs2 as i32 }),
+            (5, 0x20) => Some(Instruction::Sraiw { rd, rs1, imm: rs2 as i32 }),
+            (5, 0x30) => Some(Instruction::Roriw { rd, rs1, imm: rs2 as i32 }),
+            (1, 0x04 | 0x05) => Some(Instruction::SlliUw { rd, rs1, imm: ((f7 & 1) << 5 | rs2 as u32) as i32 }),
+            _ => None,
+        },
+        LOAD => decode_load(f3, rd, rs1, i_imm),
+        LOAD_FP if vector_eew(f3).is_some() => decode_vmem(word, true),
+        LOAD_FP => decode_fp_load(f3, rd, rs1, i_imm),
+        STORE => decode_store(f3, rs1, rs2, s_imm),
+        STORE_FP if vector_eew(f3).is_some() => decode_vmem(word, false),
+        STORE_FP => decode_fp_store(f3, rs1, rs2, s_imm),
+        BRANCH => {
+            let offset = ((word as i32) >> 31) << 12
+                | (bits(word, 7, 7) << 11 | bits(word, 30, 25) << 5 | bits(word, 11, 8) << 1) as i32;
+            decode_branch(f3, rs1, rs2, offset)
+        }
+        JAL => {
+            let offset = ((word as i32) >> 31) << 20
+                | (bits(word, 19, 12) << 12 | bits(word, 20, 20) << 11 | bits(word, 30, 21) << 1) as i32;
+            Some(Instruction::Jal { rd, offset })
+        }
+        JALR if f3 == 0 => Some(Instruction::Jalr { rd, rs1, imm: i_imm }),
+        LUI => Some(Instruction::Lui { rd, imm: (word >> 12) as i32 }),
+        AUIPC => Some(Instruction::Auipc { rd, imm: (word >> 12) as i32 }),
+        AMO if f3 == 2 => decode_amo(f7 >> 2, rd, rs1, rs2, f7 & 2 != 0, f7 & 1 != 0),
+        MISC_MEM if f3 == 0 && rd == 0 && rs1 == 0 && word >> 28 == 0 => {
+            Some(Instruction::Fence { pred: bits(word, 27, 24) as u8, succ: bits(word, 23, 20) as u8 })
+        }
+        SYSTEM if f3 == 0 => match word {
+            0x0000_0073 => Some(Instruction::Ecall),
+            0x0010_0073 => Some(Instruction::Ebreak),
+            0x1020_0073 => Some(Instruction::Sret),
+            0x1050_0073 => Some(Instruction::Wfi),
+            0x3020_0073 => Some(Instruction::Mret),
+            _ if f7 == 0b0001001 && rd == 0 => Some(Instruction::SfenceVma { rs1, rs2 }),
+            _ => None,
+        },
+        SYSTEM => decode_csr(f3, rd, rs1, (word >> 20) as u16),
+        OP_FP => decode_fp(f7, f3, rd, rs1, rs2),
+        MADD | MSUB | NMSUB | NMADD => {
+            let rm = RoundingMode::from_bits(f3 as u8)?;
+            decode_fma(opcode, f7 & 3, rd, rs1, rs2, (f7 >> 2) as usize, rm)
+        }
+        OP_V => decode_vector(word),
+        CUSTOM_0 if f3 == 0 && rd == 0 && i_imm == 0 => Some(Instruction::Print { rs: rs1 }),
+        _ => None,
+    }
+}
+
+// Compressed immediates are scattered over the parcel. Each segment is
+// (imm_hi, imm_lo, parcel_lo): imm[hi:lo] lives at parcel[lo + hi - lo : lo].
+type Segments = &'static [(u32, u32, u32)];
+
+const CIW_IMM: Segments = &[(5, 4, 11), (9, 6, 7), (2, 2, 6), (3, 3, 5)];
+const CL_WORD: Segments = &[(5, 3, 10), (2, 2, 6), (6, 6, 5)];
+const CL_DOUBLE: Segments = &[(5, 3, 10), (7, 6, 5)];
+const CI_IMM: Segments = &[(5, 5, 12), (4, 0, 2)];
+const CI_LUI: Segments = &[(17, 17, 12), (16, 12, 2)];
+const CI_SP16: Segments = &[(9, 9, 12), (4, 4, 6), (6, 6, 5), (8, 7, 3), (5, 5, 2)];
+const CI_LWSP: Segments = &[(5, 5, 12), (4, 2, 4), (7, 6, 2)];
+const CI_LDSP: Segments = &[(5, 5, 12), (4, 3, 5), (8, 6, 2)];
+const CSS_SWSP: Segments = &[(5, 2, 9), (7, 6, 7)];
+const CSS_SDSP: Segments = &[(5, 3, 10), (8, 6, 7)];
+const CJ_OFFSET: Segments = &[(11, 11, 12), (4, 4, 11), (9, 8, 9), (10, 10, 8), (6, 6, 7), (7, 7, 6), (3, 1, 3), (5, 5, 2)];
+const CB_OFFSET: Segments = &[(8, 8, 12), (4, 3, 10), (7, 6, 5), (2, 1, 3), (5, 5, 2)];
+
+fn gather(parcel: u16, segs: Segments, signed: bool) -> i32 {
+    let parcel = parcel as u32;
+    let value = segs
+        .iter()
+        .fold(0, |acc, &(hi, lo, at)| acc | bits(parcel, at + hi - lo, at) << lo);
+    let top = segs.iter().map(|&(hi, _, _)| hi).max().unwrap_or(0);
+    if signed {
+        ((value << (31 - top)) as i32) >> (31 - top)
+    } else {
+        value as i32
+    }
+}
+
+/// Scatters `imm` into its parcel positions, or `None` if it does not
+/// survive the round trip (out of range, misaligned, or zero when `nonzero`).
+fn scatter(imm: i32, segs: Segments, signed: bool, nonzero: bool) -> Option<u16> {
+    if nonzero && imm == 0 {
+        return None;
+    }
+    let parcel = segs
+        .iter()
+        .fold(0, |acc, &(hi, lo, at)| acc | bits(imm as u32, hi, lo) << at) as u16;
+    (gather(parcel, segs, signed) == imm).then_some(parcel)
+}
+
+/// The 3-bit register field of the compressed formats only reaches x8-x15.
+fn creg(r: usize) -> Option<u16> {
+    (8..16).contains(&r).then(|| (r - 8) as u16)
+}
+
+fn cfull(r: usize) -> Option<u16> {
+    (r < 32).then_some(r as u16)
+}
+
+fn parcel(f3: u16, op: u16, fields: u16) -> u16 {
+    f3 << 13 | fields | op
+}
+
+/// Returns the 16-bit form of `inst` if the C extension has one at `xlen`
+/// bits. Offsets are in bytes.
+pub fn compress(inst: &Instruction, xlen: u32) -> Option<u16> {
+    let rv64 = xlen == 64;
+    let shamt_ok = |imm: i32| imm < xlen as i32;
+    match *inst {
+        Instruction::Addi { rd: 0, rs1: 0, imm: 0 } => Some(0x0001), // c.nop
+        Instruction::Addi { rd, rs1: 2, imm } if creg(rd).is_some() && imm > 0 => {
+            Some(parcel(0b000, 0b00, scatter(imm, CIW_IMM, false, true)? | creg(rd)? << 2))
+        }
+        Instruction::Addi { rd: 2, rs1: 2, imm } => scatter(imm, CI_IMM, true, true)
+            .map(|imm| parcel(0b000, 0b01, imm | 2 << 7))
+            .or_else(|| Some(parcel(0b011, 0b01, scatter(imm, CI_SP16, true, true)? | 2 << 7))),
+        Instruction::Addi { rd, rs1: 0, imm } if rd != 0 => {
+            Some(parcel(0b010, 0b01, scatter(imm, CI_IMM, true, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Addi { rd, rs1, imm } if rd == rs1 && rd != 0 => {
+            Some(parcel(0b000, 0b01, scatter(imm, CI_IMM, true, true)? | cfull(rd)? << 7))
+        }
+        Instruction::Addiw { rd, rs1, imm } if rv64 && rd == rs1 && rd != 0 => {
+            Some(parcel(0b001, 0b01, scatter(imm, CI_IMM, true, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Lui { rd, imm } if rd != 0 && rd != 2 => {
+            let imm = (imm << 12) >> 12; // sign-extend the 20-bit field
+            Some(parcel(0b011, 0b01, scatter(imm << 12, CI_LUI, true, true)? | cfull(rd)? << 7))
+        }
+        Instruction::Srli { rd, rs1, imm } if rd == rs1 && shamt_ok(imm) => {
+            Some(parcel(0b100, 0b01, scatter(imm, CI_IMM, false, true)? | creg(rd)? << 7))
+        }
+        Instruction::Srai { rd, rs1, imm } if rd == rs1 && shamt_ok(imm) => {
+            Some(parcel(0b100, 0b01, 0b01 << 10 | scatter(imm, CI_IMM, false, true)? | creg(rd)? << 7))
+        }
+        Instruction::Andi { rd, rs1, imm } if rd == rs1 => {
+            Some(parcel(0b100, 0b01, 0b10 << 10 | scatter(imm, CI_IMM, true, false)? | creg(rd)? << 7))
+        }
+        Instruction::Sub { rd, rs1, rs2 } if rd == rs1 => compress_ca(0b00, rd, rs2),
+        Instruction::Xor { rd, rs1, rs2 } if rd == rs1 => compress_ca(0b01, rd, rs2),
+        Instruction::Or { rd, rs1, rs2 } if rd == rs1 => compress_ca(0b10, rd, rs2),
+        Instruction::And { rd, rs1, rs2 } if rd == rs1 => compress_ca(0b11, rd, rs2),
+        Instruction::Subw { rd, rs1, rs2 } if rv64 && rd == rs1 => compress_ca(0b00, rd, rs2).map(|p| p | 1 << 12),
+        Instruction::Addw { rd, rs1, rs2 } if rv64 && rd == rs1 => compress_ca(0b01, rd, rs2).map(|p| p | 1 << 12),
+        Instruction::Slli { rd, rs1, imm } if rd == rs1 && rd != 0 && shamt_ok(imm) => {
+            Some(parcel(0b000, 0b10, scatter(imm, CI_IMM, false, true)? | cfull(rd)? << 7))
+        }
+        Instruction::Add { rd, rs1: 0, rs2 } if rd != 0 && rs2 != 0 => {
+            Some(parcel(0b100, 0b10, cfull(rd)? << 7 | cfull(rs2)? << 2)) // c.mv
+        }
+        Instruction::Add { rd, rs1, rs2 } if rd == rs1 && rd != 0 && rs2 != 0 => {
+            Some(parcel(0b100, 0b10, 1 << 12 | cfull(rd)? << 7 | cfull(rs2)? << 2))
+        }
+        Instruction::Add { rd, rs1, rs2 } if rd == rs2 && rd != 0 && rs1 != 0 => {
+            Some(parcel(0b100, 0b10, 1 << 12 | cfull(rd)? << 7 | cfull(rs1)? << 2))
+        }
+        Instruction::Jalr { rd: 0, rs1, imm: 0 } if rs1 != 0 => Some(parcel(0b100, 0b10, cfull(rs1)? << 7)),
+        Instruction::Jalr { rd: 1, rs1, imm: 0 } if rs1 != 0 => Some(parcel(0b100, 0b10, 1 << 12 | cfull(rs1)? << 7)),
+        Instruction::Jal { rd: 0, offset } => Some(parcel(0b101, 0b01, scatter(offset, CJ_OFFSET, true, false)?)),
+        // RV64 reuses the c.jal encoding for c.addiw.
+        Instruction::Jal { rd: 1, offset } if !rv64 => {
+            Some(parcel(0b001, 0b01, scatter(offset, CJ_OFFSET, true, false)?))
+        }
+        Instruction::Beq { rs1, rs2: 0, offset } => {
+            Some(parcel(0b110, 0b01, scatter(offset, CB_OFFSET, true, false)? | creg(rs1)? << 7))
+        }
+        Instruction::Bne { rs1, rs2: 0, offset } => {
+            Some(parcel(0b111, 0b01, scatter(offset, CB_OFFSET, true, false)? | creg(rs1)? << 7))
+        }
+        Instruction::Lw { rd, rs1: 2, imm } if rd != 0 => {
+            Some(parcel(0b010, 0b10, scatter(imm, CI_LWSP, false, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Ld { rd, rs1: 2, imm } if rv64 && rd != 0 => {
+            Some(parcel(0b011, 0b10, scatter(imm, CI_LDSP, false, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Flw { rd, rs1: 2, imm } if !rv64 => {
+            Some(parcel(0b011, 0b10, scatter(imm, CI_LWSP, false, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Fld { rd, rs1: 2, imm } => {
+            Some(parcel(0b001, 0b10, scatter(imm, CI_LDSP, false, false)? | cfull(rd)? << 7))
+        }
+        Instruction::Sw { rs1: 2, rs2, imm } => {
+            Some(parcel(0b110, 0b10, scatter(imm, CSS_SWSP, false, false)? | cfull(rs2)? << 2))
+        }
+        Instruction::Sd { rs1: 2, rs2, imm } if rv64 => {
+            Some(parcel(0b111, 0b10, scatter(imm, CSS_SDSP, false, false)? | cfull(rs2)? << 2))
+        }
+        Instruction::Fsw { rs1: 2, rs2, imm } if !rv64 => {
+            Some(parcel(0b111, 0b10, scatter(imm, CSS_SWSP, false, false)? | cfull(rs2)? << 2))
+        }
+        Instruction::Fsd { rs1: 2, rs2, imm } => {
+            Some(parcel(0b101, 0b10, scatter(imm, CSS_SDSP, false, false)? | cfull(rs2)? << 2))
+        }
+        Instruction::Lw { rd, rs1, imm } => compress_cl(0b010, CL_WORD, rd, rs1, imm),
+        Instruction::Ld { rd, rs1, imm } if rv64 => compress_cl(0b011, CL_DOUBLE, rd, rs1, imm),
+        Instruction::Flw { rd, rs1, imm } if !rv64 => compress_cl(0b011, CL_WORD, rd, rs1, imm),
+        Instruction::Fld { rd, rs1, imm } => compress_cl(0b001, CL_DOUBLE, rd, rs1, imm),
+        Instruction::Sw { rs1, rs2, imm } => compress_cl(0b110, CL_WORD, rs2, rs1, imm),
+        Instruction::Sd { rs1, rs2, imm } if rv64 => compress_cl(0b111, CL_DOUBLE, rs2, rs1, imm),
+        Instruction::Fsw { rs1, rs2, imm } if !rv64 => compress_cl(0b111, CL_WORD, rs2, rs1, imm),
+        Instruction::Fsd { rs1, rs2, imm } => compress_cl(0b101, CL_DOUBLE, rs2, rs1, imm),
+        _ => None,
+    }
+}
+
+fn compress_ca(funct2: u16, rd: usize, rs2: usize) -> Option<u16> {
+    Some(parcel(0b100, 0b01, 0b011 << 10 | creg(rd)? << 7 | funct2 << 5 | creg(rs2)? << 2))
+}
+
+/// CL and CS share a layout: the data register in bits 4:2, the base in 9:7.
+fn compress_cl(f3: u16, segs: Segments, data: usize, base: usize, imm: i32) -> Option<u16> {
+    Some(parcel(f3, 0b00, scatter(imm, segs, false, false)? | creg(base)? << 7 | creg(data)? << 2))
+}
+
+/// Expands a 16-bit parcel into the equivalent 32-bit instruction. Some
+/// encodings mean different instructions at each `xlen`.
+pub fn decompress(parcel: u16, xlen: u32) -> Option<Instruction> {
+    let rv64 = xlen == 64;
+    let p = parcel as u32;
+    let op = bits(p, 1, 0);
+    let f3 = bits(p, 15, 13);
+    let rd = bits(p, 11, 7) as usize;
+    let rs2 = bits(p, 6, 2) as usize;
+    let rd_short = bits(p, 4, 2) as usize + 8;
+    let rs1_short = bits(p, 9, 7) as usize + 8;
+
+    match (op, f3) {
+        (0b00, 0b000) if parcel != 0 => {
+            let imm = gather(parcel, CIW_IMM, false);
+            (imm != 0).then_some(Instruction::Addi { rd: rd_short, rs1: 2, imm })
+        }
+        (0b00, 0b001) => Some(Instruction::Fld { rd: rd_short, rs1: rs1_short, imm: gather(parcel, CL_DOUBLE, false) }),
+        (0b00, 0b010) => Some(Instruction::Lw { rd: rd_short, rs1: rs1_short, imm: gather(parcel, CL_WORD, false) }),
+        (0b00, 0b011) if rv64 => Some(Instruction::Ld { rd: rd_short, rs1: rs1_short, imm: gather(parcel, CL_DOUBLE, false) }),
+        (0b00, 0b011) => Some(Instruction::Flw { rd: rd_short, rs1: rs1_short, imm: gather(parcel, CL_WORD, false) }),
+        (0b00, 0b101) => Some(Instruction::Fsd { rs1: rs1_short, rs2: rd_short, imm: gather(parcel, CL_DOUBLE, false) }),
+        (0b00, 0b110) => Some(Instruction::Sw { rs1: rs1_short, rs2: rd_short, imm: gather(parcel, CL_WORD, false) }),
+        (0b00, 0b111) if rv64 => Some(Instruction::Sd { rs1: rs1_short, rs2: rd_short, imm: gather(parcel, CL_DOUBLE, false) }),
+        (0b00, 0b111) => Some(Instruction::Fsw { rs1: rs1_short, rs2: rd_short, imm: gather(parcel, CL_WORD, false) }),
+
+        (0b01, 0b000) => Some(Instruction::Addi { rd, rs1: rd, imm: gather(parcel, CI_IMM, true) }),
+        (0b01, 0b001) if rv64 && rd != 0 => Some(Instruction::Addiw { rd, rs1: rd, imm: gather(parcel, CI_IMM, true) }),
+        (0b01, 0b001) if !rv64 => Some(Instruction::Jal { rd: 1, offset: gather(parcel, CJ_OFFSET, true) }),
+        (0b01, 0b010) if rd != 0 => Some(Instruction::Addi { rd, rs1: 0, imm: gather(parcel, CI_IMM, true) }),
+        (0b01, 0b011) if rd == 2 => {
+            let imm = gather(parcel, CI_SP16, true);
+            (imm != 0).then_some(Instruction::Addi { rd: 2, rs1: 2, imm })
+        }
+        (0b01, 0b011) if rd != 0 => {
+            let imm = gather(parcel, CI_LUI, true);
+            (imm != 0).then_some(Instruction::Lui { rd, imm: (imm >> 12) & 0xFFFFF })
+        }
+        (0b01, 0b100) => {
+            let rd = rs1_short;
+            match (bits(p, 11, 10), bits(p, 12, 12), bits(p, 6, 5)) {
+                (0b00, shamt5, _) if rv64 || shamt5 == 0 => {
+                    Some(Instruction::Srli { rd, rs1: rd, imm: gather(parcel, CI_IMM, false) })
+                }
+                (0b01, shamt5, _) if rv64 || shamt5 == 0 => {
+                    Some(Instruction::Srai { rd, rs1: rd, imm: gather(parcel, CI_IMM, false) })
+                }
+                (0b10, _, _) => Some(Instruction::Andi { rd, rs1: rd, imm: gather(parcel, CI_IMM, true) }),
+                (0b11, 0, 0b00) => Some(Instruction::Sub { rd, rs1: rd, rs2: rd_short }),
+                (0b11, 0, 0b01) => Some(Instruction::Xor { rd, rs1: rd, rs2: rd_short }),
+                (0b11, 0, 0b10) => Some(Instruction::Or { rd, rs1: rd, rs2: rd_short }),
+                (0b11, 0, 0b11) => Some(Instruction::And { rd, rs1: rd, rs2: rd_short }),
+                (0b11, 1, 0b00) if rv64 => Some(Instruction::Subw { rd, rs1: rd, rs2: rd_short }),
+                (0b11, 1, 0b01) if rv64 => Some(Instruction::Addw { rd, rs1: rd, rs2: rd_short }),
+                _ => None,
+            }
+        }
+        (0b01, 0b101) => Some(Instruction::Jal { rd: 0, offset: gather(parcel, CJ_OFFSET, true) }),
+        (0b01, 0b110) => Some(Instruction::Beq { rs1: rs1_short, rs2: 0, offset: gather(parcel, CB_OFFSET, true) }),
+        (0b01, 0b111) => Some(Instruction::Bne { rs1: rs1_short, rs2: 0, offset: gather(parcel, CB_OFFSET, true) }),
+
+        (0b10, 0b000) if rv64 || bits(p, 12, 12) == 0 => Some(Instruction::Slli { rd, rs1: rd, imm: gather(parcel, CI_IMM, false) }),
+        (0b10, 0b001) => Some(Instruction::Fld { rd, rs1: 2, imm: gather(parcel, CI_LDSP, false) }),
+        (0b10, 0b010) if rd != 0 => Some(Instruction::Lw { rd, rs1: 2, imm: gather(parcel, CI_LWSP, false) }),
+        (0b10, 0b011) if rv64 && rd != 0 => Some(Instruction::Ld { rd, rs1: 2, imm: gather(parcel, CI_LDSP, false) }),
+        (0b10, 0b011) if !rv64 => Some(Instruction::Flw { rd, rs1: 2, imm: gather(parcel, CI_LWSP, false) }),
+        (0b10, 0b100) => match (bits(p, 12, 12), rd, rs2) {
+            (_, 0, _) => None,
+            (0, _, 0) => Some(Instruction::Jalr { rd: 0, rs1: rd, imm: 0 }),
+            (0, _, _) => Some(Instruction::Add { rd, rs1: 0, rs2 }),
+            (_, _, 0) => Some(Instruction::Jalr { rd: 1, rs1: rd, imm: 0 }),
+            _ => Some(Instruction::Add { rd, rs1: rd, rs2 }),
+        },
+        (0b10, 0b101) => Some(Instruction::Fsd { rs1: 2, rs2, imm: gather(parcel, CSS_SDSP, false) }),
+        (0b10, 0b110) => Some(Instruction::Sw { rs1: 2, rs2, imm: gather(parcel, CSS_SWSP, false) }),
+        (0b10, 0b111) if rv64 => Some(Instruction::Sd { rs1: 2, rs2, imm: gather(parcel, CSS_SDSP, false) }),
+        (0b10, 0b111) => Some(Instruction::Fsw { rs1: 2, rs2, imm: gather(parcel, CSS_SWSP, false) }),
+        _ => None,
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which may be a 16-bit
+/// parcel or a full 32-bit word, for an `xlen`-bit hart.
+pub fn decode_at(bytes: &[u8], xlen: u32) -> Option<Decoded> {
+    let low = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]);
+    if low & 0b11 != 0b11 {
+        return Some(Decoded { inst: decompress(low, xlen)?, compressed: true });
+    }
+    let high = u16::from_le_bytes([*bytes.get(2)?, *bytes.get(3)?]);
+    let inst = decode((high as u32) << 16 | low as u32)?;
+    Some(Decoded { inst, compressed: false })
+}
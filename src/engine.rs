@@ -0,0 +1,127 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use crate::cpu::{Cpu, CpuError};
+use crate::multicycle::MultiCycle;
+use crate::pipeline::{Pipeline, PipelineConfig};
+use crate::timing::TimingModel;
+use crate::tomasulo::Tomasulo;
+use crate::xlen::Xlen;
+
+/// A microarchitecture driving a [`Cpu`]. The ISA semantics are always
+/// [`Cpu::execute_next`]'s: an engine only decides when each instruction
+/// executes and how many cycles that takes, so every engine leaves the CPU
+/// in the same architectural state.
+pub trait Engine<X: Xlen> {
+    fn name(&self) -> &'static str;
+
+    /// Runs one clock cycle. Returns `Ok(false)` once the program is done
+    /// and nothing is left in flight.
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError>;
+
+    fn cycles(&self) -> usize;
+
+    fn retired(&self) -> usize;
+
+    /// Cycles per retired instruction.
+    fn cpi(&self) -> f64 {
+        if self.retired() == 0 { 0.0 } else { self.cycles() as f64 / self.retired() as f64 }
+    }
+
+    /// Runs until the program is done or `limit` cycles have passed, and
+    /// returns the number of cycles run.
+    fn run(&mut self, cpu: &mut Cpu<X>, limit: usize) -> Result<usize, CpuError> {
+        let start = self.cycles();
+        while self.cycles() - start < limit && self.step(cpu)? {}
+        Ok(self.cycles() - start)
+    }
+}
+
+/// The interpreter on its own: one instruction per cycle, as on the
+/// single-cycle datapath.
+#[derive(Debug, Default)]
+pub struct Functional {
+    cycles: usize,
+    retired: usize,
+}
+
+impl<X: Xlen> Engine<X> for Functional {
+    fn name(&self) -> &'static str {
+        "functional"
+    }
+
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        let instret = cpu.instret();
+        let stepped = cpu.execute_next()?;
+        self.cycles += stepped as usize;
+        self.retired += (cpu.instret() - instret) as usize;
+        Ok(stepped)
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    fn retired(&self) -> usize {
+        self.retired
+    }
+}
+
+/// The engines a run can be given, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+    Functional,
+    MultiCycle,
+    Pipeline,
+    Tomasulo,
+    Timing,
+}
+
+impl EngineKind {
+    pub const ALL: [EngineKind; 5] = [
+        EngineKind::Functional,
+        EngineKind::MultiCycle,
+        EngineKind::Pipeline,
+        EngineKind::Tomasulo,
+        EngineKind::Timing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EngineKind::Functional => "functional",
+            EngineKind::MultiCycle => "multicycle",
+            EngineKind::Pipeline => "pipeline",
+            EngineKind::Tomasulo => "tomasulo",
+            EngineKind::Timing => "timing",
+        }
+    }
+
+    /// A fresh engine of this kind, with its default configuration.
+    pub fn build<X: Xlen>(self) -> Box<dyn Engine<X>> {
+        match self {
+            EngineKind::Functional => Box::new(Functional::default()),
+            EngineKind::MultiCycle => Box::new(MultiCycle::new()),
+            EngineKind::Pipeline => Box::new(Pipeline::new(PipelineConfig::default())),
+            EngineKind::Tomasulo => Box::new(Tomasulo::default()),
+            EngineKind::Timing => Box::new(TimingModel::default()),
+        }
+    }
+}
+
+impl fmt::Display for EngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown engine `{0}`, expected functional, multicycle, pipeline, tomasulo or timing")]
+pub struct UnknownEngine(pub String);
+
+impl FromStr for EngineKind {
+    type Err = UnknownEngine;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EngineKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| UnknownEngine(s.to_string()))
+    }
+}
@@ -0,0 +1,470 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// fflags bits
+pub const NX: u8 = 0x01; // inexact
+pub const UF: u8 = 0x02; // underflow
+pub const OF: u8 = 0x04; // overflow
+pub const DZ: u8 = 0x08; // divide by zero
+pub const NV: u8 = 0x10; // invalid operation
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Rne,
+    Rtz,
+    Rdn,
+    Rup,
+    Rmm,
+    Dyn,
+}
+
+impl RoundingMode {
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(RoundingMode::Rne),
+            1 => Some(RoundingMode::Rtz),
+            2 => Some(RoundingMode::Rdn),
+            3 => Some(RoundingMode::Rup),
+            4 => Some(RoundingMode::Rmm),
+            7 => Some(RoundingMode::Dyn),
+            _ => None,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            RoundingMode::Rne => 0,
+            RoundingMode::Rtz => 1,
+            RoundingMode::Rdn => 2,
+            RoundingMode::Rup => 3,
+            RoundingMode::Rmm => 4,
+            RoundingMode::Dyn => 7,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rne" => Some(RoundingMode::Rne),
+            "rtz" => Some(RoundingMode::Rtz),
+            "rdn" => Some(RoundingMode::Rdn),
+            "rup" => Some(RoundingMode::Rup),
+            "rmm" => Some(RoundingMode::Rmm),
+            "dyn" => Some(RoundingMode::Dyn),
+            _ => None,
+        }
+    }
+}
+
+/// The operations riscviz needs from `f32` and `f64`, plus how each format
+/// lives in a 64-bit FP register.
+pub trait Float:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const MAX: Self;
+    const MIN_POSITIVE: Self;
+    const CANONICAL_NAN: Self;
+    const SIGN_BIT: u64;
+    const QUIET_BIT: u64;
+
+    fn to_raw(self) -> u64;
+    fn from_raw(bits: u64) -> Self;
+    /// Reads the value out of an FP register, honouring NaN-boxing.
+    fn unbox(reg: u64) -> Self;
+    /// Produces the FP register image of the value.
+    fn boxed(self) -> u64;
+    fn to_f64(self) -> f64;
+    fn from_f64(v: f64) -> Self;
+
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_finite(self) -> bool;
+    fn is_subnormal(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn mul_add(self, a: Self, b: Self) -> Self;
+    fn next_up(self) -> Self;
+    fn next_down(self) -> Self;
+
+    fn is_snan(self) -> bool {
+        self.is_nan() && self.to_raw() & Self::QUIET_BIT == 0
+    }
+}
+
+macro_rules! impl_float {
+    ($t:ty, $bits:ty, $sign:expr, $quiet:expr, $canonical:expr) => {
+        impl Float for $t {
+            const ZERO: Self = 0.0;
+            const MAX: Self = <$t>::MAX;
+            const MIN_POSITIVE: Self = <$t>::MIN_POSITIVE;
+            const CANONICAL_NAN: Self = <$t>::from_bits($canonical);
+            const SIGN_BIT: u64 = $sign;
+            const QUIET_BIT: u64 = $quiet;
+
+            fn to_raw(self) -> u64 {
+                self.to_bits() as u64
+            }
+            fn from_raw(bits: u64) -> Self {
+                <$t>::from_bits(bits as $bits)
+            }
+            fn unbox(reg: u64) -> Self {
+                if std::mem::size_of::<$t>() == 8 || reg >> 32 == 0xFFFF_FFFF {
+                    Self::from_raw(reg)
+                } else {
+                    Self::CANONICAL_NAN
+                }
+            }
+            fn boxed(self) -> u64 {
+                if std::mem::size_of::<$t>() == 8 {
+                    self.to_raw()
+                } else {
+                    0xFFFF_FFFF_0000_0000 | self.to_raw()
+                }
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(v: f64) -> Self {
+                v as $t
+            }
+            fn is_nan(self) -> bool {
+                <$t>::is_nan(self)
+            }
+            fn is_infinite(self) -> bool {
+                <$t>::is_infinite(self)
+            }
+            fn is_finite(self) -> bool {
+                <$t>::is_finite(self)
+            }
+            fn is_subnormal(self) -> bool {
+                <$t>::is_subnormal(self)
+            }
+            fn is_sign_negative(self) -> bool {
+                <$t>::is_sign_negative(self)
+            }
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+            fn mul_add(self, a: Self, b: Self) -> Self {
+                <$t>::mul_add(self, a, b)
+            }
+            fn next_up(self) -> Self {
+                <$t>::next_up(self)
+            }
+            fn next_down(self) -> Self {
+                <$t>::next_down(self)
+            }
+        }
+    };
+}
+
+impl_float!(f32, u32, 0x8000_0000, 0x0040_0000, 0x7FC0_0000);
+impl_float!(f64, u64, 0x8000_0000_0000_0000, 0x0008_0000_0000_0000, 0x7FF8_0000_0000_0000);
+
+fn toward_zero<T: Float>(r: T) -> T {
+    if r > T::ZERO { r.next_down() } else { r.next_up() }
+}
+
+fn away_from_zero<T: Float>(r: T) -> T {
+    if r.is_sign_negative() { r.next_down() } else { r.next_up() }
+}
+
+/// Adjusts the round-to-nearest-even result `r` to `rm`, given the signed
+/// rounding error `err = exact - r`.
+fn round_with<T: Float>(r: T, err: T, rm: RoundingMode) -> T {
+    if err == T::ZERO || err.is_nan() {
+        return r;
+    }
+    let err_negative = err < T::ZERO;
+    match rm {
+        RoundingMode::Rne | RoundingMode::Dyn => r,
+        RoundingMode::Rtz if r != T::ZERO && err_negative != r.is_sign_negative() => toward_zero(r),
+        RoundingMode::Rtz => r,
+        RoundingMode::Rdn if err_negative => r.next_down(),
+        RoundingMode::Rup if !err_negative => r.next_up(),
+        RoundingMode::Rdn | RoundingMode::Rup => r,
+        RoundingMode::Rmm => {
+            let away = away_from_zero(r);
+            let tie = err.abs() + err.abs() == (away - r).abs();
+            if tie && err_negative == r.is_sign_negative() { away } else { r }
+        }
+    }
+}
+
+fn overflow<T: Float>(negative: bool, rm: RoundingMode) -> T {
+    let inf = T::MAX + T::MAX;
+    let max = match rm {
+        RoundingMode::Rtz => true,
+        RoundingMode::Rdn => !negative,
+        RoundingMode::Rup => negative,
+        _ => false,
+    };
+    match (max, negative) {
+        (true, false) => T::MAX,
+        (true, true) => -T::MAX,
+        (false, false) => inf,
+        (false, true) => -inf,
+    }
+}
+
+/// Turns the nearest-even result of an operation into the architectural
+/// result: canonical NaNs, directed rounding and the accrued exception flags.
+fn finish<T: Float>(inputs: &[T], r: T, err: T, rm: RoundingMode, flags: &mut u8) -> T {
+    if inputs.iter().any(|x| x.is_snan()) {
+        *flags |= NV;
+    }
+    if r.is_nan() {
+        if !inputs.iter().any(|x| x.is_nan()) {
+            *flags |= NV;
+        }
+        return T::CANONICAL_NAN;
+    }
+    if r.is_infinite() {
+        if inputs.iter().all(|x| x.is_finite()) {
+            *flags |= OF | NX;
+            return overflow(r.is_sign_negative(), rm);
+        }
+        return r;
+    }
+    if err == T::ZERO || err.is_nan() {
+        return r;
+    }
+    *flags |= NX;
+    let rounded = round_with(r, err, rm);
+    if rounded.is_infinite() {
+        *flags |= OF;
+    } else if rounded.abs() < T::MIN_POSITIVE {
+        *flags |= UF;
+    }
+    rounded
+}
+
+pub fn add<T: Float>(a: T, b: T, rm: RoundingMode, flags: &mut u8) -> T {
+    let s = a + b;
+    let bb = s - a;
+    let err = (a - (s - bb)) + (b - bb);
+    let s = if s == T::ZERO && rm == RoundingMode::Rdn && a.is_sign_negative() != b.is_sign_negative() {
+        -T::ZERO // x + -x is -0 when rounding down
+    } else {
+        s
+    };
+    finish(&[a, b], s, err, rm, flags)
+}
+
+pub fn sub<T: Float>(a: T, b: T, rm: RoundingMode, flags: &mut u8) -> T {
+    add(a, -b, rm, flags)
+}
+
+pub fn mul<T: Float>(a: T, b: T, rm: RoundingMode, flags: &mut u8) -> T {
+    let p = a * b;
+    let err = a.mul_add(b, -p);
+    finish(&[a, b], p, err, rm, flags)
+}
+
+pub fn div<T: Float>(a: T, b: T, rm: RoundingMode, flags: &mut u8) -> T {
+    if b == T::ZERO && a.is_finite() && a != T::ZERO {
+        *flags |= DZ;
+        return a / b;
+    }
+    let q = a / b;
+    let err = (-q).mul_add(b, a) / b;
+    finish(&[a, b], q, err, rm, flags)
+}
+
+pub fn sqrt<T: Float>(a: T, rm: RoundingMode, flags: &mut u8) -> T {
+    let r = a.sqrt();
+    let err = (-r).mul_add(r, a) / (r + r);
+    finish(&[a], r, err, rm, flags)
+}
+
+/// Computes `(±a * b) ± c` with a single rounding.
+pub fn fma<T: Float>(a: T, b: T, c: T, negate_product: bool, negate_addend: bool, rm: RoundingMode, flags: &mut u8) -> T {
+    if (a.is_infinite() && b == T::ZERO) || (a == T::ZERO && b.is_infinite()) {
+        *flags |= NV;
+    }
+    let a = if negate_product { -a } else { a };
+    let c = if negate_addend { -c } else { c };
+    let r = a.mul_add(b, c);
+    let p = a * b;
+    let err = ((p - r) + c) + a.mul_add(b, -p);
+    finish(&[a, b, c], r, err, rm, flags)
+}
+
+pub fn min<T: Float>(a: T, b: T, flags: &mut u8) -> T {
+    min_max(a, b, flags, true)
+}
+
+pub fn max<T: Float>(a: T, b: T, flags: &mut u8) -> T {
+    min_max(a, b, flags, false)
+}
+
+fn min_max<T: Float>(a: T, b: T, flags: &mut u8, want_min: bool) -> T {
+    if a.is_snan() || b.is_snan() {
+        *flags |= NV;
+    }
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => T::CANONICAL_NAN,
+        (true, false) => b,
+        (false, true) => a,
+        // -0.0 is considered less than +0.0
+        _ if a == b => {
+            if a.is_sign_negative() == want_min { a } else { b }
+        }
+        _ if (a < b) == want_min => a,
+        _ => b,
+    }
+}
+
+pub fn eq<T: Float>(a: T, b: T, flags: &mut u8) -> bool {
+    if a.is_snan() || b.is_snan() {
+        *flags |= NV;
+    }
+    a == b
+}
+
+pub fn lt<T: Float>(a: T, b: T, flags: &mut u8) -> bool {
+    if a.is_nan() || b.is_nan() {
+        *flags |= NV;
+    }
+    a < b
+}
+
+pub fn le<T: Float>(a: T, b: T, flags: &mut u8) -> bool {
+    if a.is_nan() || b.is_nan() {
+        *flags |= NV;
+    }
+    a <= b
+}
+
+pub fn sign_inject<T: Float>(a: T, b: T, negate: bool, xor: bool) -> T {
+    let sign = match (negate, xor) {
+        (_, true) => (a.to_raw() ^ b.to_raw()) & T::SIGN_BIT,
+        (true, false) => !b.to_raw() & T::SIGN_BIT,
+        (false, false) => b.to_raw() & T::SIGN_BIT,
+    };
+    T::from_raw((a.to_raw() & !T::SIGN_BIT) | sign)
+}
+
+/// The 10-bit `fclass` mask.
+pub fn classify<T: Float>(x: T) -> u32 {
+    let negative = x.is_sign_negative();
+    let bit = if x.is_nan() {
+        if x.is_snan() { 8 } else { 9 }
+    } else if x.is_infinite() {
+        if negative { 0 } else { 7 }
+    } else if x == T::ZERO {
+        if negative { 3 } else { 4 }
+    } else if x.is_subnormal() {
+        if negative { 2 } else { 5 }
+    } else if negative {
+        1
+    } else {
+        6
+    };
+    1 << bit
+}
+
+fn round_to_integer(x: f64, rm: RoundingMode) -> f64 {
+    match rm {
+        RoundingMode::Rne | RoundingMode::Dyn => x.round_ties_even(),
+        RoundingMode::Rtz => x.trunc(),
+        RoundingMode::Rdn => x.floor(),
+        RoundingMode::Rup => x.ceil(),
+        RoundingMode::Rmm => x.round(),
+    }
+}
+
+/// `fcvt.w.*`: converts to a signed word, saturating out-of-range values.
+pub fn to_i32<T: Float>(x: T, rm: RoundingMode, flags: &mut u8) -> i32 {
+    if x.is_nan() {
+        *flags |= NV;
+        return i32::MAX;
+    }
+    let x = x.to_f64();
+    let r = round_to_integer(x, rm);
+    if r < i32::MIN as f64 {
+        *flags |= NV;
+        i32::MIN
+    } else if r > i32::MAX as f64 {
+        *flags |= NV;
+        i32::MAX
+    } else {
+        if r != x {
+            *flags |= NX;
+        }
+        r as i32
+    }
+}
+
+/// `fcvt.wu.*`: converts to an unsigned word, saturating out-of-range values.
+pub fn to_u32<T: Float>(x: T, rm: RoundingMode, flags: &mut u8) -> u32 {
+    if x.is_nan() {
+        *flags |= NV;
+        return u32::MAX;
+    }
+    let x = x.to_f64();
+    let r = round_to_integer(x, rm);
+    if r < 0.0 {
+        *flags |= NV;
+        0
+    } else if r > u32::MAX as f64 {
+        *flags |= NV;
+        u32::MAX
+    } else {
+        if r != x {
+            *flags |= NX;
+        }
+        r as u32
+    }
+}
+
+/// `fcvt.*.w` / `fcvt.*.wu`: converts an integer to floating point.
+pub fn from_int<T: Float>(v: i64, rm: RoundingMode, flags: &mut u8) -> T {
+    let exact = v as f64;
+    let r = T::from_f64(exact);
+    let err = T::from_f64(exact - r.to_f64());
+    finish(&[], r, err, rm, flags)
+}
+
+/// `fcvt.s.d`
+pub fn narrow(d: f64, rm: RoundingMode, flags: &mut u8) -> f32 {
+    if d.is_nan() {
+        if d.is_snan() {
+            *flags |= NV;
+        }
+        return f32::CANONICAL_NAN;
+    }
+    let r = d as f32;
+    if r.is_infinite() && d.is_finite() {
+        *flags |= OF | NX;
+        return overflow(d.is_sign_negative(), rm);
+    }
+    let err64 = d - r as f64;
+    let err = match err64 as f32 {
+        // keep the sign of errors too small for single precision
+        e if e == 0.0 && err64 != 0.0 => f32::from_bits(1).copysign(err64 as f32),
+        e => e,
+    };
+    finish(&[], r, err, rm, flags)
+}
+
+/// `fcvt.d.s`
+pub fn widen(s: f32, flags: &mut u8) -> f64 {
+    if s.is_nan() {
+        if s.is_snan() {
+            *flags |= NV;
+        }
+        return f64::CANONICAL_NAN;
+    }
+    s as f64
+}
@@ -1,4 +1,27 @@
-#[derive(Debug, Clone, Copy)]
+use crate::fpu::RoundingMode;
+use crate::isa::Extension;
+use crate::vector::Sew;
+
+/// An architectural register an instruction reads or writes. Vector
+/// operands name the first register of their group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Reg {
+    X(usize),
+    F(usize),
+    V(usize),
+}
+
+impl std::fmt::Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reg::X(r) => write!(f, "x{r}"),
+            Reg::F(r) => write!(f, "f{r}"),
+            Reg::V(r) => write!(f, "v{r}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     // R-Format
     Add { rd: usize, rs1: usize, rs2: usize },
@@ -57,11 +80,547 @@ pub enum Instruction {
     Lui { rd: usize, imm: i32 },
     Auipc { rd: usize, imm: i32 },
 
+    // RV64I/RV64M
+    Ld { rd: usize, rs1: usize, imm: i32 },
+    Lwu { rd: usize, rs1: usize, imm: i32 },
+    Sd { rs1: usize, rs2: usize, imm: i32 },
+    Addiw { rd: usize, rs1: usize, imm: i32 },
+    Slliw { rd: usize, rs1: usize, imm: i32 },
+    Srliw { rd: usize, rs1: usize, imm: i32 },
+    Sraiw { rd: usize, rs1: usize, imm: i32 },
+    Addw { rd: usize, rs1: usize, rs2: usize },
+    Subw { rd: usize, rs1: usize, rs2: usize },
+    Sllw { rd: usize, rs1: usize, rs2: usize },
+    Srlw { rd: usize, rs1: usize, rs2: usize },
+    Sraw { rd: usize, rs1: usize, rs2: usize },
+    Mulw { rd: usize, rs1: usize, rs2: usize },
+    Divw { rd: usize, rs1: usize, rs2: usize },
+    Divuw { rd: usize, rs1: usize, rs2: usize },
+    Remw { rd: usize, rs1: usize, rs2: usize },
+    Remuw { rd: usize, rs1: usize, rs2: usize },
+
+    // Zba
+    Sh1add { rd: usize, rs1: usize, rs2: usize },
+    Sh2add { rd: usize, rs1: usize, rs2: usize },
+    Sh3add { rd: usize, rs1: usize, rs2: usize },
+    AddUw { rd: usize, rs1: usize, rs2: usize },
+    Sh1addUw { rd: usize, rs1: usize, rs2: usize },
+    Sh2addUw { rd: usize, rs1: usize, rs2: usize },
+    Sh3addUw { rd: usize, rs1: usize, rs2: usize },
+    SlliUw { rd: usize, rs1: usize, imm: i32 },
+
+    // Zbb
+    Andn { rd: usize, rs1: usize, rs2: usize },
+    Orn { rd: usize, rs1: usize, rs2: usize },
+    Xnor { rd: usize, rs1: usize, rs2: usize },
+    Clz { rd: usize, rs1: usize },
+    Ctz { rd: usize, rs1: usize },
+    Cpop { rd: usize, rs1: usize },
+    Clzw { rd: usize, rs1: usize },
+    Ctzw { rd: usize, rs1: usize },
+    Cpopw { rd: usize, rs1: usize },
+    Max { rd: usize, rs1: usize, rs2: usize },
+    Maxu { rd: usize, rs1: usize, rs2: usize },
+    Min { rd: usize, rs1: usize, rs2: usize },
+    Minu { rd: usize, rs1: usize, rs2: usize },
+    SextB { rd: usize, rs1: usize },
+    SextH { rd: usize, rs1: usize },
+    ZextH { rd: usize, rs1: usize },
+    Rol { rd: usize, rs1: usize, rs2: usize },
+    Ror { rd: usize, rs1: usize, rs2: usize },
+    Rori { rd: usize, rs1: usize, imm: i32 },
+    Rolw { rd: usize, rs1: usize, rs2: usize },
+    Rorw { rd: usize, rs1: usize, rs2: usize },
+    Roriw { rd: usize, rs1: usize, imm: i32 },
+    OrcB { rd: usize, rs1: usize },
+    Rev8 { rd: usize, rs1: usize },
+
+    // Zbc
+    Clmul { rd: usize, rs1: usize, rs2: usize },
+    Clmulh { rd: usize, rs1: usize, rs2: usize },
+    Clmulr { rd: usize, rs1: usize, rs2: usize },
+
+    // Zbs
+    Bclr { rd: usize, rs1: usize, rs2: usize },
+    Bclri { rd: usize, rs1: usize, imm: i32 },
+    Bext { rd: usize, rs1: usize, rs2: usize },
+    Bexti { rd: usize, rs1: usize, imm: i32 },
+    Binv { rd: usize, rs1: usize, rs2: usize },
+    Binvi { rd: usize, rs1: usize, imm: i32 },
+    Bset { rd: usize, rs1: usize, rs2: usize },
+    Bseti { rd: usize, rs1: usize, imm: i32 },
+
+    // A-Extension
+    LrW { rd: usize, rs1: usize, aq: bool, rl: bool },
+    ScW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoswapW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoaddW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoxorW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoandW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoorW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmominW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmomaxW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmominuW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmomaxuW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+
+    // Zicsr
+    Csrrw { rd: usize, rs1: usize, csr: u16 },
+    Csrrs { rd: usize, rs1: usize, csr: u16 },
+    Csrrc { rd: usize, rs1: usize, csr: u16 },
+    Csrrwi { rd: usize, imm: i32, csr: u16 },
+    Csrrsi { rd: usize, imm: i32, csr: u16 },
+    Csrrci { rd: usize, imm: i32, csr: u16 },
+
+    // Memory ordering; `pred` and `succ` are IORW bit sets.
+    Fence { pred: u8, succ: u8 },
+
+    // Environment calls and privileged instructions
+    Ecall,
+    Ebreak,
+    Mret,
+    Sret,
+    Wfi,
+    SfenceVma { rs1: usize, rs2: usize },
+
+    // F/D-Extension (loads, stores and moves)
+    Flw { rd: usize, rs1: usize, imm: i32 },
+    Fsw { rs1: usize, rs2: usize, imm: i32 },
+    Fld { rd: usize, rs1: usize, imm: i32 },
+    Fsd { rs1: usize, rs2: usize, imm: i32 },
+    FmvXW { rd: usize, rs1: usize },
+    FmvWX { rd: usize, rs1: usize },
+    FcvtSD { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtDS { rd: usize, rs1: usize, rm: RoundingMode },
+
+    // F/D-Extension (single, then double precision)
+    FaddS { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FsubS { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FmulS { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FdivS { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FsqrtS { rd: usize, rs1: usize, rm: RoundingMode },
+    FminS { rd: usize, rs1: usize, rs2: usize },
+    FmaxS { rd: usize, rs1: usize, rs2: usize },
+    FmaddS { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FmsubS { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FnmsubS { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FnmaddS { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FsgnjS { rd: usize, rs1: usize, rs2: usize },
+    FsgnjnS { rd: usize, rs1: usize, rs2: usize },
+    FsgnjxS { rd: usize, rs1: usize, rs2: usize },
+    FeqS { rd: usize, rs1: usize, rs2: usize },
+    FltS { rd: usize, rs1: usize, rs2: usize },
+    FleS { rd: usize, rs1: usize, rs2: usize },
+    FclassS { rd: usize, rs1: usize },
+    FcvtWS { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtWuS { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtSW { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtSWu { rd: usize, rs1: usize, rm: RoundingMode },
+
+    FaddD { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FsubD { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FmulD { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FdivD { rd: usize, rs1: usize, rs2: usize, rm: RoundingMode },
+    FsqrtD { rd: usize, rs1: usize, rm: RoundingMode },
+    FminD { rd: usize, rs1: usize, rs2: usize },
+    FmaxD { rd: usize, rs1: usize, rs2: usize },
+    FmaddD { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FmsubD { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FnmsubD { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FnmaddD { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: RoundingMode },
+    FsgnjD { rd: usize, rs1: usize, rs2: usize },
+    FsgnjnD { rd: usize, rs1: usize, rs2: usize },
+    FsgnjxD { rd: usize, rs1: usize, rs2: usize },
+    FeqD { rd: usize, rs1: usize, rs2: usize },
+    FltD { rd: usize, rs1: usize, rs2: usize },
+    FleD { rd: usize, rs1: usize, rs2: usize },
+    FclassD { rd: usize, rs1: usize },
+    FcvtWD { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtWuD { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtDW { rd: usize, rs1: usize, rm: RoundingMode },
+    FcvtDWu { rd: usize, rs1: usize, rm: RoundingMode },
+
+    // V-Extension (configuration, loads and stores). `vm` is false when the
+    // instruction is masked by `v0`.
+    Vsetvli { rd: usize, rs1: usize, vtypei: u16 },
+    Vsetivli { rd: usize, uimm: u8, vtypei: u16 },
+    VleV { vd: usize, rs1: usize, eew: Sew, vm: bool },
+    VseV { vs3: usize, rs1: usize, eew: Sew, vm: bool },
+    VlseV { vd: usize, rs1: usize, rs2: usize, eew: Sew, vm: bool },
+    VsseV { vs3: usize, rs1: usize, rs2: usize, eew: Sew, vm: bool },
+
+    // V-Extension (integer arithmetic and moves)
+    VaddVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VaddVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VaddVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VsubVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VsubVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VmulVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VmulVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VandVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VandVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VandVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VorVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VorVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VorVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VxorVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VxorVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VxorVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VmvVv { vd: usize, vs1: usize },
+    VmvVx { vd: usize, rs1: usize },
+    VmvVi { vd: usize, imm: i32 },
+    VmvXS { rd: usize, vs2: usize },
+    VmvSX { vd: usize, rs1: usize },
+
+    // V-Extension (reductions)
+    VredsumVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VredandVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VredorVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VredxorVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VredminVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VredmaxVs { vd: usize, vs2: usize, vs1: usize, vm: bool },
+
+    // V-Extension (compares and mask operations)
+    VmseqVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VmseqVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VmseqVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VmsneVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VmsneVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VmsneVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VmsltVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VmsltVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VmsleVv { vd: usize, vs2: usize, vs1: usize, vm: bool },
+    VmsleVx { vd: usize, vs2: usize, rs1: usize, vm: bool },
+    VmsleVi { vd: usize, vs2: usize, imm: i32, vm: bool },
+    VmandMm { vd: usize, vs2: usize, vs1: usize },
+    VmandnMm { vd: usize, vs2: usize, vs1: usize },
+    VmorMm { vd: usize, vs2: usize, vs1: usize },
+    VmxorMm { vd: usize, vs2: usize, vs1: usize },
+    VcpopM { rd: usize, vs2: usize, vm: bool },
+    VfirstM { rd: usize, vs2: usize, vm: bool },
+
     // Debug
     Print { rs: usize },
 }
 
 impl Instruction {
+    /// Whether the instruction only exists on RV64.
+    pub fn is_rv64_only(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Ld { .. }
+                | Instruction::Lwu { .. }
+                | Instruction::Sd { .. }
+                | Instruction::Addiw { .. }
+                | Instruction::Slliw { .. }
+                | Instruction::Srliw { .. }
+                | Instruction::Sraiw { .. }
+                | Instruction::Addw { .. }
+                | Instruction::Subw { .. }
+                | Instruction::Sllw { .. }
+                | Instruction::Srlw { .. }
+                | Instruction::Sraw { .. }
+                | Instruction::Mulw { .. }
+                | Instruction::Divw { .. }
+                | Instruction::Divuw { .. }
+                | Instruction::Remw { .. }
+                | Instruction::Remuw { .. }
+                | Instruction::AddUw { .. }
+                | Instruction::Sh1addUw { .. }
+                | Instruction::Sh2addUw { .. }
+                | Instruction::Sh3addUw { .. }
+                | Instruction::SlliUw { .. }
+                | Instruction::Clzw { .. }
+                | Instruction::Ctzw { .. }
+                | Instruction::Cpopw { .. }
+                | Instruction::Rolw { .. }
+                | Instruction::Rorw { .. }
+                | Instruction::Roriw { .. }
+        )
+    }
+
+    /// The assembler mnemonic, from the variant name: `FcvtSWu` is
+    /// `fcvt.s.wu`.
+    pub fn mnemonic(&self) -> String {
+        let debug = format!("{self:?}");
+        let name = debug.split([' ', '{']).next().unwrap_or_default();
+        let mut mnemonic = String::new();
+        for (i, c) in name.chars().enumerate() {
+            if c.is_ascii_uppercase() && i > 0 {
+                mnemonic.push('.');
+            }
+            mnemonic.push(c.to_ascii_lowercase());
+        }
+        mnemonic
+    }
+
+    /// Whether the instruction is a conditional branch.
+    pub fn is_branch(&self) -> bool {
+        use Instruction::*;
+        matches!(self, Beq { .. } | Bne { .. } | Blt { .. } | Bge { .. } | Bltu { .. } | Bgeu { .. })
+    }
+
+    /// Whether the instruction is an unconditional jump.
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Instruction::Jal { .. } | Instruction::Jalr { .. })
+    }
+
+    /// Whether the instruction stores to memory; AMOs count as stores.
+    pub fn writes_memory(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Sb { .. }
+                | Instruction::Sh { .. }
+                | Instruction::Sw { .. }
+                | Instruction::Sd { .. }
+                | Instruction::Fsw { .. }
+                | Instruction::Fsd { .. }
+                | Instruction::VseV { .. }
+                | Instruction::VsseV { .. }
+                | Instruction::ScW { .. }
+                | Instruction::AmoswapW { .. }
+                | Instruction::AmoaddW { .. }
+                | Instruction::AmoxorW { .. }
+                | Instruction::AmoandW { .. }
+                | Instruction::AmoorW { .. }
+                | Instruction::AmominW { .. }
+                | Instruction::AmomaxW { .. }
+                | Instruction::AmominuW { .. }
+                | Instruction::AmomaxuW { .. }
+        )
+    }
+
+    /// Whether the instruction loads from memory into a register; AMOs and
+    /// `sc.w` count as loads since their result comes from memory.
+    pub fn reads_memory(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            Lb { .. } | Lh { .. } | Lw { .. } | Ld { .. } | Lbu { .. } | Lhu { .. } | Lwu { .. } | Flw { .. }
+                | Fld { .. } | VleV { .. } | VlseV { .. } | LrW { .. } | ScW { .. } | AmoswapW { .. }
+                | AmoaddW { .. } | AmoxorW { .. } | AmoandW { .. } | AmoorW { .. } | AmominW { .. }
+                | AmomaxW { .. } | AmominuW { .. } | AmomaxuW { .. }
+        )
+    }
+
+    /// The register the instruction writes, if any; writes to `x0` are
+    /// discarded and so not reported.
+    pub fn destination(&self) -> Option<Reg> {
+        use Instruction::*;
+        let reg = match *self {
+            Flw { rd, .. } | Fld { rd, .. } | FmvWX { rd, .. } | FcvtSD { rd, .. } | FcvtDS { rd, .. }
+            | FaddS { rd, .. } | FsubS { rd, .. } | FmulS { rd, .. } | FdivS { rd, .. } | FsqrtS { rd, .. }
+            | FminS { rd, .. } | FmaxS { rd, .. } | FmaddS { rd, .. } | FmsubS { rd, .. } | FnmsubS { rd, .. }
+            | FnmaddS { rd, .. } | FsgnjS { rd, .. } | FsgnjnS { rd, .. } | FsgnjxS { rd, .. } | FcvtSW { rd, .. }
+            | FcvtSWu { rd, .. } | FaddD { rd, .. } | FsubD { rd, .. } | FmulD { rd, .. } | FdivD { rd, .. }
+            | FsqrtD { rd, .. } | FminD { rd, .. } | FmaxD { rd, .. } | FmaddD { rd, .. } | FmsubD { rd, .. }
+            | FnmsubD { rd, .. } | FnmaddD { rd, .. } | FsgnjD { rd, .. } | FsgnjnD { rd, .. } | FsgnjxD { rd, .. }
+            | FcvtDW { rd, .. } | FcvtDWu { rd, .. } => Reg::F(rd),
+
+            VleV { vd, .. } | VlseV { vd, .. } | VaddVv { vd, .. } | VaddVx { vd, .. } | VaddVi { vd, .. }
+            | VsubVv { vd, .. } | VsubVx { vd, .. } | VmulVv { vd, .. } | VmulVx { vd, .. } | VandVv { vd, .. }
+            | VandVx { vd, .. } | VandVi { vd, .. } | VorVv { vd, .. } | VorVx { vd, .. } | VorVi { vd, .. }
+            | VxorVv { vd, .. } | VxorVx { vd, .. } | VxorVi { vd, .. } | VmvVv { vd, .. } | VmvVx { vd, .. }
+            | VmvVi { vd, .. } | VmvSX { vd, .. } | VredsumVs { vd, .. } | VredandVs { vd, .. }
+            | VredorVs { vd, .. } | VredxorVs { vd, .. } | VredminVs { vd, .. } | VredmaxVs { vd, .. }
+            | VmseqVv { vd, .. } | VmseqVx { vd, .. } | VmseqVi { vd, .. } | VmsneVv { vd, .. }
+            | VmsneVx { vd, .. } | VmsneVi { vd, .. } | VmsltVv { vd, .. } | VmsltVx { vd, .. }
+            | VmsleVv { vd, .. } | VmsleVx { vd, .. } | VmsleVi { vd, .. } | VmandMm { vd, .. }
+            | VmandnMm { vd, .. } | VmorMm { vd, .. } | VmxorMm { vd, .. } => Reg::V(vd),
+
+            Sw { .. } | Sb { .. } | Sh { .. } | Sd { .. } | Fsw { .. } | Fsd { .. } | VseV { .. } | VsseV { .. }
+            | Beq { .. } | Bne { .. } | Blt { .. } | Bltu { .. } | Bge { .. } | Bgeu { .. } | Fence { .. } | Ecall
+            | Ebreak | Mret | Sret | Wfi | SfenceVma { .. } | Print { .. } => return None,
+
+            Add { rd, .. } | Sub { rd, .. } | Mul { rd, .. } | Mulh { rd, .. } | Mulhsu { rd, .. } | Mulhu { rd, .. }
+            | Div { rd, .. } | Divu { rd, .. } | Rem { rd, .. } | Remu { rd, .. } | And { rd, .. } | Or { rd, .. }
+            | Xor { rd, .. } | Sll { rd, .. } | Srl { rd, .. } | Sra { rd, .. } | Slt { rd, .. } | Sltu { rd, .. }
+            | Addi { rd, .. } | Andi { rd, .. } | Ori { rd, .. } | Xori { rd, .. } | Slli { rd, .. }
+            | Srli { rd, .. } | Srai { rd, .. } | Slti { rd, .. } | Lw { rd, .. } | Jalr { rd, .. } | Lb { rd, .. }
+            | Lh { rd, .. } | Lbu { rd, .. } | Lhu { rd, .. } | Sltiu { rd, .. } | Jal { rd, .. } | Lui { rd, .. }
+            | Auipc { rd, .. } | Ld { rd, .. } | Lwu { rd, .. } | Addiw { rd, .. } | Slliw { rd, .. }
+            | Srliw { rd, .. } | Sraiw { rd, .. } | Addw { rd, .. } | Subw { rd, .. } | Sllw { rd, .. }
+            | Srlw { rd, .. } | Sraw { rd, .. } | Mulw { rd, .. } | Divw { rd, .. } | Divuw { rd, .. }
+            | Remw { rd, .. } | Remuw { rd, .. } | Sh1add { rd, .. } | Sh2add { rd, .. } | Sh3add { rd, .. }
+            | AddUw { rd, .. } | Sh1addUw { rd, .. } | Sh2addUw { rd, .. } | Sh3addUw { rd, .. }
+            | SlliUw { rd, .. } | Andn { rd, .. } | Orn { rd, .. } | Xnor { rd, .. } | Clz { rd, .. }
+            | Ctz { rd, .. } | Cpop { rd, .. } | Clzw { rd, .. } | Ctzw { rd, .. } | Cpopw { rd, .. }
+            | Max { rd, .. } | Maxu { rd, .. } | Min { rd, .. } | Minu { rd, .. } | SextB { rd, .. }
+            | SextH { rd, .. } | ZextH { rd, .. } | Rol { rd, .. } | Ror { rd, .. } | Rori { rd, .. }
+            | Rolw { rd, .. } | Rorw { rd, .. } | Roriw { rd, .. } | OrcB { rd, .. } | Rev8 { rd, .. }
+            | Clmul { rd, .. } | Clmulh { rd, .. } | Clmulr { rd, .. } | Bclr { rd, .. } | Bclri { rd, .. }
+            | Bext { rd, .. } | Bexti { rd, .. } | Binv { rd, .. } | Binvi { rd, .. } | Bset { rd, .. }
+            | Bseti { rd, .. } | LrW { rd, .. } | ScW { rd, .. } | AmoswapW { rd, .. } | AmoaddW { rd, .. }
+            | AmoxorW { rd, .. } | AmoandW { rd, .. } | AmoorW { rd, .. } | AmominW { rd, .. }
+            | AmomaxW { rd, .. } | AmominuW { rd, .. } | AmomaxuW { rd, .. } | Csrrw { rd, .. } | Csrrs { rd, .. }
+            | Csrrc { rd, .. } | Csrrwi { rd, .. } | Csrrsi { rd, .. } | Csrrci { rd, .. } | FmvXW { rd, .. }
+            | FeqS { rd, .. } | FltS { rd, .. } | FleS { rd, .. } | FclassS { rd, .. } | FcvtWS { rd, .. }
+            | FcvtWuS { rd, .. } | FeqD { rd, .. } | FltD { rd, .. } | FleD { rd, .. } | FclassD { rd, .. }
+            | FcvtWD { rd, .. } | FcvtWuD { rd, .. } | Vsetvli { rd, .. } | Vsetivli { rd, .. }
+            | VmvXS { rd, .. } | VcpopM { rd, .. } | VfirstM { rd, .. } => Reg::X(rd),
+        };
+        (reg != Reg::X(0)).then_some(reg)
+    }
+
+    /// The registers the instruction reads, except `x0`. Masked vector
+    /// instructions also read `v0`.
+    pub fn sources(&self) -> Vec<Reg> {
+        let mut regs = self.operands();
+        regs.retain(|r| *r != Reg::X(0));
+        regs.dedup();
+        regs
+    }
+
+    /// The source register fields as encoded, in order and including `x0`.
+    pub fn operands(&self) -> Vec<Reg> {
+        use Instruction::*;
+        use Reg::{F, V, X};
+        let mask = |vm: bool| if vm { vec![] } else { vec![V(0)] };
+        match *self {
+            Add { rs1, rs2, .. } | Sub { rs1, rs2, .. } | Mul { rs1, rs2, .. } | Mulh { rs1, rs2, .. }
+            | Mulhsu { rs1, rs2, .. } | Mulhu { rs1, rs2, .. } | Div { rs1, rs2, .. } | Divu { rs1, rs2, .. }
+            | Rem { rs1, rs2, .. } | Remu { rs1, rs2, .. } | And { rs1, rs2, .. } | Or { rs1, rs2, .. }
+            | Xor { rs1, rs2, .. } | Sll { rs1, rs2, .. } | Srl { rs1, rs2, .. } | Sra { rs1, rs2, .. }
+            | Slt { rs1, rs2, .. } | Sltu { rs1, rs2, .. } | Sw { rs1, rs2, .. } | Sb { rs1, rs2, .. }
+            | Sh { rs1, rs2, .. } | Sd { rs1, rs2, .. } | Beq { rs1, rs2, .. } | Bne { rs1, rs2, .. }
+            | Blt { rs1, rs2, .. } | Bltu { rs1, rs2, .. } | Bge { rs1, rs2, .. } | Bgeu { rs1, rs2, .. }
+            | Addw { rs1, rs2, .. } | Subw { rs1, rs2, .. } | Sllw { rs1, rs2, .. } | Srlw { rs1, rs2, .. }
+            | Sraw { rs1, rs2, .. } | Mulw { rs1, rs2, .. } | Divw { rs1, rs2, .. } | Divuw { rs1, rs2, .. }
+            | Remw { rs1, rs2, .. } | Remuw { rs1, rs2, .. } | Sh1add { rs1, rs2, .. } | Sh2add { rs1, rs2, .. }
+            | Sh3add { rs1, rs2, .. } | AddUw { rs1, rs2, .. } | Sh1addUw { rs1, rs2, .. }
+            | Sh2addUw { rs1, rs2, .. } | Sh3addUw { rs1, rs2, .. } | Andn { rs1, rs2, .. } | Orn { rs1, rs2, .. }
+            | Xnor { rs1, rs2, .. } | Max { rs1, rs2, .. } | Maxu { rs1, rs2, .. } | Min { rs1, rs2, .. }
+            | Minu { rs1, rs2, .. } | Rol { rs1, rs2, .. } | Ror { rs1, rs2, .. } | Rolw { rs1, rs2, .. }
+            | Rorw { rs1, rs2, .. } | Clmul { rs1, rs2, .. } | Clmulh { rs1, rs2, .. } | Clmulr { rs1, rs2, .. }
+            | Bclr { rs1, rs2, .. } | Bext { rs1, rs2, .. } | Binv { rs1, rs2, .. } | Bset { rs1, rs2, .. }
+            | ScW { rs1, rs2, .. } | AmoswapW { rs1, rs2, .. } | AmoaddW { rs1, rs2, .. } | AmoxorW { rs1, rs2, .. }
+            | AmoandW { rs1, rs2, .. } | AmoorW { rs1, rs2, .. } | AmominW { rs1, rs2, .. }
+            | AmomaxW { rs1, rs2, .. } | AmominuW { rs1, rs2, .. } | AmomaxuW { rs1, rs2, .. }
+            | SfenceVma { rs1, rs2 } | VlseV { rs1, rs2, .. } => vec![X(rs1), X(rs2)],
+
+            Addi { rs1, .. } | Andi { rs1, .. } | Ori { rs1, .. } | Xori { rs1, .. } | Slli { rs1, .. }
+            | Srli { rs1, .. } | Srai { rs1, .. } | Slti { rs1, .. } | Lw { rs1, .. } | Jalr { rs1, .. }
+            | Lb { rs1, .. } | Lh { rs1, .. } | Lbu { rs1, .. } | Lhu { rs1, .. } | Sltiu { rs1, .. }
+            | Ld { rs1, .. } | Lwu { rs1, .. } | Addiw { rs1, .. } | Slliw { rs1, .. } | Srliw { rs1, .. }
+            | Sraiw { rs1, .. } | SlliUw { rs1, .. } | Clz { rs1, .. } | Ctz { rs1, .. } | Cpop { rs1, .. }
+            | Clzw { rs1, .. } | Ctzw { rs1, .. } | Cpopw { rs1, .. } | SextB { rs1, .. } | SextH { rs1, .. }
+            | ZextH { rs1, .. } | Rori { rs1, .. } | Roriw { rs1, .. } | OrcB { rs1, .. } | Rev8 { rs1, .. }
+            | Bclri { rs1, .. } | Bexti { rs1, .. } | Binvi { rs1, .. } | Bseti { rs1, .. } | LrW { rs1, .. }
+            | Csrrw { rs1, .. } | Csrrs { rs1, .. } | Csrrc { rs1, .. } | Flw { rs1, .. } | Fld { rs1, .. }
+            | FmvWX { rs1, .. } | FcvtSW { rs1, .. } | FcvtSWu { rs1, .. } | FcvtDW { rs1, .. }
+            | FcvtDWu { rs1, .. } | Vsetvli { rs1, .. } | VmvVx { rs1, .. } | VmvSX { rs1, .. }
+            | Print { rs: rs1 } => vec![X(rs1)],
+
+            Jal { .. } | Lui { .. } | Auipc { .. } | Csrrwi { .. } | Csrrsi { .. } | Csrrci { .. } | Fence { .. }
+            | Ecall | Ebreak | Mret | Sret | Wfi | Vsetivli { .. } | VmvVi { .. } => vec![],
+
+            Fsw { rs1, rs2, .. } | Fsd { rs1, rs2, .. } => vec![X(rs1), F(rs2)],
+            FaddS { rs1, rs2, .. } | FsubS { rs1, rs2, .. } | FmulS { rs1, rs2, .. } | FdivS { rs1, rs2, .. }
+            | FminS { rs1, rs2, .. } | FmaxS { rs1, rs2, .. } | FsgnjS { rs1, rs2, .. } | FsgnjnS { rs1, rs2, .. }
+            | FsgnjxS { rs1, rs2, .. } | FeqS { rs1, rs2, .. } | FltS { rs1, rs2, .. } | FleS { rs1, rs2, .. }
+            | FaddD { rs1, rs2, .. } | FsubD { rs1, rs2, .. } | FmulD { rs1, rs2, .. } | FdivD { rs1, rs2, .. }
+            | FminD { rs1, rs2, .. } | FmaxD { rs1, rs2, .. } | FsgnjD { rs1, rs2, .. } | FsgnjnD { rs1, rs2, .. }
+            | FsgnjxD { rs1, rs2, .. } | FeqD { rs1, rs2, .. } | FltD { rs1, rs2, .. } | FleD { rs1, rs2, .. } => {
+                vec![F(rs1), F(rs2)]
+            }
+            FsqrtS { rs1, .. } | FsqrtD { rs1, .. } | FcvtSD { rs1, .. } | FcvtDS { rs1, .. } | FmvXW { rs1, .. }
+            | FclassS { rs1, .. } | FclassD { rs1, .. } | FcvtWS { rs1, .. } | FcvtWuS { rs1, .. }
+            | FcvtWD { rs1, .. } | FcvtWuD { rs1, .. } => vec![F(rs1)],
+            FmaddS { rs1, rs2, rs3, .. } | FmsubS { rs1, rs2, rs3, .. } | FnmsubS { rs1, rs2, rs3, .. }
+            | FnmaddS { rs1, rs2, rs3, .. } | FmaddD { rs1, rs2, rs3, .. } | FmsubD { rs1, rs2, rs3, .. }
+            | FnmsubD { rs1, rs2, rs3, .. } | FnmaddD { rs1, rs2, rs3, .. } => vec![F(rs1), F(rs2), F(rs3)],
+
+            VleV { rs1, vm, .. } => [vec![X(rs1)], mask(vm)].concat(),
+            VseV { vs3, rs1, vm, .. } => [vec![X(rs1), V(vs3)], mask(vm)].concat(),
+            VsseV { vs3, rs1, rs2, vm, .. } => [vec![X(rs1), X(rs2), V(vs3)], mask(vm)].concat(),
+            VaddVv { vs2, vs1, vm, .. } | VsubVv { vs2, vs1, vm, .. } | VmulVv { vs2, vs1, vm, .. }
+            | VandVv { vs2, vs1, vm, .. } | VorVv { vs2, vs1, vm, .. } | VxorVv { vs2, vs1, vm, .. }
+            | VredsumVs { vs2, vs1, vm, .. } | VredandVs { vs2, vs1, vm, .. } | VredorVs { vs2, vs1, vm, .. }
+            | VredxorVs { vs2, vs1, vm, .. } | VredminVs { vs2, vs1, vm, .. } | VredmaxVs { vs2, vs1, vm, .. }
+            | VmseqVv { vs2, vs1, vm, .. } | VmsneVv { vs2, vs1, vm, .. } | VmsltVv { vs2, vs1, vm, .. }
+            | VmsleVv { vs2, vs1, vm, .. } => [vec![V(vs2), V(vs1)], mask(vm)].concat(),
+            VaddVx { vs2, rs1, vm, .. } | VsubVx { vs2, rs1, vm, .. } | VmulVx { vs2, rs1, vm, .. }
+            | VandVx { vs2, rs1, vm, .. } | VorVx { vs2, rs1, vm, .. } | VxorVx { vs2, rs1, vm, .. }
+            | VmseqVx { vs2, rs1, vm, .. } | VmsneVx { vs2, rs1, vm, .. } | VmsltVx { vs2, rs1, vm, .. }
+            | VmsleVx { vs2, rs1, vm, .. } => [vec![V(vs2), X(rs1)], mask(vm)].concat(),
+            VaddVi { vs2, vm, .. } | VandVi { vs2, vm, .. } | VorVi { vs2, vm, .. } | VxorVi { vs2, vm, .. }
+            | VmseqVi { vs2, vm, .. } | VmsneVi { vs2, vm, .. } | VmsleVi { vs2, vm, .. } | VcpopM { vs2, vm, .. }
+            | VfirstM { vs2, vm, .. } => [vec![V(vs2)], mask(vm)].concat(),
+            VmandMm { vs2, vs1, .. } | VmandnMm { vs2, vs1, .. } | VmorMm { vs2, vs1, .. }
+            | VmxorMm { vs2, vs1, .. } => vec![V(vs2), V(vs1)],
+            VmvVv { vs1, .. } => vec![V(vs1)],
+            VmvXS { vs2, .. } => vec![V(vs2)],
+        }
+    }
+
+    /// The extension that defines the instruction.
+    pub fn extension(&self) -> Extension {
+        use Instruction::*;
+        match self {
+            Mul { .. } | Mulh { .. } | Mulhsu { .. } | Mulhu { .. } | Div { .. } | Divu { .. } | Rem { .. }
+            | Remu { .. } | Mulw { .. } | Divw { .. } | Divuw { .. } | Remw { .. } | Remuw { .. } => Extension::M,
+
+            LrW { .. } | ScW { .. } | AmoswapW { .. } | AmoaddW { .. } | AmoxorW { .. } | AmoandW { .. }
+            | AmoorW { .. } | AmominW { .. } | AmomaxW { .. } | AmominuW { .. } | AmomaxuW { .. } => Extension::A,
+
+            Csrrw { .. } | Csrrs { .. } | Csrrc { .. } | Csrrwi { .. } | Csrrsi { .. } | Csrrci { .. } => {
+                Extension::Zicsr
+            }
+
+            Flw { .. } | Fsw { .. } | FmvXW { .. } | FmvWX { .. } | FaddS { .. } | FsubS { .. } | FmulS { .. }
+            | FdivS { .. } | FsqrtS { .. } | FminS { .. } | FmaxS { .. } | FmaddS { .. } | FmsubS { .. }
+            | FnmsubS { .. } | FnmaddS { .. } | FsgnjS { .. } | FsgnjnS { .. } | FsgnjxS { .. } | FeqS { .. }
+            | FltS { .. } | FleS { .. } | FclassS { .. } | FcvtWS { .. } | FcvtWuS { .. } | FcvtSW { .. }
+            | FcvtSWu { .. } => Extension::F,
+
+            Fld { .. } | Fsd { .. } | FcvtSD { .. } | FcvtDS { .. } | FaddD { .. } | FsubD { .. } | FmulD { .. }
+            | FdivD { .. } | FsqrtD { .. } | FminD { .. } | FmaxD { .. } | FmaddD { .. } | FmsubD { .. }
+            | FnmsubD { .. } | FnmaddD { .. } | FsgnjD { .. } | FsgnjnD { .. } | FsgnjxD { .. } | FeqD { .. }
+            | FltD { .. } | FleD { .. } | FclassD { .. } | FcvtWD { .. } | FcvtWuD { .. } | FcvtDW { .. }
+            | FcvtDWu { .. } => Extension::D,
+
+            Sh1add { .. } | Sh2add { .. } | Sh3add { .. } | AddUw { .. } | Sh1addUw { .. } | Sh2addUw { .. }
+            | Sh3addUw { .. } | SlliUw { .. } => Extension::Zba,
+
+            Andn { .. } | Orn { .. } | Xnor { .. } | Clz { .. } | Ctz { .. } | Cpop { .. } | Clzw { .. }
+            | Ctzw { .. } | Cpopw { .. } | Max { .. } | Maxu { .. } | Min { .. } | Minu { .. } | SextB { .. }
+            | SextH { .. } | ZextH { .. } | Rol { .. } | Ror { .. } | Rori { .. } | Rolw { .. } | Rorw { .. }
+            | Roriw { .. } | OrcB { .. } | Rev8 { .. } => Extension::Zbb,
+
+            Clmul { .. } | Clmulh { .. } | Clmulr { .. } => Extension::Zbc,
+
+            Bclr { .. } | Bclri { .. } | Bext { .. } | Bexti { .. } | Binv { .. } | Binvi { .. } | Bset { .. }
+            | Bseti { .. } => Extension::Zbs,
+
+            Vsetvli { .. } | Vsetivli { .. } | VleV { .. } | VseV { .. } | VlseV { .. } | VsseV { .. }
+            | VaddVv { .. } | VaddVx { .. } | VaddVi { .. } | VsubVv { .. } | VsubVx { .. } | VmulVv { .. }
+            | VmulVx { .. } | VandVv { .. } | VandVx { .. } | VandVi { .. } | VorVv { .. } | VorVx { .. }
+            | VorVi { .. } | VxorVv { .. } | VxorVx { .. } | VxorVi { .. } | VmvVv { .. } | VmvVx { .. }
+            | VmvVi { .. } | VmvXS { .. } | VmvSX { .. } | VredsumVs { .. } | VredandVs { .. } | VredorVs { .. }
+            | VredxorVs { .. } | VredminVs { .. } | VredmaxVs { .. } | VmseqVv { .. } | VmseqVx { .. }
+            | VmseqVi { .. } | VmsneVv { .. } | VmsneVx { .. } | VmsneVi { .. } | VmsltVv { .. } | VmsltVx { .. }
+            | VmsleVv { .. } | VmsleVx { .. } | VmsleVi { .. } | VmandMm { .. } | VmandnMm { .. } | VmorMm { .. }
+            | VmxorMm { .. } | VcpopM { .. } | VfirstM { .. } => Extension::V,
+
+            Print { .. } => Extension::Xprint,
+
+            _ => Extension::I,
+        }
+    }
+
+    /// The label-relative offset of a branch or `jal`, in instructions.
+    pub fn label_offset(&self) -> Option<i32> {
+        match self {
+            Instruction::Beq { offset, .. }
+            | Instruction::Bne { offset, .. }
+            | Instruction::Blt { offset, .. }
+            | Instruction::Bltu { offset, .. }
+            | Instruction::Bge { offset, .. }
+            | Instruction::Bgeu { offset, .. }
+            | Instruction::Jal { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
     pub fn patch_label(&mut self, label_offset: i32) {
         match self {
             Instruction::Beq { offset, .. }
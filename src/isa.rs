@@ -0,0 +1,252 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use crate::instruction::Instruction;
+use crate::xlen;
+
+/// An ISA extension an instruction can belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    I,
+    M,
+    A,
+    F,
+    D,
+    C,
+    V,
+    Zicsr,
+    Zba,
+    Zbb,
+    Zbc,
+    Zbs,
+    /// The simulator's `print` debug instruction.
+    Xprint,
+}
+
+impl Extension {
+    pub const ALL: [Extension; 13] = [
+        Extension::I,
+        Extension::M,
+        Extension::A,
+        Extension::F,
+        Extension::D,
+        Extension::C,
+        Extension::V,
+        Extension::Zicsr,
+        Extension::Zba,
+        Extension::Zbb,
+        Extension::Zbc,
+        Extension::Zbs,
+        Extension::Xprint,
+    ];
+
+    /// The lower-case name used in ISA strings.
+    pub fn name(self) -> &'static str {
+        match self {
+            Extension::I => "i",
+            Extension::M => "m",
+            Extension::A => "a",
+            Extension::F => "f",
+            Extension::D => "d",
+            Extension::C => "c",
+            Extension::V => "v",
+            Extension::Zicsr => "zicsr",
+            Extension::Zba => "zba",
+            Extension::Zbb => "zbb",
+            Extension::Zbc => "zbc",
+            Extension::Zbs => "zbs",
+            Extension::Xprint => "xprint",
+        }
+    }
+
+    /// The name as written in prose: `M`, `Zicsr`.
+    pub fn label(self) -> String {
+        let name = self.name();
+        name[..1].to_ascii_uppercase() + &name[1..]
+    }
+
+    fn single_letter(self) -> Option<char> {
+        let name = self.name();
+        (name.len() == 1).then(|| name.as_bytes()[0] as char)
+    }
+
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// A set of enabled extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extensions(u32);
+
+impl Extensions {
+    pub fn empty() -> Self {
+        Extensions(0)
+    }
+
+    pub fn all() -> Self {
+        Extension::ALL.iter().fold(Extensions(0), |set, ext| set.with(*ext))
+    }
+
+    /// Just the base integer ISA.
+    pub fn base() -> Self {
+        Extensions(Extension::I.bit())
+    }
+
+    pub fn contains(self, ext: Extension) -> bool {
+        self.0 & ext.bit() != 0
+    }
+
+    pub fn with(self, ext: Extension) -> Self {
+        Extensions(self.0 | ext.bit())
+    }
+
+    pub fn without(self, ext: Extension) -> Self {
+        Extensions(self.0 & !ext.bit())
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Extension> {
+        Extension::ALL.into_iter().filter(move |ext| self.contains(*ext))
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IsaError {
+    #[error("ISA string must start with rv32 or rv64: {0}")]
+    BadPrefix(String),
+    #[error("ISA string must name the base ISA (i or g) first: {0}")]
+    MissingBase(String),
+    #[error("unknown extension `{0}`")]
+    UnknownExtension(String),
+    #[error("requires the {} extension, which {isa} does not enable", ext.label())]
+    Disabled { ext: Extension, isa: Isa },
+    #[error("only available on RV64, not {0}")]
+    Rv64Only(Isa),
+    #[error("has no 16-bit form on {0}")]
+    NoCompressedForm(Isa),
+}
+
+/// A register width and the extensions enabled on top of the base ISA,
+/// as named by an ISA string such as `rv32im_zicsr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Isa {
+    pub xlen: u32,
+    pub extensions: Extensions,
+}
+
+impl Isa {
+    pub fn has(&self, ext: Extension) -> bool {
+        self.extensions.contains(ext)
+    }
+
+    /// Whether `inst` may be assembled and executed under this ISA.
+    pub fn check(&self, inst: &Instruction) -> Result<(), IsaError> {
+        if inst.is_rv64_only() && self.xlen != 64 {
+            return Err(IsaError::Rv64Only(*self));
+        }
+        let ext = inst.extension();
+        if !self.has(ext) {
+            return Err(IsaError::Disabled { ext, isa: *self });
+        }
+        Ok(())
+    }
+
+    /// The `misa` CSR: MXL in the top two bits, one bit per letter extension,
+    /// `B` when Zba, Zbb and Zbs are all present, `S` and `U` for the
+    /// privilege modes, and `X` for non-standard extensions.
+    pub fn misa(&self) -> u64 {
+        let mxl: u64 = if self.xlen == 64 { 2 } else { 1 };
+        let mut misa = mxl << (self.xlen - 2);
+        for letter in self.extensions.iter().filter_map(Extension::single_letter) {
+            misa |= 1 << (letter as u8 - b'a');
+        }
+        if [Extension::Zba, Extension::Zbb, Extension::Zbs].iter().all(|ext| self.has(*ext)) {
+            misa |= 1 << 1;
+        }
+        // S- and U-mode are always implemented.
+        misa |= (1 << (b's' - b'a')) | (1 << (b'u' - b'a'));
+        if self.has(Extension::Xprint) {
+            misa |= 1 << (b'x' - b'a');
+        }
+        misa
+    }
+}
+
+/// Every extension the simulator implements, on RV32.
+impl Default for Isa {
+    fn default() -> Self {
+        Isa { xlen: 32, extensions: Extensions::all() }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rv{}", self.xlen)?;
+        for ext in self.extensions.iter().filter(|ext| ext.single_letter().is_some()) {
+            write!(f, "{}", ext.name())?;
+        }
+        for ext in self.extensions.iter().filter(|ext| ext.single_letter().is_none()) {
+            write!(f, "_{}", ext.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Isa {
+    type Err = IsaError;
+
+    /// Accepts single-letter extensions after the base (`g` and `b` expand to
+    /// their components) followed by `_`-separated multi-letter ones.
+    fn from_str(s: &str) -> Result<Self, IsaError> {
+        let lower = s.trim().to_ascii_lowercase();
+        let xlen = xlen::bits_of(&lower).ok_or_else(|| IsaError::BadPrefix(s.to_string()))?;
+        let rest = &lower[4..];
+        if !rest.starts_with(['i', 'g']) {
+            return Err(IsaError::MissingBase(s.to_string()));
+        }
+
+        // A multi-letter name may follow the letters without an underscore.
+        let split = rest.find(['_', 'z', 'x']).unwrap_or(rest.len());
+        let (letters, names) = rest.split_at(split);
+
+        let mut extensions = Extensions::empty();
+        for letter in letters.chars() {
+            let expansion: &[Extension] = match letter {
+                'g' => &[Extension::I, Extension::M, Extension::A, Extension::F, Extension::D, Extension::Zicsr],
+                'b' => &[Extension::Zba, Extension::Zbb, Extension::Zbs],
+                _ => &[],
+            };
+            if !expansion.is_empty() {
+                extensions = expansion.iter().fold(extensions, |set, ext| set.with(*ext));
+                continue;
+            }
+            let ext = Extension::ALL
+                .into_iter()
+                .find(|ext| ext.single_letter() == Some(letter))
+                .ok_or_else(|| IsaError::UnknownExtension(letter.to_string()))?;
+            extensions = extensions.with(ext);
+        }
+        for name in names.split('_').filter(|name| !name.is_empty()) {
+            let ext = Extension::ALL
+                .into_iter()
+                .find(|ext| ext.single_letter().is_none() && ext.name() == name)
+                .ok_or_else(|| IsaError::UnknownExtension(name.to_string()))?;
+            extensions = extensions.with(ext);
+        }
+
+        // D builds on F; F needs Zicsr for fcsr, and V for vl and vtype.
+        if extensions.contains(Extension::D) {
+            extensions = extensions.with(Extension::F);
+        }
+        if extensions.contains(Extension::F) || extensions.contains(Extension::V) {
+            extensions = extensions.with(Extension::Zicsr);
+        }
+        Ok(Isa { xlen, extensions })
+    }
+}
@@ -1,5 +1,29 @@
+pub mod bus;
+pub mod cache;
+pub mod clint;
 pub mod cpu;
+pub mod datapath;
+pub mod csr;
+pub mod encoding;
+pub mod engine;
+pub mod fpu;
 pub mod instruction;
+pub mod isa;
+pub mod litmus;
+pub mod machine;
 pub mod memory;
+pub mod mmu;
+pub mod multicycle;
+pub mod pipeline;
+pub mod plic;
+pub mod predictor;
+pub mod privilege;
+pub mod stats;
+pub mod timing;
+pub mod tomasulo;
+pub mod trace;
+pub mod uart;
 pub mod utils;
+pub mod vector;
+pub mod xlen;
 pub mod asm_parser;
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+use crate::asm_parser::parse_instruction;
+use crate::bus::Bus;
+use crate::instruction::Instruction;
+use crate::machine::{HartError, Machine};
+
+/// Shared locations are consecutive words from here.
+const LOCATION_BASE: u64 = 0x100;
+const RAM_SIZE: usize = 0x1000;
+/// Interleavings longer than this are assumed not to terminate.
+const MAX_STEPS: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum LitmusError {
+    #[error("line {0}: {1}")]
+    Syntax(usize, String),
+    #[error("an interleaving ran for {MAX_STEPS} steps without finishing")]
+    TooLong,
+    #[error(transparent)]
+    Hart(#[from] HartError),
+}
+
+/// A value a litmus test can inspect in the final state.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Observable {
+    Reg { hart: usize, reg: usize },
+    Location(String),
+}
+
+impl fmt::Display for Observable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Observable::Reg { hart, reg } => write!(f, "{hart}:x{reg}"),
+            Observable::Location(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A small multi-hart program in a herd-like format:
+///
+/// ```text
+/// RISCV SB
+/// { x=0; y=0; 0:x10=x; 0:x11=y; 1:x10=y; 1:x11=x }
+/// P0:
+///     addi x5, x0, 1
+///     sw x5, 0(x10)
+///     lw x6, 0(x11)
+/// P1:
+///     ...
+/// exists (0:x6=0 /\ 1:x6=0)
+/// ```
+///
+/// The initial state names the shared locations, with their values, and
+/// presets registers to numbers or location addresses. `#` starts a comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Litmus {
+    pub name: String,
+    pub locations: Vec<(String, i32)>,
+    /// `(hart, register, value)`
+    pub registers: Vec<(usize, usize, i64)>,
+    pub programs: Vec<Vec<Instruction>>,
+    pub exists: Vec<(Observable, i64)>,
+}
+
+impl Litmus {
+    pub fn parse(source: &str) -> Result<Self, LitmusError> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(n, line)| (n + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let syntax = |n: usize, msg: &str| LitmusError::Syntax(n, msg.to_string());
+
+        let (n, header) = lines.next().ok_or(syntax(1, "empty litmus test"))?;
+        let name = header.strip_prefix("RISCV ").ok_or(syntax(n, "expected `RISCV <name>`"))?.trim().to_string();
+
+        let (mut n, first) = lines.next().ok_or(syntax(n, "expected the initial state"))?;
+        let mut init = first.strip_prefix('{').ok_or(syntax(n, "expected `{`"))?.to_string();
+        while !init.contains('}') {
+            let (next, line) = lines.next().ok_or(syntax(n, "unterminated initial state"))?;
+            n = next;
+            init.push(';');
+            init.push_str(line);
+        }
+        let init = init.trim_end().strip_suffix('}').ok_or(syntax(n, "unexpected text after `}`"))?;
+
+        let mut test = Litmus { name, locations: vec![], registers: vec![], programs: vec![], exists: vec![] };
+        let mut presets = vec![];
+        for item in init.split(';').map(str::trim).filter(|item| !item.is_empty()) {
+            let (lhs, rhs) = item.split_once('=').ok_or(syntax(n, "expected `<name>=<value>`"))?;
+            match parse_observable(lhs.trim()) {
+                Some(Observable::Reg { hart, reg }) => presets.push((hart, reg, rhs.trim())),
+                _ => {
+                    let value = parse_value(rhs.trim()).ok_or(syntax(n, "location values must be numbers"))?;
+                    test.locations.push((lhs.trim().to_string(), value as i32));
+                }
+            }
+        }
+        for (hart, reg, value) in presets {
+            let value = match test.locations.iter().position(|(name, _)| name == value) {
+                Some(i) => (LOCATION_BASE + 4 * i as u64) as i64,
+                None => parse_value(value).ok_or(syntax(n, "unknown location in register preset"))?,
+            };
+            test.registers.push((hart, reg, value));
+        }
+
+        for (n, line) in lines {
+            if let Some(cond) = line.strip_prefix("exists") {
+                let cond = cond.trim().trim_start_matches('(').trim_end_matches(')');
+                for term in cond.split("/\\") {
+                    let (lhs, rhs) = term.split_once('=').ok_or(syntax(n, "expected `<observable>=<value>`"))?;
+                    let observable = parse_observable(lhs.trim()).ok_or(syntax(n, "bad observable"))?;
+                    if let Observable::Location(name) = &observable
+                        && !test.locations.iter().any(|(loc, _)| loc == name)
+                    {
+                        return Err(syntax(n, "unknown location in condition"));
+                    }
+                    let value = parse_value(rhs.trim()).ok_or(syntax(n, "bad value"))?;
+                    test.exists.push((observable, value));
+                }
+            } else if let Some(hart) = line.strip_prefix('P').and_then(|l| l.strip_suffix(':')) {
+                if hart.parse::<usize>().ok() != Some(test.programs.len()) {
+                    return Err(syntax(n, "harts must be numbered P0, P1, ... in order"));
+                }
+                test.programs.push(vec![]);
+            } else {
+                let program = test.programs.last_mut().ok_or(syntax(n, "instruction outside a hart"))?;
+                program.push(parse_instruction(line).ok_or(syntax(n, "bad instruction"))?);
+            }
+        }
+        if test.programs.is_empty() {
+            return Err(syntax(n, "no harts"));
+        }
+        Ok(test)
+    }
+
+    /// The values reported for every final state: all locations, then the
+    /// registers the condition mentions.
+    pub fn observed(&self) -> Vec<Observable> {
+        let mut observed = self.locations.iter().map(|(name, _)| Observable::Location(name.clone())).collect::<Vec<_>>();
+        let mut regs = self.exists.iter().map(|(o, _)| o.clone()).filter(|o| !observed.contains(o)).collect::<Vec<_>>();
+        regs.sort();
+        regs.dedup();
+        observed.extend(regs);
+        observed
+    }
+
+    fn machine(&self) -> Machine {
+        let mut machine: Machine = Machine::new(Bus::new(RAM_SIZE), self.programs.len());
+        for (i, (_, value)) in self.locations.iter().enumerate() {
+            machine.bus_mut().write_word(LOCATION_BASE + 4 * i as u64, *value).expect("location in RAM");
+        }
+        for (hart, program) in self.programs.iter().enumerate() {
+            machine.with_hart(hart, |cpu| {
+                cpu.load_instructions(program.clone());
+                cpu.pc = 0;
+            });
+        }
+        for &(hart, reg, value) in &self.registers {
+            machine.with_hart(hart, |cpu| cpu.regs[reg] = value as i32);
+        }
+        machine
+    }
+
+    fn value(&self, machine: &Machine, observable: &Observable) -> i64 {
+        match observable {
+            Observable::Reg { hart, reg } => machine.hart(*hart).regs[*reg] as i64,
+            Observable::Location(name) => {
+                let i = self.locations.iter().position(|(loc, _)| loc == name).expect("known location");
+                machine.bus().ram().read_word(LOCATION_BASE + 4 * i as u64).expect("location in RAM") as i64
+            }
+        }
+    }
+
+    /// Runs every interleaving of the harts' instructions, which is exactly
+    /// the set of sequentially consistent executions, and collects the
+    /// final states. Each interleaving is replayed from the initial state.
+    pub fn explore(&self) -> Result<Outcomes, LitmusError> {
+        let observed = self.observed();
+        let mut states = BTreeMap::new();
+        let mut interleavings = 0;
+        let mut pending = vec![vec![]];
+        while let Some(order) = pending.pop() {
+            let mut machine = self.machine();
+            for &hart in &order {
+                machine.step_hart(hart)?;
+            }
+            let running = (0..self.programs.len())
+                .filter(|&h| machine.hart(h).pc < machine.hart(h).program().len())
+                .collect::<Vec<_>>();
+            if running.is_empty() {
+                let state = observed.iter().map(|o| self.value(&machine, o)).collect::<Vec<_>>();
+                *states.entry(state).or_insert(0) += 1;
+                interleavings += 1;
+            } else if order.len() >= MAX_STEPS {
+                return Err(LitmusError::TooLong);
+            } else {
+                for &hart in running.iter().rev() {
+                    let mut next = order.clone();
+                    next.push(hart);
+                    pending.push(next);
+                }
+            }
+        }
+        Ok(Outcomes { name: self.name.clone(), observed, states, interleavings, exists: self.exists.clone() })
+    }
+}
+
+fn parse_observable(s: &str) -> Option<Observable> {
+    match s.split_once(':') {
+        Some((hart, reg)) => Some(Observable::Reg {
+            hart: hart.trim().parse().ok()?,
+            reg: reg.trim().strip_prefix('x')?.parse().ok().filter(|&r| r < 32)?,
+        }),
+        None if s.chars().all(|c| c.is_alphanumeric() || c == '_') && !s.is_empty() => {
+            Some(Observable::Location(s.to_string()))
+        }
+        None => None,
+    }
+}
+
+fn parse_value(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// The final states a litmus test can reach under sequential consistency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcomes {
+    pub name: String,
+    pub observed: Vec<Observable>,
+    /// Each reachable state, as values of `observed`, with the number of
+    /// interleavings that end in it.
+    pub states: BTreeMap<Vec<i64>, usize>,
+    pub interleavings: usize,
+    pub exists: Vec<(Observable, i64)>,
+}
+
+impl Outcomes {
+    /// Whether some final state satisfies the test's condition.
+    pub fn reachable(&self) -> bool {
+        self.states.keys().any(|state| {
+            self.exists.iter().all(|(observable, value)| {
+                let i = self.observed.iter().position(|o| o == observable).expect("observed");
+                state[i] == *value
+            })
+        })
+    }
+
+    pub fn contains(&self, state: &[(Observable, i64)]) -> bool {
+        self.states.keys().any(|values| {
+            state.iter().all(|(observable, value)| {
+                self.observed.iter().position(|o| o == observable).is_some_and(|i| values[i] == *value)
+            })
+        })
+    }
+}
+
+impl fmt::Display for Outcomes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Test {} under sequential consistency", self.name)?;
+        writeln!(f, "States {}", self.states.len())?;
+        for (state, count) in &self.states {
+            for (observable, value) in self.observed.iter().zip(state) {
+                write!(f, "{observable}={value}; ")?;
+            }
+            writeln!(f, "({count} of {} interleavings)", self.interleavings)?;
+        }
+        if !self.exists.is_empty() {
+            let cond = self.exists.iter().map(|(o, v)| format!("{o}={v}")).collect::<Vec<_>>().join(" /\\ ");
+            let verdict = if self.reachable() { "reachable" } else { "never" };
+            write!(f, "exists ({cond}): {verdict}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::mem;
+use thiserror::Error;
+use crate::asm_parser::Program;
+use crate::bus::Bus;
+use crate::cpu::{Cpu, CpuError};
+use crate::instruction::Instruction;
+use crate::xlen::{Rv32, Xlen};
+
+/// How [`Machine::step`] picks the hart that runs next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// One instruction per running hart, in hart order.
+    RoundRobin,
+    /// A running hart drawn from a generator seeded with the given value,
+    /// so the same seed replays the same interleaving.
+    Random(u64),
+}
+
+#[derive(Debug, Error)]
+#[error("hart {hart}: {error}")]
+pub struct HartError {
+    pub hart: usize,
+    #[source]
+    pub error: CpuError,
+}
+
+/// Several harts sharing one bus. Each hart has its own registers, CSRs,
+/// TLB and copy of the program; `mhartid` tells them apart. Harts start
+/// identical, with `sp` at the end of RAM, so programs carve out per-hart
+/// stacks from `mhartid`.
+pub struct Machine<X: Xlen = Rv32> {
+    bus: Bus,
+    harts: Vec<Cpu<X>>,
+    schedule: Schedule,
+    /// Round-robin position, or the state of the random generator.
+    cursor: u64,
+}
+
+impl<X: Xlen> Machine<X> {
+    pub fn new(bus: Bus, harts: usize) -> Self {
+        let ram_end = bus.ram_end();
+        let harts = (0..harts.max(1))
+            .map(|id| {
+                // The hart's own bus is only a stand-in; see `with_hart`.
+                let mut cpu = Cpu::with_bus(Bus::with_ram(bus.ram_base(), 0));
+                cpu.set_hartid(id);
+                cpu.regs[2] = X::from_i64(ram_end as i64);
+                cpu
+            })
+            .collect();
+        Machine { bus, harts, schedule: Schedule::RoundRobin, cursor: 0 }
+    }
+
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+        self.cursor = match schedule {
+            Schedule::RoundRobin => 0,
+            Schedule::Random(seed) => seed.max(1),
+        };
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn len(&self) -> usize {
+        self.harts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.harts.is_empty()
+    }
+
+    /// A hart's architectural state. Its memory is reached through
+    /// [`Machine::bus`] or [`Machine::with_hart`], not [`Cpu::memory`].
+    pub fn hart(&self, hart: usize) -> &Cpu<X> {
+        &self.harts[hart]
+    }
+
+    pub fn harts(&self) -> &[Cpu<X>] {
+        &self.harts
+    }
+
+    /// Runs `f` on a hart with the shared bus attached.
+    pub fn with_hart<R>(&mut self, hart: usize, f: impl FnOnce(&mut Cpu<X>) -> R) -> R {
+        let cpu = &mut self.harts[hart];
+        mem::swap(&mut self.bus, cpu.bus_mut());
+        let result = f(cpu);
+        mem::swap(&mut self.bus, cpu.bus_mut());
+        result
+    }
+
+    /// Loads the same program into every hart.
+    pub fn load_program(&mut self, program: Program) {
+        for cpu in &mut self.harts {
+            cpu.load_program(program.clone());
+        }
+    }
+
+    pub fn load_instructions(&mut self, program: Vec<Instruction>) {
+        for cpu in &mut self.harts {
+            cpu.load_instructions(program.clone());
+            cpu.pc = 0;
+        }
+    }
+
+    fn running(&self, hart: usize) -> bool {
+        self.harts[hart].pc < self.harts[hart].program().len()
+    }
+
+    /// Executes one instruction on `hart`. Its stores break the other
+    /// harts' reservations, so `sc.w` fails after a racing write.
+    pub fn step_hart(&mut self, hart: usize) -> Result<bool, HartError> {
+        let stepped = self.with_hart(hart, Cpu::execute_next).map_err(|error| HartError { hart, error })?;
+        let stores = self.harts[hart].last_stores().to_vec();
+        for other in self.harts.iter_mut().filter(|cpu| cpu.hartid() != hart) {
+            for &addr in &stores {
+                other.snoop_store(addr);
+            }
+        }
+        Ok(stepped)
+    }
+
+    /// Executes one instruction on the hart the schedule picks, returning
+    /// which one ran, or `None` once every hart has run off its program.
+    pub fn step(&mut self) -> Result<Option<usize>, HartError> {
+        let running = (0..self.harts.len()).filter(|&h| self.running(h)).collect::<Vec<_>>();
+        if running.is_empty() {
+            return Ok(None);
+        }
+        let hart = match self.schedule {
+            Schedule::RoundRobin => {
+                let start = self.cursor as usize;
+                let hart = *running.iter().find(|&&h| h >= start).unwrap_or(&running[0]);
+                self.cursor = hart as u64 + 1;
+                hart
+            }
+            Schedule::Random(_) => {
+                // xorshift64
+                self.cursor ^= self.cursor << 13;
+                self.cursor ^= self.cursor >> 7;
+                self.cursor ^= self.cursor << 17;
+                running[(self.cursor % running.len() as u64) as usize]
+            }
+        };
+        self.step_hart(hart)?;
+        Ok(Some(hart))
+    }
+
+    /// Steps until every hart has finished, or `limit` steps have run.
+    /// Returns the order the harts ran in.
+    pub fn run(&mut self, limit: usize) -> Result<Vec<usize>, HartError> {
+        let mut order = Vec::new();
+        while order.len() < limit {
+            match self.step()? {
+                Some(hart) => order.push(hart),
+                None => break,
+            }
+        }
+        Ok(order)
+    }
+}
@@ -1,6 +1,25 @@
+use std::fs::{self, File};
 use std::io::{self, Write};
-use riscviz::asm_parser::{load_asm, parse_instruction};
+use riscviz::asm_parser::{load_asm_for, parse_instruction};
+use riscviz::bus::Bus;
+use riscviz::cache::{CacheConfig, Caches};
+use riscviz::clint::{self, Clint};
 use riscviz::cpu::Cpu;
+use riscviz::datapath::Fetched;
+use riscviz::engine::{Engine, EngineKind};
+use riscviz::isa::{Extension, Isa};
+use riscviz::litmus::Litmus;
+use riscviz::machine::{Machine, Schedule};
+use riscviz::mmu::Access;
+use riscviz::multicycle::MultiCycle;
+use riscviz::pipeline::{Pipeline, PipelineConfig};
+use riscviz::plic::{self, Plic};
+use riscviz::predictor::{BranchPredictor, PredictorKind};
+use riscviz::stats::ExecStats;
+use riscviz::timing::{Latencies, TimingModel};
+use riscviz::tomasulo::{RobEntry, Tomasulo, TomasuloConfig};
+use riscviz::uart::{self, Uart};
+use riscviz::xlen::{Rv32, Rv64, Xlen};
 use tabled::{Table, Tabled, settings::Style};
 
 #[derive(Tabled)]
@@ -8,51 +27,837 @@ struct RegRow {
     #[tabled(rename = "Reg")]
     reg1: String,
     #[tabled(rename = "Val")]
-    val1: i32,
+    val1: i64,
     #[tabled(rename = "Reg")]
     reg2: String,
     #[tabled(rename = "Val")]
-    val2: i32,
+    val2: i64,
     #[tabled(rename = "Reg")]
     reg3: String,
     #[tabled(rename = "Val")]
-    val3: i32,
+    val3: i64,
     #[tabled(rename = "Reg")]
     reg4: String,
     #[tabled(rename = "Val")]
-    val4: i32,
+    val4: i64,
 }
 
-fn print_registers(cpu: &Cpu) {
+#[derive(Tabled)]
+struct FpRegRow {
+    #[tabled(rename = "Reg")]
+    reg1: String,
+    #[tabled(rename = "Val")]
+    val1: String,
+    #[tabled(rename = "Reg")]
+    reg2: String,
+    #[tabled(rename = "Val")]
+    val2: String,
+    #[tabled(rename = "Reg")]
+    reg3: String,
+    #[tabled(rename = "Val")]
+    val3: String,
+    #[tabled(rename = "Reg")]
+    reg4: String,
+    #[tabled(rename = "Val")]
+    val4: String,
+}
+
+#[derive(Tabled)]
+struct StationRow {
+    #[tabled(rename = "Station")]
+    name: String,
+    #[tabled(rename = "Busy")]
+    busy: &'static str,
+    #[tabled(rename = "Op")]
+    op: String,
+    #[tabled(rename = "V")]
+    ready: String,
+    #[tabled(rename = "Q")]
+    waiting: String,
+    #[tabled(rename = "Dest")]
+    dest: String,
+}
+
+#[derive(Tabled)]
+struct RobRow {
+    #[tabled(rename = "Entry")]
+    entry: String,
+    #[tabled(rename = "PC")]
+    pc: usize,
+    #[tabled(rename = "Instruction")]
+    inst: String,
+    #[tabled(rename = "State")]
+    state: String,
+    #[tabled(rename = "Dest")]
+    dest: String,
+    #[tabled(rename = "Value")]
+    value: String,
+}
+
+#[derive(Tabled)]
+struct RenameRow {
+    #[tabled(rename = "Reg")]
+    reg: String,
+    #[tabled(rename = "ROB")]
+    entry: String,
+}
+
+#[derive(Tabled)]
+struct TimingRow {
+    #[tabled(rename = "PC")]
+    pc: usize,
+    #[tabled(rename = "Instruction")]
+    inst: String,
+    #[tabled(rename = "Issue")]
+    issue: usize,
+    #[tabled(rename = "Execute")]
+    execute: String,
+    #[tabled(rename = "Write")]
+    write: String,
+    #[tabled(rename = "Commit")]
+    commit: String,
+}
+
+const FP_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7",
+    "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7",
+    "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+fn fp_name(r: usize) -> String {
+    format!("f{}/{}", r, FP_ABI_NAMES[r])
+}
+
+/// NaN-boxed registers are shown as singles, everything else as a double.
+fn fp_value(bits: u64) -> String {
+    if bits >> 32 == 0xFFFF_FFFF {
+        format!("{}f", f32::from_bits(bits as u32))
+    } else {
+        format!("{}", f64::from_bits(bits))
+    }
+}
+
+fn print_fp_registers<X: Xlen>(cpu: &Cpu<X>) {
+    let mut rows = Vec::new();
+
+    for row in 0..8 {
+        rows.push(FpRegRow {
+            reg1: fp_name(row),
+            val1: fp_value(cpu.fregs[row]),
+            reg2: fp_name(row + 8),
+            val2: fp_value(cpu.fregs[row + 8]),
+            reg3: fp_name(row + 16),
+            val3: fp_value(cpu.fregs[row + 16]),
+            reg4: fp_name(row + 24),
+            val4: fp_value(cpu.fregs[row + 24]),
+        });
+    }
+
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{table}");
+
+    let fcsr = cpu.fcsr();
+    let flags = [(0x10, "NV"), (0x08, "DZ"), (0x04, "OF"), (0x02, "UF"), (0x01, "NX")]
+        .iter()
+        .filter(|(bit, _)| fcsr & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    println!("fcsr: 0x{:02x} (frm={}, fflags=[{}])", fcsr, fcsr >> 5, flags.join(" "));
+}
+
+fn print_registers<X: Xlen>(cpu: &Cpu<X>) {
     let mut rows = Vec::new();
 
     for row in 0..8 {
         rows.push(RegRow {
             reg1: format!("x{}", row),
-            val1: cpu.regs[row],
+            val1: X::to_i64(cpu.regs[row]),
             reg2: format!("x{}", row + 8),
-            val2: cpu.regs[row + 8],
+            val2: X::to_i64(cpu.regs[row + 8]),
             reg3: format!("x{}", row + 16),
-            val3: cpu.regs[row + 16],
+            val3: X::to_i64(cpu.regs[row + 16]),
             reg4: format!("x{}", row + 24),
-            val4: cpu.regs[row + 24],
+            val4: X::to_i64(cpu.regs[row + 24]),
         });
     }
 
     let mut table = Table::new(rows);
     table.with(Style::rounded());
     println!("{table}");
+    println!("hart {} pc: {} ({:?} mode)", cpu.hartid(), cpu.pc, cpu.privilege());
+    let tlb = cpu.mmu().tlb().stats();
+    println!("tlb: {} hits, {} misses, {} flushes", tlb.hits, tlb.misses, tlb.flushes);
+    print_fp_registers(cpu);
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// `--ram=<size>[@<base>]`, with an optional `K`/`M` suffix on the size.
+fn parse_ram(spec: &str) -> Option<Bus> {
+    let (size, base) = match spec.split_once('@') {
+        Some((size, base)) => (size, parse_number(base)?),
+        None => (spec, 0),
+    };
+    let (digits, scale) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 1 << 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 1 << 20),
+        _ => (size, 1),
+    };
+    Some(Bus::with_ram(base, (parse_number(digits)? * scale) as usize))
+}
+
+/// `--uart[=<addr>]` maps a UART writing to stdout, or to `--uart-out=<file>`,
+/// and reading from `--uart-in=<file>`, or from stdin when `console` is set.
+/// The REPL owns stdin, so only the batch modes make the terminal a console.
+fn attach_uart(bus: &mut Bus, args: &[String], console: bool) -> Result<(), String> {
+    let Some(flag) = args.iter().find(|a| *a == "--uart" || a.starts_with("--uart=")) else {
+        return Ok(());
+    };
+    let base = match flag.strip_prefix("--uart=") {
+        Some(addr) => parse_number(addr).ok_or(format!("--uart: bad address {addr}"))?,
+        None => uart::VIRT_UART_BASE,
+    };
+    let output: Box<dyn Write> = match args.iter().find_map(|a| a.strip_prefix("--uart-out=")) {
+        Some(path) => Box::new(File::create(path).map_err(|e| format!("--uart-out: {path}: {e}"))?),
+        None => Box::new(io::stdout()),
+    };
+    let input = match args.iter().find_map(|a| a.strip_prefix("--uart-in=")) {
+        Some(path) => Some(uart::input_from(File::open(path).map_err(|e| format!("--uart-in: {path}: {e}"))?)),
+        None if console => Some(uart::input_from(io::stdin())),
+        None => None,
+    };
+    let uart = Uart::new(output, input);
+    bus.attach(base, Some(uart::VIRT_UART_IRQ), Box::new(uart)).map_err(|e| e.to_string())
+}
+
+/// `--icache=`, `--dcache=` and `--l2=` each take a cache configuration,
+/// `<size>:<line>:<ways>` plus `:`-separated policy options.
+fn caches(args: &[String]) -> Result<Caches, String> {
+    let config = |flag: &str| -> Result<Option<CacheConfig>, String> {
+        let prefix = format!("--{flag}=");
+        match args.iter().find_map(|a| a.strip_prefix(prefix.as_str())) {
+            Some(spec) => spec.parse().map(Some).map_err(|e| format!("--{flag}: {e}")),
+            None => Ok(None),
+        }
+    };
+    Ok(Caches::new(config("icache")?, config("dcache")?, config("l2")?))
+}
+
+/// `\\k`: the current hart's caches, the set of the last access marked.
+fn print_caches<X: Xlen>(cpu: &Cpu<X>) {
+    if cpu.caches().is_empty() {
+        println!("(no caches; start with --icache=, --dcache= or --l2=)");
+    } else {
+        println!("{}", cpu.caches());
+    }
+}
+
+/// `--predictor=<kind>[,<kind>...]` or `--predictor=all` scores branch
+/// predictors, each misprediction costing `--mispredict-penalty=<cycles>`.
+fn predictors(args: &[String]) -> Result<Vec<PredictorKind>, String> {
+    match args.iter().find_map(|a| a.strip_prefix("--predictor=")) {
+        Some("all") => Ok(PredictorKind::ALL.to_vec()),
+        Some(list) => list.split(',').map(|name| name.parse().map_err(|e| format!("--predictor: {e}"))).collect(),
+        None => Ok(vec![]),
+    }
+}
+
+fn mispredict_penalty(args: &[String]) -> Result<usize, String> {
+    match args.iter().find_map(|a| a.strip_prefix("--mispredict-penalty=")) {
+        Some(n) => parse_number(n).map(|n| n as usize).ok_or(format!("--mispredict-penalty: bad cycle count {n}")),
+        None => Ok(BranchPredictor::DEFAULT_PENALTY),
+    }
+}
+
+/// The caches and branch predictors every hart gets.
+struct HartConfig {
+    caches: Caches,
+    predictors: Vec<PredictorKind>,
+    penalty: usize,
+}
+
+impl HartConfig {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        Ok(HartConfig { caches: caches(args)?, predictors: predictors(args)?, penalty: mispredict_penalty(args)? })
+    }
+
+    fn apply<X: Xlen>(&self, cpu: &mut Cpu<X>) {
+        cpu.set_caches(self.caches.clone());
+        for kind in &self.predictors {
+            cpu.add_predictor(BranchPredictor::new(kind.build(BranchPredictor::DEFAULT_ENTRIES), self.penalty));
+        }
+    }
+}
+
+/// `\\b`: how each branch predictor has done on the current hart.
+fn print_predictors<X: Xlen>(cpu: &Cpu<X>) {
+    if cpu.predictors().is_empty() {
+        println!("(no branch predictors; start with --predictor=)");
+    }
+    for predictor in cpu.predictors() {
+        println!("{predictor}");
+    }
+}
+
+/// `--clint[=<ratio>]` maps a CLINT whose `mtime` advances once every
+/// `ratio` executed instructions (default 1).
+fn attach_clint(bus: &mut Bus, args: &[String], harts: usize) -> Result<(), String> {
+    let Some(flag) = args.iter().find(|a| *a == "--clint" || a.starts_with("--clint=")) else {
+        return Ok(());
+    };
+    let ratio = match flag.strip_prefix("--clint=") {
+        Some(ratio) => parse_number(ratio).filter(|&r| r > 0).ok_or(format!("--clint: bad tick ratio {ratio}"))?,
+        None => 1,
+    };
+    bus.attach(clint::VIRT_CLINT_BASE, None, Box::new(Clint::new(harts, ratio))).map_err(|e| e.to_string())
+}
+
+/// `--plic` maps a PLIC routing device interrupts, such as the UART's, to the
+/// harts' external interrupts.
+fn attach_plic(bus: &mut Bus, args: &[String], harts: usize) -> Result<(), String> {
+    if !args.iter().any(|a| a == "--plic") {
+        return Ok(());
+    }
+    bus.attach(plic::VIRT_PLIC_BASE, None, Box::new(Plic::new(harts))).map_err(|e| e.to_string())
+}
+
+/// `\t <vaddr> [r|w|x]`: walks the page table for `vaddr` step by step.
+fn explain_translation<X: Xlen>(cpu: &Cpu<X>, args: &str) {
+    let mut parts = args.split_whitespace();
+    let vaddr = parts.next().and_then(parse_number);
+    let access = match parts.next().unwrap_or("r") {
+        "r" => Some(Access::Load),
+        "w" => Some(Access::Store),
+        "x" => Some(Access::Fetch),
+        _ => None,
+    };
+    match (vaddr, access) {
+        (Some(vaddr), Some(access)) => println!("{}", cpu.explain_translation(vaddr, access)),
+        _ => eprintln!("[ERR] usage: \\t <vaddr> [r|w|x]"),
+    }
+}
+/// `--rob=<entries>` and `--alu=`, `--mul=`, `--div=` and `--mem=`, each
+/// `<stations>:<units>:<latency>`, configure the Tomasulo engine.
+fn tomasulo_config(args: &[String]) -> Result<TomasuloConfig, String> {
+    let mut config = TomasuloConfig::default();
+    if let Some(n) = args.iter().find_map(|a| a.strip_prefix("--rob=")) {
+        config.rob = parse_number(n).filter(|&n| n > 0).ok_or(format!("--rob: bad entry count {n}"))? as usize;
+    }
+    for (flag, unit) in [
+        ("alu", &mut config.alu),
+        ("mul", &mut config.mul),
+        ("div", &mut config.div),
+        ("mem", &mut config.mem),
+    ] {
+        let prefix = format!("--{flag}=");
+        if let Some(spec) = args.iter().find_map(|a| a.strip_prefix(prefix.as_str())) {
+            *unit = spec.parse().map_err(|e| format!("--{flag}: {e}"))?;
+        }
+    }
+    Ok(config)
+}
+
+fn print_table<T: Tabled>(rows: Vec<T>) {
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{table}");
+}
+
+/// `\o`: what the last Tomasulo cycle did, then the reservation
+/// stations, the reorder buffer and the register status.
+fn print_tomasulo(tomasulo: &Tomasulo) {
+    println!("cycle {}:", tomasulo.cycles());
+    for event in tomasulo.events() {
+        println!("  {event}");
+    }
+    let dest = |entry: &RobEntry| entry.dest.map_or("-".to_string(), |reg| reg.to_string());
+    print_table(
+        tomasulo
+            .stations()
+            .iter()
+            .map(|station| {
+                let list = |ready: bool| {
+                    let sources = station.entry.map_or(vec![], |entry| entry.sources.clone());
+                    let operands = sources
+                        .iter()
+                        .filter(|op| op.producer.is_none() == ready)
+                        .map(|op| op.to_string())
+                        .collect::<Vec<_>>();
+                    operands.join(", ")
+                };
+                StationRow {
+                    name: station.name(),
+                    busy: if station.entry.is_some() { "yes" } else { "no" },
+                    op: station.entry.map_or(String::new(), |entry| format!("{:?}", entry.inst)),
+                    ready: list(true),
+                    waiting: list(false),
+                    dest: station.entry.map_or(String::new(), |entry| format!("#{}", entry.id)),
+                }
+            })
+            .collect(),
+    );
+    print_table(
+        tomasulo
+            .rob()
+            .iter()
+            .map(|entry| RobRow {
+                entry: format!("#{}", entry.id),
+                pc: entry.pc,
+                inst: format!("{:?}", entry.inst),
+                state: entry.status.to_string(),
+                dest: dest(entry),
+                value: entry.value.map_or(String::new(), |value| value.to_string()),
+            })
+            .collect(),
+    );
+    print_table(
+        tomasulo
+            .register_status()
+            .iter()
+            .map(|(reg, id)| RenameRow { reg: reg.to_string(), entry: format!("#{id}") })
+            .collect(),
+    );
+    println!("{tomasulo}");
+}
+
+/// The cycle each committed instruction issued, executed, wrote and
+/// committed in.
+fn print_tomasulo_timing(tomasulo: &Tomasulo) {
+    let cycle = |cycle: Option<usize>| cycle.map_or(String::new(), |c| c.to_string());
+    print_table(
+        tomasulo
+            .committed()
+            .iter()
+            .chain(tomasulo.rob())
+            .map(|entry| TimingRow {
+                pc: entry.pc,
+                inst: format!("{:?}", entry.inst),
+                issue: entry.timing.issue,
+                execute: entry.timing.execute.map_or(String::new(), |(start, end)| {
+                    if start == end { start.to_string() } else { format!("{start}-{end}") }
+                }),
+                write: cycle(entry.timing.write),
+                commit: cycle(entry.timing.commit),
+            })
+            .collect(),
+    );
+    println!("{tomasulo}");
+}
+
+/// The single-cycle datapath view of the instruction `fetched` captured,
+/// now that `cpu` has executed it.
+fn print_datapath<X: Xlen>(fetched: Option<Fetched>, cpu: &Cpu<X>) {
+    match fetched {
+        Some(fetched) => println!("{}", fetched.complete(cpu)),
+        None => println!("(not on the single-cycle datapath)"),
+    }
+}
+
+/// What the last step on `cpu` did, once `\\e` has turned tracing on.
+fn print_effects<X: Xlen>(cpu: &Cpu<X>) {
+    if let Some(record) = cpu.last_record() {
+        println!("{record}");
+    }
+}
+
+/// `\\h`: one line per hart, marking the one commands apply to.
+fn print_harts<X: Xlen>(machine: &Machine<X>, current: usize) {
+    for cpu in machine.harts() {
+        let marker = if cpu.hartid() == current { '*' } else { ' ' };
+        println!("{marker} hart {}: pc {} ({:?} mode)", cpu.hartid(), cpu.pc, cpu.privilege());
+    }
+}
+
+/// `--litmus <file>`: prints every final state the test reaches over all
+/// interleavings of its harts.
+fn run_litmus(args: &[String]) {
+    let Some(path) = args.iter().skip(1).find(|a| !a.starts_with("--")) else {
+        eprintln!("[ERR] --litmus needs a test file");
+        return;
+    };
+    let outcomes = fs::read_to_string(path)
+        .map_err(|e| format!("{path}: {e}"))
+        .and_then(|source| Litmus::parse(&source).map_err(|e| format!("{path}: {e}")))
+        .and_then(|test| test.explore().map_err(|e| format!("{path}: {e}")));
+    match outcomes {
+        Ok(outcomes) => println!("{outcomes}"),
+        Err(e) => eprintln!("[ERR] {e}"),
+    }
+}
+
+/// `--stats[=<top>]` prints the execution report after a batch run,
+/// listing the `top` hottest instructions and labels.
+fn stats_top(args: &[String]) -> Result<Option<usize>, String> {
+    for arg in args {
+        if arg == "--stats" {
+            return Ok(Some(ExecStats::DEFAULT_TOP));
+        }
+        if let Some(n) = arg.strip_prefix("--stats=") {
+            return parse_number(n).map(|n| Some(n as usize)).ok_or(format!("--stats: bad count {n}"));
+        }
+    }
+    Ok(None)
+}
+
+/// The execution report, if `--stats` asked for one.
+fn print_stats<X: Xlen>(args: &[String], cpu: &Cpu<X>) {
+    match stats_top(args) {
+        Ok(Some(top)) => println!("{}", cpu.stats(top)),
+        Ok(None) => {}
+        Err(e) => eprintln!("[ERR] {e}"),
+    }
+}
+
+/// Engine runs longer than this many cycles are cut short.
+const ENGINE_CYCLE_LIMIT: usize = 10_000;
+
+/// A CPU set up as the REPL's would be, with the same devices, and the
+/// program file from `args` loaded, for the batch modes; `mode` names the
+/// flag in errors. The engines drive a single hart, so `--harts` is refused.
+fn batch_cpu<X: Xlen>(args: &[String], isa: &Isa, mode: &str) -> Option<Cpu<X>> {
+    let Some(path) = args.iter().skip(1).find(|a| !a.starts_with("--")) else {
+        eprintln!("[ERR] {mode} needs a program");
+        return None;
+    };
+    let setup = hart_count(args).and_then(|harts| match harts {
+        1 => Ok((vlen(args)?, HartConfig::from_args(args)?)),
+        _ => Err(format!("{mode} runs a single hart; --harts={harts} needs the REPL")),
+    });
+    let (vlen, config) = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return None;
+        }
+    };
+    let bus = device_bus(args, 1, true)?;
+    let program = match load_asm_for(path, isa) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return None;
+        }
+    };
+    let mut cpu = Cpu::<X>::with_bus(bus);
+    setup_hart(&mut cpu, isa, &config, vlen);
+    cpu.load_program(program);
+    Some(cpu)
+}
+
+/// Runs `engine` on `cpu`, reporting a cycle limit hit or an error.
+fn run_engine_to_end<X: Xlen>(engine: &mut dyn Engine<X>, cpu: &mut Cpu<X>) {
+    match engine.run(cpu, ENGINE_CYCLE_LIMIT) {
+        Ok(ENGINE_CYCLE_LIMIT) => eprintln!("[ERR] stopped after {ENGINE_CYCLE_LIMIT} cycles"),
+        Ok(_) => {}
+        Err(e) => eprintln!("[ERR] exec: {e}"),
+    }
+}
+
+/// `--pipeline <file>`: runs the program through the five-stage pipeline
+/// model and prints its diagram and hazards. `--no-forwarding` turns the
+/// bypass paths off.
+fn run_pipeline<X: Xlen>(args: &[String], isa: Isa) {
+    let Some(mut cpu) = batch_cpu::<X>(args, &isa, "--pipeline") else {
+        return;
+    };
+    let config = PipelineConfig { forwarding: !args.iter().any(|a| a == "--no-forwarding") };
+    let mut pipeline = Pipeline::new(config);
+    run_engine_to_end(&mut pipeline, &mut cpu);
+    println!("{pipeline}");
+    for event in pipeline.events() {
+        println!("{event}");
+    }
+    print_stats(args, &cpu);
+}
+
+/// `--tomasulo <file>`: runs the program through the Tomasulo model and
+/// prints when each instruction issued, executed, wrote and committed.
+fn run_tomasulo<X: Xlen>(args: &[String], isa: Isa) {
+    let config = match tomasulo_config(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return;
+        }
+    };
+    let Some(mut cpu) = batch_cpu::<X>(args, &isa, "--tomasulo") else {
+        return;
+    };
+    let mut tomasulo = Tomasulo::new(config);
+    run_engine_to_end(&mut tomasulo, &mut cpu);
+    print_tomasulo_timing(&tomasulo);
+    print_stats(args, &cpu);
+}
+
+/// `--latencies=<file>` reads per-class latencies from a latency file.
+fn latencies(args: &[String]) -> Result<Latencies, String> {
+    match args.iter().find_map(|a| a.strip_prefix("--latencies=")) {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("--latencies: {path}: {e}"))?;
+            text.parse().map_err(|e| format!("--latencies: {path}: {e}"))
+        }
+        None => Ok(Latencies::default()),
+    }
+}
+
+/// `--timing <file>`: runs the program charging each instruction its
+/// class's latency, and prints the cycles by class and by label.
+fn run_timing<X: Xlen>(args: &[String], isa: Isa) {
+    let latencies = match latencies(args) {
+        Ok(latencies) => latencies,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return;
+        }
+    };
+    let Some(mut cpu) = batch_cpu::<X>(args, &isa, "--timing") else {
+        return;
+    };
+    let mut timing = TimingModel::new(latencies);
+    run_engine_to_end(&mut timing, &mut cpu);
+    println!("{timing}");
+    if !cpu.caches().is_empty() {
+        println!("{}", cpu.caches());
+    }
+    print_stats(args, &cpu);
+}
+
+/// `--engine=<name> <file>`: runs the program to the end on the named
+/// engine and prints the registers, its cycle count, and any caches and
+/// branch predictors asked for.
+fn run_on_engine<X: Xlen>(args: &[String], isa: Isa, kind: EngineKind) {
+    let Some(mut cpu) = batch_cpu::<X>(args, &isa, "--engine") else {
+        return;
+    };
+    let mut engine = kind.build::<X>();
+    run_engine_to_end(engine.as_mut(), &mut cpu);
+    print_registers(&cpu);
+    println!(
+        "{}: {} cycles, {} instructions, CPI {:.2}",
+        engine.name(),
+        engine.cycles(),
+        engine.retired(),
+        engine.cpi()
+    );
+    if !cpu.caches().is_empty() {
+        println!("{}", cpu.caches());
+    }
+    // Only the functional engine is free of branch costs; the others already
+    // charge for their own flushes, so a penalty on top would count twice.
+    for predictor in cpu.predictors() {
+        println!("{predictor}");
+        if kind == EngineKind::Functional {
+            let cycles = predictor.adjusted_cycles(engine.retired());
+            println!("  {cycles} cycles at 1 per instruction plus misprediction penalties");
+        }
+    }
+    print_stats(args, &cpu);
+}
+
+/// The bus with the RAM that `--ram` asks for, or 1 KiB at 0.
+fn ram_bus(args: &[String]) -> Option<Bus> {
+    match args.iter().find_map(|a| a.strip_prefix("--ram=")) {
+        Some(spec) => {
+            let bus = parse_ram(spec);
+            if bus.is_none() {
+                eprintln!("[ERR] --ram: expected <size>[@<base>], got {spec}");
+            }
+            bus
+        }
+        None => Some(Bus::new(1024)),
+    }
 }
 
 fn main() {
-    let mut cpu = Cpu::default();
     let args = std::env::args().collect::<Vec<_>>();
+    if args.iter().any(|a| a == "--litmus") {
+        run_litmus(&args);
+        return;
+    }
+    let isa = match args.iter().find_map(|a| a.strip_prefix("--isa=")) {
+        Some(name) => match name.parse::<Isa>() {
+            Ok(isa) => isa,
+            Err(e) => {
+                eprintln!("[ERR] --isa: {e}");
+                return;
+            }
+        },
+        None => Isa::default(),
+    };
+    let engine = match args.iter().find_map(|a| a.strip_prefix("--engine=")) {
+        Some(name) => match name.parse::<EngineKind>() {
+            Ok(kind) => Some(kind),
+            Err(e) => {
+                eprintln!("[ERR] --engine: {e}");
+                return;
+            }
+        },
+        None => None,
+    };
+    match isa.xlen {
+        64 if args.iter().any(|a| a == "--pipeline") => run_pipeline::<Rv64>(&args, isa),
+        _ if args.iter().any(|a| a == "--pipeline") => run_pipeline::<Rv32>(&args, isa),
+        64 if args.iter().any(|a| a == "--tomasulo") => run_tomasulo::<Rv64>(&args, isa),
+        _ if args.iter().any(|a| a == "--tomasulo") => run_tomasulo::<Rv32>(&args, isa),
+        64 if args.iter().any(|a| a == "--timing") => run_timing::<Rv64>(&args, isa),
+        _ if args.iter().any(|a| a == "--timing") => run_timing::<Rv32>(&args, isa),
+        64 if let Some(kind) = engine => run_on_engine::<Rv64>(&args, isa, kind),
+        _ if let Some(kind) = engine => run_on_engine::<Rv32>(&args, isa, kind),
+        64 if args.iter().any(|a| a == "--run") => run_machine::<Rv64>(&args, isa),
+        _ if args.iter().any(|a| a == "--run") => run_machine::<Rv32>(&args, isa),
+        64 => repl::<Rv64>(&args, isa),
+        _ => repl::<Rv32>(&args, isa),
+    }
+}
+
+/// `--harts=<n>`: how many harts share the bus.
+fn hart_count(args: &[String]) -> Result<usize, String> {
+    match args.iter().find_map(|a| a.strip_prefix("--harts=")) {
+        Some(n) => parse_number(n).filter(|&n| n > 0).map(|n| n as usize).ok_or(format!("--harts: bad hart count {n}")),
+        None => Ok(1),
+    }
+}
+
+/// `--vlen=<bits>`: the vector register length, if not the default.
+fn vlen(args: &[String]) -> Result<Option<usize>, String> {
+    match args.iter().find_map(|a| a.strip_prefix("--vlen=")) {
+        Some(n) => match parse_number(n).filter(|&n| n >= 64 && n.is_power_of_two()) {
+            Some(n) => Ok(Some(n as usize)),
+            None => Err(format!("--vlen: expected a power of two of at least 64, got {n}")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The `--ram` bus with the devices `args` ask for, wired for `harts`;
+/// `console` feeds stdin to the UART.
+fn device_bus(args: &[String], harts: usize, console: bool) -> Option<Bus> {
+    let mut bus = ram_bus(args)?;
+    match attach_uart(&mut bus, args, console)
+        .and_then(|_| attach_clint(&mut bus, args, harts))
+        .and_then(|_| attach_plic(&mut bus, args, harts))
+    {
+        Ok(()) => Some(bus),
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            None
+        }
+    }
+}
+
+/// Configures a hart as the command line asks.
+fn setup_hart<X: Xlen>(cpu: &mut Cpu<X>, isa: &Isa, config: &HartConfig, vlen: Option<usize>) {
+    cpu.set_extensions(isa.extensions);
+    config.apply(cpu);
+    if let Some(vlen) = vlen {
+        cpu.set_vlen(vlen);
+    }
+}
+
+/// The harts, bus and devices `args` ask for, with the program file, if
+/// any, loaded on every hart; `console` feeds stdin to the UART.
+fn build_machine<X: Xlen>(args: &[String], isa: &Isa, console: bool) -> Option<Machine<X>> {
+    let (harts, vlen, config) = match hart_count(args)
+        .and_then(|harts| Ok((harts, vlen(args)?, HartConfig::from_args(args)?)))
+    {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return None;
+        }
+    };
+    let bus = device_bus(args, harts, console)?;
+    let mut machine = Machine::<X>::new(bus, harts);
+    for hart in 0..harts {
+        machine.with_hart(hart, |cpu| setup_hart(cpu, isa, &config, vlen));
+    }
+    if let Some(seed) = args.iter().find_map(|a| a.strip_prefix("--seed=")) {
+        match parse_number(seed) {
+            Some(seed) => machine.set_schedule(Schedule::Random(seed)),
+            None => {
+                eprintln!("[ERR] --seed: bad seed {seed}");
+                return None;
+            }
+        }
+    }
+
+    let compress = args.iter().any(|a| a == "--compress");
+    if let Some(path) = args.iter().skip(1).find(|a| !a.starts_with("--")) {
+        let mut program = match load_asm_for(path, isa) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("[ERR] {e}");
+                return None;
+            }
+        };
+        if compress && !isa.has(Extension::C) {
+            eprintln!("[ERR] --compress needs the C extension, which {isa} does not enable");
+        } else if compress {
+            println!("{}", program.compress(isa.xlen));
+        }
+        machine.load_program(program);
+    }
+    Some(machine)
+}
 
-    if args.len() == 2 {
-        let program = load_asm(&args[1]).unwrap();
-        cpu.load_program(program);
+/// `--run <file>`: runs the program to the end on every hart with the
+/// devices attached, as a `virt` machine would, with the terminal as the
+/// UART's console, then prints each hart's registers.
+fn run_machine<X: Xlen>(args: &[String], isa: Isa) {
+    if !args.iter().skip(1).any(|a| !a.starts_with("--")) {
+        eprintln!("[ERR] --run needs a program");
+        return;
+    }
+    let Some(mut machine) = build_machine::<X>(args, &isa, true) else {
+        return;
+    };
+    loop {
+        match machine.step() {
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("[ERR] exec: {e}");
+                break;
+            }
+        }
     }
+    for cpu in machine.harts() {
+        print_registers(cpu);
+    }
+    print_stats(args, machine.hart(0));
+}
+
+fn repl<X: Xlen>(args: &[String], isa: Isa) {
+    let tomasulo_config = match tomasulo_config(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[ERR] {e}");
+            return;
+        }
+    };
+    let Some(mut machine) = build_machine::<X>(args, &isa, false) else {
+        return;
+    };
+    let harts = machine.len();
 
+    // The hart that `\d`, `\i`, `\t` and typed instructions apply to.
+    let mut current = 0;
+    // Whether `\p` asked for the datapath after every step.
+    let mut show_datapath = false;
+    // Whether `\e` asked for each step's effects.
+    let mut show_effects = false;
+    // The multi-cycle FSM `\\c` clocks on the current hart; anything else
+    // that moves a hart on starts it afresh.
+    let mut multicycle = MultiCycle::new();
+    // Likewise the Tomasulo model `\\o` clocks.
+    let mut tomasulo = Tomasulo::new(tomasulo_config);
     loop {
         print!("🐚 > ");
         io::stdout().flush().ok();
@@ -77,16 +882,80 @@ fn main() {
         // Commands
         if input.starts_with('\\') {
             match input {
-                "\\d" => print_registers(&cpu),
-                "\\i" => cpu.print_instructions(),
+                "\\d" => print_registers(machine.hart(current)),
+                "\\i" => machine.hart(current).print_instructions(),
                 "\\f" => {
-                    match cpu.execute_next() {
-                        Ok(true) => println!("[OK]"),
-                        Ok(false) => break,
+                    multicycle = MultiCycle::new();
+                    tomasulo = Tomasulo::new(tomasulo_config);
+                    let mut fetched = (0..harts).map(|h| Fetched::new(machine.hart(h))).collect::<Vec<_>>();
+                    match machine.step() {
+                        Ok(Some(hart)) => {
+                            if harts > 1 {
+                                println!("[OK] hart {hart}");
+                            } else {
+                                println!("[OK]");
+                            }
+                            if show_datapath {
+                                print_datapath(fetched[hart].take(), machine.hart(hart));
+                            }
+                            if show_effects {
+                                print_effects(machine.hart(hart));
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(e) => eprintln!("[ERR] exec: {e}"),
+                    }
+                }
+                "\\c" => {
+                    tomasulo = Tomasulo::new(tomasulo_config);
+                    match machine.with_hart(current, |cpu| multicycle.step(cpu)) {
+                        Ok(true) => println!("{multicycle}"),
+                        Ok(false) => println!("[OK] nothing left to fetch"),
+                        Err(e) => eprintln!("[ERR] exec: {e}"),
+                    }
+                }
+                "\\o" => {
+                    multicycle = MultiCycle::new();
+                    match machine.with_hart(current, |cpu| tomasulo.step(cpu)) {
+                        Ok(true) => print_tomasulo(&tomasulo),
+                        Ok(false) => println!("[OK] nothing left to issue"),
                         Err(e) => eprintln!("[ERR] exec: {e}"),
                     }
                 }
+                "\\p" => {
+                    show_datapath = !show_datapath;
+                    println!("[OK] datapath view {}", if show_datapath { "on" } else { "off" });
+                }
+                "\\e" => {
+                    show_effects = !show_effects;
+                    for hart in 0..harts {
+                        machine.with_hart(hart, |cpu| cpu.set_tracing(show_effects));
+                    }
+                    println!("[OK] step effects {}", if show_effects { "on" } else { "off" });
+                }
+                "\\h" => print_harts(&machine, current),
+                "\\k" => print_caches(machine.hart(current)),
+                "\\b" => print_predictors(machine.hart(current)),
+                "\\s" => println!("{}", machine.hart(current).stats(ExecStats::DEFAULT_TOP)),
+                "\\v" => print!("{}", machine.hart(current).vector()),
+                "\\m" => machine.bus().regions().iter().for_each(|region| println!("{region}")),
                 "\\q" => break,
+                cmd if cmd.starts_with("\\h ") => match cmd[3..].trim().parse::<usize>() {
+                    Ok(hart) if hart < harts => {
+                        current = hart;
+                        multicycle = MultiCycle::new();
+                        tomasulo = Tomasulo::new(tomasulo_config);
+                    }
+                    _ => eprintln!("[ERR] usage: \\h <hart>, with {harts} hart(s)"),
+                },
+                cmd if cmd.starts_with("\\s ") => match cmd[3..].trim() {
+                    "reset" => machine.with_hart(current, |cpu| cpu.reset_counters()),
+                    n => match parse_number(n) {
+                        Some(top) => println!("{}", machine.hart(current).stats(top as usize)),
+                        None => eprintln!("[ERR] usage: \\s [<top>|reset]"),
+                    },
+                },
+                cmd if cmd.starts_with("\\t ") => machine.with_hart(current, |cpu| explain_translation(cpu, &cmd[3..])),
                 _ => eprintln!("[ERR] unknown command: {input}"),
             }
             continue;
@@ -96,16 +965,29 @@ fn main() {
             eprintln!("[ERR] parse error: {input}");
             continue;
         };
+        if let Err(e) = isa.check(&inst) {
+            eprintln!("[ERR] {input}: {e}");
+            continue;
+        }
 
-        cpu.add_instruction(inst);
+        machine.with_hart(current, |cpu| cpu.add_instruction(inst));
+        multicycle = MultiCycle::new();
+        tomasulo = Tomasulo::new(tomasulo_config);
 
-        match cpu.execute_next() {
+        let fetched = Fetched::new(machine.hart(current));
+        match machine.step_hart(current) {
             Ok(_) => {
                 println!("[OK] {input}");
+                if show_datapath {
+                    print_datapath(fetched, machine.hart(current));
+                }
+                if show_effects {
+                    print_effects(machine.hart(current));
+                }
             }
             Err(e) => {
                 eprintln!("[ERR] exec: {e}");
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -3,9 +3,9 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum MemoryError {
     #[error("Address out of bounds: 0x{0:08x}")]
-    OutOfBounds(u32),
+    OutOfBounds(u64),
     #[error("Misaligned access at 0x{0:08x}")]
-    MisalignedAccess(u32),
+    MisalignedAccess(u64),
 }
 
 pub struct Memory {
@@ -26,54 +26,47 @@ impl Memory {
         &self.data
     }
 
-    pub fn read_byte(&self, addr: u32) -> Result<i8, MemoryError> {
-        let addr = addr as usize;
-        if addr >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
+    /// Bounds-checks `len` bytes at `addr` and returns the start index.
+    fn index(&self, addr: u64, len: usize) -> Result<usize, MemoryError> {
+        match usize::try_from(addr) {
+            Ok(idx) if idx.checked_add(len).is_some_and(|end| end <= self.size()) => Ok(idx),
+            _ => Err(MemoryError::OutOfBounds(addr)),
         }
+    }
+
+    pub fn read_byte(&self, addr: u64) -> Result<i8, MemoryError> {
+        let addr = self.index(addr, 1)?;
         Ok(self.data[addr] as i8)
     }
 
-    pub fn write_byte(&mut self, addr: u32, val: u8) -> Result<(), MemoryError> {
-        let addr = addr as usize;
-        if addr >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
-        }
+    pub fn write_byte(&mut self, addr: u64, val: u8) -> Result<(), MemoryError> {
+        let addr = self.index(addr, 1)?;
         self.data[addr] = val;
         Ok(())
     }
-    pub fn read_halfword(&self, addr: u32) -> Result<i16, MemoryError> {
+    pub fn read_halfword(&self, addr: u64) -> Result<i16, MemoryError> {
         if addr & 1 != 0 {
             return Err(MemoryError::MisalignedAccess(addr));
         }
-        let addr = addr as usize;
-        if addr + 1 >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
-        }
+        let addr = self.index(addr, 2)?;
         Ok(i16::from_le_bytes([self.data[addr], self.data[addr + 1]]))
     }
 
-    pub fn write_halfword(&mut self, addr: u32, val: u16) -> Result<(), MemoryError> {
+    pub fn write_halfword(&mut self, addr: u64, val: u16) -> Result<(), MemoryError> {
         if addr & 1 != 0 {
             return Err(MemoryError::MisalignedAccess(addr));
         }
-        let addr = addr as usize;
-        if addr + 1 >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
-        }
+        let addr = self.index(addr, 2)?;
         let bytes = val.to_le_bytes();
         self.data[addr] = bytes[0];
         self.data[addr + 1] = bytes[1];
         Ok(())
     }
-    pub fn read_word(&self, addr: u32) -> Result<i32, MemoryError> {
+    pub fn read_word(&self, addr: u64) -> Result<i32, MemoryError> {
         if !addr.is_multiple_of(4) {
             return Err(MemoryError::MisalignedAccess(addr));
         }
-        let addr = addr as usize;
-        if addr + 3 >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
-        }
+        let addr = self.index(addr, 4)?;
         Ok(i32::from_le_bytes([
             self.data[addr],
             self.data[addr + 1],
@@ -81,14 +74,11 @@ impl Memory {
             self.data[addr + 3],
         ]))
     }
-    pub fn write_word(&mut self, addr: u32, val: i32) -> Result<(), MemoryError> {
+    pub fn write_word(&mut self, addr: u64, val: i32) -> Result<(), MemoryError> {
         if !addr.is_multiple_of(4) {
             return Err(MemoryError::MisalignedAccess(addr));
         }
-        let addr = addr as usize;
-        if addr + 3 >= self.size() {
-            return Err(MemoryError::OutOfBounds(addr as u32));
-        }
+        let addr = self.index(addr, 4)?;
         let bytes = val.to_le_bytes();
         self.data[addr] = bytes[0];
         self.data[addr + 1] = bytes[1];
@@ -96,4 +86,21 @@ impl Memory {
         self.data[addr + 3] = bytes[3];
         Ok(())
     }
+    pub fn read_doubleword(&self, addr: u64) -> Result<i64, MemoryError> {
+        if !addr.is_multiple_of(8) {
+            return Err(MemoryError::MisalignedAccess(addr));
+        }
+        let addr = self.index(addr, 8)?;
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.data[addr..addr + 8]);
+        Ok(i64::from_le_bytes(bytes))
+    }
+    pub fn write_doubleword(&mut self, addr: u64, val: i64) -> Result<(), MemoryError> {
+        if !addr.is_multiple_of(8) {
+            return Err(MemoryError::MisalignedAccess(addr));
+        }
+        let addr = self.index(addr, 8)?;
+        self.data[addr..addr + 8].copy_from_slice(&val.to_le_bytes());
+        Ok(())
+    }
 }
@@ -0,0 +1,396 @@
+//! Sv32 address translation for loads and stores.
+//!
+//! Instructions are fetched from the program vector by index rather than
+//! over the bus, so only data accesses are translated. `Access::Fetch` is
+//! still modelled for permission checks and [`Mmu::explain`].
+
+use std::collections::VecDeque;
+use std::fmt;
+use thiserror::Error;
+use crate::bus::Bus;
+use crate::privilege::{self, Privilege};
+
+// Page table entry bits
+pub const PTE_V: u32 = 1 << 0;
+pub const PTE_R: u32 = 1 << 1;
+pub const PTE_W: u32 = 1 << 2;
+pub const PTE_X: u32 = 1 << 3;
+pub const PTE_U: u32 = 1 << 4;
+pub const PTE_G: u32 = 1 << 5;
+pub const PTE_A: u32 = 1 << 6;
+pub const PTE_D: u32 = 1 << 7;
+
+/// `satp.MODE` selecting Sv32; clear means Bare (no translation).
+pub const SATP_SV32: u64 = 1 << 31;
+const SATP_ASID_SHIFT: u32 = 22;
+const SATP_PPN: u64 = (1 << 22) - 1;
+
+const PAGE_SHIFT: u32 = 12;
+const VPN_BITS: u32 = 10;
+const LEVELS: usize = 2;
+const DEFAULT_TLB_ENTRIES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Load,
+    Store,
+    Fetch,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Access::Load => "load",
+            Access::Store => "store",
+            Access::Fetch => "fetch",
+        })
+    }
+}
+
+/// Why a translation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Fault {
+    #[error("level {0} PTE is not valid")]
+    Invalid(usize),
+    #[error("level {0} PTE is writable but not readable")]
+    Reserved(usize),
+    #[error("level 0 PTE is not a leaf")]
+    NoLeaf,
+    #[error("superpage is not aligned to 4 MiB")]
+    MisalignedSuperpage,
+    #[error("page does not permit {0}")]
+    Permission(Access),
+    #[error("U-mode access to a supervisor page")]
+    SupervisorPage,
+    #[error("S-mode access to a user page")]
+    UserPage,
+    #[error("page table entry at 0x{0:08x} is outside memory")]
+    PteOutOfBounds(u64),
+}
+
+/// One page table entry read during a walk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkStep {
+    pub level: usize,
+    pub pte_addr: u64,
+    pub pte: u32,
+}
+
+/// A page table walk, kept step by step so it can be shown to students.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub vaddr: u64,
+    pub access: Access,
+    pub privilege: Privilege,
+    pub satp: u64,
+    /// Whether the TLB currently caches this page.
+    pub tlb_hit: bool,
+    pub steps: Vec<WalkStep>,
+    pub result: Result<u64, Fault>,
+}
+
+fn vpn(vaddr: u64, level: usize) -> u64 {
+    (vaddr >> (PAGE_SHIFT + VPN_BITS * level as u32)) & ((1 << VPN_BITS) - 1)
+}
+
+fn pte_ppn(pte: u32) -> u64 {
+    (pte >> 10) as u64
+}
+
+fn flags(pte: u32) -> String {
+    [(PTE_V, 'V'), (PTE_R, 'R'), (PTE_W, 'W'), (PTE_X, 'X'), (PTE_U, 'U'), (PTE_G, 'G'), (PTE_A, 'A'), (PTE_D, 'D')]
+        .iter()
+        .map(|(bit, c)| if pte & bit != 0 { *c } else { '-' })
+        .collect()
+}
+
+/// The physical address of `vaddr` within the page mapped by a leaf PTE
+/// found at `level`.
+fn physical(pte: u32, level: usize, vaddr: u64) -> u64 {
+    let offset_bits = PAGE_SHIFT + VPN_BITS * level as u32;
+    let offset = vaddr & ((1 << offset_bits) - 1);
+    ((pte_ppn(pte) << PAGE_SHIFT) & !((1 << offset_bits) - 1)) | offset
+}
+
+/// Checks a leaf PTE's permissions for an access at `privilege`.
+fn check_leaf(pte: u32, access: Access, privilege: Privilege, mstatus: u64) -> Result<(), Fault> {
+    let readable = pte & PTE_R != 0 || (mstatus & privilege::MXR != 0 && pte & PTE_X != 0);
+    let allowed = match access {
+        Access::Load => readable,
+        Access::Store => pte & PTE_W != 0,
+        Access::Fetch => pte & PTE_X != 0,
+    };
+    if !allowed {
+        return Err(Fault::Permission(access));
+    }
+    let user_page = pte & PTE_U != 0;
+    match privilege {
+        Privilege::User if !user_page => Err(Fault::SupervisorPage),
+        Privilege::Supervisor if user_page && (access == Access::Fetch || mstatus & privilege::SUM == 0) => {
+            Err(Fault::UserPage)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TlbStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub flushes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TlbEntry {
+    vpn: u64,
+    asid: u64,
+    level: usize,
+    pte: u32,
+}
+
+impl TlbEntry {
+    fn matches(&self, vaddr: u64, asid: u64) -> bool {
+        let shift = VPN_BITS * self.level as u32;
+        (vaddr >> PAGE_SHIFT) >> shift == self.vpn >> shift && (self.asid == asid || self.pte & PTE_G != 0)
+    }
+}
+
+/// A fully associative TLB with FIFO replacement. A capacity of zero
+/// disables caching, so every access walks the page table.
+#[derive(Debug, Clone)]
+pub struct Tlb {
+    capacity: usize,
+    entries: VecDeque<TlbEntry>,
+    stats: TlbStats,
+}
+
+impl Tlb {
+    pub fn new(capacity: usize) -> Self {
+        Tlb { capacity, entries: VecDeque::with_capacity(capacity), stats: TlbStats::default() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> TlbStats {
+        self.stats
+    }
+
+    fn lookup(&self, vaddr: u64, asid: u64) -> Option<usize> {
+        self.entries.iter().position(|e| e.matches(vaddr, asid))
+    }
+
+    fn insert(&mut self, entry: TlbEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Drops the entries `sfence.vma` selects: all of them, those mapping
+    /// `vaddr`, and/or the non-global ones of `asid`.
+    fn flush(&mut self, vaddr: Option<u64>, asid: Option<u64>) {
+        self.stats.flushes += 1;
+        self.entries.retain(|e| {
+            let page = vaddr.is_none_or(|va| e.matches(va, e.asid));
+            let space = asid.is_none_or(|asid| e.asid == asid && e.pte & PTE_G == 0);
+            !(page && space)
+        });
+    }
+}
+
+/// The `satp` CSR and the TLB in front of the page table walker.
+#[derive(Debug, Clone)]
+pub struct Mmu {
+    satp: u64,
+    tlb: Tlb,
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Mmu { satp: 0, tlb: Tlb::new(DEFAULT_TLB_ENTRIES) }
+    }
+}
+
+impl Mmu {
+    pub fn satp(&self) -> u64 {
+        self.satp
+    }
+
+    /// Changing `satp` does not flush the TLB; software issues `sfence.vma`.
+    pub fn set_satp(&mut self, satp: u64) {
+        self.satp = satp & 0xFFFF_FFFF;
+    }
+
+    pub fn tlb(&self) -> &Tlb {
+        &self.tlb
+    }
+
+    /// Replaces the TLB with an empty one of `capacity` entries.
+    pub fn set_tlb_capacity(&mut self, capacity: usize) {
+        self.tlb = Tlb::new(capacity);
+    }
+
+    fn asid(&self) -> u64 {
+        (self.satp >> SATP_ASID_SHIFT) & 0x1FF
+    }
+
+    /// Whether accesses at `privilege` are translated. M-mode never is.
+    pub fn enabled(&self, privilege: Privilege) -> bool {
+        self.satp & SATP_SV32 != 0 && privilege != Privilege::Machine
+    }
+
+    pub fn fence(&mut self, vaddr: Option<u64>, asid: Option<u64>) {
+        self.tlb.flush(vaddr, asid);
+    }
+
+    /// Translates `vaddr`, consulting and filling the TLB and setting the
+    /// leaf PTE's A (and, for stores, D) bits.
+    pub fn translate(
+        &mut self,
+        bus: &mut Bus,
+        vaddr: u64,
+        access: Access,
+        privilege: Privilege,
+        mstatus: u64,
+    ) -> Result<u64, Fault> {
+        if !self.enabled(privilege) {
+            return Ok(vaddr);
+        }
+        let asid = self.asid();
+        if let Some(idx) = self.tlb.lookup(vaddr, asid) {
+            let entry = self.tlb.entries[idx];
+            // A store through a clean entry has to set D in memory.
+            if access != Access::Store || entry.pte & PTE_D != 0 {
+                self.tlb.stats.hits += 1;
+                check_leaf(entry.pte, access, privilege, mstatus)?;
+                return Ok(physical(entry.pte, entry.level, vaddr));
+            }
+            self.tlb.entries.remove(idx);
+        }
+        self.tlb.stats.misses += 1;
+
+        let walk = self.walk(bus, vaddr, access, privilege, mstatus);
+        let paddr = walk.result?;
+        let leaf = walk.steps.last().copied().expect("a successful walk reads a leaf");
+        let mut pte = leaf.pte | PTE_A;
+        if access == Access::Store {
+            pte |= PTE_D;
+        }
+        if pte != leaf.pte {
+            bus
+                .write_word(leaf.pte_addr, pte as i32)
+                .map_err(|_| Fault::PteOutOfBounds(leaf.pte_addr))?;
+        }
+        self.tlb.insert(TlbEntry { vpn: vaddr >> PAGE_SHIFT, asid, level: leaf.level, pte });
+        Ok(paddr)
+    }
+
+    /// Walks the page table for `vaddr` without touching the TLB or memory.
+    /// Page tables have to live in RAM.
+    pub fn explain(&self, bus: &Bus, vaddr: u64, access: Access, privilege: Privilege, mstatus: u64) -> Translation {
+        let mut translation = self.walk(bus, vaddr, access, privilege, mstatus);
+        translation.tlb_hit = self.enabled(privilege) && self.tlb.lookup(vaddr, self.asid()).is_some();
+        translation
+    }
+
+    fn walk(&self, bus: &Bus, vaddr: u64, access: Access, privilege: Privilege, mstatus: u64) -> Translation {
+        let mut translation = Translation {
+            vaddr,
+            access,
+            privilege,
+            satp: self.satp,
+            tlb_hit: false,
+            steps: Vec::new(),
+            result: Ok(vaddr),
+        };
+        if !self.enabled(privilege) {
+            return translation;
+        }
+        translation.result = self.walk_steps(bus, vaddr, access, privilege, mstatus, &mut translation.steps);
+        translation
+    }
+
+    fn walk_steps(
+        &self,
+        bus: &Bus,
+        vaddr: u64,
+        access: Access,
+        privilege: Privilege,
+        mstatus: u64,
+        steps: &mut Vec<WalkStep>,
+    ) -> Result<u64, Fault> {
+        let mut table = (self.satp & SATP_PPN) << PAGE_SHIFT;
+        for level in (0..LEVELS).rev() {
+            let pte_addr = table + vpn(vaddr, level) * 4;
+            let pte = bus.peek_word(pte_addr).map_err(|_| Fault::PteOutOfBounds(pte_addr))? as u32;
+            steps.push(WalkStep { level, pte_addr, pte });
+
+            if pte & PTE_V == 0 {
+                return Err(Fault::Invalid(level));
+            }
+            if pte & PTE_R == 0 && pte & PTE_W != 0 {
+                return Err(Fault::Reserved(level));
+            }
+            if pte & (PTE_R | PTE_X) == 0 {
+                table = pte_ppn(pte) << PAGE_SHIFT;
+                continue;
+            }
+            check_leaf(pte, access, privilege, mstatus)?;
+            if level > 0 && pte_ppn(pte) & ((1 << (VPN_BITS * level as u32)) - 1) != 0 {
+                return Err(Fault::MisalignedSuperpage);
+            }
+            return Ok(physical(pte, level, vaddr));
+        }
+        Err(Fault::NoLeaf)
+    }
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} of 0x{:08x} in {:?} mode: vpn[1]=0x{:03x} vpn[0]=0x{:03x} offset=0x{:03x}",
+            self.access,
+            self.vaddr,
+            self.privilege,
+            vpn(self.vaddr, 1),
+            vpn(self.vaddr, 0),
+            self.vaddr & ((1 << PAGE_SHIFT) - 1),
+        )?;
+        if self.satp & SATP_SV32 == 0 || self.privilege == Privilege::Machine {
+            return write!(f, "translation off: physical address 0x{:08x}", self.vaddr);
+        }
+        writeln!(
+            f,
+            "satp: Sv32, asid={}, root table at 0x{:08x}{}",
+            (self.satp >> SATP_ASID_SHIFT) & 0x1FF,
+            (self.satp & SATP_PPN) << PAGE_SHIFT,
+            if self.tlb_hit { " (TLB hit)" } else { "" },
+        )?;
+        for step in &self.steps {
+            write!(f, "level {}: pte at 0x{:08x} = 0x{:08x} [{}]", step.level, step.pte_addr, step.pte, flags(step.pte))?;
+            if step.pte & PTE_V != 0 && step.pte & (PTE_R | PTE_X | PTE_W) == 0 {
+                writeln!(f, " -> next table at 0x{:08x}", pte_ppn(step.pte) << PAGE_SHIFT)?;
+            } else {
+                writeln!(f)?;
+            }
+        }
+        match self.result {
+            Ok(paddr) => write!(f, "physical address 0x{paddr:08x}"),
+            Err(fault) => write!(f, "page fault: {fault}"),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+use crate::cpu::{Cpu, CpuError};
+use crate::datapath::{ControlSignals, DatapathTrace, Fetched, MemToReg, read_ports};
+use crate::engine::Engine;
+use crate::instruction::Instruction;
+use crate::privilege::Privilege;
+use crate::xlen::Xlen;
+
+/// The states of the multi-cycle control FSM, one clock cycle each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Fetch,
+    Decode,
+    Execute,
+    Memory,
+    WriteBack,
+}
+
+impl State {
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Fetch => "Fetch",
+            State::Decode => "Decode",
+            State::Execute => "Execute",
+            State::Memory => "Memory",
+            State::WriteBack => "WriteBack",
+        }
+    }
+
+    /// The states `inst` passes through: every instruction is fetched,
+    /// decoded and executed, then visits Memory if it accesses memory and
+    /// WriteBack if it writes a register. Its length is the instruction's
+    /// cycle count: 3 for branches, 4 for ALU ops, jumps and stores, 5 for
+    /// loads.
+    pub fn path(inst: &Instruction) -> Vec<State> {
+        let (memory, write_back) = match ControlSignals::for_instruction(inst) {
+            Some(s) => (s.mem_read || s.mem_write, s.reg_write),
+            None => (inst.reads_memory() || inst.writes_memory(), inst.destination().is_some()),
+        };
+        let mut path = vec![State::Fetch, State::Decode, State::Execute];
+        if memory {
+            path.push(State::Memory);
+        }
+        if write_back {
+            path.push(State::WriteBack);
+        }
+        path
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The internal registers the states hand values through. Like the
+/// hardware's, they keep their value until a state overwrites them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Latches {
+    /// Instruction register, written by Fetch.
+    pub ir: Option<Instruction>,
+    /// Register-file outputs, written by Decode.
+    pub a: i64,
+    pub b: i64,
+    /// ALU result, written by Execute.
+    pub alu_out: i64,
+    /// Memory data register, written by Memory on loads.
+    pub mdr: i64,
+}
+
+impl fmt::Display for Latches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ir {
+            Some(ir) => write!(f, "IR {ir:?}")?,
+            None => write!(f, "IR -")?,
+        }
+        write!(f, " | A {} | B {} | ALUOut {} | MDR {}", self.a, self.b, self.alu_out, self.mdr)
+    }
+}
+
+/// One state the FSM ran: which instruction, where it is on its path,
+/// and the register transfer it performed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub cycle: usize,
+    pub pc: usize,
+    pub inst: Instruction,
+    pub state: State,
+    pub path: Vec<State>,
+    pub transfer: String,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self
+            .path
+            .iter()
+            .map(|&s| if s == self.state { format!("[{s}]") } else { s.to_string() })
+            .collect::<Vec<_>>()
+            .join(" > ");
+        writeln!(f, "cycle {}: {}: {:?}", self.cycle, self.pc, self.inst)?;
+        writeln!(f, "  {path}")?;
+        write!(f, "  {}", self.transfer)
+    }
+}
+
+/// The instruction the FSM is working through.
+struct InFlight {
+    pc: usize,
+    inst: Instruction,
+    path: Vec<State>,
+    next: usize,
+    /// The datapath inputs until Execute, for instructions on the datapath.
+    fetched: Option<Fetched>,
+    trace: Option<DatapathTrace>,
+    /// Whether Execute retired it rather than trapping.
+    retires: bool,
+}
+
+/// A multi-cycle implementation driving a [`Cpu`] one FSM state per
+/// cycle. Each instruction executes on the CPU in its Execute state, so
+/// the architectural results are the interpreter's; the model adds the
+/// per-instruction cycle counts and the internal registers between states.
+#[derive(Default)]
+pub struct MultiCycle {
+    latches: Latches,
+    current: Option<InFlight>,
+    last: Option<Step>,
+    cycles: usize,
+    retired: usize,
+}
+
+impl MultiCycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn latches(&self) -> &Latches {
+        &self.latches
+    }
+
+    /// The state run by the last [`step`](Self::step).
+    pub fn last(&self) -> Option<&Step> {
+        self.last.as_ref()
+    }
+
+    /// The state the next step runs, or `None` between instructions.
+    pub fn next_state(&self) -> Option<State> {
+        self.current.as_ref().map(|flight| flight.path[flight.next])
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn retired(&self) -> usize {
+        self.retired
+    }
+
+    /// Cycles per retired instruction.
+    pub fn cpi(&self) -> f64 {
+        if self.retired == 0 { 0.0 } else { self.cycles as f64 / self.retired as f64 }
+    }
+
+    /// Runs one state of the instruction at `cpu.pc`. Returns `Ok(false)`
+    /// when there is nothing left to fetch.
+    pub fn step<X: Xlen>(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        if self.current.is_none() {
+            let Some(&inst) = cpu.program().get(cpu.pc) else {
+                return Ok(false);
+            };
+            let path = State::path(&inst);
+            self.current = Some(InFlight { pc: cpu.pc, inst, path, next: 0, fetched: Fetched::new(cpu), trace: None, retires: false });
+        }
+        let flight = self.current.as_mut().expect("an instruction is in flight");
+        let state = flight.path[flight.next];
+        flight.next += 1;
+        self.cycles += 1;
+
+        let latches = &mut self.latches;
+        let transfer = match state {
+            State::Fetch => {
+                latches.ir = Some(flight.inst);
+                format!("IR <- M[{}]; PC <- {}", cpu.address_of(flight.pc), cpu.address_of(flight.pc + 1))
+            }
+            State::Decode => {
+                let (rs1, rs2) = read_ports(&flight.inst);
+                let mut reads = vec![];
+                if let Some(r) = rs1 {
+                    latches.a = X::to_i64(cpu.regs[r]);
+                    reads.push(format!("A <- x{r} = {}", latches.a));
+                }
+                if let Some(r) = rs2 {
+                    latches.b = X::to_i64(cpu.regs[r]);
+                    reads.push(format!("B <- x{r} = {}", latches.b));
+                }
+                if reads.is_empty() { "no register reads".to_string() } else { reads.join("; ") }
+            }
+            State::Execute => {
+                let instret = cpu.instret();
+                if let Err(e) = cpu.execute_next() {
+                    self.current = None;
+                    return Err(e);
+                }
+                flight.retires = cpu.instret() != instret;
+                if !flight.retires {
+                    // Trapped or interrupted: nothing reaches Memory or
+                    // WriteBack, and the next Fetch is from the handler.
+                    flight.path.truncate(flight.next);
+                    let vector = if cpu.privilege() == Privilege::Supervisor { "stvec" } else { "mtvec" };
+                    format!("trap; PC <- {vector} = {}", cpu.pc_address())
+                } else {
+                    flight.trace = flight.fetched.take().map(|fetched| fetched.complete(cpu));
+                    match &flight.trace {
+                        Some(trace) => {
+                            latches.alu_out = trace.alu_out;
+                            let mut transfer =
+                                format!("ALUOut <- ALU({}, {}) = {}", trace.alu_a, trace.alu_b, trace.alu_out);
+                            if trace.taken() {
+                                transfer += &format!("; PC <- {}", trace.next_address);
+                            } else if trace.signals.branch {
+                                transfer += "; not taken";
+                            }
+                            transfer
+                        }
+                        None => format!("executed on the CPU; PC <- {}", cpu.pc_address()),
+                    }
+                }
+            }
+            State::Memory => match &flight.trace {
+                Some(DatapathTrace { signals, mem_addr: Some(addr), mem_data: Some(data), .. }) => {
+                    if signals.mem_read {
+                        latches.mdr = *data;
+                        format!("MDR <- M[{addr:#x}] = {data}")
+                    } else {
+                        format!("M[{addr:#x}] <- B = {data}")
+                    }
+                }
+                _ => "memory access".to_string(),
+            },
+            State::WriteBack => match &flight.trace {
+                Some(DatapathTrace { signals, write_back: Some((rd, value)), address, .. }) => {
+                    let source = match signals.mem_to_reg {
+                        MemToReg::Alu => "ALUOut".to_string(),
+                        MemToReg::Mem => "MDR".to_string(),
+                        MemToReg::PcNext => format!("PC+{}", cpu.address_of(flight.pc + 1) as u64 - address),
+                    };
+                    format!("x{rd} <- {source} = {value}")
+                }
+                _ => match flight.inst.destination() {
+                    Some(reg) => format!("{reg} written"),
+                    None => "register write".to_string(),
+                },
+            },
+        };
+
+        self.last = Some(Step {
+            cycle: self.cycles,
+            pc: flight.pc,
+            inst: flight.inst,
+            state,
+            path: flight.path.clone(),
+            transfer,
+        });
+        if flight.next == flight.path.len() {
+            self.retired += flight.retires as usize;
+            self.current = None;
+        }
+        Ok(true)
+    }
+}
+
+impl<X: Xlen> Engine<X> for MultiCycle {
+    fn name(&self) -> &'static str {
+        "multicycle"
+    }
+
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        MultiCycle::step(self, cpu)
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    fn retired(&self) -> usize {
+        self.retired
+    }
+}
+
+/// The last state run, the internal registers, and the totals.
+impl fmt::Display for MultiCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(step) = &self.last {
+            writeln!(f, "{step}")?;
+        }
+        writeln!(f, "{}", self.latches)?;
+        write!(f, "{} cycles, {} instructions, CPI {:.2}", self.cycles, self.retired, self.cpi())
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::cpu::{Cpu, CpuError};
+use crate::instruction::{Instruction, Reg};
+use crate::engine::Engine;
+use crate::xlen::Xlen;
+
+/// The stages of the classic five-stage pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    If,
+    Id,
+    Ex,
+    Mem,
+    Wb,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [Stage::If, Stage::Id, Stage::Ex, Stage::Mem, Stage::Wb];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::If => "IF",
+            Stage::Id => "ID",
+            Stage::Ex => "EX",
+            Stage::Mem => "MEM",
+            Stage::Wb => "WB",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Bypass results from the EX/MEM and MEM/WB latches to EX. Without
+    /// it, an instruction waits in ID until its producers have reached WB,
+    /// which writes the register file in the first half of the cycle.
+    pub forwarding: bool,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig { forwarding: true }
+    }
+}
+
+/// What one instruction did in one cycle of the diagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Stage(Stage),
+    /// Held in the stage it occupied the cycle before.
+    Stall,
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell::Stage(stage) => write!(f, "{stage}"),
+            Cell::Stall => write!(f, "--"),
+        }
+    }
+}
+
+/// One fetched instruction and the cycles it spent in the pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub pc: usize,
+    pub inst: Instruction,
+    /// Cycle number to what happened in it, from 1.
+    pub cells: BTreeMap<usize, Cell>,
+    /// Fetched down the wrong path and squashed by a taken branch or jump,
+    /// or trapped in EX.
+    pub flushed: bool,
+    /// Trapped or was interrupted in EX, so it never reached MEM or WB.
+    pub trapped: bool,
+}
+
+/// A hazard the pipeline resolved, at the cycle it was resolved in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The instruction in ID waited for `reg` from an older instruction.
+    Stall { cycle: usize, pc: usize, reg: Reg, producer: usize, load_use: bool },
+    /// `reg` was bypassed into EX from the named pipeline latch.
+    Forward { cycle: usize, pc: usize, reg: Reg, producer: usize, from: &'static str },
+    /// A taken branch or jump in EX squashed the instructions behind it.
+    Flush { cycle: usize, pc: usize, target: usize, squashed: usize },
+    /// The instruction in EX trapped or was interrupted; it and the
+    /// instructions behind it were squashed for the handler at `target`.
+    Trap { cycle: usize, pc: usize, target: usize, squashed: usize },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Stall { cycle, pc, reg, producer, load_use } => {
+                let kind = if *load_use { "load-use stall" } else { "stall" };
+                write!(f, "cycle {cycle}: {kind}: {pc} waits for {reg} from {producer}")
+            }
+            Event::Forward { cycle, pc, reg, producer, from } => {
+                write!(f, "cycle {cycle}: forward {reg} from {producer} to {pc} via {from}")
+            }
+            Event::Flush { cycle, pc, target, squashed } => {
+                write!(f, "cycle {cycle}: {pc} redirects to {target}, flushing {squashed} instruction(s)")
+            }
+            Event::Trap { cycle, pc, target, squashed } => {
+                write!(f, "cycle {cycle}: {pc} traps to {target}, flushing {squashed} instruction(s)")
+            }
+        }
+    }
+}
+
+/// An instruction in flight: its index in `rows`, and whether it was held
+/// in place last cycle.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    row: usize,
+    held: bool,
+}
+
+/// A cycle-by-cycle model of the classic IF/ID/EX/MEM/WB pipeline driving a
+/// [`Cpu`]. Instructions are fetched in order, predicting every branch not
+/// taken; each one executes on the CPU when it enters EX, where branches and
+/// jumps resolve, so the architectural results are the interpreter's and the
+/// pipeline only decides timing. Data hazards stall in ID; an instruction
+/// that traps in EX is squashed with everything behind it.
+#[derive(Default)]
+pub struct Pipeline {
+    config: PipelineConfig,
+    /// Occupants of IF, ID, EX, MEM and WB.
+    stages: [Option<Slot>; 5],
+    /// Where the next fetch comes from; the CPU's pc until the first cycle.
+    fetch_pc: Option<usize>,
+    cycle: usize,
+    rows: Vec<Row>,
+    events: Vec<Event>,
+    retired: usize,
+}
+
+impl Pipeline {
+    /// An empty pipeline, which starts fetching from the CPU's pc.
+    pub fn new(config: PipelineConfig) -> Self {
+        Pipeline { config, ..Default::default() }
+    }
+
+    pub fn config(&self) -> PipelineConfig {
+        self.config
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn retired(&self) -> usize {
+        self.retired
+    }
+
+    /// Cycles per retired instruction.
+    pub fn cpi(&self) -> f64 {
+        if self.retired == 0 { 0.0 } else { self.cycle as f64 / self.retired as f64 }
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn stalls(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, Event::Stall { .. })).count()
+    }
+
+    pub fn flushes(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, Event::Flush { .. })).count()
+    }
+
+    pub fn traps(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e, Event::Trap { .. })).count()
+    }
+
+    /// The instruction in `stage` this cycle, as an index into [`rows`](Self::rows).
+    pub fn occupant(&self, stage: Stage) -> Option<usize> {
+        self.stages[stage as usize].map(|slot| slot.row)
+    }
+
+    fn is_drained(&self, fetch_pc: usize, program_len: usize) -> bool {
+        self.stages.iter().all(Option::is_none) && fetch_pc >= program_len
+    }
+
+    fn inst(&self, stage: Stage) -> Option<&Row> {
+        self.stages[stage as usize].map(|slot| &self.rows[slot.row])
+    }
+
+    /// The youngest instruction in EX or MEM writing `reg`, with its stage.
+    fn producer(&self, reg: Reg) -> Option<(Stage, &Row)> {
+        [Stage::Ex, Stage::Mem]
+            .into_iter()
+            .find_map(|stage| self.inst(stage).filter(|row| row.inst.destination() == Some(reg)).map(|row| (stage, row)))
+    }
+
+    /// Whether the instruction in ID has to wait this cycle; records the
+    /// stall or the forwarding paths it will use in EX next cycle.
+    fn data_hazard(&mut self) -> bool {
+        let Some(consumer) = self.inst(Stage::Id) else {
+            return false;
+        };
+        let mut forwards = vec![];
+        for reg in consumer.inst.sources() {
+            let Some((stage, producer)) = self.producer(reg) else {
+                continue;
+            };
+            let load_use = stage == Stage::Ex && producer.inst.reads_memory();
+            if !self.config.forwarding || load_use {
+                let event = Event::Stall { cycle: self.cycle, pc: consumer.pc, reg, producer: producer.pc, load_use };
+                self.events.push(event);
+                return true;
+            }
+            let from = if stage == Stage::Ex { "EX/MEM" } else { "MEM/WB" };
+            forwards.push(Event::Forward { cycle: self.cycle + 1, pc: consumer.pc, reg, producer: producer.pc, from });
+        }
+        self.events.extend(forwards);
+        false
+    }
+
+    /// Runs one clock cycle. Returns `Ok(false)` once every instruction has
+    /// left the pipeline and there is nothing left to fetch.
+    pub fn step<X: Xlen>(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        let fetch_pc = *self.fetch_pc.get_or_insert(cpu.pc);
+        if self.is_drained(fetch_pc, cpu.program().len()) {
+            return Ok(false);
+        }
+        self.cycle += 1;
+
+        if self.stages[Stage::If as usize].is_none() && fetch_pc < cpu.program().len() {
+            self.rows.push(Row { pc: fetch_pc, inst: cpu.program()[fetch_pc], cells: BTreeMap::new(), flushed: false, trapped: false });
+            self.stages[Stage::If as usize] = Some(Slot { row: self.rows.len() - 1, held: false });
+            self.fetch_pc = Some(fetch_pc + 1);
+        }
+        for stage in Stage::ALL {
+            if let Some(slot) = self.stages[stage as usize] {
+                let cell = if slot.held { Cell::Stall } else { Cell::Stage(stage) };
+                self.rows[slot.row].cells.insert(self.cycle, cell);
+            }
+        }
+
+        // The instruction entering EX executes now and resolves control flow.
+        // One that traps instead of retiring goes no further.
+        let (mut redirect, mut trapped) = (None, false);
+        if let Some(slot) = self.stages[Stage::Ex as usize] {
+            let pc = self.rows[slot.row].pc;
+            debug_assert_eq!(cpu.pc, pc, "instructions reach EX in program order");
+            let instret = cpu.instret();
+            cpu.execute_next()?;
+            trapped = cpu.instret() == instret;
+            if trapped || cpu.pc != pc + 1 {
+                redirect = Some((pc, cpu.pc));
+            }
+        }
+
+        let stall = redirect.is_none() && self.data_hazard();
+        if self.stages[Stage::Wb as usize].take().is_some() {
+            self.retired += 1;
+        }
+        self.stages[Stage::Wb as usize] = self.stages[Stage::Mem as usize].take();
+        self.stages[Stage::Mem as usize] = self.stages[Stage::Ex as usize].take();
+        if trapped && let Some(slot) = self.stages[Stage::Mem as usize].take() {
+            let row = &mut self.rows[slot.row];
+            (row.flushed, row.trapped) = (true, true);
+        }
+        if let Some((pc, target)) = redirect {
+            let mut squashed = 0;
+            for stage in [Stage::If, Stage::Id] {
+                if let Some(slot) = self.stages[stage as usize].take() {
+                    self.rows[slot.row].flushed = true;
+                    squashed += 1;
+                }
+            }
+            self.events.push(if trapped {
+                Event::Trap { cycle: self.cycle, pc, target, squashed }
+            } else {
+                Event::Flush { cycle: self.cycle, pc, target, squashed }
+            });
+            self.fetch_pc = Some(target);
+        } else if stall {
+            for stage in [Stage::If, Stage::Id] {
+                if let Some(slot) = &mut self.stages[stage as usize] {
+                    slot.held = true;
+                }
+            }
+        } else {
+            self.stages[Stage::Ex as usize] = self.stages[Stage::Id as usize].take().map(|slot| Slot { held: false, ..slot });
+            self.stages[Stage::Id as usize] = self.stages[Stage::If as usize].take().map(|slot| Slot { held: false, ..slot });
+        }
+        Ok(true)
+    }
+}
+
+impl<X: Xlen> Engine<X> for Pipeline {
+    fn name(&self) -> &'static str {
+        "pipeline"
+    }
+
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        Pipeline::step(self, cpu)
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycle
+    }
+
+    fn retired(&self) -> usize {
+        self.retired
+    }
+}
+
+/// The pipeline diagram: one line per fetched instruction, one column per
+/// cycle, then the totals.
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels = self.rows.iter().map(|row| format!("{:>3}: {:?}", row.pc, row.inst)).collect::<Vec<_>>();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        write!(f, "{:width$}", "")?;
+        for cycle in 1..=self.cycle {
+            write!(f, " {cycle:>3}")?;
+        }
+        writeln!(f)?;
+        for (row, label) in self.rows.iter().zip(&labels) {
+            write!(f, "{label:width$}")?;
+            let last = row.cells.keys().next_back().copied().unwrap_or(0);
+            for cycle in 1..=last {
+                match row.cells.get(&cycle) {
+                    Some(cell) => write!(f, " {:>3}", cell.to_string())?,
+                    None => write!(f, "    ")?,
+                }
+            }
+            if row.trapped {
+                write!(f, "  (trapped)")?;
+            } else if row.flushed {
+                write!(f, "  (flushed)")?;
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "{} cycles, {} instructions, CPI {:.2} ({} stalls, {} flushes, {} traps, forwarding {})",
+            self.cycle,
+            self.retired,
+            self.cpi(),
+            self.stalls(),
+            self.flushes(),
+            self.traps(),
+            if self.config.forwarding { "on" } else { "off" }
+        )
+    }
+}
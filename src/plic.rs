@@ -0,0 +1,141 @@
+use crate::bus::Device;
+use crate::memory::MemoryError;
+use crate::privilege::{MEIP, SEIP};
+
+/// Where QEMU's `virt` machine puts its PLIC.
+pub const VIRT_PLIC_BASE: u64 = 0x0C00_0000;
+
+/// Sources 1..64; source 0 means "no interrupt".
+const SOURCES: usize = 64;
+const MAX_PRIORITY: u32 = 7;
+
+const PRIORITY_BASE: u64 = 0x0000;
+const PENDING_BASE: u64 = 0x1000;
+const ENABLE_BASE: u64 = 0x2000;
+const ENABLE_STRIDE: u64 = 0x80;
+const CONTEXT_BASE: u64 = 0x20_0000;
+const CONTEXT_STRIDE: u64 = 0x1000;
+const CLAIM: u64 = 4;
+
+/// A PLIC-compatible platform-level interrupt controller with 32-bit
+/// registers. Each hart has two contexts, as on QEMU's `virt`: `2 * hart`
+/// drives its machine external interrupt and `2 * hart + 1` its supervisor
+/// one. A source stays pending while its device holds the line raised and
+/// it is not being serviced, i.e. claimed but not yet completed.
+pub struct Plic {
+    priority: [u32; SOURCES],
+    pending: u64,
+    claimed: u64,
+    enable: Vec<u64>,
+    threshold: Vec<u32>,
+}
+
+impl Plic {
+    pub fn new(harts: usize) -> Self {
+        Plic {
+            priority: [0; SOURCES],
+            pending: 0,
+            claimed: 0,
+            enable: vec![0; 2 * harts],
+            threshold: vec![0; 2 * harts],
+        }
+    }
+
+    /// The pending, enabled source with the highest priority above the
+    /// context's threshold; ties go to the lowest id.
+    fn best(&self, context: usize) -> Option<usize> {
+        let candidates = self.pending & self.enable[context];
+        (1..SOURCES)
+            .filter(|&src| candidates & 1 << src != 0 && self.priority[src] > self.threshold[context])
+            .min_by_key(|&src| std::cmp::Reverse(self.priority[src]))
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        let Some(src) = self.best(context) else { return 0 };
+        self.pending &= !(1 << src);
+        self.claimed |= 1 << src;
+        src as u32
+    }
+
+    fn complete(&mut self, context: usize, src: u64) {
+        if (src as usize) < SOURCES && self.enable[context] & 1 << src != 0 {
+            self.claimed &= !(1 << src);
+        }
+    }
+
+    /// The context and register offset within it for `offset` in the
+    /// enable or context areas.
+    fn context_of(&self, offset: u64, base: u64, stride: u64) -> Option<(usize, u64)> {
+        let context = ((offset - base) / stride) as usize;
+        (context < self.enable.len()).then_some((context, (offset - base) % stride))
+    }
+
+    fn read_reg(&mut self, offset: u64) -> Option<u32> {
+        Some(match offset {
+            o if o < PENDING_BASE => *self.priority.get(((o - PRIORITY_BASE) / 4) as usize)?,
+            o if o < ENABLE_BASE => self.pending.checked_shr(8 * (o - PENDING_BASE) as u32)? as u32,
+            o if o < CONTEXT_BASE => {
+                let (context, word) = self.context_of(o, ENABLE_BASE, ENABLE_STRIDE)?;
+                self.enable[context].checked_shr(8 * word as u32)? as u32
+            }
+            o => match self.context_of(o, CONTEXT_BASE, CONTEXT_STRIDE)? {
+                (context, 0) => self.threshold[context],
+                (context, CLAIM) => self.claim(context),
+                _ => 0,
+            },
+        })
+    }
+
+    fn write_reg(&mut self, offset: u64, val: u32) -> Option<()> {
+        match offset {
+            o if o < PENDING_BASE => {
+                let src = ((o - PRIORITY_BASE) / 4) as usize;
+                if src > 0 {
+                    *self.priority.get_mut(src)? = val.min(MAX_PRIORITY);
+                }
+            }
+            o if o < ENABLE_BASE => {} // pending bits are read-only
+            o if o < CONTEXT_BASE => {
+                let (context, word) = self.context_of(o, ENABLE_BASE, ENABLE_STRIDE)?;
+                let shift = 8 * word as u32;
+                let field = 0xFFFF_FFFF_u64.checked_shl(shift)?;
+                let enable = (self.enable[context] & !field) | ((val as u64) << shift);
+                self.enable[context] = enable & !1;
+            }
+            o => match self.context_of(o, CONTEXT_BASE, CONTEXT_STRIDE)? {
+                (context, 0) => self.threshold[context] = val.min(MAX_PRIORITY),
+                (context, CLAIM) => self.complete(context, val as u64),
+                _ => {}
+            },
+        }
+        Some(())
+    }
+}
+
+impl Device for Plic {
+    fn name(&self) -> &str {
+        "plic"
+    }
+
+    fn size(&self) -> u64 {
+        CONTEXT_BASE + CONTEXT_STRIDE * self.enable.len() as u64
+    }
+
+    fn read(&mut self, offset: u64, _size: usize) -> Result<u64, MemoryError> {
+        self.read_reg(offset & !3).map(|val| val as u64).ok_or(MemoryError::OutOfBounds(offset))
+    }
+
+    fn write(&mut self, offset: u64, _size: usize, val: u64) -> Result<(), MemoryError> {
+        self.write_reg(offset & !3, val as u32).ok_or(MemoryError::OutOfBounds(offset))
+    }
+
+    fn interrupt_lines(&mut self, lines: u64) {
+        self.pending = lines & !self.claimed & !1;
+    }
+
+    fn hart_interrupts(&self, hart: usize) -> u64 {
+        let machine = self.enable.get(2 * hart).and_then(|_| self.best(2 * hart)).map_or(0, |_| MEIP);
+        let supervisor = self.enable.get(2 * hart + 1).and_then(|_| self.best(2 * hart + 1)).map_or(0, |_| SEIP);
+        machine | supervisor
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Guesses conditional branch directions before they resolve. `pc` and
+/// `target` are instruction indices.
+pub trait Predictor {
+    fn name(&self) -> &'static str;
+
+    fn predict(&self, pc: usize, target: usize) -> bool;
+
+    /// Learns the resolved direction of the branch at `pc`.
+    fn update(&mut self, pc: usize, target: usize, taken: bool);
+}
+
+/// Predicts every branch falls through.
+pub struct NotTaken;
+
+impl Predictor for NotTaken {
+    fn name(&self) -> &'static str {
+        "static not-taken"
+    }
+
+    fn predict(&self, _pc: usize, _target: usize) -> bool {
+        false
+    }
+
+    fn update(&mut self, _pc: usize, _target: usize, _taken: bool) {}
+}
+
+/// Backward taken, forward not taken: loops close with backward branches.
+pub struct Btfn;
+
+impl Predictor for Btfn {
+    fn name(&self) -> &'static str {
+        "BTFN"
+    }
+
+    fn predict(&self, pc: usize, target: usize) -> bool {
+        target <= pc
+    }
+
+    fn update(&mut self, _pc: usize, _target: usize, _taken: bool) {}
+}
+
+/// Predicts each branch goes the way it went last time.
+pub struct OneBit {
+    table: Vec<bool>,
+}
+
+impl OneBit {
+    pub fn new(entries: usize) -> Self {
+        OneBit { table: vec![false; entries] }
+    }
+}
+
+impl Predictor for OneBit {
+    fn name(&self) -> &'static str {
+        "1-bit"
+    }
+
+    fn predict(&self, pc: usize, _target: usize) -> bool {
+        self.table[pc % self.table.len()]
+    }
+
+    fn update(&mut self, pc: usize, _target: usize, taken: bool) {
+        let len = self.table.len();
+        self.table[pc % len] = taken;
+    }
+}
+
+/// A table of 2-bit saturating counters; 2 and 3 predict taken.
+#[derive(Debug, Clone)]
+struct Counters(Vec<u8>);
+
+impl Counters {
+    /// Every counter starts weakly not-taken.
+    fn new(entries: usize) -> Self {
+        Counters(vec![1; entries])
+    }
+
+    fn taken(&self, index: usize) -> bool {
+        self.0[index % self.0.len()] >= 2
+    }
+
+    fn train(&mut self, index: usize, taken: bool) {
+        let len = self.0.len();
+        let counter = &mut self.0[index % len];
+        *counter = if taken { (*counter + 1).min(3) } else { counter.saturating_sub(1) };
+    }
+}
+
+/// 2-bit saturating counters indexed by pc, so one odd outcome does not
+/// flip a strongly biased branch.
+pub struct TwoBit {
+    counters: Counters,
+}
+
+impl TwoBit {
+    pub fn new(entries: usize) -> Self {
+        TwoBit { counters: Counters::new(entries) }
+    }
+}
+
+impl Predictor for TwoBit {
+    fn name(&self) -> &'static str {
+        "2-bit saturating"
+    }
+
+    fn predict(&self, pc: usize, _target: usize) -> bool {
+        self.counters.taken(pc)
+    }
+
+    fn update(&mut self, pc: usize, _target: usize, taken: bool) {
+        self.counters.train(pc, taken);
+    }
+}
+
+/// 2-bit counters indexed by pc XOR a global history of recent outcomes,
+/// which catches branches correlated with the ones before them.
+pub struct Gshare {
+    counters: Counters,
+    history: usize,
+}
+
+impl Gshare {
+    pub fn new(entries: usize) -> Self {
+        Gshare { counters: Counters::new(entries), history: 0 }
+    }
+
+    fn index(&self, pc: usize) -> usize {
+        pc ^ self.history
+    }
+}
+
+impl Predictor for Gshare {
+    fn name(&self) -> &'static str {
+        "gshare"
+    }
+
+    fn predict(&self, pc: usize, _target: usize) -> bool {
+        self.counters.taken(self.index(pc))
+    }
+
+    fn update(&mut self, pc: usize, _target: usize, taken: bool) {
+        self.counters.train(self.index(pc), taken);
+        self.history = ((self.history << 1) | taken as usize) % self.counters.0.len();
+    }
+}
+
+/// Runs a 2-bit and a gshare predictor side by side, and per branch a
+/// 2-bit chooser picks whichever has been right more often.
+pub struct Tournament {
+    local: TwoBit,
+    global: Gshare,
+    /// 2 and 3 pick gshare.
+    chooser: Counters,
+}
+
+impl Tournament {
+    pub fn new(entries: usize) -> Self {
+        Tournament { local: TwoBit::new(entries), global: Gshare::new(entries), chooser: Counters::new(entries) }
+    }
+}
+
+impl Predictor for Tournament {
+    fn name(&self) -> &'static str {
+        "tournament"
+    }
+
+    fn predict(&self, pc: usize, target: usize) -> bool {
+        if self.chooser.taken(pc) { self.global.predict(pc, target) } else { self.local.predict(pc, target) }
+    }
+
+    fn update(&mut self, pc: usize, target: usize, taken: bool) {
+        let local = self.local.predict(pc, target) == taken;
+        let global = self.global.predict(pc, target) == taken;
+        if local != global {
+            self.chooser.train(pc, global);
+        }
+        self.local.update(pc, target, taken);
+        self.global.update(pc, target, taken);
+    }
+}
+
+/// The predictors a run can be given, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictorKind {
+    NotTaken,
+    Btfn,
+    OneBit,
+    TwoBit,
+    Gshare,
+    Tournament,
+}
+
+impl PredictorKind {
+    pub const ALL: [PredictorKind; 6] = [
+        PredictorKind::NotTaken,
+        PredictorKind::Btfn,
+        PredictorKind::OneBit,
+        PredictorKind::TwoBit,
+        PredictorKind::Gshare,
+        PredictorKind::Tournament,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PredictorKind::NotTaken => "not-taken",
+            PredictorKind::Btfn => "btfn",
+            PredictorKind::OneBit => "1bit",
+            PredictorKind::TwoBit => "2bit",
+            PredictorKind::Gshare => "gshare",
+            PredictorKind::Tournament => "tournament",
+        }
+    }
+
+    /// A fresh predictor of this kind; tables have `entries` entries.
+    pub fn build(self, entries: usize) -> Box<dyn Predictor> {
+        match self {
+            PredictorKind::NotTaken => Box::new(NotTaken),
+            PredictorKind::Btfn => Box::new(Btfn),
+            PredictorKind::OneBit => Box::new(OneBit::new(entries)),
+            PredictorKind::TwoBit => Box::new(TwoBit::new(entries)),
+            PredictorKind::Gshare => Box::new(Gshare::new(entries)),
+            PredictorKind::Tournament => Box::new(Tournament::new(entries)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown predictor `{0}`, expected not-taken, btfn, 1bit, 2bit, gshare or tournament")]
+pub struct UnknownPredictor(pub String);
+
+impl FromStr for PredictorKind {
+    type Err = UnknownPredictor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PredictorKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| UnknownPredictor(s.to_string()))
+    }
+}
+
+/// How one static branch fared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchRecord {
+    pub executed: usize,
+    pub taken: usize,
+    pub correct: usize,
+}
+
+impl BranchRecord {
+    pub fn accuracy(&self) -> f64 {
+        if self.executed == 0 { 0.0 } else { self.correct as f64 / self.executed as f64 }
+    }
+}
+
+/// A predictor scored against the branches a CPU resolves.
+pub struct BranchPredictor {
+    predictor: Box<dyn Predictor>,
+    /// Cycles lost to each misprediction.
+    penalty: usize,
+    branches: BTreeMap<usize, BranchRecord>,
+}
+
+impl BranchPredictor {
+    /// Table entries of the dynamic predictors.
+    pub const DEFAULT_ENTRIES: usize = 1024;
+    /// What a misprediction costs the five-stage pipeline, which resolves
+    /// branches in EX.
+    pub const DEFAULT_PENALTY: usize = 2;
+
+    pub fn new(predictor: Box<dyn Predictor>, penalty: usize) -> Self {
+        BranchPredictor { predictor, penalty, branches: BTreeMap::new() }
+    }
+
+    pub fn of_kind(kind: PredictorKind) -> Self {
+        Self::new(kind.build(Self::DEFAULT_ENTRIES), Self::DEFAULT_PENALTY)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.predictor.name()
+    }
+
+    pub fn penalty(&self) -> usize {
+        self.penalty
+    }
+
+    /// Predicts the branch at `pc`, then trains on its outcome. Returns
+    /// whether the prediction was right.
+    pub fn resolve(&mut self, pc: usize, target: usize, taken: bool) -> bool {
+        let correct = self.predictor.predict(pc, target) == taken;
+        self.predictor.update(pc, target, taken);
+        let record = self.branches.entry(pc).or_default();
+        record.executed += 1;
+        record.taken += taken as usize;
+        record.correct += correct as usize;
+        correct
+    }
+
+    /// Each static branch by pc.
+    pub fn branches(&self) -> &BTreeMap<usize, BranchRecord> {
+        &self.branches
+    }
+
+    pub fn predictions(&self) -> usize {
+        self.branches.values().map(|r| r.executed).sum()
+    }
+
+    pub fn mispredictions(&self) -> usize {
+        self.branches.values().map(|r| r.executed - r.correct).sum()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        let predictions = self.predictions();
+        if predictions == 0 { 0.0 } else { 1.0 - self.mispredictions() as f64 / predictions as f64 }
+    }
+
+    /// `cycles` plus the penalty of every misprediction.
+    pub fn adjusted_cycles(&self, cycles: usize) -> usize {
+        cycles + self.mispredictions() * self.penalty
+    }
+}
+
+/// The overall accuracy, then one line per static branch.
+impl fmt::Display for BranchPredictor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}/{} correct ({:.1}%), {} mispredictions x {} cycles",
+            self.name(),
+            self.predictions() - self.mispredictions(),
+            self.predictions(),
+            100.0 * self.accuracy(),
+            self.mispredictions(),
+            self.penalty
+        )?;
+        for (pc, record) in &self.branches {
+            write!(
+                f,
+                "\n  {pc:>4}: {:>5} executed, {:>5} taken, {:>5} correct ({:.1}%)",
+                record.executed,
+                record.taken,
+                record.correct,
+                100.0 * record.accuracy()
+            )?;
+        }
+        Ok(())
+    }
+}
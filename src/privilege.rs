@@ -0,0 +1,240 @@
+use crate::csr;
+
+/// Privilege level, ordered so that `Machine > Supervisor > User`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    pub fn from_bits(bits: u64) -> Option<Self> {
+        match bits {
+            0 => Some(Privilege::User),
+            1 => Some(Privilege::Supervisor),
+            3 => Some(Privilege::Machine),
+            _ => None,
+        }
+    }
+
+    pub fn bits(self) -> u64 {
+        self as u64
+    }
+}
+
+/// Synchronous exception causes, as written to `mcause`/`scause`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAddressMisaligned = 0,
+    InstructionAccessFault = 1,
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadAddressMisaligned = 4,
+    LoadAccessFault = 5,
+    StoreAddressMisaligned = 6,
+    StoreAccessFault = 7,
+    UserEcall = 8,
+    SupervisorEcall = 9,
+    MachineEcall = 11,
+    InstructionPageFault = 12,
+    LoadPageFault = 13,
+    StorePageFault = 15,
+}
+
+impl Exception {
+    pub fn code(self) -> u64 {
+        self as u64
+    }
+
+    pub fn ecall_from(privilege: Privilege) -> Self {
+        match privilege {
+            Privilege::User => Exception::UserEcall,
+            Privilege::Supervisor => Exception::SupervisorEcall,
+            Privilege::Machine => Exception::MachineEcall,
+        }
+    }
+}
+
+/// Interrupt causes. The code is also the interrupt's bit in `mip`/`mie`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware = 1,
+    MachineSoftware = 3,
+    SupervisorTimer = 5,
+    MachineTimer = 7,
+    SupervisorExternal = 9,
+    MachineExternal = 11,
+}
+
+impl Interrupt {
+    /// In the order they are taken when several are pending.
+    pub const PRIORITY: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+
+    pub fn code(self) -> u64 {
+        self as u64
+    }
+
+    pub fn bit(self) -> u64 {
+        1 << self.code()
+    }
+}
+
+/// What a trap was caused by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    Exception(Exception),
+    Interrupt(Interrupt),
+}
+
+// mip/mie bits
+pub const SSIP: u64 = 1 << 1;
+pub const MSIP: u64 = 1 << 3;
+pub const STIP: u64 = 1 << 5;
+pub const MTIP: u64 = 1 << 7;
+pub const SEIP: u64 = 1 << 9;
+pub const MEIP: u64 = 1 << 11;
+
+// mstatus fields
+pub const SIE: u64 = 1 << 1;
+pub const MIE: u64 = 1 << 3;
+pub const SPIE: u64 = 1 << 5;
+pub const MPIE: u64 = 1 << 7;
+pub const SPP: u64 = 1 << 8;
+pub const MPP_SHIFT: u32 = 11;
+pub const MPP: u64 = 3 << MPP_SHIFT;
+pub const MPRV: u64 = 1 << 17;
+pub const SUM: u64 = 1 << 18;
+pub const MXR: u64 = 1 << 19;
+pub const TVM: u64 = 1 << 20;
+pub const TW: u64 = 1 << 21;
+pub const TSR: u64 = 1 << 22;
+
+const MSTATUS_MASK: u64 = SIE | MIE | SPIE | MPIE | SPP | MPP | MPRV | SUM | MXR | TVM | TW | TSR;
+/// The part of `mstatus` visible through `sstatus`.
+const SSTATUS_MASK: u64 = SIE | SPIE | SPP | SUM | MXR;
+/// Environment calls from M-mode cannot be delegated.
+const MEDELEG_MASK: u64 = 0xB3FF;
+/// Supervisor software, timer and external interrupts.
+const MIDELEG_MASK: u64 = SSIP | STIP | SEIP;
+const MIE_MASK: u64 = SSIP | MSIP | STIP | MTIP | SEIP | MEIP;
+/// The `mip` bits software may write; the rest follow the interrupt lines.
+const MIP_WRITABLE: u64 = SSIP | STIP | SEIP;
+
+/// The trap-handling CSRs of M- and S-mode.
+///
+/// Like `jalr` targets, trap vectors and exception PCs are instruction
+/// indices, and only direct-mode vectors are modelled. A trap vector that
+/// has never been written is `None`: exceptions that would go there are
+/// reported to the host instead of trapping.
+#[derive(Debug, Clone, Default)]
+pub struct TrapCsrs {
+    pub mstatus: u64,
+    pub medeleg: u64,
+    pub mideleg: u64,
+    pub mtvec: Option<u64>,
+    pub mscratch: u64,
+    pub mepc: u64,
+    pub mcause: u64,
+    pub mtval: u64,
+    pub stvec: Option<u64>,
+    pub sscratch: u64,
+    pub sepc: u64,
+    pub scause: u64,
+    pub stval: u64,
+    pub mie: u64,
+    /// The software-written bits of `mip`.
+    pub mip: u64,
+    /// The `mip` bits driven by devices, refreshed before every step.
+    pub interrupt_lines: u64,
+}
+
+impl TrapCsrs {
+    pub fn read(&self, csr: u16) -> Option<u64> {
+        Some(match csr {
+            csr::MSTATUS => self.mstatus,
+            csr::SSTATUS => self.mstatus & SSTATUS_MASK,
+            csr::MEDELEG => self.medeleg,
+            csr::MIDELEG => self.mideleg,
+            csr::MTVEC => self.mtvec.unwrap_or(0),
+            csr::MSCRATCH => self.mscratch,
+            csr::MEPC => self.mepc,
+            csr::MCAUSE => self.mcause,
+            csr::MTVAL => self.mtval,
+            csr::STVEC => self.stvec.unwrap_or(0),
+            csr::SSCRATCH => self.sscratch,
+            csr::SEPC => self.sepc,
+            csr::SCAUSE => self.scause,
+            csr::STVAL => self.stval,
+            csr::MIE => self.mie,
+            csr::MIP => self.pending_interrupts(),
+            csr::SIE => self.mie & self.mideleg,
+            csr::SIP => self.pending_interrupts() & self.mideleg,
+            _ => return None,
+        })
+    }
+
+    /// Returns `false` for CSRs this struct does not hold.
+    pub fn write(&mut self, csr: u16, val: u64) -> bool {
+        match csr {
+            csr::MSTATUS => self.set_status(val, MSTATUS_MASK),
+            csr::SSTATUS => self.set_status(val, SSTATUS_MASK),
+            csr::MEDELEG => self.medeleg = val & MEDELEG_MASK,
+            csr::MIDELEG => self.mideleg = val & MIDELEG_MASK,
+            csr::MTVEC => self.mtvec = Some(val),
+            csr::MSCRATCH => self.mscratch = val,
+            csr::MEPC => self.mepc = val,
+            csr::MCAUSE => self.mcause = val,
+            csr::MTVAL => self.mtval = val,
+            csr::STVEC => self.stvec = Some(val),
+            csr::SSCRATCH => self.sscratch = val,
+            csr::SEPC => self.sepc = val,
+            csr::SCAUSE => self.scause = val,
+            csr::STVAL => self.stval = val,
+            csr::MIE => self.mie = val & MIE_MASK,
+            csr::MIP => self.mip = (self.mip & !MIP_WRITABLE) | (val & MIP_WRITABLE),
+            csr::SIE => self.mie = (self.mie & !self.mideleg) | (val & self.mideleg),
+            csr::SIP => {
+                let writable = SSIP & self.mideleg;
+                self.mip = (self.mip & !writable) | (val & writable);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// MPP is WARL: the reserved encoding 2 leaves the field unchanged.
+    fn set_status(&mut self, val: u64, mask: u64) {
+        let mut mask = mask;
+        if (val & MPP) >> MPP_SHIFT == 2 {
+            mask &= !MPP;
+        }
+        self.mstatus = (self.mstatus & !mask) | (val & mask);
+    }
+
+    /// The value of `mip`.
+    pub fn pending_interrupts(&self) -> u64 {
+        self.mip | self.interrupt_lines
+    }
+
+    pub fn mpp(&self) -> Privilege {
+        Privilege::from_bits((self.mstatus & MPP) >> MPP_SHIFT).unwrap_or(Privilege::User)
+    }
+
+    /// The privilege loads and stores are checked against: MPP when M-mode
+    /// code sets `mstatus.MPRV`, `current` otherwise.
+    pub fn data_privilege(&self, current: Privilege) -> Privilege {
+        if current == Privilege::Machine && self.mstatus & MPRV != 0 { self.mpp() } else { current }
+    }
+
+    pub fn spp(&self) -> Privilege {
+        if self.mstatus & SPP != 0 { Privilege::Supervisor } else { Privilege::User }
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::cpu::Cpu;
+use crate::encoding::{self, Format};
+use crate::instruction::Instruction;
+use crate::xlen::Xlen;
+
+/// The execution counts a [`Cpu`] keeps per instruction index as it runs;
+/// everything in [`ExecStats`] is derived from them and the program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counters {
+    executed: Vec<u64>,
+    /// Taken conditional branches.
+    taken: Vec<u64>,
+}
+
+fn bump(counts: &mut Vec<u64>, idx: usize) {
+    if counts.len() <= idx {
+        counts.resize(idx + 1, 0);
+    }
+    counts[idx] += 1;
+}
+
+impl Counters {
+    pub(crate) fn record(&mut self, pc: usize) {
+        bump(&mut self.executed, pc);
+    }
+
+    pub(crate) fn record_taken(&mut self, pc: usize) {
+        bump(&mut self.taken, pc);
+    }
+
+    /// Times the instruction at `pc` executed.
+    pub fn executed(&self, pc: usize) -> u64 {
+        self.executed.get(pc).copied().unwrap_or(0)
+    }
+
+    /// Times the branch at `pc` was taken.
+    pub fn taken(&self, pc: usize) -> u64 {
+        self.taken.get(pc).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.executed.iter().sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.executed.clear();
+        self.taken.clear();
+    }
+}
+
+/// How often a conditional branch went each way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCount {
+    pub executed: u64,
+    pub taken: u64,
+}
+
+impl BranchCount {
+    pub fn not_taken(&self) -> u64 {
+        self.executed - self.taken
+    }
+
+    pub fn taken_ratio(&self) -> f64 {
+        if self.executed == 0 { 0.0 } else { self.taken as f64 / self.executed as f64 }
+    }
+}
+
+/// One of the most executed instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotSpot {
+    pub pc: usize,
+    pub inst: Instruction,
+    pub count: u64,
+}
+
+/// A report on what a CPU has executed so far.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecStats {
+    pub executed: u64,
+    /// Most executed first, ties by name.
+    pub mnemonics: Vec<(String, u64)>,
+    /// Instructions that do not encode are left out.
+    pub formats: BTreeMap<Format, u64>,
+    /// AMOs count as both.
+    pub loads: u64,
+    pub stores: u64,
+    /// Each conditional branch executed, by pc.
+    pub branches: BTreeMap<usize, BranchCount>,
+    /// Hottest first, ties by pc.
+    pub hot_pcs: Vec<HotSpot>,
+    /// The hottest labels as `(label, count)`, counting every instruction
+    /// up to the next label.
+    pub hot_labels: Vec<(String, u64)>,
+}
+
+impl ExecStats {
+    /// How many hot instructions and labels a report lists.
+    pub const DEFAULT_TOP: usize = 5;
+
+    /// Builds the report from `cpu`'s counters, keeping the `top` hottest
+    /// instructions and labels.
+    pub fn collect<X: Xlen>(cpu: &Cpu<X>, top: usize) -> Self {
+        let counters = cpu.counters();
+        let mut stats = ExecStats::default();
+        let mut mnemonics = BTreeMap::<String, u64>::new();
+        let mut labels = BTreeMap::<&str, u64>::new();
+        for (pc, inst) in cpu.program().iter().enumerate() {
+            let count = counters.executed(pc);
+            if count == 0 {
+                continue;
+            }
+            stats.executed += count;
+            *mnemonics.entry(inst.mnemonic()).or_default() += count;
+            if let Some(format) = encoding::format(inst) {
+                *stats.formats.entry(format).or_default() += count;
+            }
+            if inst.reads_memory() {
+                stats.loads += count;
+            }
+            if inst.writes_memory() {
+                stats.stores += count;
+            }
+            if inst.is_branch() {
+                stats.branches.insert(pc, BranchCount { executed: count, taken: counters.taken(pc) });
+            }
+            if let Some(label) = cpu.label_for(pc) {
+                *labels.entry(label).or_default() += count;
+            }
+            stats.hot_pcs.push(HotSpot { pc, inst: *inst, count });
+        }
+        stats.mnemonics = mnemonics.into_iter().collect();
+        stats.mnemonics.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        stats.hot_pcs.sort_by_key(|spot| std::cmp::Reverse(spot.count));
+        stats.hot_pcs.truncate(top);
+        stats.hot_labels = labels.into_iter().map(|(label, count)| (label.to_string(), count)).collect();
+        stats.hot_labels.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        stats.hot_labels.truncate(top);
+        stats
+    }
+
+    /// All conditional branches together.
+    pub fn branch_totals(&self) -> BranchCount {
+        self.branches.values().fold(BranchCount::default(), |total, branch| BranchCount {
+            executed: total.executed + branch.executed,
+            taken: total.taken + branch.taken,
+        })
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        if self.executed == 0 { 0.0 } else { 100.0 * count as f64 / self.executed as f64 }
+    }
+}
+
+/// The totals, then one section each for mnemonics, formats, branches and
+/// the hot spots.
+impl fmt::Display for ExecStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions executed, {} loads, {} stores", self.executed, self.loads, self.stores)?;
+        writeln!(f, "mnemonics:")?;
+        for (mnemonic, count) in &self.mnemonics {
+            writeln!(f, "  {mnemonic:<12} {count:>8} ({:.1}%)", self.share(*count))?;
+        }
+        let mix = Format::ALL
+            .iter()
+            .map(|format| {
+                let count = self.formats.get(format).copied().unwrap_or(0);
+                format!("{format} {count} ({:.1}%)", self.share(count))
+            })
+            .collect::<Vec<_>>();
+        writeln!(f, "formats: {}", mix.join(", "))?;
+        let totals = self.branch_totals();
+        writeln!(
+            f,
+            "branches: {} executed, {} taken, {} not taken ({:.1}% taken)",
+            totals.executed,
+            totals.taken,
+            totals.not_taken(),
+            100.0 * totals.taken_ratio()
+        )?;
+        for (pc, branch) in &self.branches {
+            writeln!(
+                f,
+                "  {pc:>4}: {:>8} executed, {:>8} taken ({:.1}%)",
+                branch.executed,
+                branch.taken,
+                100.0 * branch.taken_ratio()
+            )?;
+        }
+        writeln!(f, "hot instructions:")?;
+        for spot in &self.hot_pcs {
+            writeln!(f, "  {:>4}: {:>8} ({:.1}%) {:?}", spot.pc, spot.count, self.share(spot.count), spot.inst)?;
+        }
+        write!(f, "hot labels:")?;
+        for (label, count) in &self.hot_labels {
+            write!(f, "\n  {label:<12} {count:>8} ({:.1}%)", self.share(*count))?;
+        }
+        Ok(())
+    }
+}
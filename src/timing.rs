@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use crate::cpu::{Cpu, CpuError};
+use crate::engine::Engine;
+use crate::instruction::Instruction;
+use crate::tomasulo::Unit;
+use crate::xlen::Xlen;
+
+/// The instruction classes latencies are given for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Class {
+    Alu,
+    Mul,
+    Div,
+    Load,
+    Store,
+    Taken,
+    NotTaken,
+}
+
+impl Class {
+    pub const ALL: [Class; 7] =
+        [Class::Alu, Class::Mul, Class::Div, Class::Load, Class::Store, Class::Taken, Class::NotTaken];
+
+    /// The key the class goes by in a latency file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Alu => "alu",
+            Class::Mul => "mul",
+            Class::Div => "div",
+            Class::Load => "load",
+            Class::Store => "store",
+            Class::Taken => "taken",
+            Class::NotTaken => "not-taken",
+        }
+    }
+
+    /// The class of `inst` at `pc`, which moved on to `next_pc`. Jumps
+    /// count as taken branches, AMOs as loads.
+    pub fn of(inst: &Instruction, pc: usize, next_pc: usize) -> Class {
+        if inst.is_jump() || (inst.is_branch() && next_pc != pc + 1) {
+            return Class::Taken;
+        }
+        if inst.is_branch() {
+            return Class::NotTaken;
+        }
+        match Unit::of(inst) {
+            Unit::Alu => Class::Alu,
+            Unit::Mul => Class::Mul,
+            Unit::Div => Class::Div,
+            Unit::Mem if inst.reads_memory() => Class::Load,
+            Unit::Mem => Class::Store,
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TimingError {
+    #[error("line {line}: expected <key> = <cycles>")]
+    BadLine { line: usize },
+    #[error("line {line}: unknown latency `{key}`")]
+    UnknownKey { line: usize, key: String },
+    #[error("line {line}: bad cycle count `{value}`")]
+    BadNumber { line: usize, value: String },
+}
+
+/// Cycles per instruction class, plus `memory` cycles for every access
+/// that reaches memory. Without caches that is each load and store; with
+/// caches, each line a miss or write-back moves, instruction fetches
+/// included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latencies {
+    pub alu: usize,
+    pub mul: usize,
+    pub div: usize,
+    pub load: usize,
+    pub store: usize,
+    pub taken: usize,
+    pub not_taken: usize,
+    pub memory: usize,
+}
+
+impl Latencies {
+    pub fn of(&self, class: Class) -> usize {
+        match class {
+            Class::Alu => self.alu,
+            Class::Mul => self.mul,
+            Class::Div => self.div,
+            Class::Load => self.load,
+            Class::Store => self.store,
+            Class::Taken => self.taken,
+            Class::NotTaken => self.not_taken,
+        }
+    }
+
+    fn key_mut(&mut self, key: &str) -> Option<&mut usize> {
+        match key {
+            "alu" => Some(&mut self.alu),
+            "mul" => Some(&mut self.mul),
+            "div" => Some(&mut self.div),
+            "load" => Some(&mut self.load),
+            "store" => Some(&mut self.store),
+            "taken" => Some(&mut self.taken),
+            "not-taken" => Some(&mut self.not_taken),
+            "memory" => Some(&mut self.memory),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Latencies { alu: 1, mul: 3, div: 20, load: 2, store: 1, taken: 2, not_taken: 1, memory: 10 }
+    }
+}
+
+/// Parses a latency file: `<key> = <cycles>` lines, where the keys are the
+/// [`Class`] names and `memory`. Keys left out keep their defaults; `#`
+/// starts a comment.
+impl FromStr for Latencies {
+    type Err = TimingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut latencies = Latencies::default();
+        for (i, text) in s.lines().enumerate() {
+            let line = i + 1;
+            let text = text.split('#').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = text.split_once('=') else {
+                return Err(TimingError::BadLine { line });
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let slot = latencies.key_mut(key).ok_or_else(|| TimingError::UnknownKey { line, key: key.to_string() })?;
+            *slot = value.parse().map_err(|_| TimingError::BadNumber { line, value: value.to_string() })?;
+        }
+        Ok(latencies)
+    }
+}
+
+/// In the latency file format.
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for class in Class::ALL {
+            writeln!(f, "{class} = {}", self.of(class))?;
+        }
+        write!(f, "memory = {}", self.memory)
+    }
+}
+
+/// Instructions run and the cycles they took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub instructions: usize,
+    pub cycles: usize,
+}
+
+impl Tally {
+    fn add(&mut self, cycles: usize) {
+        self.instructions += 1;
+        self.cycles += cycles;
+    }
+}
+
+/// The code from one label up to the next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelTime {
+    /// `None` for code before the first label.
+    pub label: Option<String>,
+    pub time: Tally,
+}
+
+/// Runs a [`Cpu`] one instruction at a time, each taking its class's
+/// latency plus the memory latency of its memory traffic, at least one
+/// cycle, and breaks the cycles down by class and by label.
+#[derive(Debug, Clone, Default)]
+pub struct TimingModel {
+    latencies: Latencies,
+    classes: BTreeMap<Class, Tally>,
+    memory_accesses: usize,
+    /// By the index of the label's instruction.
+    labels: BTreeMap<usize, LabelTime>,
+    /// Cycles the last instruction still has to run.
+    busy: usize,
+    cycles: usize,
+    retired: usize,
+}
+
+impl TimingModel {
+    pub fn new(latencies: Latencies) -> Self {
+        TimingModel { latencies, ..Self::default() }
+    }
+
+    pub fn latencies(&self) -> Latencies {
+        self.latencies
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn retired(&self) -> usize {
+        self.retired
+    }
+
+    /// Cycles per retired instruction.
+    pub fn cpi(&self) -> f64 {
+        if self.retired == 0 { 0.0 } else { self.cycles as f64 / self.retired as f64 }
+    }
+
+    /// The classes run so far; memory cycles are not included.
+    pub fn classes(&self) -> &BTreeMap<Class, Tally> {
+        &self.classes
+    }
+
+    pub fn memory_accesses(&self) -> usize {
+        self.memory_accesses
+    }
+
+    /// The time spent under each label, in program order.
+    pub fn labels(&self) -> impl Iterator<Item = &LabelTime> {
+        self.labels.values()
+    }
+
+    /// Runs one clock cycle, executing the instruction at `cpu.pc` once the
+    /// last one is done. Returns `Ok(false)` when there is nothing left to
+    /// run.
+    pub fn step<X: Xlen>(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        if self.busy > 0 {
+            self.busy -= 1;
+            self.cycles += 1;
+            return Ok(true);
+        }
+        let pc = cpu.pc;
+        let Some(&inst) = cpu.program().get(pc) else {
+            return Ok(false);
+        };
+        let traffic = |cpu: &Cpu<X>| cpu.caches().memory_reads + cpu.caches().memory_writes;
+        let (before, instret) = (traffic(cpu), cpu.instret());
+        if !cpu.execute_next()? {
+            return Ok(false);
+        }
+        if cpu.instret() == instret {
+            // A trap or interrupt: one cycle to redirect to the handler, with
+            // nothing retired and nothing charged to a class or label.
+            self.cycles += 1;
+            return Ok(true);
+        }
+        let class = Class::of(&inst, pc, cpu.pc);
+        let accesses = if cpu.caches().is_empty() {
+            (inst.reads_memory() || inst.writes_memory()) as usize
+        } else {
+            (traffic(cpu) - before) as usize
+        };
+        let latency = self.latencies.of(class);
+        let cycles = (latency + accesses * self.latencies.memory).max(1);
+
+        self.classes.entry(class).or_default().add(latency);
+        self.memory_accesses += accesses;
+        let (start, label) = match cpu.labels().range(..=pc).next_back() {
+            Some((&start, label)) => (start, Some(label.clone())),
+            None => (0, None),
+        };
+        self.labels.entry(start).or_insert(LabelTime { label, time: Tally::default() }).time.add(cycles);
+        self.busy = cycles - 1;
+        self.cycles += 1;
+        self.retired += 1;
+        Ok(true)
+    }
+}
+
+impl<X: Xlen> Engine<X> for TimingModel {
+    fn name(&self) -> &'static str {
+        "timing"
+    }
+
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        TimingModel::step(self, cpu)
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    fn retired(&self) -> usize {
+        self.retired
+    }
+}
+
+/// The totals, the cycles by class, then by label.
+impl fmt::Display for TimingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} cycles, {} instructions, CPI {:.2}", self.cycles, self.retired, self.cpi())?;
+        for (class, tally) in &self.classes {
+            write!(
+                f,
+                "\n  {:<10} {:>7} x {:>3} = {:>8} cycles",
+                class.name(),
+                tally.instructions,
+                self.latencies.of(*class),
+                tally.cycles
+            )?;
+        }
+        write!(
+            f,
+            "\n  {:<10} {:>7} x {:>3} = {:>8} cycles",
+            "memory",
+            self.memory_accesses,
+            self.latencies.memory,
+            self.memory_accesses * self.latencies.memory
+        )?;
+        for label in self.labels() {
+            let share = if self.cycles == 0 { 0.0 } else { 100.0 * label.time.cycles as f64 / self.cycles as f64 };
+            write!(
+                f,
+                "\n  {:<20} {:>7} instructions {:>8} cycles ({share:.1}%)",
+                label.label.as_deref().unwrap_or("(no label)"),
+                label.time.instructions,
+                label.time.cycles
+            )?;
+        }
+        Ok(())
+    }
+}
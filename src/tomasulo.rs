@@ -0,0 +1,576 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use crate::cpu::{Cpu, CpuError};
+use crate::datapath::Fetched;
+use crate::engine::Engine;
+use crate::instruction::{Instruction, Reg};
+use crate::xlen::Xlen;
+
+/// The functional-unit classes instructions are dispatched to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unit {
+    Alu,
+    Mul,
+    Div,
+    Mem,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 4] = [Unit::Alu, Unit::Mul, Unit::Div, Unit::Mem];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Alu => "ALU",
+            Unit::Mul => "Mul",
+            Unit::Div => "Div",
+            Unit::Mem => "Mem",
+        }
+    }
+
+    /// Multiplies and fused multiply-adds go to Mul, divides, remainders
+    /// and square roots to Div, memory accesses to Mem, the rest to ALU.
+    pub fn of(inst: &Instruction) -> Unit {
+        use Instruction::*;
+        match inst {
+            Mul { .. } | Mulh { .. } | Mulhsu { .. } | Mulhu { .. } | Mulw { .. } | Clmul { .. } | Clmulh { .. }
+            | Clmulr { .. } | FmulS { .. } | FmulD { .. } | FmaddS { .. } | FmsubS { .. } | FnmsubS { .. }
+            | FnmaddS { .. } | FmaddD { .. } | FmsubD { .. } | FnmsubD { .. } | FnmaddD { .. } | VmulVv { .. }
+            | VmulVx { .. } => Unit::Mul,
+            Div { .. } | Divu { .. } | Rem { .. } | Remu { .. } | Divw { .. } | Divuw { .. } | Remw { .. }
+            | Remuw { .. } | FdivS { .. } | FdivD { .. } | FsqrtS { .. } | FsqrtD { .. } => Unit::Div,
+            _ if inst.reads_memory() || inst.writes_memory() => Unit::Mem,
+            _ => Unit::Alu,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TomasuloError {
+    #[error("bad number `{0}`")]
+    BadNumber(String),
+    #[error("expected <stations>:<units>:<latency>, got `{0}`")]
+    BadUnit(String),
+    #[error("{0} must be at least 1")]
+    Zero(&'static str),
+}
+
+/// One unit class: the reservation stations feeding it, how many units
+/// share them, and the cycles each instruction spends in a unit. Units are
+/// not pipelined, so a unit takes a new instruction once the last is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnitConfig {
+    pub stations: usize,
+    pub units: usize,
+    pub latency: usize,
+}
+
+impl UnitConfig {
+    pub fn new(stations: usize, units: usize, latency: usize) -> Result<Self, TomasuloError> {
+        for (what, value) in [("stations", stations), ("units", units), ("latency", latency)] {
+            if value == 0 {
+                return Err(TomasuloError::Zero(what));
+            }
+        }
+        Ok(UnitConfig { stations, units, latency })
+    }
+}
+
+/// Parses `<stations>:<units>:<latency>`.
+impl FromStr for UnitConfig {
+    type Err = TomasuloError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        let [stations, units, latency] = parts[..] else {
+            return Err(TomasuloError::BadUnit(s.to_string()));
+        };
+        let number = |part: &str| part.parse::<usize>().map_err(|_| TomasuloError::BadNumber(part.to_string()));
+        UnitConfig::new(number(stations)?, number(units)?, number(latency)?)
+    }
+}
+
+impl fmt::Display for UnitConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} stations, {} unit(s), {} cycle(s)", self.stations, self.units, self.latency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TomasuloConfig {
+    /// Reorder buffer entries.
+    pub rob: usize,
+    pub alu: UnitConfig,
+    pub mul: UnitConfig,
+    pub div: UnitConfig,
+    pub mem: UnitConfig,
+}
+
+impl TomasuloConfig {
+    pub fn unit(&self, unit: Unit) -> UnitConfig {
+        match unit {
+            Unit::Alu => self.alu,
+            Unit::Mul => self.mul,
+            Unit::Div => self.div,
+            Unit::Mem => self.mem,
+        }
+    }
+}
+
+impl Default for TomasuloConfig {
+    fn default() -> Self {
+        TomasuloConfig {
+            rob: 8,
+            alu: UnitConfig { stations: 3, units: 1, latency: 1 },
+            mul: UnitConfig { stations: 2, units: 1, latency: 4 },
+            div: UnitConfig { stations: 2, units: 1, latency: 12 },
+            mem: UnitConfig { stations: 3, units: 1, latency: 2 },
+        }
+    }
+}
+
+/// A source operand as a reservation station holds it: the value once it
+/// is known, or the ROB entry that will broadcast it on the CDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operand {
+    pub reg: Reg,
+    pub value: i64,
+    pub producer: Option<usize>,
+    /// The first cycle the value can be used in.
+    ready_at: usize,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.producer, self.reg) {
+            (Some(id), reg) => write!(f, "{reg}=#{id}"),
+            (None, Reg::V(r)) => write!(f, "v{r}"),
+            (None, reg) => write!(f, "{reg}={}", self.value),
+        }
+    }
+}
+
+/// Where an instruction is between issue and commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// In a reservation station, waiting for operands or a free unit.
+    Issued,
+    Executing,
+    /// Done in its unit, waiting for the CDB.
+    Finished,
+    /// Result in the ROB, waiting to reach the head.
+    Written,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Status::Issued => "issued",
+            Status::Executing => "executing",
+            Status::Finished => "waiting for CDB",
+            Status::Written => "written",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The cycles an instruction passed each step in, from 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub issue: usize,
+    /// First and last cycle in a unit.
+    pub execute: Option<(usize, usize)>,
+    pub write: Option<usize>,
+    pub commit: Option<usize>,
+}
+
+/// A reorder buffer entry, tagged `#id` in the tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RobEntry {
+    pub id: usize,
+    pub pc: usize,
+    pub inst: Instruction,
+    pub unit: Unit,
+    /// Index of its reservation station within the unit class.
+    pub station: usize,
+    pub dest: Option<Reg>,
+    pub sources: Vec<Operand>,
+    pub status: Status,
+    /// The result, once written. Vector results are not shown.
+    pub value: Option<i64>,
+    pub timing: Timing,
+    /// The effective address, for the memory accesses the datapath traces.
+    pub address: Option<u64>,
+    result: Option<i64>,
+    /// Branches, jumps and `xret`s, which hold up issue until
+    /// they are written.
+    control: bool,
+}
+
+/// A reservation station and the ROB entry it holds, if busy.
+#[derive(Debug, Clone, Copy)]
+pub struct Station<'a> {
+    pub unit: Unit,
+    pub index: usize,
+    pub entry: Option<&'a RobEntry>,
+}
+
+impl Station<'_> {
+    /// `Mul2` for the second Mul station.
+    pub fn name(&self) -> String {
+        format!("{}{}", self.unit, self.index + 1)
+    }
+}
+
+/// What happened in the last cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Issue { id: usize, pc: usize, station: String },
+    Execute { id: usize, unit: Unit, done: usize },
+    Write { id: usize, value: Option<i64> },
+    Commit { id: usize, pc: usize },
+    /// A trap or interrupt at `pc` redirected issue to the handler; nothing
+    /// entered the ROB.
+    Flush { pc: usize, target: usize },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Issue { id, pc, station } => write!(f, "issue {pc} as #{id} to {station}"),
+            Event::Execute { id, unit, done } => write!(f, "#{id} starts on {unit}, done in cycle {done}"),
+            Event::Write { id, value: Some(value) } => write!(f, "#{id} broadcasts {value} on the CDB"),
+            Event::Write { id, value: None } => write!(f, "#{id} written"),
+            Event::Commit { id, pc } => write!(f, "commit #{id} ({pc})"),
+            Event::Flush { pc, target } => write!(f, "trap at {pc}, issue continues at {target}"),
+        }
+    }
+}
+
+/// Whether two accesses may touch the same doubleword; unknown addresses
+/// may alias anything.
+fn may_alias(a: Option<u64>, b: Option<u64>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a >> 3 == b >> 3,
+        _ => true,
+    }
+}
+
+/// Tomasulo's algorithm with a reorder buffer driving a [`Cpu`]: one
+/// instruction issues per cycle in order to a reservation station, renaming
+/// its destination to its ROB entry; it executes once its operands are on
+/// hand and a unit is free, broadcasts its result on the single CDB, and
+/// commits in order from the ROB head. Each instruction executes on the CPU
+/// as it issues, so the architectural results are the interpreter's and the
+/// model only decides timing. There is no speculation: issue stops behind a
+/// branch or jump until it is written.
+pub struct Tomasulo {
+    config: TomasuloConfig,
+    rob: VecDeque<RobEntry>,
+    /// Per unit class, the ROB entry each station holds.
+    stations: [Vec<Option<usize>>; 4],
+    /// Per unit class, the cycle each unit is free from.
+    units: [Vec<usize>; 4],
+    /// Register status: the ROB entry that will write each renamed register.
+    renamed: BTreeMap<Reg, usize>,
+    committed: Vec<RobEntry>,
+    events: Vec<Event>,
+    next_id: usize,
+    cycle: usize,
+    retired: usize,
+}
+
+impl Tomasulo {
+    pub fn new(config: TomasuloConfig) -> Self {
+        let stations = Unit::ALL.map(|unit| vec![None; config.unit(unit).stations]);
+        let units = Unit::ALL.map(|unit| vec![0; config.unit(unit).units]);
+        Tomasulo {
+            config,
+            rob: VecDeque::new(),
+            stations,
+            units,
+            renamed: BTreeMap::new(),
+            committed: vec![],
+            events: vec![],
+            next_id: 1,
+            cycle: 0,
+            retired: 0,
+        }
+    }
+
+    pub fn config(&self) -> TomasuloConfig {
+        self.config
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn retired(&self) -> usize {
+        self.retired
+    }
+
+    /// Cycles per retired instruction.
+    pub fn cpi(&self) -> f64 {
+        if self.retired == 0 { 0.0 } else { self.cycle as f64 / self.retired as f64 }
+    }
+
+    /// The reorder buffer, head first.
+    pub fn rob(&self) -> &VecDeque<RobEntry> {
+        &self.rob
+    }
+
+    /// Every reservation station, by unit class.
+    pub fn stations(&self) -> Vec<Station<'_>> {
+        Unit::ALL
+            .into_iter()
+            .flat_map(|unit| {
+                self.stations[unit as usize].iter().enumerate().map(move |(index, id)| Station {
+                    unit,
+                    index,
+                    entry: id.and_then(|id| self.entry(id)),
+                })
+            })
+            .collect()
+    }
+
+    /// The registers renamed to an uncommitted ROB entry.
+    pub fn register_status(&self) -> &BTreeMap<Reg, usize> {
+        &self.renamed
+    }
+
+    /// The instructions committed so far, in order.
+    pub fn committed(&self) -> &[RobEntry] {
+        &self.committed
+    }
+
+    /// What the last [`step`](Self::step) did.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    fn entry(&self, id: usize) -> Option<&RobEntry> {
+        self.rob.iter().find(|entry| entry.id == id)
+    }
+
+    /// Runs one clock cycle: commit, write result, execute, then issue, so
+    /// each instruction spends at least a cycle in every step. Returns
+    /// `Ok(false)` once the ROB is empty and there is nothing left to issue.
+    pub fn step<X: Xlen>(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        if self.rob.is_empty() && cpu.program().get(cpu.pc).is_none() {
+            return Ok(false);
+        }
+        self.cycle += 1;
+        self.events.clear();
+        self.commit();
+        self.write_result();
+        self.start_execution();
+        self.issue(cpu)?;
+        Ok(true)
+    }
+
+    fn commit(&mut self) {
+        if self.rob.front().is_none_or(|head| head.status != Status::Written) {
+            return;
+        }
+        let mut entry = self.rob.pop_front().expect("the head was written");
+        entry.timing.commit = Some(self.cycle);
+        if let Some(dest) = entry.dest
+            && self.renamed.get(&dest) == Some(&entry.id)
+        {
+            self.renamed.remove(&dest);
+        }
+        self.retired += 1;
+        self.events.push(Event::Commit { id: entry.id, pc: entry.pc });
+        self.committed.push(entry);
+    }
+
+    /// Instructions without a destination are written without the CDB;
+    /// the oldest finished one with a destination gets the bus.
+    fn write_result(&mut self) {
+        let cycle = self.cycle;
+        for entry in self.rob.iter_mut() {
+            if entry.status == Status::Executing && entry.timing.execute.is_some_and(|(_, done)| done < cycle) {
+                entry.status = Status::Finished;
+            }
+        }
+        let ids = self
+            .rob
+            .iter()
+            .filter(|entry| entry.status == Status::Finished)
+            .filter(|entry| entry.dest.is_none())
+            .map(|entry| entry.id)
+            .chain(self.rob.iter().find(|entry| entry.status == Status::Finished && entry.dest.is_some()).map(|e| e.id))
+            .collect::<Vec<_>>();
+        for id in ids {
+            let entry = self.rob.iter_mut().find(|entry| entry.id == id).expect("the entry is in the ROB");
+            entry.status = Status::Written;
+            entry.timing.write = Some(cycle);
+            entry.value = entry.result;
+            self.stations[entry.unit as usize][entry.station] = None;
+            self.events.push(Event::Write { id, value: entry.value });
+            let value = entry.value;
+            for operand in self.rob.iter_mut().flat_map(|entry| entry.sources.iter_mut()) {
+                if operand.producer == Some(id) {
+                    operand.producer = None;
+                    operand.value = value.unwrap_or(operand.value);
+                    operand.ready_at = cycle + 1;
+                }
+            }
+        }
+    }
+
+    /// Starts every issued instruction, oldest first, whose operands are
+    /// ready and whose unit class has a free unit. A load also waits for
+    /// older stores that may write its address.
+    fn start_execution(&mut self) {
+        let cycle = self.cycle;
+        for i in 0..self.rob.len() {
+            let entry = &self.rob[i];
+            if entry.status != Status::Issued || entry.timing.issue >= cycle {
+                continue;
+            }
+            if entry.sources.iter().any(|op| op.producer.is_some() || op.ready_at > cycle) {
+                continue;
+            }
+            if entry.inst.reads_memory()
+                && self.rob.iter().take(i).any(|older| {
+                    older.inst.writes_memory() && older.status != Status::Written && may_alias(older.address, entry.address)
+                })
+            {
+                continue;
+            }
+            let unit = entry.unit;
+            let Some(free) = self.units[unit as usize].iter().position(|&free_at| free_at <= cycle) else {
+                continue;
+            };
+            let latency = self.config.unit(unit).latency;
+            self.units[unit as usize][free] = cycle + latency;
+            let entry = &mut self.rob[i];
+            entry.status = Status::Executing;
+            entry.timing.execute = Some((cycle, cycle + latency - 1));
+            self.events.push(Event::Execute { id: entry.id, unit, done: cycle + latency - 1 });
+        }
+    }
+
+    /// Issues the instruction at `cpu.pc` if the ROB and a station of its
+    /// unit class have room and no branch or jump is unresolved.
+    fn issue<X: Xlen>(&mut self, cpu: &mut Cpu<X>) -> Result<(), CpuError> {
+        let pc = cpu.pc;
+        let Some(&inst) = cpu.program().get(pc) else {
+            return Ok(());
+        };
+        if self.rob.len() >= self.config.rob || self.rob.iter().any(|e| e.control && e.status != Status::Written) {
+            return Ok(());
+        }
+        let unit = Unit::of(&inst);
+        let Some(station) = self.stations[unit as usize].iter().position(Option::is_none) else {
+            return Ok(());
+        };
+
+        let sources = inst
+            .sources()
+            .into_iter()
+            .map(|reg| {
+                let value = match reg {
+                    Reg::X(r) => X::to_i64(cpu.regs[r]),
+                    Reg::F(r) => cpu.fregs[r] as i64,
+                    Reg::V(_) => 0,
+                };
+                let producer = self
+                    .renamed
+                    .get(&reg)
+                    .copied()
+                    .filter(|&id| self.entry(id).is_some_and(|entry| entry.status != Status::Written));
+                Operand { reg, value, producer, ready_at: self.cycle + 1 }
+            })
+            .collect();
+        let (fetched, instret) = (Fetched::new(cpu), cpu.instret());
+        cpu.execute_next()?;
+        if cpu.instret() == instret {
+            self.events.push(Event::Flush { pc, target: cpu.pc });
+            return Ok(());
+        }
+        let address = fetched.and_then(|fetched| fetched.complete(cpu).mem_addr);
+        let dest = inst.destination();
+        let result = dest.and_then(|reg| match reg {
+            Reg::X(r) => Some(X::to_i64(cpu.regs[r])),
+            Reg::F(r) => Some(cpu.fregs[r] as i64),
+            Reg::V(_) => None,
+        });
+
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(dest) = dest {
+            self.renamed.insert(dest, id);
+        }
+        self.stations[unit as usize][station] = Some(id);
+        let name = Station { unit, index: station, entry: None }.name();
+        self.events.push(Event::Issue { id, pc, station: name });
+        self.rob.push_back(RobEntry {
+            id,
+            pc,
+            inst,
+            unit,
+            station,
+            dest,
+            sources,
+            status: Status::Issued,
+            value: None,
+            timing: Timing { issue: self.cycle, ..Timing::default() },
+            address,
+            result,
+            control: inst.is_branch() || inst.is_jump() || cpu.pc != pc + 1,
+        });
+        Ok(())
+    }
+}
+
+impl Default for Tomasulo {
+    fn default() -> Self {
+        Self::new(TomasuloConfig::default())
+    }
+}
+
+impl<X: Xlen> Engine<X> for Tomasulo {
+    fn name(&self) -> &'static str {
+        "tomasulo"
+    }
+
+    fn step(&mut self, cpu: &mut Cpu<X>) -> Result<bool, CpuError> {
+        Tomasulo::step(self, cpu)
+    }
+
+    fn cycles(&self) -> usize {
+        self.cycle
+    }
+
+    fn retired(&self) -> usize {
+        self.retired
+    }
+}
+
+/// The totals and the configuration.
+impl fmt::Display for Tomasulo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cycles, {} instructions, CPI {:.2} ({} ROB entries",
+            self.cycle,
+            self.retired,
+            self.cpi(),
+            self.config.rob
+        )?;
+        for unit in Unit::ALL {
+            write!(f, "; {unit}: {}", self.config.unit(unit))?;
+        }
+        write!(f, ")")
+    }
+}
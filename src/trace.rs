@@ -0,0 +1,115 @@
+use std::fmt;
+use crate::instruction::{Instruction, Reg};
+use crate::mmu::Access;
+use crate::privilege::{Privilege, Trap};
+
+/// A scalar register an instruction read, and its value then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegRead {
+    pub reg: Reg,
+    pub value: i64,
+}
+
+/// A scalar register write. FP registers hold their raw bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegWrite {
+    pub reg: Reg,
+    pub old: i64,
+    pub new: i64,
+}
+
+/// One data access. `old` and `new` are the memory contents before and
+/// after the step, the same for loads; they are `None` outside RAM, where
+/// reading back could disturb a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub size: usize,
+    pub access: Access,
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+/// A trap the step entered, and the mode its handler runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapRecord {
+    pub trap: Trap,
+    pub tval: u64,
+    pub privilege: Privilege,
+}
+
+/// Everything one step of a CPU did, for tracers and checkers that would
+/// otherwise diff the whole CPU. `pc` and `next_pc` are instruction
+/// indices. Vector registers are left out of the register reads and
+/// writes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepRecord {
+    pub pc: usize,
+    pub next_pc: usize,
+    /// `None` when the step took an interrupt instead of executing.
+    pub inst: Option<Instruction>,
+    pub reads: Vec<RegRead>,
+    /// `None` when the instruction writes no scalar register or trapped.
+    pub write: Option<RegWrite>,
+    pub memory: Vec<MemAccess>,
+    /// Whether a conditional branch was taken.
+    pub branch: Option<bool>,
+    pub trap: Option<TrapRecord>,
+}
+
+/// What the CPU notes during a step while tracing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Recording {
+    pub(crate) memory: Vec<MemAccess>,
+    pub(crate) branch: Option<bool>,
+    pub(crate) trap: Option<TrapRecord>,
+}
+
+/// Bytes each data access of `inst` moves.
+pub(crate) fn access_size(inst: &Instruction) -> usize {
+    use Instruction::*;
+    match inst {
+        Lb { .. } | Lbu { .. } | Sb { .. } => 1,
+        Lh { .. } | Lhu { .. } | Sh { .. } => 2,
+        Ld { .. } | Sd { .. } | Fld { .. } | Fsd { .. } => 8,
+        VleV { eew, .. } | VlseV { eew, .. } | VseV { eew, .. } | VsseV { eew, .. } => eew.bytes(),
+        _ => 4,
+    }
+}
+
+/// `pc -> next_pc: instruction`, then one indented line per effect.
+impl fmt::Display for StepRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.inst {
+            Some(inst) => write!(f, "{} -> {}: {inst:?}", self.pc, self.next_pc)?,
+            None => write!(f, "{} -> {}: interrupted", self.pc, self.next_pc)?,
+        }
+        if !self.reads.is_empty() {
+            let reads = self.reads.iter().map(|r| format!("{} = {}", r.reg, r.value)).collect::<Vec<_>>();
+            write!(f, "\n  read {}", reads.join(", "))?;
+        }
+        if let Some(w) = &self.write {
+            write!(f, "\n  {}: {} -> {}", w.reg, w.old, w.new)?;
+        }
+        for m in &self.memory {
+            let value = |v: Option<u64>| v.map_or("?".to_string(), |v| format!("{v:#x}"));
+            write!(f, "\n  {} {} bytes at {:#x}", m.access, m.size, m.vaddr)?;
+            if m.paddr != m.vaddr {
+                write!(f, " (phys {:#x})", m.paddr)?;
+            }
+            if m.access == Access::Store {
+                write!(f, ": {} -> {}", value(m.old), value(m.new))?;
+            } else {
+                write!(f, ": {}", value(m.new))?;
+            }
+        }
+        if let Some(taken) = self.branch {
+            write!(f, "\n  branch {}", if taken { "taken" } else { "not taken" })?;
+        }
+        if let Some(t) = &self.trap {
+            write!(f, "\n  trap {:?}, tval {:#x}, into {:?} mode", t.trap, t.tval, t.privilege)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+use std::io::{BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use crate::bus::Device;
+use crate::memory::MemoryError;
+
+/// Where QEMU's `virt` machine puts its UART, and the PLIC source it uses.
+pub const VIRT_UART_BASE: u64 = 0x1000_0000;
+pub const VIRT_UART_IRQ: u32 = 10;
+
+// Register offsets; 0 and 1 hold the divisor latch while LCR.DLAB is set.
+const RBR_THR: u64 = 0;
+const IER: u64 = 1;
+const IIR_FCR: u64 = 2;
+const LCR: u64 = 3;
+const MCR: u64 = 4;
+const LSR: u64 = 5;
+const MSR: u64 = 6;
+const SCR: u64 = 7;
+
+pub const IER_RX: u8 = 1 << 0;
+pub const IER_THRE: u8 = 1 << 1;
+
+const IIR_NONE: u8 = 0x01;
+const IIR_THRE: u8 = 0x02;
+const IIR_RX: u8 = 0x04;
+const IIR_FIFO: u8 = 0xC0;
+
+pub const LSR_DR: u8 = 1 << 0;
+pub const LSR_THRE: u8 = 1 << 5;
+pub const LSR_TEMT: u8 = 1 << 6;
+
+const LCR_DLAB: u8 = 1 << 7;
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR_RX: u8 = 1 << 1;
+
+/// Spawns a thread that forwards the bytes of `reader` to a UART.
+pub fn input_from(reader: impl Read + Send + 'static) -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in BufReader::new(reader).bytes() {
+            match byte {
+                Ok(byte) if tx.send(byte).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+    rx
+}
+
+/// A 16550-compatible UART with byte-wide registers. Transmission is
+/// instantaneous, so the transmitter always reads as empty; received bytes
+/// arrive from `input` as the device ticks.
+pub struct Uart {
+    output: Box<dyn Write>,
+    input: Option<Receiver<u8>>,
+    rx: VecDeque<u8>,
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    fcr: u8,
+    scr: u8,
+    divisor: u16,
+    /// THR-empty interrupt, cleared by reading IIR or writing THR.
+    thre_pending: bool,
+}
+
+impl Uart {
+    pub fn new(output: Box<dyn Write>, input: Option<Receiver<u8>>) -> Self {
+        Uart {
+            output,
+            input,
+            rx: VecDeque::new(),
+            ier: 0,
+            lcr: 0,
+            mcr: 0,
+            fcr: 0,
+            scr: 0,
+            divisor: 0,
+            thre_pending: false,
+        }
+    }
+
+    fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+
+    /// The highest-priority pending interrupt, as IIR reports it.
+    fn pending(&self) -> u8 {
+        if self.ier & IER_RX != 0 && !self.rx.is_empty() {
+            IIR_RX
+        } else if self.ier & IER_THRE != 0 && self.thre_pending {
+            IIR_THRE
+        } else {
+            IIR_NONE
+        }
+    }
+
+    fn read_reg(&mut self, offset: u64) -> u8 {
+        match offset {
+            RBR_THR if self.dlab() => self.divisor as u8,
+            RBR_THR => self.rx.pop_front().unwrap_or(0),
+            IER if self.dlab() => (self.divisor >> 8) as u8,
+            IER => self.ier,
+            IIR_FCR => {
+                let iir = self.pending();
+                if iir == IIR_THRE {
+                    self.thre_pending = false;
+                }
+                let fifo = if self.fcr & FCR_ENABLE != 0 { IIR_FIFO } else { 0 };
+                iir | fifo
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => LSR_THRE | LSR_TEMT | if self.rx.is_empty() { 0 } else { LSR_DR },
+            MSR => 0,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write_reg(&mut self, offset: u64, val: u8) {
+        match offset {
+            RBR_THR if self.dlab() => self.divisor = (self.divisor & 0xFF00) | val as u16,
+            RBR_THR => {
+                self.output.write_all(&[val]).and_then(|_| self.output.flush()).ok();
+                self.thre_pending = true;
+            }
+            IER if self.dlab() => self.divisor = (self.divisor & 0x00FF) | (val as u16) << 8,
+            IER => {
+                // Enabling the THR-empty interrupt raises it right away.
+                if val & IER_THRE != 0 && self.ier & IER_THRE == 0 {
+                    self.thre_pending = true;
+                }
+                self.ier = val & 0x0F;
+            }
+            IIR_FCR => {
+                if val & FCR_CLEAR_RX != 0 {
+                    self.rx.clear();
+                }
+                self.fcr = val;
+            }
+            LCR => self.lcr = val,
+            MCR => self.mcr = val,
+            SCR => self.scr = val,
+            _ => {}
+        }
+    }
+}
+
+impl Device for Uart {
+    fn name(&self) -> &str {
+        "uart"
+    }
+
+    /// QEMU reserves a 256-byte window; only the first eight are registers.
+    fn size(&self) -> u64 {
+        0x100
+    }
+
+    fn read(&mut self, offset: u64, _size: usize) -> Result<u64, MemoryError> {
+        Ok(self.read_reg(offset) as u64)
+    }
+
+    fn write(&mut self, offset: u64, _size: usize, val: u64) -> Result<(), MemoryError> {
+        self.write_reg(offset, val as u8);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        if let Some(input) = &self.input {
+            self.rx.extend(input.try_iter());
+        }
+    }
+
+    fn interrupt(&self) -> bool {
+        self.pending() != IIR_NONE
+    }
+}
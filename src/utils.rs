@@ -1,14 +1,63 @@
-use crate::cpu::Cpu;
+use crate::cpu::{Cpu, CpuError};
+use crate::engine::EngineKind;
 use crate::instruction::Instruction;
+use crate::xlen::{Rv32, Xlen};
 
 // Test Utils
 pub fn run_program(program: Vec<Instruction>, entry: usize) -> Cpu {
-    let mut cpu = Cpu::default();
-    cpu.load_instructions(program);
-    cpu.pc = entry;
-    while cpu.execute_next().unwrap() {}
-    cpu
+    run_program_on::<Rv32>(program, entry)
 }
+
+/// [`run_program`] for a chosen register width.
+pub fn run_program_on<X: Xlen>(program: Vec<Instruction>, entry: usize) -> Cpu<X> {
+    run_on_every_engine(|| {
+        let mut cpu = Cpu::<X>::with_memory(1024);
+        cpu.load_instructions(program.clone());
+        cpu.pc = entry;
+        cpu
+    })
+    .unwrap()
+}
+
+/// Runs a CPU from `setup` to the end on each [`EngineKind`] and panics
+/// unless they all finish in the same architectural state, or fail with
+/// the same error, and each engine retired what the CPU did. Returns the
+/// functional engine's outcome.
+pub fn run_on_every_engine<X: Xlen>(setup: impl Fn() -> Cpu<X>) -> Result<Cpu<X>, CpuError> {
+    let mut outcomes = EngineKind::ALL.into_iter().map(|kind| {
+        let mut cpu = setup();
+        let instret = cpu.instret();
+        let mut engine = kind.build::<X>();
+        let result = loop {
+            match engine.step(&mut cpu) {
+                Ok(true) => {}
+                Ok(false) => {
+                    let retired = (cpu.instret() - instret) as usize;
+                    assert_eq!(engine.retired(), retired, "{kind}: retired count differs from instret");
+                    break Ok(cpu);
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        (kind, result)
+    });
+    let (_, reference) = outcomes.next().expect("the functional engine comes first");
+    for (kind, result) in outcomes {
+        match (&reference, &result) {
+            (Ok(expected), Ok(cpu)) => {
+                assert_eq!(cpu.regs, expected.regs, "{kind}: registers differ");
+                assert_eq!(cpu.fregs, expected.fregs, "{kind}: FP registers differ");
+                assert_eq!(cpu.pc, expected.pc, "{kind}: pc differs");
+                assert_eq!(cpu.privilege(), expected.privilege(), "{kind}: privilege differs");
+                assert!(cpu.memory().get_data() == expected.memory().get_data(), "{kind}: memory differs");
+            }
+            (Err(expected), Err(e)) => assert_eq!(format!("{e:?}"), format!("{expected:?}"), "{kind}: errors differ"),
+            _ => panic!("{kind}: {:?}, but the functional engine: {:?}", result.as_ref().err(), reference.as_ref().err()),
+        }
+    }
+    reference
+}
+
 #[macro_export]
 macro_rules! run_program {
     ($program:expr) => {
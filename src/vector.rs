@@ -0,0 +1,357 @@
+use std::fmt;
+
+/// Element width, as encoded in `vtype.vsew`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sew {
+    E8 = 0,
+    E16 = 1,
+    E32 = 2,
+    E64 = 3,
+}
+
+impl Sew {
+    pub fn from_bits(bits: u64) -> Option<Sew> {
+        match bits {
+            0 => Some(Sew::E8),
+            1 => Some(Sew::E16),
+            2 => Some(Sew::E32),
+            3 => Some(Sew::E64),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Sew> {
+        match name {
+            "e8" | "8" => Some(Sew::E8),
+            "e16" | "16" => Some(Sew::E16),
+            "e32" | "32" => Some(Sew::E32),
+            "e64" | "64" => Some(Sew::E64),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(self) -> usize {
+        1 << self as usize
+    }
+
+    pub fn bits(self) -> u32 {
+        8 * self.bytes() as u32
+    }
+
+    /// Truncates `v` to this width.
+    pub fn truncate(self, v: u64) -> u64 {
+        if self == Sew::E64 { v } else { v & ((1 << self.bits()) - 1) }
+    }
+
+    pub fn sign_extend(self, v: u64) -> i64 {
+        let shift = 64 - self.bits();
+        ((v << shift) as i64) >> shift
+    }
+}
+
+impl fmt::Display for Sew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "e{}", self.bits())
+    }
+}
+
+// vtype fields
+pub const VTYPE_TA: u64 = 1 << 6;
+pub const VTYPE_MA: u64 = 1 << 7;
+
+/// Builds a `vtype` immediate: `lmul` is log2 of LMUL, from -3 (mf8) to 3 (m8).
+pub fn vtype(sew: Sew, lmul: i8, ta: bool, ma: bool) -> u16 {
+    let vlmul = (lmul as u16) & 7;
+    vlmul | (sew as u16) << 3 | if ta { VTYPE_TA as u16 } else { 0 } | if ma { VTYPE_MA as u16 } else { 0 }
+}
+
+/// The second source of an arithmetic or compare instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Vector(usize),
+    Scalar(u64),
+}
+
+/// The vector register file and its configuration. Elements are stored
+/// little-endian and a register group of LMUL registers is one long
+/// array; masks keep element `i` in bit `i` of their register. Inactive and
+/// tail elements are always left undisturbed, which is what agnostic
+/// policies also allow. Operations return `None` for encodings that are
+/// illegal in the current configuration.
+#[derive(Debug, Clone)]
+pub struct VectorUnit {
+    vlen: usize,
+    regs: Vec<u8>,
+    vl: u64,
+    vtype: u64,
+    vill: bool,
+}
+
+impl Default for VectorUnit {
+    fn default() -> Self {
+        Self::new(128)
+    }
+}
+
+impl VectorUnit {
+    /// `vlen` is the width of one register in bits, a power of two of at
+    /// least 64.
+    pub fn new(vlen: usize) -> Self {
+        assert!(vlen.is_power_of_two() && vlen >= 64, "VLEN must be a power of two of at least 64");
+        VectorUnit { vlen, regs: vec![0; 32 * vlen / 8], vl: 0, vtype: 0, vill: true }
+    }
+
+    pub fn vlen(&self) -> usize {
+        self.vlen
+    }
+
+    pub fn vlenb(&self) -> usize {
+        self.vlen / 8
+    }
+
+    /// Whether `vtype` is unsupported, which makes vector instructions other
+    /// than `vsetvl*` illegal.
+    pub fn vill(&self) -> bool {
+        self.vill
+    }
+
+    pub fn vl(&self) -> u64 {
+        self.vl
+    }
+
+    /// `vtype` as read through the CSR, with `vill` in bit `xlen - 1`.
+    pub fn vtype(&self, xlen: u32) -> u64 {
+        if self.vill { 1 << (xlen - 1) } else { self.vtype }
+    }
+
+    pub fn sew(&self) -> Sew {
+        Sew::from_bits((self.vtype >> 3) & 7).unwrap_or(Sew::E8)
+    }
+
+    /// log2 of LMUL.
+    pub fn lmul(&self) -> i8 {
+        (((self.vtype & 7) as i8) << 5) >> 5
+    }
+
+    /// The most elements an instruction can process at `sew` and `lmul`.
+    pub fn vlmax(&self, sew: Sew, lmul: i8) -> u64 {
+        let bits = if lmul >= 0 { self.vlen << lmul } else { self.vlen >> -lmul };
+        (bits / sew.bits() as usize) as u64
+    }
+
+    /// `vsetvl*`: sets `vtype` and `vl = min(avl, VLMAX)`, or `vill` and
+    /// `vl = 0` for an unsupported `vtype`. Returns the new `vl`.
+    pub fn configure(&mut self, avl: u64, vtype: u64) -> u64 {
+        let sew = Sew::from_bits((vtype >> 3) & 7);
+        let vlmul = vtype & 7;
+        let reserved = vtype >> 8 != 0 || vlmul == 4;
+        match sew {
+            Some(sew) if !reserved => {
+                self.vtype = vtype;
+                self.vill = false;
+                let vlmax = self.vlmax(sew, self.lmul());
+                self.vl = avl.min(vlmax);
+                if vlmax == 0 {
+                    self.vill = true;
+                    self.vl = 0;
+                }
+            }
+            _ => {
+                self.vill = true;
+                self.vtype = 0;
+                self.vl = 0;
+            }
+        }
+        self.vl
+    }
+
+    /// Registers per group, for checking register numbers.
+    fn group(&self) -> usize {
+        1 << self.lmul().max(0)
+    }
+
+    /// Checks `vill` and that every register starts a whole group.
+    fn check(&self, regs: &[usize]) -> Option<()> {
+        let group = self.group();
+        (!self.vill && regs.iter().all(|r| r % group == 0 && r + group <= 32)).then_some(())
+    }
+
+    /// Checks `vill` and that `vl` elements of `eew` from `vd` stay within
+    /// the register file.
+    pub fn check_access(&self, vd: usize, eew: Sew) -> Option<()> {
+        if self.vill {
+            return None;
+        }
+        let bytes = self.vl as usize * eew.bytes();
+        let regs = bytes.div_ceil(self.vlenb()).max(1);
+        (vd + regs <= 32).then_some(())
+    }
+
+    pub fn register(&self, r: usize) -> &[u8] {
+        &self.regs[r * self.vlenb()..(r + 1) * self.vlenb()]
+    }
+
+    /// Element `i` of the group starting at `r`, zero-extended.
+    pub fn element(&self, r: usize, i: usize, sew: Sew) -> u64 {
+        let start = r * self.vlenb() + i * sew.bytes();
+        self.regs[start..start + sew.bytes()].iter().rev().fold(0, |v, b| v << 8 | *b as u64)
+    }
+
+    pub fn set_element(&mut self, r: usize, i: usize, sew: Sew, val: u64) {
+        let start = r * self.vlenb() + i * sew.bytes();
+        for (k, byte) in self.regs[start..start + sew.bytes()].iter_mut().enumerate() {
+            *byte = (val >> (8 * k)) as u8;
+        }
+    }
+
+    pub fn mask_bit(&self, r: usize, i: usize) -> bool {
+        self.regs[r * self.vlenb() + i / 8] >> (i % 8) & 1 != 0
+    }
+
+    pub fn set_mask_bit(&mut self, r: usize, i: usize, bit: bool) {
+        let index = r * self.vlenb() + i / 8;
+        let byte = &mut self.regs[index];
+        *byte = (*byte & !(1 << (i % 8))) | (bit as u8) << (i % 8);
+    }
+
+    /// Whether element `i` takes part: unmasked (`vm`) or enabled by `v0`.
+    pub fn active(&self, i: usize, vm: bool) -> bool {
+        vm || self.mask_bit(0, i)
+    }
+
+    /// The indices below `vl` that take part.
+    pub fn active_elements(&self, vm: bool) -> Vec<usize> {
+        (0..self.vl as usize).filter(|&i| self.active(i, vm)).collect()
+    }
+
+    fn operand(&self, src: Operand, i: usize) -> u64 {
+        match src {
+            Operand::Vector(r) => self.element(r, i, self.sew()),
+            Operand::Scalar(v) => self.sew().truncate(v),
+        }
+    }
+
+    /// `vd[i] = op(vs2[i], src[i])` over the active elements.
+    pub fn binop(&mut self, vd: usize, vs2: usize, src: Operand, vm: bool, op: impl Fn(u64, u64) -> u64) -> Option<()> {
+        let vs1 = match src {
+            Operand::Vector(r) => r,
+            Operand::Scalar(_) => 0,
+        };
+        self.check(&[vd, vs2, vs1])?;
+        let sew = self.sew();
+        for i in self.active_elements(vm) {
+            let val = op(self.element(vs2, i, sew), self.operand(src, i));
+            self.set_element(vd, i, sew, sew.truncate(val));
+        }
+        Some(())
+    }
+
+    /// `vd.mask[i] = cmp(vs2[i], src[i])` on signed elements.
+    pub fn compare(&mut self, vd: usize, vs2: usize, src: Operand, vm: bool, cmp: impl Fn(i64, i64) -> bool) -> Option<()> {
+        let vs1 = match src {
+            Operand::Vector(r) => r,
+            Operand::Scalar(_) => 0,
+        };
+        self.check(&[vs2, vs1])?;
+        let sew = self.sew();
+        for i in self.active_elements(vm) {
+            let bit = cmp(sew.sign_extend(self.element(vs2, i, sew)), sew.sign_extend(self.operand(src, i)));
+            self.set_mask_bit(vd, i, bit);
+        }
+        Some(())
+    }
+
+    /// `vd[0] = fold(op, vs1[0], active vs2[*])`, on signed elements.
+    pub fn reduce(&mut self, vd: usize, vs2: usize, vs1: usize, vm: bool, op: impl Fn(i64, i64) -> i64) -> Option<()> {
+        self.check(&[vs2])?;
+        if self.vl == 0 {
+            return Some(());
+        }
+        let sew = self.sew();
+        let acc = (0..self.vl as usize)
+            .filter(|&i| self.active(i, vm))
+            .fold(sew.sign_extend(self.element(vs1, 0, sew)), |acc, i| op(acc, sew.sign_extend(self.element(vs2, i, sew))));
+        self.set_element(vd, 0, sew, sew.truncate(acc as u64));
+        Some(())
+    }
+
+    /// `vd.mask[i] = op(vs2.mask[i], vs1.mask[i])` for `i < vl`.
+    pub fn mask_op(&mut self, vd: usize, vs2: usize, vs1: usize, op: impl Fn(bool, bool) -> bool) -> Option<()> {
+        if self.vill {
+            return None;
+        }
+        for i in 0..self.vl as usize {
+            let bit = op(self.mask_bit(vs2, i), self.mask_bit(vs1, i));
+            self.set_mask_bit(vd, i, bit);
+        }
+        Some(())
+    }
+
+    /// Indices below `vl` that are active and set in mask `vs2`.
+    pub fn mask_set(&self, vs2: usize, vm: bool) -> Option<Vec<usize>> {
+        if self.vill {
+            return None;
+        }
+        Some((0..self.vl as usize).filter(|&i| self.active(i, vm) && self.mask_bit(vs2, i)).collect())
+    }
+
+    /// `vmv.v.*`: copies `src` into the first `vl` elements of `vd`.
+    pub fn splat(&mut self, vd: usize, src: Operand) -> Option<()> {
+        self.binop(vd, 0, src, true, |_, b| b)
+    }
+
+    /// Register `r` as elements of the current SEW, with `|` after the last
+    /// element below `vl`.
+    pub fn show(&self, r: usize) -> String {
+        let sew = self.sew();
+        let per_reg = self.vlenb() / sew.bytes();
+        let first = (r % self.group()) * per_reg;
+        let mut out = String::from("[");
+        for j in 0..per_reg {
+            if j > 0 {
+                out.push_str(if first + j == self.vl as usize { " | " } else { ", " });
+            } else if first as u64 >= self.vl {
+                out.push_str("| ");
+            }
+            out.push_str(&sew.sign_extend(self.element(r, j, sew)).to_string());
+        }
+        out.push(']');
+        out
+    }
+
+    /// Mask register `r` as one digit per element below VLMAX, element 0
+    /// first.
+    pub fn show_mask(&self, r: usize) -> String {
+        let vlmax = if self.vill { self.vlen as u64 / 8 } else { self.vlmax(self.sew(), self.lmul()) };
+        (0..vlmax as usize).map(|i| if self.mask_bit(r, i) { '1' } else { '0' }).collect()
+    }
+
+    fn lmul_name(&self) -> String {
+        match self.lmul() {
+            l if l >= 0 => format!("m{}", 1 << l),
+            l => format!("mf{}", 1 << -l),
+        }
+    }
+}
+
+/// The configuration, then every register holding a non-zero value.
+impl fmt::Display for VectorUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VLEN={} vl={} ", self.vlen, self.vl)?;
+        if self.vill {
+            writeln!(f, "vtype=vill")?;
+        } else {
+            let ta = if self.vtype & VTYPE_TA != 0 { "ta" } else { "tu" };
+            let ma = if self.vtype & VTYPE_MA != 0 { "ma" } else { "mu" };
+            writeln!(f, "vtype={},{},{ta},{ma}", self.sew(), self.lmul_name())?;
+        }
+        for r in (0..32).filter(|&r| self.register(r).iter().any(|b| *b != 0)) {
+            write!(f, "v{r:<2} {}", self.show(r))?;
+            if r == 0 {
+                write!(f, " mask {}", self.show_mask(0))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::{Debug, Display};
+
+/// The integer register width. Every integer operation is carried out on
+/// `i64`/`u64` and then narrowed back with [`Xlen::from_i64`], which is what
+/// gives RV32 its 32-bit wrap-around.
+pub trait Xlen: Copy + Debug + Default + 'static {
+    type Reg: Copy + Debug + Display + Default + PartialEq + Eq + PartialOrd + Ord;
+    const BITS: u32;
+
+    /// Truncates to XLEN bits.
+    fn from_i64(v: i64) -> Self::Reg;
+    /// Sign-extends to 64 bits.
+    fn to_i64(r: Self::Reg) -> i64;
+
+    /// Zero-extends to 64 bits.
+    fn to_u64(r: Self::Reg) -> u64 {
+        let v = Self::to_i64(r) as u64;
+        if Self::BITS == 64 { v } else { v & ((1 << Self::BITS) - 1) }
+    }
+
+    /// Mask applied to register shift amounts: 5 bits on RV32, 6 on RV64.
+    fn shamt_mask() -> u32 {
+        Self::BITS - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rv32;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rv64;
+
+impl Xlen for Rv32 {
+    type Reg = i32;
+    const BITS: u32 = 32;
+
+    fn from_i64(v: i64) -> i32 {
+        v as i32
+    }
+    fn to_i64(r: i32) -> i64 {
+        r as i64
+    }
+}
+
+impl Xlen for Rv64 {
+    type Reg = i64;
+    const BITS: u32 = 64;
+
+    fn from_i64(v: i64) -> i64 {
+        v
+    }
+    fn to_i64(r: i64) -> i64 {
+        r
+    }
+}
+
+/// The register width named by an ISA string such as `rv64im`.
+pub fn bits_of(isa: &str) -> Option<u32> {
+    let isa = isa.to_ascii_lowercase();
+    if isa.starts_with("rv32") {
+        Some(32)
+    } else if isa.starts_with("rv64") {
+        Some(64)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,12 @@
+# sum 1..=5 with explicit and auto-compressible instructions
+_start:
+    c.li x8, 0
+    c.li x9, 5
+loop:
+    c.add x8, x9
+    c.addi x9, -1
+    c.bnez x9, loop
+    addi x10, x8, 0
+    slli x10, x10, 1
+    c.mv x11, x10
+    print x11
@@ -0,0 +1,10 @@
+# RV64C forms: c.ld, c.sd, c.addiw and c.addw take the encodings
+# RV32C uses for c.flw, c.fsw and c.jal
+_start:
+    c.li x8, 64
+    c.li x9, -3
+    c.addiw x9, 1
+    c.sd x9, 8(x8)
+    c.ld x10, 8(x8)
+    c.addw x10, x9
+    print x10
@@ -0,0 +1,12 @@
+# Echoes what the 16550 UART of QEMU's virt machine receives, up to and
+# including a newline.
+_start:
+    lui x5, 0x10000
+    addi x6, x0, 10
+getc:
+    lbu x7, 5(x5)
+    andi x7, x7, 1
+    beq x7, x0, getc
+    lbu x10, 0(x5)
+    sb x10, 0(x5)
+    bne x10, x6, getc
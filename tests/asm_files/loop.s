@@ -0,0 +1,11 @@
+_start:
+    addi x5, x0, 3
+    addi x6, x0, 0
+loop:
+    addi x6, x6, 2
+    mul x7, x6, x6
+    sw x7, 0x100(x0)
+    addi x5, x5, -1
+    bne x5, x0, loop
+done:
+    lw x8, 0x100(x0)
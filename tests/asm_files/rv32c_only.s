@@ -0,0 +1,5 @@
+# c.jal only exists on RV32
+_start:
+    c.jal done
+done:
+    print x1
@@ -0,0 +1,16 @@
+# Prints "Hi\n" through the 16550 UART of QEMU's virt machine.
+putc:
+    lbu x7, 5(x5)
+    andi x7, x7, 32
+    beq x7, x0, putc
+    sb x10, 0(x5)
+    jalr x0, x1, 0
+
+_start:
+    lui x5, 0x10000
+    addi x10, x0, 72
+    jal x1, putc
+    addi x10, x0, 105
+    jal x1, putc
+    addi x10, x0, 10
+    jal x1, putc
@@ -0,0 +1,112 @@
+use riscviz::instruction::Instruction;
+use riscviz::run_program;
+
+#[test]
+fn test_lr_sc_success() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 16 },  // lock address
+        Instruction::Addi { rd: 2, rs1: 0, imm: 1 },
+        Instruction::LrW { rd: 3, rs1: 1, aq: true, rl: false },
+        Instruction::ScW { rd: 4, rs1: 1, rs2: 2, aq: false, rl: true },
+        Instruction::Lw { rd: 5, rs1: 1, imm: 0 },
+    ]);
+    assert_eq!(cpu.regs[3], 0);
+    assert_eq!(cpu.regs[4], 0); // success
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.reservation(), None);
+}
+
+#[test]
+fn test_sc_without_reservation_fails() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 16 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 7 },
+        Instruction::ScW { rd: 4, rs1: 1, rs2: 2, aq: false, rl: false },
+        Instruction::Lw { rd: 5, rs1: 1, imm: 0 },
+    ]);
+    assert_eq!(cpu.regs[4], 1); // failure
+    assert_eq!(cpu.regs[5], 0); // memory untouched
+}
+
+#[test]
+fn test_intervening_store_breaks_reservation() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 16 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 7 },
+        Instruction::LrW { rd: 3, rs1: 1, aq: false, rl: false },
+        Instruction::Sb { rs1: 1, rs2: 2, imm: 2 },     // byte inside the reserved word
+        Instruction::ScW { rd: 4, rs1: 1, rs2: 2, aq: false, rl: false },
+    ]);
+    assert_eq!(cpu.regs[4], 1);
+}
+
+#[test]
+fn test_store_elsewhere_keeps_reservation() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 16 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 7 },
+        Instruction::LrW { rd: 3, rs1: 1, aq: false, rl: false },
+        Instruction::Sw { rs1: 1, rs2: 2, imm: 4 },
+        Instruction::ScW { rd: 4, rs1: 1, rs2: 2, aq: false, rl: false },
+    ]);
+    assert_eq!(cpu.regs[4], 0);
+}
+
+#[test]
+fn test_amo_arithmetic() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 32 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 10 },
+        Instruction::Sw { rs1: 1, rs2: 2, imm: 0 },                                   // mem = 10
+        Instruction::Addi { rd: 3, rs1: 0, imm: 5 },
+        Instruction::AmoaddW { rd: 4, rs1: 1, rs2: 3, aq: false, rl: false },          // mem = 15
+        Instruction::AmoswapW { rd: 5, rs1: 1, rs2: 0, aq: true, rl: true },           // mem = 0
+        Instruction::AmoorW { rd: 6, rs1: 1, rs2: 3, aq: false, rl: false },           // mem = 5
+        Instruction::AmoxorW { rd: 7, rs1: 1, rs2: 2, aq: false, rl: false },          // mem = 15
+        Instruction::AmoandW { rd: 8, rs1: 1, rs2: 2, aq: false, rl: false },          // mem = 10
+        Instruction::Lw { rd: 9, rs1: 1, imm: 0 },
+    ]);
+    assert_eq!(cpu.regs[4], 10);
+    assert_eq!(cpu.regs[5], 15);
+    assert_eq!(cpu.regs[6], 0);
+    assert_eq!(cpu.regs[7], 5);
+    assert_eq!(cpu.regs[8], 15);
+    assert_eq!(cpu.regs[9], 10);
+}
+
+#[test]
+fn test_amo_min_max_signedness() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 32 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: -1 },
+        Instruction::Addi { rd: 3, rs1: 0, imm: 1 },
+        Instruction::Sw { rs1: 1, rs2: 3, imm: 0 },
+        Instruction::AmominW { rd: 0, rs1: 1, rs2: 2, aq: false, rl: false },
+        Instruction::Lw { rd: 4, rs1: 1, imm: 0 },                                     // -1
+        Instruction::AmominuW { rd: 0, rs1: 1, rs2: 3, aq: false, rl: false },
+        Instruction::Lw { rd: 5, rs1: 1, imm: 0 },                                     // 1
+        Instruction::AmomaxuW { rd: 0, rs1: 1, rs2: 2, aq: false, rl: false },
+        Instruction::Lw { rd: 6, rs1: 1, imm: 0 },                                     // -1
+        Instruction::AmomaxW { rd: 0, rs1: 1, rs2: 3, aq: false, rl: false },
+        Instruction::Lw { rd: 7, rs1: 1, imm: 0 },                                     // 1
+    ]);
+    assert_eq!(cpu.regs[4], -1);
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.regs[6], -1);
+    assert_eq!(cpu.regs[7], 1);
+}
+
+#[test]
+fn test_spinlock_acquire_release() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 64 },                                  // 0: &lock
+        Instruction::Addi { rd: 2, rs1: 0, imm: 1 },                                   // 1
+        Instruction::AmoswapW { rd: 3, rs1: 1, rs2: 2, aq: true, rl: false },          // 2: acquire
+        Instruction::Bne { rs1: 3, rs2: 0, offset: -1 },                               // 3: spin
+        Instruction::Addi { rd: 10, rs1: 10, imm: 1 },                                 // 4: critical section
+        Instruction::AmoswapW { rd: 0, rs1: 1, rs2: 0, aq: false, rl: true },          // 5: release
+        Instruction::Lw { rd: 4, rs1: 1, imm: 0 },                                     // 6
+    ]);
+    assert_eq!(cpu.regs[10], 1);
+    assert_eq!(cpu.regs[4], 0);
+}
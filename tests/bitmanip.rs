@@ -0,0 +1,198 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::isa::{Extension, Extensions};
+use riscviz::run_program;
+use riscviz::utils::run_program_on;
+use riscviz::xlen::Rv64;
+
+#[test]
+fn test_zba() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 3 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 4 },
+        Instruction::Sh1add { rd: 3, rs1: 1, rs2: 2 },
+        Instruction::Sh2add { rd: 4, rs1: 1, rs2: 2 },
+        Instruction::Sh3add { rd: 5, rs1: 1, rs2: 2 },
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[3], 10);
+    assert_eq!(cpu.regs[4], 16);
+    assert_eq!(cpu.regs[5], 28);
+
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: -1 },
+        Instruction::AddUw { rd: 2, rs1: 1, rs2: 0 },
+        Instruction::Sh2addUw { rd: 3, rs1: 1, rs2: 1 },
+        Instruction::SlliUw { rd: 4, rs1: 1, imm: 32 },
+    ];
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[2], 0xFFFF_FFFF);
+    assert_eq!(cpu.regs[3], 0x3_FFFF_FFFB);
+    assert_eq!(cpu.regs[4], -0x1_0000_0000);
+}
+
+#[test]
+fn test_zbb_counts_and_logic() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 0xF0 },
+        Instruction::Clz { rd: 2, rs1: 1 },
+        Instruction::Ctz { rd: 3, rs1: 1 },
+        Instruction::Cpop { rd: 4, rs1: 1 },
+        Instruction::Ctz { rd: 5, rs1: 0 },
+        Instruction::Addi { rd: 6, rs1: 0, imm: 0x30 },
+        Instruction::Andn { rd: 7, rs1: 1, rs2: 6 },
+        Instruction::Xnor { rd: 8, rs1: 1, rs2: 1 },
+        Instruction::Orn { rd: 9, rs1: 0, rs2: 8 },
+    ];
+    let cpu = run_program!(program.clone());
+    assert_eq!(cpu.regs[2], 24);
+    assert_eq!(cpu.regs[3], 4);
+    assert_eq!(cpu.regs[4], 4);
+    assert_eq!(cpu.regs[5], 32);
+    assert_eq!(cpu.regs[7], 0xC0);
+    assert_eq!(cpu.regs[8], -1);
+    assert_eq!(cpu.regs[9], 0);
+
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[2], 56);
+    assert_eq!(cpu.regs[5], 64);
+}
+
+#[test]
+fn test_zbb_min_max_and_extension() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 0xF0 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: -1 },
+        Instruction::Min { rd: 3, rs1: 1, rs2: 2 },
+        Instruction::Minu { rd: 4, rs1: 1, rs2: 2 },
+        Instruction::Max { rd: 5, rs1: 1, rs2: 2 },
+        Instruction::Maxu { rd: 6, rs1: 1, rs2: 2 },
+        Instruction::SextB { rd: 7, rs1: 1 },
+        Instruction::Lui { rd: 8, imm: 0x8 },
+        Instruction::SextH { rd: 9, rs1: 8 },
+        Instruction::ZextH { rd: 10, rs1: 2 },
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[3], -1);
+    assert_eq!(cpu.regs[4], 0xF0);
+    assert_eq!(cpu.regs[5], 0xF0);
+    assert_eq!(cpu.regs[6], -1);
+    assert_eq!(cpu.regs[7], -16);
+    assert_eq!(cpu.regs[9], -32768);
+    assert_eq!(cpu.regs[10], 0xFFFF);
+}
+
+#[test]
+fn test_zbb_rotates_and_bytes() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::Ror { rd: 2, rs1: 1, rs2: 1 },
+        Instruction::Rol { rd: 3, rs1: 2, rs2: 1 },
+        Instruction::Addi { rd: 4, rs1: 0, imm: 0xF0 },
+        Instruction::Rori { rd: 5, rs1: 4, imm: 4 },
+        Instruction::Addi { rd: 6, rs1: 0, imm: 0x101 },
+        Instruction::OrcB { rd: 7, rs1: 6 },
+        Instruction::Rev8 { rd: 8, rs1: 1 },
+    ];
+    let cpu = run_program!(program.clone());
+    assert_eq!(cpu.regs[2], i32::MIN);
+    assert_eq!(cpu.regs[3], 1);
+    assert_eq!(cpu.regs[5], 0xF);
+    assert_eq!(cpu.regs[7], 0xFFFF);
+    assert_eq!(cpu.regs[8], 1 << 24);
+
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[2], i64::MIN);
+    assert_eq!(cpu.regs[3], 1);
+    assert_eq!(cpu.regs[8], 1 << 56);
+}
+
+#[test]
+fn test_zbc() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 0b101 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 0b11 },
+        Instruction::Clmul { rd: 3, rs1: 1, rs2: 2 },
+        Instruction::Lui { rd: 4, imm: 0x80000 },
+        Instruction::Addi { rd: 5, rs1: 0, imm: 2 },
+        Instruction::Clmulh { rd: 6, rs1: 4, rs2: 5 },
+        Instruction::Clmulr { rd: 7, rs1: 4, rs2: 5 },
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[3], 0b1111);
+    assert_eq!(cpu.regs[6], 1);
+    assert_eq!(cpu.regs[7], 2);
+}
+
+#[test]
+fn test_zbs() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 0xF0 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 31 },
+        Instruction::Bset { rd: 3, rs1: 0, rs2: 2 },
+        Instruction::Bclri { rd: 4, rs1: 1, imm: 4 },
+        Instruction::Binvi { rd: 5, rs1: 1, imm: 0 },
+        Instruction::Bexti { rd: 6, rs1: 1, imm: 4 },
+        Instruction::Addi { rd: 7, rs1: 0, imm: 3 },
+        Instruction::Bext { rd: 8, rs1: 1, rs2: 7 },
+        Instruction::Bclr { rd: 9, rs1: 1, rs2: 7 },
+        Instruction::Binv { rd: 10, rs1: 1, rs2: 7 },
+        Instruction::Bseti { rd: 11, rs1: 0, imm: 5 },
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[3], i32::MIN);
+    assert_eq!(cpu.regs[4], 0xE0);
+    assert_eq!(cpu.regs[5], 0xF1);
+    assert_eq!(cpu.regs[6], 1);
+    assert_eq!(cpu.regs[8], 0);
+    assert_eq!(cpu.regs[9], 0xF0);
+    assert_eq!(cpu.regs[10], 0xF8);
+    assert_eq!(cpu.regs[11], 32);
+}
+
+#[test]
+fn test_disabled_extension_is_illegal() {
+    assert_eq!(Instruction::Clz { rd: 1, rs1: 2 }.extension(), Extension::Zbb);
+    assert_eq!(Instruction::Bseti { rd: 1, rs1: 2, imm: 3 }.extension(), Extension::Zbs);
+    assert_eq!(Instruction::Mul { rd: 1, rs1: 2, rs2: 3 }.extension(), Extension::M);
+
+    let mut cpu = Cpu::default();
+    cpu.set_extensions(Extensions::all().without(Extension::Zbb));
+    cpu.load_instructions(vec![
+        Instruction::Sh1add { rd: 1, rs1: 0, rs2: 0 },
+        Instruction::Clz { rd: 1, rs1: 0 },
+    ]);
+    assert!(cpu.execute_next().unwrap());
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Clz { .. }))));
+}
+
+#[test]
+fn test_bitmanip_parsing_and_encoding() {
+    assert_eq!(parse_instruction("sext.b x1, x2"), Some(Instruction::SextB { rd: 1, rs1: 2 }));
+    assert_eq!(parse_instruction("sh2add x1, x2, x3"), Some(Instruction::Sh2add { rd: 1, rs1: 2, rs2: 3 }));
+    assert_eq!(parse_instruction("bexti x1, x2, 7"), Some(Instruction::Bexti { rd: 1, rs1: 2, imm: 7 }));
+    assert_eq!(parse_instruction("zext.w x1, x2"), Some(Instruction::AddUw { rd: 1, rs1: 2, rs2: 0 }));
+
+    assert_eq!(encode(&Instruction::Clz { rd: 1, rs1: 2 }), Some(0x6001_1093));
+    assert_eq!(encode(&Instruction::Sh1add { rd: 1, rs1: 2, rs2: 3 }), Some(0x2031_20B3));
+    assert_eq!(encode(&Instruction::Rev8 { rd: 1, rs1: 2 }), Some(0x6981_5093));
+    assert_eq!(encode(&Instruction::OrcB { rd: 1, rs1: 2 }), Some(0x2871_5093));
+
+    let insts = [
+        Instruction::Andn { rd: 1, rs1: 2, rs2: 3 },
+        Instruction::Cpopw { rd: 1, rs1: 2 },
+        Instruction::ZextH { rd: 1, rs1: 2 },
+        Instruction::Rori { rd: 1, rs1: 2, imm: 40 },
+        Instruction::Roriw { rd: 1, rs1: 2, imm: 31 },
+        Instruction::SlliUw { rd: 1, rs1: 2, imm: 33 },
+        Instruction::Clmulh { rd: 1, rs1: 2, rs2: 3 },
+        Instruction::Binvi { rd: 1, rs1: 2, imm: 63 },
+        Instruction::Bexti { rd: 1, rs1: 2, imm: 5 },
+        Instruction::Slli { rd: 1, rs1: 2, imm: 5 },
+    ];
+    for inst in insts {
+        assert_eq!(decode(encode(&inst).unwrap()), Some(inst));
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use riscviz::bus::{Bus, BusError, Device};
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::instruction::Instruction;
+use riscviz::memory::MemoryError;
+
+/// A register that counts ticks and raises its interrupt line once it
+/// reaches the value last written to it.
+struct Timer {
+    ticks: Rc<Cell<u64>>,
+    compare: u64,
+}
+
+impl Device for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    fn size(&self) -> u64 {
+        8
+    }
+
+    fn read(&mut self, offset: u64, _size: usize) -> Result<u64, MemoryError> {
+        match offset {
+            0 => Ok(self.ticks.get()),
+            4 => Ok(self.compare),
+            _ => Err(MemoryError::OutOfBounds(offset)),
+        }
+    }
+
+    fn write(&mut self, offset: u64, _size: usize, val: u64) -> Result<(), MemoryError> {
+        match offset {
+            4 => self.compare = val,
+            _ => return Err(MemoryError::OutOfBounds(offset)),
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.ticks.set(self.ticks.get() + 1);
+    }
+
+    fn interrupt(&self) -> bool {
+        self.compare != 0 && self.ticks.get() >= self.compare
+    }
+}
+
+fn timer() -> (Box<Timer>, Rc<Cell<u64>>) {
+    let ticks = Rc::new(Cell::new(0));
+    (Box::new(Timer { ticks: ticks.clone(), compare: 0 }), ticks)
+}
+
+#[test]
+fn test_memory_map() {
+    let mut bus = Bus::with_ram(0x8000_0000, 0x1000);
+    let (dev, _) = timer();
+    bus.attach(0x1000_0000, Some(3), dev).unwrap();
+
+    let (dev, _) = timer();
+    assert_eq!(
+        bus.attach(0x8000_0ff8, None, dev),
+        Err(BusError::Overlap { name: "timer".into(), base: 0x8000_0ff8, end: 0x8000_1000, other: "RAM".into() })
+    );
+    let (dev, _) = timer();
+    assert!(matches!(bus.attach(0x1000_0004, None, dev), Err(BusError::Overlap { .. })));
+
+    let map = bus.regions().iter().map(|r| r.to_string()).collect::<Vec<_>>();
+    assert_eq!(map, ["0x10000000..0x10000008 timer (irq 3)", "0x80000000..0x80001000 RAM"]);
+
+    bus.write_word(0x8000_0010, -2).unwrap();
+    assert_eq!(bus.read_halfword(0x8000_0012).unwrap(), -1);
+    assert_eq!(bus.ram().read_word(0x10).unwrap(), -2);
+    assert!(matches!(bus.read_word(0x10), Err(MemoryError::OutOfBounds(0x10))));
+    assert!(matches!(bus.read_word(0x8000_0002), Err(MemoryError::MisalignedAccess(_))));
+}
+
+#[test]
+fn test_cpu_reaches_devices_through_the_bus() {
+    let mut bus = Bus::with_ram(0x8000_0000, 0x1000);
+    let (dev, ticks) = timer();
+    bus.attach(0x1000_0000, Some(1), dev).unwrap();
+    let mut cpu: Cpu = Cpu::with_bus(bus);
+    assert_eq!(cpu.regs[2], 0x8000_1000_u32 as i32);
+
+    cpu.load_instructions(vec![
+        Instruction::Lui { rd: 1, imm: 0x10000 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 4 },
+        Instruction::Sw { rs1: 1, rs2: 2, imm: 4 }, // compare = 4
+        Instruction::Lw { rd: 3, rs1: 1, imm: 0 },
+        Instruction::Lw { rd: 4, rs1: 1, imm: 8 },
+    ]);
+    for _ in 0..4 {
+        assert!(cpu.execute_next().unwrap());
+    }
+    assert_eq!(cpu.regs[3], 3); // ticked after each of the first three steps
+    assert_eq!(ticks.get(), 4);
+    assert_eq!(cpu.bus().interrupts(), 1 << 1);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::MemoryError(MemoryError::OutOfBounds(_)))));
+}
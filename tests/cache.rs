@@ -0,0 +1,149 @@
+use riscviz::cache::{Cache, CacheConfig, CacheError, Caches, Replacement, WritePolicy};
+use riscviz::cpu::Cpu;
+use riscviz::instruction::Instruction;
+
+mod common;
+use common::asm;
+
+fn cache(spec: &str) -> Cache {
+    Cache::new("test", spec.parse().unwrap())
+}
+
+/// Whether each access to `addrs` hit.
+fn hits(cache: &mut Cache, addrs: &[u64]) -> Vec<bool> {
+    addrs.iter().map(|&addr| cache.access(addr, false).hit).collect()
+}
+
+#[test]
+fn test_parse_config() {
+    let config = "256:16:2:fifo:wt:nwa".parse::<CacheConfig>().unwrap();
+    assert_eq!((config.size, config.line, config.ways, config.sets()), (256, 16, 2, 8));
+    assert_eq!((config.replacement, config.write, config.write_allocate), (Replacement::Fifo, WritePolicy::WriteThrough, false));
+    assert_eq!(config.to_string(), "256 B, 16 B lines, 2-way, 8 sets, FIFO, write-through, no-write-allocate");
+    assert_eq!("256:16:2".parse::<CacheConfig>().unwrap(), CacheConfig::new(256, 16, 2).unwrap());
+
+    assert_eq!("256:12:2".parse::<CacheConfig>(), Err(CacheError::NotPowerOfTwo { what: "line size", value: 12 }));
+    assert_eq!("32:16:4".parse::<CacheConfig>(), Err(CacheError::TooSmall { size: 32, line: 16, ways: 4 }));
+    assert_eq!("256:16".parse::<CacheConfig>(), Err(CacheError::BadNumber(String::new())));
+    assert_eq!("256:16:2:plru".parse::<CacheConfig>(), Err(CacheError::UnknownOption("plru".to_string())));
+}
+
+#[test]
+fn test_conflict_misses_need_associativity() {
+    // 0x00 and 0x40 share set 0 of a 64-byte cache with 16-byte lines.
+    let pattern = [0x00, 0x40, 0x04, 0x44, 0x08, 0x48];
+    let mut direct = cache("64:16:1");
+    assert_eq!(hits(&mut direct, &pattern), [false; 6]);
+    assert_eq!(direct.stats().evictions, 5);
+
+    let mut two_way = cache("64:16:2");
+    assert_eq!(hits(&mut two_way, &pattern), [false, false, true, true, true, true]);
+    assert_eq!(two_way.locate(0x48), (0, 2));
+    assert_eq!(two_way.line_address(0, 2), 0x40);
+}
+
+#[test]
+fn test_replacement_policies() {
+    // A, B, A, C, A in one two-way set.
+    let pattern = [0x00, 0x20, 0x00, 0x40, 0x00];
+    let mut lru = cache("64:16:2:lru");
+    assert_eq!(hits(&mut lru, &pattern), [false, false, true, false, true]);
+    assert_eq!(lru.last().unwrap().evicted, None);
+
+    let mut fifo = cache("64:16:2:fifo");
+    assert_eq!(hits(&mut fifo, &pattern), [false, false, true, false, false]);
+    assert_eq!(fifo.last().unwrap().evicted, Some(0x20));
+
+    // Random replacement is seeded, so runs repeat.
+    let run = || {
+        let mut random = cache("64:16:2:random");
+        hits(&mut random, &[0x00, 0x20, 0x40, 0x60, 0x00, 0x20, 0x40, 0x60])
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn test_write_policies() {
+    let mut back = cache("64:16:1:wb");
+    assert_eq!(back.access(0x00, true).below, [(0x00, false)]);
+    assert_eq!(back.access(0x04, true).below, []);
+    assert!(back.sets()[0][0].dirty);
+    // Evicting the dirty line writes it back before the fill.
+    assert_eq!(back.access(0x40, false).below, [(0x00, true), (0x40, false)]);
+    assert_eq!(back.stats().writebacks, 1);
+
+    let mut through = cache("64:16:1:wt");
+    assert_eq!(through.access(0x00, true).below, [(0x00, false), (0x00, true)]);
+    assert_eq!(through.access(0x04, true).below, [(0x00, true)]);
+    assert!(!through.sets()[0][0].dirty);
+
+    let mut no_allocate = cache("64:16:1:wt:nwa");
+    let miss = no_allocate.access(0x10, true);
+    assert_eq!((miss.hit, miss.way, miss.below), (false, None, vec![(0x10, true)]));
+    assert!(!no_allocate.access(0x10, false).hit);
+}
+
+#[test]
+fn test_l2_filters_memory_traffic() {
+    let l1 = CacheConfig::new(32, 16, 1).unwrap();
+    let l2 = CacheConfig::new(256, 16, 4).unwrap();
+    let mut caches = Caches::new(None, Some(l1), Some(l2));
+    for _ in 0..3 {
+        caches.data(0x00, false);
+        caches.data(0x20, false);
+    }
+    let dcache = caches.dcache.as_ref().unwrap().stats();
+    let l2 = caches.l2.as_ref().unwrap().stats();
+    assert_eq!((dcache.hits, dcache.misses), (0, 6));
+    assert_eq!((l2.hits, l2.misses), (4, 2));
+    assert_eq!((caches.memory_reads, caches.memory_writes), (2, 0));
+}
+
+#[test]
+fn test_cpu_accesses() {
+    // Sums four words at 0x80 twice over.
+    let program = vec![
+        asm("addi x7, x0, 2"),
+        asm("addi x5, x0, 0x80"),
+        asm("addi x8, x0, 4"),
+        asm("lw x6, 0(x5)"),
+        asm("add x10, x10, x6"),
+        asm("addi x5, x5, 4"),
+        asm("addi x8, x8, -1"),
+        Instruction::Bne { rs1: 8, rs2: 0, offset: -4 },
+        asm("addi x7, x7, -1"),
+        Instruction::Bne { rs1: 7, rs2: 0, offset: -8 },
+        asm("sw x10, 0x40(x0)"),
+    ];
+    let mut cpu: Cpu = Cpu::new(1024);
+    let icache = CacheConfig::new(64, 16, 1).unwrap();
+    let dcache = CacheConfig::new(64, 16, 2).unwrap();
+    cpu.set_caches(Caches::new(Some(icache), Some(dcache), None));
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    let mut executed = 0;
+    while cpu.execute_next().unwrap() {
+        executed += 1;
+    }
+
+    let caches = cpu.caches();
+    let icache = caches.icache.as_ref().unwrap();
+    let dcache = caches.dcache.as_ref().unwrap();
+    // 44 bytes of code span three lines.
+    assert_eq!(icache.stats().accesses(), executed);
+    assert_eq!(icache.stats().misses, 3);
+    // The first pass misses once on the 16-byte line, the second hits.
+    assert_eq!((dcache.stats().hits, dcache.stats().misses), (7, 2));
+    assert_eq!(dcache.last().unwrap().set, 0);
+    assert!(dcache.sets()[0].iter().any(|line| line.dirty));
+    assert!(dcache.to_string().contains("> set   0: [0x00000080   ] [0x00000040 D*]"));
+}
+
+#[test]
+fn test_no_caches_sees_no_traffic() {
+    let mut cpu: Cpu = Cpu::new(1024);
+    cpu.load_instructions(vec![asm("addi x5, x0, 0x80"), asm("lw x6, 0(x5)"), asm("sw x6, 4(x5)")]);
+    while cpu.execute_next().unwrap() {}
+    assert!(cpu.caches().is_empty());
+    assert_eq!((cpu.caches().memory_reads, cpu.caches().memory_writes), (0, 0));
+}
@@ -0,0 +1,7 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::instruction::Instruction;
+
+/// Parses one line of assembly, panicking on anything the parser rejects.
+pub fn asm(line: &str) -> Instruction {
+    parse_instruction(line).unwrap_or_else(|| panic!("bad instruction: {line}"))
+}
@@ -0,0 +1,239 @@
+use riscviz::asm_parser::{load_asm, load_asm_for, parse_instruction, Program};
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::encoding::{compress, decode, decode_at, decompress, encode};
+use riscviz::fpu::RoundingMode;
+use riscviz::instruction::Instruction;
+use riscviz::isa::Isa;
+use riscviz::xlen::Rv64;
+
+#[test]
+fn test_known_encodings() {
+    assert_eq!(encode(&Instruction::Addi { rd: 1, rs1: 0, imm: 5 }), Some(0x0050_0093));
+    assert_eq!(encode(&Instruction::Add { rd: 3, rs1: 1, rs2: 2 }), Some(0x0020_81B3));
+    assert_eq!(encode(&Instruction::Sw { rs1: 2, rs2: 1, imm: 8 }), Some(0x0011_2423));
+    assert_eq!(encode(&Instruction::Beq { rs1: 1, rs2: 2, offset: -8 }), Some(0xFE20_8CE3));
+    assert_eq!(encode(&Instruction::Jal { rd: 1, offset: 2048 }), Some(0x0010_00EF));
+    assert_eq!(compress(&Instruction::Addi { rd: 10, rs1: 0, imm: 5 }, 32), Some(0x4515)); // c.li a0, 5
+    assert_eq!(compress(&Instruction::Add { rd: 10, rs1: 0, rs2: 11 }, 32), Some(0x852E)); // c.mv a0, a1
+    assert_eq!(compress(&Instruction::Jalr { rd: 0, rs1: 1, imm: 0 }, 32), Some(0x8082)); // c.jr ra (ret)
+    assert_eq!(compress(&Instruction::Addi { rd: 2, rs1: 2, imm: -16 }, 32), Some(0x1141)); // c.addi16sp
+    assert_eq!(compress(&Instruction::Sw { rs1: 2, rs2: 1, imm: 12 }, 32), Some(0xC606)); // c.swsp ra, 12
+}
+
+#[test]
+fn test_out_of_range_operands_do_not_encode() {
+    assert!(encode(&Instruction::Addi { rd: 1, rs1: 0, imm: 4096 }).is_none());
+    assert!(encode(&Instruction::Beq { rs1: 1, rs2: 2, offset: 3 }).is_none());
+    assert!(compress(&Instruction::Addi { rd: 10, rs1: 0, imm: 32 }, 32).is_none());
+    assert!(compress(&Instruction::Lw { rd: 1, rs1: 5, imm: 0 }, 32).is_none()); // x5 has no 3-bit form
+    assert!(compress(&Instruction::Lw { rd: 8, rs1: 9, imm: 2 }, 32).is_none()); // misaligned offset
+    assert!(compress(&Instruction::Bne { rs1: 8, rs2: 0, offset: 256 }, 32).is_none());
+}
+
+#[test]
+fn test_32_bit_round_trip() {
+    let insts = [
+        Instruction::Sub { rd: 5, rs1: 6, rs2: 7 },
+        Instruction::Mulhsu { rd: 31, rs1: 30, rs2: 29 },
+        Instruction::Srai { rd: 1, rs1: 2, imm: 31 },
+        Instruction::Sltiu { rd: 1, rs1: 2, imm: -1 },
+        Instruction::Lhu { rd: 3, rs1: 4, imm: -2048 },
+        Instruction::Sb { rs1: 3, rs2: 4, imm: 2047 },
+        Instruction::Bgeu { rs1: 1, rs2: 2, offset: 4094 },
+        Instruction::Jal { rd: 0, offset: -1048576 },
+        Instruction::Lui { rd: 7, imm: 0xFFFFF },
+        Instruction::Auipc { rd: 7, imm: 0x12345 },
+        Instruction::AmomaxuW { rd: 1, rs1: 2, rs2: 3, aq: true, rl: false },
+        Instruction::LrW { rd: 1, rs1: 2, aq: false, rl: true },
+        Instruction::Csrrci { rd: 1, imm: 31, csr: 0x003 },
+        Instruction::FmaddD { rd: 1, rs1: 2, rs2: 3, rs3: 4, rm: RoundingMode::Rmm },
+        Instruction::FcvtWuS { rd: 1, rs1: 2, rm: RoundingMode::Rtz },
+        Instruction::FsgnjxD { rd: 1, rs1: 2, rs2: 3 },
+        Instruction::FclassD { rd: 1, rs1: 2 },
+        Instruction::Fsd { rs1: 2, rs2: 3, imm: -8 },
+        Instruction::Print { rs: 9 },
+    ];
+    for inst in insts {
+        let word = encode(&inst).unwrap();
+        assert_eq!(decode(word), Some(inst), "0x{word:08x}");
+        let decoded = decode_at(&word.to_le_bytes(), 32).unwrap();
+        assert!(!decoded.compressed);
+    }
+}
+
+#[test]
+fn test_16_bit_round_trip() {
+    let insts = [
+        Instruction::Addi { rd: 0, rs1: 0, imm: 0 },
+        Instruction::Addi { rd: 9, rs1: 2, imm: 1020 },
+        Instruction::Addi { rd: 2, rs1: 2, imm: 496 },
+        Instruction::Addi { rd: 5, rs1: 5, imm: -32 },
+        Instruction::Lui { rd: 5, imm: 0xFFFFF },
+        Instruction::Srai { rd: 15, rs1: 15, imm: 31 },
+        Instruction::Andi { rd: 8, rs1: 8, imm: -1 },
+        Instruction::Xor { rd: 8, rs1: 8, rs2: 15 },
+        Instruction::Slli { rd: 31, rs1: 31, imm: 1 },
+        Instruction::Add { rd: 3, rs1: 3, rs2: 4 },
+        Instruction::Jalr { rd: 1, rs1: 5, imm: 0 },
+        Instruction::Jal { rd: 1, offset: -2048 },
+        Instruction::Jal { rd: 0, offset: 2046 },
+        Instruction::Beq { rs1: 8, rs2: 0, offset: -256 },
+        Instruction::Lw { rd: 1, rs1: 2, imm: 252 },
+        Instruction::Fld { rd: 8, rs1: 9, imm: 248 },
+        Instruction::Fsd { rs1: 2, rs2: 3, imm: 504 },
+        Instruction::Sw { rs1: 10, rs2: 11, imm: 124 },
+    ];
+    for inst in insts {
+        let parcel = compress(&inst, 32).unwrap_or_else(|| panic!("{inst:?} should compress"));
+        assert_eq!(decompress(parcel, 32), Some(inst), "0x{parcel:04x}");
+        assert!(decode_at(&parcel.to_le_bytes(), 32).unwrap().compressed);
+    }
+}
+
+#[test]
+fn test_rv64_16_bit_round_trip() {
+    let insts = [
+        Instruction::Addiw { rd: 10, rs1: 10, imm: 1 },
+        Instruction::Addiw { rd: 5, rs1: 5, imm: 0 }, // sext.w
+        Instruction::Addw { rd: 8, rs1: 8, rs2: 15 },
+        Instruction::Subw { rd: 9, rs1: 9, rs2: 10 },
+        Instruction::Ld { rd: 10, rs1: 11, imm: 8 },
+        Instruction::Sd { rs1: 8, rs2: 9, imm: 248 },
+        Instruction::Ld { rd: 1, rs1: 2, imm: 504 },
+        Instruction::Sd { rs1: 2, rs2: 1, imm: 8 },
+        Instruction::Slli { rd: 5, rs1: 5, imm: 63 },
+        Instruction::Srai { rd: 8, rs1: 8, imm: 40 },
+        Instruction::Jal { rd: 0, offset: 64 },
+    ];
+    for inst in insts {
+        let parcel = compress(&inst, 64).unwrap_or_else(|| panic!("{inst:?} should compress"));
+        assert_eq!(decompress(parcel, 64), Some(inst), "0x{parcel:04x}");
+    }
+    assert_eq!(compress(&Instruction::Addiw { rd: 10, rs1: 10, imm: 1 }, 64), Some(0x2505)); // c.addiw a0, 1
+    assert_eq!(compress(&Instruction::Ld { rd: 10, rs1: 11, imm: 8 }, 64), Some(0x6588)); // c.ld a0, 8(a1)
+
+    // The same parcels mean c.jal and c.flw on RV32.
+    assert!(matches!(decompress(0x2505, 32), Some(Instruction::Jal { rd: 1, .. })));
+    assert!(matches!(decompress(0x6588, 32), Some(Instruction::Flw { .. })));
+    assert!(compress(&Instruction::Jal { rd: 1, offset: 64 }, 64).is_none());
+    assert!(compress(&Instruction::Flw { rd: 8, rs1: 9, imm: 0 }, 64).is_none());
+    assert!(compress(&Instruction::Ld { rd: 8, rs1: 9, imm: 0 }, 32).is_none());
+    assert!(compress(&Instruction::Slli { rd: 5, rs1: 5, imm: 32 }, 32).is_none());
+}
+
+#[test]
+fn test_parse_compressed_mnemonics() {
+    assert_eq!(parse_instruction("c.li x10, 5"), Some(Instruction::Addi { rd: 10, rs1: 0, imm: 5 }));
+    assert_eq!(parse_instruction("c.mv x10, x11"), Some(Instruction::Add { rd: 10, rs1: 0, rs2: 11 }));
+    assert_eq!(parse_instruction("c.lwsp x1, 8(x2)"), Some(Instruction::Lw { rd: 1, rs1: 2, imm: 8 }));
+    assert_eq!(parse_instruction("c.sw x9, 4(x8)"), Some(Instruction::Sw { rs1: 8, rs2: 9, imm: 4 }));
+    assert_eq!(parse_instruction("c.addi16sp x2, -32"), Some(Instruction::Addi { rd: 2, rs1: 2, imm: -32 }));
+    assert_eq!(parse_instruction("c.jr x1"), Some(Instruction::Jalr { rd: 0, rs1: 1, imm: 0 }));
+
+    assert!(parse_instruction("c.lw x1, 0(x2)").is_none()); // needs x8-x15 or c.lwsp
+    assert!(parse_instruction("c.lwsp x1, 0(x8)").is_none());
+    assert!(parse_instruction("c.li x10, 100").is_none());
+    assert!(parse_instruction("c.add x0, x1").is_none());
+}
+
+#[test]
+fn test_compress_program_and_image_round_trip() {
+    let mut program = Program::new(vec![
+        Instruction::Addi { rd: 8, rs1: 0, imm: 0 },     // c.li
+        Instruction::Addi { rd: 9, rs1: 0, imm: 100 },   // too large for c.li
+        Instruction::Add { rd: 8, rs1: 8, rs2: 9 },      // c.add
+        Instruction::Addi { rd: 9, rs1: 9, imm: -1 },    // c.addi
+        Instruction::Bne { rs1: 9, rs2: 0, offset: -2 }, // c.bnez
+        Instruction::Print { rs: 8 },
+    ]);
+    let report = program.compress(32);
+    assert_eq!(report.compressed, 4);
+    assert_eq!(report.uncompressed_bytes, 24);
+    assert_eq!(report.bytes, 16);
+    assert_eq!(report.saved_bytes(), 8);
+    assert_eq!(program.addresses(), vec![0, 2, 6, 8, 10, 12, 16]);
+
+    let image = program.encode(32).unwrap();
+    assert_eq!(image.len(), 16);
+    let decoded = Program::decode(&image, 32).unwrap();
+    assert_eq!(decoded.instructions, program.instructions);
+    assert_eq!(decoded.compressed, program.compressed);
+
+    let mut cpu = Cpu::default();
+    cpu.load_program(decoded);
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(cpu.regs[8], 5050);
+}
+
+#[test]
+fn test_far_branch_stays_uncompressed() {
+    let mut insts = vec![Instruction::Beq { rs1: 8, rs2: 0, offset: 200 }];
+    insts.extend(std::iter::repeat_n(Instruction::Lui { rd: 1, imm: 0x12345 }, 199));
+    insts.push(Instruction::Print { rs: 8 });
+    let mut program = Program::new(insts);
+    program.compress(32);
+    assert!(!program.compressed[0]); // 199 * 4 bytes is beyond c.beqz's reach
+    let image = program.encode(32).unwrap();
+    assert_eq!(Program::decode(&image, 32).unwrap().instructions, program.instructions);
+}
+
+#[test]
+fn test_pc_advances_by_instruction_size() {
+    let mut cpu = Cpu::default();
+    cpu.load_program(load_asm("tests/asm_files/compressed.s").unwrap());
+    let mut addrs = vec![cpu.pc_address()];
+    for _ in 0..4 {
+        cpu.execute_next().unwrap();
+        addrs.push(cpu.pc_address());
+    }
+    assert_eq!(addrs, vec![0, 2, 4, 6, 8]);
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(cpu.regs[11], 30);
+    assert_eq!(cpu.address_of(8), 20);
+}
+
+#[test]
+fn test_compressed_forms_follow_xlen() {
+    let rv64: Isa = "rv64imac_xprint".parse().unwrap();
+    let mut cpu = Cpu::<Rv64>::with_memory(1024);
+    cpu.load_program(load_asm_for("tests/asm_files/compressed_rv64.s", &rv64).unwrap());
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(cpu.regs[10], -4);
+    assert!(load_asm_for("tests/asm_files/compressed_rv64.s", &"rv32imac_xprint".parse().unwrap()).is_err());
+
+    let err = load_asm_for("tests/asm_files/rv32c_only.s", &rv64).err().unwrap();
+    assert!(err.to_string().contains("has no 16-bit form on rv64"), "{err}");
+    assert!(load_asm_for("tests/asm_files/rv32c_only.s", &"rv32imac_xprint".parse().unwrap()).is_ok());
+}
+
+#[test]
+fn test_links_and_auipc_use_byte_addresses() {
+    let insts = vec![
+        Instruction::Addi { rd: 8, rs1: 0, imm: 1 }, // 0x0, c.li
+        Instruction::Auipc { rd: 5, imm: 0 },        // 0x2
+        Instruction::Jal { rd: 1, offset: 2 },       // 0x6, c.jal
+        Instruction::Addi { rd: 9, rs1: 0, imm: 1 }, // 0x8, skipped
+        Instruction::Add { rd: 10, rs1: 0, rs2: 1 }, // 0xa, c.mv
+    ];
+    let mut program = Program::new(insts.clone());
+    program.compressed = vec![true, false, true, false, true];
+    let mut cpu = Cpu::default();
+    cpu.load_program(program);
+    while cpu.execute_next().unwrap() {}
+    assert_eq!((cpu.regs[5], cpu.regs[1], cpu.regs[9], cpu.regs[10]), (2, 8, 0, 8));
+
+    let mut cpu = Cpu::default();
+    cpu.load_instructions(insts);
+    while cpu.execute_next().unwrap() {}
+    assert_eq!((cpu.regs[5], cpu.regs[1]), (4, 12));
+
+    // Jumping into the middle of the 4-byte auipc faults.
+    let mut program = Program::new(vec![Instruction::Addi { rd: 8, rs1: 0, imm: 4 }, Instruction::Auipc { rd: 5, imm: 0 }]);
+    program.instructions.push(Instruction::Jalr { rd: 0, rs1: 8, imm: 0 });
+    program.compressed = vec![true, false, true];
+    let mut cpu = Cpu::default();
+    cpu.load_program(program);
+    cpu.execute_next().unwrap();
+    cpu.pc = 2;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::MisalignedJump(4))));
+}
@@ -0,0 +1,134 @@
+use riscviz::asm_parser::Program;
+use riscviz::bus::Bus;
+use riscviz::cpu::Cpu;
+use riscviz::datapath::{self, AluOp, ControlSignals, DatapathTrace, ImmSel, MemToReg};
+use riscviz::instruction::Instruction;
+
+mod common;
+use common::asm;
+
+fn signals(line: &str) -> ControlSignals {
+    ControlSignals::for_instruction(&asm(line)).unwrap()
+}
+
+/// Runs `program` and returns the trace of its last instruction.
+fn trace_last(program: Vec<Instruction>) -> DatapathTrace {
+    let mut cpu: Cpu = Cpu::with_bus(Bus::new(0x1000));
+    let n = program.len();
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    for _ in 1..n {
+        cpu.execute_next().unwrap();
+    }
+    datapath::step(&mut cpu).unwrap().unwrap()
+}
+
+#[test]
+fn test_control_signals() {
+    let add = signals("add x1, x2, x3");
+    assert!(add.reg_write && !add.alu_src && !add.mem_read && !add.mem_write);
+    assert_eq!((add.alu_op, add.imm_sel, add.mem_to_reg), (AluOp::Funct, ImmSel::None, MemToReg::Alu));
+
+    let lw = signals("lw x1, 4(x2)");
+    assert!(lw.reg_write && lw.alu_src && lw.mem_read && !lw.mem_write);
+    assert_eq!((lw.alu_op, lw.imm_sel, lw.mem_to_reg), (AluOp::Add, ImmSel::I, MemToReg::Mem));
+
+    let sw = signals("sw x1, 4(x2)");
+    assert!(!sw.reg_write && sw.mem_write && sw.alu_src);
+    assert_eq!(sw.imm_sel, ImmSel::S);
+
+    let beq = signals("beq x1, x2, 8");
+    assert!(beq.branch && !beq.jump && !beq.reg_write && !beq.alu_src);
+    assert_eq!((beq.alu_op, beq.imm_sel), (AluOp::Branch, ImmSel::B));
+
+    let jal = signals("jal x1, 8");
+    assert!(jal.jump && jal.reg_write);
+    assert_eq!((jal.imm_sel, jal.mem_to_reg), (ImmSel::J, MemToReg::PcNext));
+
+    assert_eq!(signals("lui x1, 5").imm_sel, ImmSel::U);
+    assert_eq!(
+        signals("addi x1, x2, 5").to_string(),
+        "RegWrite=1 ALUSrc=1 MemRead=0 MemWrite=0 MemtoReg=ALU Branch=0 Jump=0 ALUOp=10 ImmSel=I"
+    );
+    assert_eq!(ControlSignals::for_instruction(&asm("csrrw x1, mstatus, x2")), None);
+    assert_eq!(ControlSignals::for_instruction(&asm("ecall")), None);
+}
+
+#[test]
+fn test_load_and_store_buses() {
+    let store = trace_last(vec![asm("addi x5, x0, 0x80"), asm("addi x6, x0, -3"), asm("sw x6, 8(x5)")]);
+    assert_eq!((store.rs1, store.rs2), (Some((5, 0x80)), Some((6, -3))));
+    assert_eq!((store.alu_a, store.alu_b, store.alu_out), (0x80, 8, 0x88));
+    assert_eq!((store.mem_addr, store.mem_data, store.write_back), (Some(0x88), Some(-3), None));
+
+    let load = trace_last(vec![
+        asm("addi x5, x0, 0x80"),
+        asm("addi x6, x0, -3"),
+        asm("sw x6, 8(x5)"),
+        asm("lw x7, 8(x5)"),
+    ]);
+    assert_eq!((load.mem_addr, load.mem_data, load.write_back), (Some(0x88), Some(-3), Some((7, -3))));
+    assert_eq!(load.next_pc, 4);
+}
+
+#[test]
+fn test_branch_and_jump_buses() {
+    let taken = trace_last(vec![asm("addi x5, x0, 2"), Instruction::Bne { rs1: 5, rs2: 0, offset: -1 }]);
+    assert_eq!((taken.alu_a, taken.alu_b, taken.alu_out), (2, 0, 2));
+    // Offsets and the PC bus are in bytes; next_pc is the instruction index.
+    assert_eq!((taken.imm, taken.address, taken.next_address, taken.next_pc), (Some(-4), 4, 0, 0));
+    assert!(taken.taken());
+
+    let jal = trace_last(vec![asm("addi x0, x0, 0"), Instruction::Jal { rd: 1, offset: 3 }]);
+    assert_eq!((jal.alu_a, jal.alu_b, jal.alu_out), (4, 12, 16));
+    // The link is the byte address of the next instruction.
+    assert_eq!((jal.write_back, jal.next_address, jal.next_pc), (Some((1, 8)), 16, 4));
+
+    let auipc = trace_last(vec![asm("addi x0, x0, 0"), asm("auipc x5, 1")]);
+    assert_eq!((auipc.alu_a, auipc.alu_b, auipc.write_back), (4, 0x1000, Some((5, 0x1004))));
+
+    let lui = trace_last(vec![asm("lui x5, 0x12")]);
+    assert_eq!((lui.alu_a, lui.alu_b, lui.write_back), (0, 0x12000, Some((5, 0x12000))));
+}
+
+#[test]
+fn test_compressed_jump_buses() {
+    // c.li, addi, c.jal over a c.mv: the offset spans a 2-byte instruction.
+    let mut program = Program::new(vec![
+        asm("addi x8, x0, 1"),
+        asm("addi x9, x0, 1000"),
+        Instruction::Jal { rd: 1, offset: 2 },
+        asm("add x10, x0, x1"),
+        asm("addi x11, x0, 1"),
+    ]);
+    program.compressed = vec![true, false, true, true, false];
+    let mut cpu: Cpu = Cpu::with_bus(Bus::new(0x1000));
+    cpu.load_program(program);
+    cpu.execute_next().unwrap();
+    cpu.execute_next().unwrap();
+    let jal = datapath::step(&mut cpu).unwrap().unwrap();
+    assert_eq!((jal.address, jal.imm, jal.alu_out, jal.next_address), (6, Some(4), 10, 10));
+    assert_eq!(jal.write_back, Some((1, 8)));
+}
+
+#[test]
+fn test_step_skips_instructions_off_the_datapath() {
+    let mut cpu: Cpu = Cpu::with_bus(Bus::new(0x1000));
+    cpu.load_instructions(vec![asm("csrrs x5, misa, x0"), asm("addi x6, x5, 0")]);
+    cpu.pc = 0;
+    assert_eq!(datapath::step(&mut cpu).unwrap(), None);
+    assert_eq!(cpu.pc, 1);
+    assert!(datapath::step(&mut cpu).unwrap().is_some());
+}
+
+#[test]
+fn test_diagram() {
+    let add = trace_last(vec![asm("addi x5, x0, 4"), asm("add x7, x5, x5")]);
+    let text = add.to_string();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "1: Add { rd: 7, rs1: 5, rs2: 5 }");
+    assert!(lines[2].starts_with("|   PC   |-->| Imm gen  |-->| Registers  |-->|    ALU    |-->"));
+    assert!(lines[3].contains("| rs1 x5 = 4 |") && lines[4].contains("| x7 <- 8      |"));
+    assert!(lines[6].contains("| out 8     |"));
+    assert!(lines.last().unwrap().starts_with("RegWrite=1 ALUSrc=0"));
+}
@@ -0,0 +1,106 @@
+use riscviz::asm_parser::load_asm;
+use riscviz::cpu::Cpu;
+use riscviz::engine::{EngineKind, UnknownEngine};
+use riscviz::instruction::Instruction;
+use riscviz::multicycle::State;
+use riscviz::utils::run_on_every_engine;
+
+mod common;
+use common::asm;
+
+/// Counts down from 4, storing each count to 0x80.
+fn countdown() -> Vec<Instruction> {
+    vec![
+        asm("addi x5, x0, 4"),
+        asm("sw x5, 0x80(x0)"),
+        asm("lw x6, 0x80(x0)"),
+        asm("addi x5, x6, -1"),
+        Instruction::Bne { rs1: 5, rs2: 0, offset: -3 },
+    ]
+}
+
+fn cpu_with(program: Vec<Instruction>) -> Cpu {
+    let mut cpu: Cpu = Cpu::new(1024);
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    cpu
+}
+
+#[test]
+fn test_engine_names() {
+    for kind in EngineKind::ALL {
+        assert_eq!(kind.name().parse::<EngineKind>(), Ok(kind));
+        assert_eq!(kind.build::<riscviz::xlen::Rv32>().name(), kind.name());
+    }
+    assert_eq!("superscalar".parse::<EngineKind>(), Err(UnknownEngine("superscalar".to_string())));
+}
+
+#[test]
+fn test_asm_files_agree() {
+    for file in ["tests/asm_files/basic.s", "tests/asm_files/compressed.s"] {
+        run_on_every_engine(|| {
+            let mut cpu: Cpu = Cpu::default();
+            cpu.load_program(load_asm(file).unwrap());
+            cpu
+        })
+        .unwrap_or_else(|e| panic!("{file}: {e}"));
+    }
+}
+
+#[test]
+fn test_cycle_counts() {
+    let mut counts = vec![];
+    for kind in EngineKind::ALL {
+        let mut cpu = cpu_with(countdown());
+        let mut engine = kind.build();
+        engine.run(&mut cpu, 1000).unwrap();
+        assert_eq!(cpu.regs[5], 0, "{kind}");
+        assert!(!engine.step(&mut cpu).unwrap(), "{kind} is done");
+        counts.push((engine.retired(), engine.cycles()));
+    }
+    // One addi, then four passes of sw, lw, addi, bne.
+    let retired = 1 + 4 * 4;
+    let multicycle = 4 + 4 * [asm("sw x0, 0(x0)"), asm("lw x0, 0(x0)"), asm("addi x0, x0, 0"), asm("bne x0, x0, 0")]
+        .iter()
+        .map(|inst| State::path(inst).len())
+        .sum::<usize>();
+    // Fill and drain, the load-use stall in each pass, and three taken
+    // branches flushing two instructions each.
+    let pipeline = retired + 4 + 4 + 3 * 2;
+    // The first sw issues in cycle 2 and waits a cycle for x5. Each pass
+    // then takes 9 cycles to the bne's write, with the next pass issuing
+    // in that cycle: sw and lw take two cycles each in Mem, the lw, addi and
+    // bne each write a cycle after finishing, and each waits on the one
+    // before. The last bne commits the cycle after its write.
+    let tomasulo = 2 + 1 + 4 * 9 + 1;
+    // The default latencies: each sw and lw also pays 10 cycles of memory,
+    // three bne are taken and the last is not.
+    let timing = 1 + 4 * ((1 + 10) + (2 + 10) + 1) + 3 * 2 + 1;
+    assert_eq!(
+        counts,
+        [(retired, retired), (retired, multicycle), (retired, pipeline), (retired, tomasulo), (retired, timing)]
+    );
+}
+
+#[test]
+fn test_run_stops_at_limit() {
+    let mut cpu = cpu_with(vec![Instruction::Jal { rd: 0, offset: 0 }]);
+    for kind in EngineKind::ALL {
+        let mut engine = kind.build();
+        assert_eq!(engine.run(&mut cpu, 25).unwrap(), 25, "{kind}");
+        assert_eq!(engine.cycles(), 25);
+    }
+}
+
+#[test]
+fn test_trap_is_not_retired() {
+    let program = ["addi x1, x0, 12", "csrw mtvec, x1", "ecall", "addi x2, x0, 1"];
+    let cpu = run_on_every_engine(|| cpu_with(program.into_iter().map(asm).collect())).unwrap();
+    assert_eq!((cpu.regs[2], cpu.instret()), (1, 3));
+    for kind in EngineKind::ALL {
+        let mut cpu = cpu_with(program.into_iter().map(asm).collect());
+        let mut engine = kind.build();
+        engine.run(&mut cpu, 1000).unwrap();
+        assert_eq!(engine.retired(), 3, "{kind}");
+    }
+}
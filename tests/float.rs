@@ -0,0 +1,227 @@
+use riscviz::fpu::{self, RoundingMode};
+use riscviz::instruction::Instruction;
+use riscviz::run_program;
+
+const DYN: RoundingMode = RoundingMode::Dyn;
+
+#[test]
+fn test_single_arithmetic() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 3 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 4 },
+        Instruction::FcvtSW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FcvtSW { rd: 2, rs1: 2, rm: DYN },
+        Instruction::FaddS { rd: 3, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FsubS { rd: 4, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FmulS { rd: 5, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FdivS { rd: 6, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FsqrtS { rd: 7, rs1: 2, rm: DYN },
+        Instruction::FmaddS { rd: 8, rs1: 1, rs2: 2, rs3: 2, rm: DYN },   // 3*4+4
+        Instruction::FnmsubS { rd: 9, rs1: 1, rs2: 2, rs3: 2, rm: DYN },  // -(3*4)+4
+    ]);
+    assert_eq!(cpu.freg::<f32>(3), 7.0);
+    assert_eq!(cpu.freg::<f32>(4), -1.0);
+    assert_eq!(cpu.freg::<f32>(5), 12.0);
+    assert_eq!(cpu.freg::<f32>(6), 0.75);
+    assert_eq!(cpu.freg::<f32>(7), 2.0);
+    assert_eq!(cpu.freg::<f32>(8), 16.0);
+    assert_eq!(cpu.freg::<f32>(9), -8.0);
+    assert_eq!(cpu.fcsr(), 0); // all exact
+}
+
+#[test]
+fn test_nan_boxing() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::FcvtSW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FcvtDW { rd: 2, rs1: 1, rm: DYN },                    // not a boxed single
+        Instruction::FaddS { rd: 3, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FmvXW { rd: 4, rs1: 3 },
+    ]);
+    assert_eq!(cpu.fregs[1] >> 32, 0xFFFF_FFFF);
+    assert_eq!(cpu.regs[4], 0x7FC0_0000); // canonical NaN
+}
+
+#[test]
+fn test_double_precision_and_conversion() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 3 },
+        Instruction::FcvtDW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FcvtDW { rd: 2, rs1: 2, rm: DYN },
+        Instruction::FdivD { rd: 3, rs1: 1, rs2: 2, rm: DYN },
+        Instruction::FcvtSD { rd: 4, rs1: 3, rm: DYN },
+        Instruction::FcvtDS { rd: 5, rs1: 4, rm: DYN },
+    ]);
+    assert_eq!(cpu.freg::<f64>(3), 1.0 / 3.0);
+    assert_eq!(cpu.freg::<f32>(4), 1.0f32 / 3.0);
+    assert_eq!(cpu.freg::<f64>(5), (1.0f32 / 3.0) as f64);
+    assert_eq!(cpu.fcsr() as u8, fpu::NX);
+}
+
+#[test]
+fn test_directed_rounding() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 3 },
+        Instruction::FcvtSW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FcvtSW { rd: 2, rs1: 2, rm: DYN },
+        Instruction::FdivS { rd: 3, rs1: 1, rs2: 2, rm: RoundingMode::Rdn },
+        Instruction::FdivS { rd: 4, rs1: 1, rs2: 2, rm: RoundingMode::Rup },
+        Instruction::FdivS { rd: 5, rs1: 1, rs2: 2, rm: RoundingMode::Rtz },
+    ]);
+    let down = cpu.freg::<f32>(3);
+    let up = cpu.freg::<f32>(4);
+    assert!(down < 1.0 / 3.0 && (1.0 / 3.0) < up as f64 && down as f64 <= 1.0 / 3.0);
+    assert_eq!(down.next_up(), up);
+    assert_eq!(cpu.freg::<f32>(5), down);
+}
+
+#[test]
+fn test_dynamic_rounding_from_frm() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 3 },                      // RUP
+        Instruction::Csrrwi { rd: 0, imm: 3, csr: 0x002 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 5 },
+        Instruction::Addi { rd: 3, rs1: 0, imm: 2 },
+        Instruction::FcvtDW { rd: 2, rs1: 2, rm: DYN },
+        Instruction::FcvtDW { rd: 3, rs1: 3, rm: DYN },
+        Instruction::FdivD { rd: 4, rs1: 2, rs2: 3, rm: DYN },             // 2.5
+        Instruction::FcvtWD { rd: 5, rs1: 4, rm: DYN },                    // rup -> 3
+        Instruction::FcvtWD { rd: 6, rs1: 4, rm: RoundingMode::Rne },     // 2
+        Instruction::FcvtWD { rd: 7, rs1: 4, rm: RoundingMode::Rmm },     // 3
+        Instruction::FcvtWD { rd: 8, rs1: 4, rm: RoundingMode::Rdn },     // 2
+        Instruction::FsgnjnD { rd: 4, rs1: 4, rs2: 4 },                    // -2.5
+        Instruction::FcvtWD { rd: 9, rs1: 4, rm: RoundingMode::Rtz },     // -2
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: 0x003 },
+    ]);
+    assert_eq!(cpu.regs[5], 3);
+    assert_eq!(cpu.regs[6], 2);
+    assert_eq!(cpu.regs[7], 3);
+    assert_eq!(cpu.regs[8], 2);
+    assert_eq!(cpu.regs[9], -2);
+    assert_eq!(cpu.regs[10], (3 << 5) | fpu::NX as i32);
+}
+
+#[test]
+fn test_exception_flags() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::FcvtSW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FmvWX { rd: 10, rs1: 0 },                             // +0.0
+        Instruction::FdivS { rd: 2, rs1: 1, rs2: 10, rm: DYN },            // 1/0
+        Instruction::Csrrw { rd: 10, rs1: 0, csr: 0x001 },                 // read & clear
+        Instruction::FdivS { rd: 3, rs1: 10, rs2: 10, rm: DYN },           // 0/0
+        Instruction::Csrrw { rd: 11, rs1: 0, csr: 0x001 },
+        Instruction::FcvtWuS { rd: 4, rs1: 2, rm: DYN },                   // inf -> u32::MAX
+        Instruction::Csrrw { rd: 12, rs1: 0, csr: 0x001 },
+        Instruction::FmvXW { rd: 5, rs1: 3 },
+    ]);
+    assert!(cpu.freg::<f32>(2).is_infinite());
+    assert_eq!(cpu.regs[10], fpu::DZ as i32);
+    assert_eq!(cpu.regs[11], fpu::NV as i32);
+    assert_eq!(cpu.regs[12], fpu::NV as i32);
+    assert_eq!(cpu.regs[4], -1);
+    assert_eq!(cpu.regs[5], 0x7FC0_0000);
+}
+
+#[test]
+fn test_overflow_rounding() {
+    let cpu = run_program!(vec![
+        Instruction::Lui { rd: 1, imm: 0x7F7FF },                          // f32::MAX
+        Instruction::Addi { rd: 1, rs1: 1, imm: 0x7FF },
+        Instruction::Addi { rd: 1, rs1: 1, imm: 0x7FF },
+        Instruction::Addi { rd: 1, rs1: 1, imm: 1 },
+        Instruction::FmvWX { rd: 1, rs1: 1 },
+        Instruction::FaddS { rd: 2, rs1: 1, rs2: 1, rm: RoundingMode::Rne },
+        Instruction::FaddS { rd: 3, rs1: 1, rs2: 1, rm: RoundingMode::Rtz },
+        Instruction::FcvtDS { rd: 4, rs1: 1, rm: DYN },
+        Instruction::FaddD { rd: 4, rs1: 4, rs2: 4, rm: DYN },
+        Instruction::FcvtSD { rd: 5, rs1: 4, rm: DYN },
+    ]);
+    assert_eq!(cpu.freg::<f32>(1), f32::MAX);
+    assert_eq!(cpu.freg::<f32>(2), f32::INFINITY);
+    assert_eq!(cpu.freg::<f32>(3), f32::MAX);
+    assert_eq!(cpu.freg::<f32>(5), f32::INFINITY);
+    assert_eq!(cpu.fcsr() as u8, fpu::OF | fpu::NX);
+}
+
+#[test]
+fn test_min_max_and_compare() {
+    let cpu = run_program!(vec![
+        Instruction::FmvWX { rd: 2, rs1: 0 },                              // +0.0
+        Instruction::FsgnjnS { rd: 1, rs1: 2, rs2: 2 },                    // -0.0
+        Instruction::FminS { rd: 3, rs1: 2, rs2: 1 },
+        Instruction::FmaxS { rd: 4, rs1: 1, rs2: 2 },
+        Instruction::FeqS { rd: 5, rs1: 1, rs2: 2 },
+        Instruction::FltS { rd: 6, rs1: 1, rs2: 2 },
+        Instruction::FdivS { rd: 7, rs1: 2, rs2: 2, rm: DYN },             // qNaN
+        Instruction::FminS { rd: 8, rs1: 7, rs2: 2 },
+        Instruction::Csrrw { rd: 10, rs1: 0, csr: 0x001 },
+        Instruction::FeqS { rd: 9, rs1: 7, rs2: 7 },                       // quiet: no NV
+        Instruction::Csrrw { rd: 11, rs1: 0, csr: 0x001 },
+        Instruction::FleS { rd: 9, rs1: 7, rs2: 7 },                       // signalling: NV
+        Instruction::Csrrw { rd: 12, rs1: 0, csr: 0x001 },
+    ]);
+    assert!(cpu.freg::<f32>(3).is_sign_negative());
+    assert!(cpu.freg::<f32>(4).is_sign_positive());
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.regs[6], 0);
+    assert_eq!(cpu.freg::<f32>(8), 0.0);
+    assert_eq!(cpu.regs[11], 0);
+    assert_eq!(cpu.regs[12], fpu::NV as i32);
+}
+
+#[test]
+fn test_fclass() {
+    let cpu = run_program!(vec![
+        Instruction::FmvWX { rd: 1, rs1: 0 },
+        Instruction::FclassS { rd: 1, rs1: 1 },                            // +0
+        Instruction::FclassD { rd: 2, rs1: 31 },                           // +0 double
+        Instruction::FclassS { rd: 3, rs1: 31 },                           // unboxed -> qNaN
+        Instruction::Lui { rd: 4, imm: 0x7F800 },
+        Instruction::Addi { rd: 4, rs1: 4, imm: 1 },                       // sNaN
+        Instruction::FmvWX { rd: 4, rs1: 4 },
+        Instruction::FclassS { rd: 4, rs1: 4 },
+        Instruction::Lui { rd: 5, imm: 0xFF800 },                          // -inf
+        Instruction::FmvWX { rd: 5, rs1: 5 },
+        Instruction::FclassS { rd: 5, rs1: 5 },
+    ]);
+    assert_eq!(cpu.regs[1], 1 << 4);
+    assert_eq!(cpu.regs[2], 1 << 4);
+    assert_eq!(cpu.regs[3], 1 << 9);
+    assert_eq!(cpu.regs[4], 1 << 8);
+    assert_eq!(cpu.regs[5], 1 << 0);
+}
+
+#[test]
+fn test_fp_load_store() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 7 },
+        Instruction::FcvtDW { rd: 1, rs1: 1, rm: DYN },
+        Instruction::FcvtSW { rd: 2, rs1: 1, rm: DYN },
+        Instruction::Fsd { rs1: 0, rs2: 1, imm: 16 },
+        Instruction::Fsw { rs1: 0, rs2: 2, imm: 24 },
+        Instruction::Fld { rd: 3, rs1: 0, imm: 16 },
+        Instruction::Flw { rd: 4, rs1: 0, imm: 24 },
+        Instruction::Lw { rd: 5, rs1: 0, imm: 24 },
+    ]);
+    assert_eq!(cpu.freg::<f64>(3), 7.0);
+    assert_eq!(cpu.freg::<f32>(4), 7.0);
+    assert_eq!(cpu.regs[5], 7.0f32.to_bits() as i32);
+}
+
+#[test]
+fn test_unsigned_conversions() {
+    let cpu = run_program!(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: -1 },
+        Instruction::FcvtDWu { rd: 1, rs1: 1, rm: DYN },                   // 4294967295.0
+        Instruction::FcvtWuD { rd: 2, rs1: 1, rm: DYN },
+        Instruction::FsgnjnD { rd: 3, rs1: 1, rs2: 1 },
+        Instruction::FcvtWuD { rd: 4, rs1: 3, rm: DYN },                   // negative -> 0, NV
+    ]);
+    assert_eq!(cpu.freg::<f64>(1), u32::MAX as f64);
+    assert_eq!(cpu.regs[2], -1);
+    assert_eq!(cpu.regs[4], 0);
+    assert_eq!(cpu.fcsr() as u8, fpu::NV);
+}
@@ -0,0 +1,169 @@
+use riscviz::bus::Bus;
+use riscviz::clint::{Clint, VIRT_CLINT_BASE};
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::csr;
+use riscviz::instruction::Instruction;
+use riscviz::privilege::{self, Privilege};
+use riscviz::utils::run_on_every_engine;
+
+fn clint_cpu(ratio: u64) -> Cpu {
+    let mut bus = Bus::new(0x1000);
+    bus.attach(VIRT_CLINT_BASE, None, Box::new(Clint::new(1, ratio))).unwrap();
+    Cpu::with_bus(bus)
+}
+
+/// Runs `program` on a CLINT CPU on every engine.
+fn run(ratio: u64, program: Vec<Instruction>) -> Result<Cpu, CpuError> {
+    run_on_every_engine(|| {
+        let mut cpu = clint_cpu(ratio);
+        cpu.load_instructions(program.clone());
+        cpu.pc = 0;
+        cpu
+    })
+}
+
+#[test]
+fn test_timer_interrupt_preempts_loop() {
+    let cpu = run(1, vec![
+        Instruction::Lui { rd: 1, imm: 0x2004 }, // mtimecmp
+        Instruction::Addi { rd: 5, rs1: 0, imm: 10 },
+        Instruction::Sw { rs1: 1, rs2: 5, imm: 0 },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 4 },
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 11 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Addi { rd: 6, rs1: 0, imm: privilege::MTIP as i32 },
+        Instruction::Csrrs { rd: 0, rs1: 6, csr: csr::MIE },
+        Instruction::Csrrsi { rd: 0, imm: privilege::MIE as i32, csr: csr::MSTATUS },
+        Instruction::Addi { rd: 7, rs1: 7, imm: 1 },
+        Instruction::Jal { rd: 0, offset: -1 },
+        // handler
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Csrrs { rd: 11, rs1: 0, csr: csr::MEPC },
+        Instruction::Csrrs { rd: 12, rs1: 0, csr: csr::MSTATUS },
+    ]).unwrap();
+    assert_eq!(cpu.regs[10], 0x8000_0007_u32 as i32);
+    assert_eq!(cpu.regs[7], 1); // mtime reaches 10 after the tenth instruction
+    assert_eq!(cpu.regs[11], 4 * 10);
+    assert_eq!(cpu.regs[12] as u64 & (privilege::MIE | privilege::MPIE), privilege::MPIE);
+}
+
+#[test]
+fn test_software_interrupt() {
+    let cpu = run(1, vec![
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 9 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Addi { rd: 6, rs1: 0, imm: privilege::MSIP as i32 },
+        Instruction::Csrrs { rd: 0, rs1: 6, csr: csr::MIE },
+        Instruction::Csrrsi { rd: 0, imm: privilege::MIE as i32, csr: csr::MSTATUS },
+        Instruction::Lui { rd: 1, imm: 0x2000 }, // msip
+        Instruction::Addi { rd: 5, rs1: 0, imm: 1 },
+        Instruction::Sw { rs1: 1, rs2: 5, imm: 0 },
+        Instruction::Addi { rd: 7, rs1: 0, imm: 1 }, // preempted
+        // handler
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Csrrs { rd: 11, rs1: 0, csr: csr::MIP },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 0 },
+        Instruction::Csrrs { rd: 12, rs1: 0, csr: csr::MIP },
+    ]).unwrap();
+    assert_eq!(cpu.regs[10], 0x8000_0003_u32 as i32);
+    assert_eq!(cpu.regs[7], 0);
+    assert_eq!(cpu.regs[11] as u64, privilege::MSIP);
+    assert_eq!(cpu.regs[12], 0);
+}
+
+#[test]
+fn test_user_mode_takes_interrupts_regardless_of_mie() {
+    let cpu = run(1, vec![
+        Instruction::Lui { rd: 1, imm: 0x2004 },
+        Instruction::Addi { rd: 5, rs1: 0, imm: 12 },
+        Instruction::Sw { rs1: 1, rs2: 5, imm: 0 },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 4 },
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 12 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Addi { rd: 6, rs1: 0, imm: privilege::MTIP as i32 },
+        Instruction::Csrrs { rd: 0, rs1: 6, csr: csr::MIE },
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 11 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MEPC },
+        Instruction::Mret, // to U with mstatus.MIE clear
+        Instruction::Jal { rd: 0, offset: 0 },
+        // handler
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Csrrs { rd: 11, rs1: 0, csr: csr::MSTATUS },
+    ]).unwrap();
+    assert_eq!(cpu.regs[10], 0x8000_0007_u32 as i32);
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.regs[11] as u64 & privilege::MPP, 0);
+}
+
+#[test]
+fn test_machine_interrupts_wait_for_mie() {
+    let cpu = run(1, vec![
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 5 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Addi { rd: 6, rs1: 0, imm: privilege::MTIP as i32 },
+        Instruction::Csrrs { rd: 0, rs1: 6, csr: csr::MIE }, // mtimecmp resets to the maximum
+        Instruction::Jal { rd: 0, offset: 2 },
+        Instruction::Addi { rd: 7, rs1: 0, imm: 1 }, // handler
+    ]).unwrap();
+    assert_eq!(cpu.regs[7], 0);
+}
+
+#[test]
+fn test_mip_timer_bit_is_read_only() {
+    let cpu = run(1, vec![
+        Instruction::Lui { rd: 1, imm: 0x2004 },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 0 },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 4 }, // mtimecmp = 0
+        Instruction::Addi { rd: 5, rs1: 0, imm: -1 },
+        Instruction::Csrrc { rd: 0, rs1: 5, csr: csr::MIP },
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MIP },
+        Instruction::Csrrw { rd: 0, rs1: 5, csr: csr::MIP },
+        Instruction::Csrrs { rd: 11, rs1: 0, csr: csr::MIP },
+        Instruction::Csrrw { rd: 0, rs1: 5, csr: csr::MIDELEG },
+        Instruction::Csrrc { rd: 0, rs1: 5, csr: csr::SIP },
+        Instruction::Csrrs { rd: 12, rs1: 0, csr: csr::SIP },
+    ]).unwrap();
+    assert_eq!(cpu.regs[10] as u64, privilege::MTIP);
+    assert_eq!(cpu.regs[11] as u64, privilege::MTIP | privilege::SSIP | privilege::STIP | privilege::SEIP);
+    assert_eq!(cpu.regs[12] as u64, privilege::STIP | privilege::SEIP); // only SSIP is writable through sip
+}
+
+#[test]
+fn test_delegated_software_interrupt_goes_to_supervisor() {
+    let cpu = run(1, vec![
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 11 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::STVEC },
+        Instruction::Csrrsi { rd: 0, imm: privilege::SSIP as i32, csr: csr::MIDELEG },
+        Instruction::Csrrsi { rd: 0, imm: privilege::SSIP as i32, csr: csr::SIE },
+        Instruction::Csrrsi { rd: 0, imm: privilege::SSIP as i32, csr: csr::MIP }, // ignored in M
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 8 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MEPC },
+        Instruction::Mret,
+        // user program
+        Instruction::Addi { rd: 7, rs1: 0, imm: 1 },
+        Instruction::Jal { rd: 0, offset: 0 },
+        Instruction::Ebreak,
+        // supervisor handler
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::SCAUSE },
+        Instruction::Csrrs { rd: 11, rs1: 0, csr: csr::SEPC },
+        Instruction::Csrrci { rd: 12, imm: privilege::SSIP as i32, csr: csr::SIP },
+        Instruction::Csrrs { rd: 13, rs1: 0, csr: csr::SIP },
+    ]).unwrap();
+    assert_eq!(cpu.regs[10], 0x8000_0001_u32 as i32);
+    assert_eq!(cpu.regs[11], 4 * 8);
+    assert_eq!(cpu.regs[7], 0);
+    assert_eq!(cpu.regs[12] as u64, privilege::SSIP);
+    assert_eq!(cpu.regs[13], 0);
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+}
+
+#[test]
+fn test_tick_ratio() {
+    let mut program = vec![Instruction::Lui { rd: 1, imm: 0x200C }];
+    program.extend(vec![Instruction::Addi { rd: 0, rs1: 0, imm: 0 }; 9]);
+    program.push(Instruction::Lw { rd: 5, rs1: 1, imm: -8 }); // mtime
+    program.push(Instruction::Lw { rd: 6, rs1: 1, imm: -4 });
+    let cpu = run(4, program).unwrap();
+    assert_eq!(cpu.regs[5], 2); // ten instructions before the load
+    assert_eq!(cpu.regs[6], 0);
+}
@@ -0,0 +1,96 @@
+use riscviz::asm_parser::{load_asm, load_asm_for};
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::csr;
+use riscviz::instruction::Instruction;
+use riscviz::isa::{Extension, Extensions, Isa, IsaError};
+use riscviz::utils::run_program_on;
+use riscviz::xlen::Rv64;
+
+#[test]
+fn test_parse_isa_strings() {
+    let isa: Isa = "rv32i".parse().unwrap();
+    assert_eq!(isa.xlen, 32);
+    assert_eq!(isa.extensions, Extensions::base());
+
+    let isa: Isa = "rv32im_zicsr".parse().unwrap();
+    assert!(isa.has(Extension::M) && isa.has(Extension::Zicsr));
+    assert!(!isa.has(Extension::A));
+
+    let isa: Isa = "RV32IMAC".parse().unwrap();
+    assert_eq!(isa.to_string(), "rv32imac");
+
+    // g expands, d pulls in f, f pulls in zicsr
+    assert_eq!("rv64gc".parse::<Isa>().unwrap().to_string(), "rv64imafdc_zicsr");
+    assert_eq!("rv32id".parse::<Isa>().unwrap().to_string(), "rv32ifd_zicsr");
+    assert_eq!("rv32imzbb_zbs".parse::<Isa>().unwrap().to_string(), "rv32im_zbb_zbs");
+    assert_eq!("rv32ib".parse::<Isa>().unwrap().to_string(), "rv32i_zba_zbb_zbs");
+    assert_eq!(Isa::default().to_string().parse::<Isa>(), Ok(Isa::default()));
+
+    assert!(matches!("rv128i".parse::<Isa>(), Err(IsaError::BadPrefix(_))));
+    assert!(matches!("rv32m".parse::<Isa>(), Err(IsaError::MissingBase(_))));
+    assert_eq!("rv32iq".parse::<Isa>(), Err(IsaError::UnknownExtension("q".into())));
+    assert_eq!("rv32i_zfoo".parse::<Isa>(), Err(IsaError::UnknownExtension("zfoo".into())));
+}
+
+#[test]
+fn test_check_instructions() {
+    let isa: Isa = "rv32i".parse().unwrap();
+    let mul = Instruction::Mul { rd: 1, rs1: 2, rs2: 3 };
+    assert!(isa.check(&Instruction::Add { rd: 1, rs1: 2, rs2: 3 }).is_ok());
+    assert_eq!(isa.check(&mul), Err(IsaError::Disabled { ext: Extension::M, isa }));
+    assert_eq!(
+        isa.check(&mul).unwrap_err().to_string(),
+        "requires the M extension, which rv32i does not enable"
+    );
+    assert!(matches!(isa.check(&Instruction::Print { rs: 1 }), Err(IsaError::Disabled { ext: Extension::Xprint, .. })));
+    assert!(matches!(isa.check(&Instruction::Addw { rd: 1, rs1: 2, rs2: 3 }), Err(IsaError::Rv64Only(_))));
+}
+
+#[test]
+fn test_disabled_instructions_trap_at_runtime() {
+    let isa: Isa = "rv32i".parse().unwrap();
+    let mut cpu = Cpu::default();
+    cpu.set_extensions(isa.extensions);
+    cpu.load_instructions(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 6 },
+        Instruction::Mul { rd: 2, rs1: 1, rs2: 1 },
+    ]);
+    assert!(cpu.execute_next().unwrap());
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Mul { .. }))));
+    assert_eq!(cpu.pc, 1);
+}
+
+#[test]
+fn test_misa() {
+    let mut cpu = Cpu::default();
+    cpu.set_extensions("rv32imac".parse::<Isa>().unwrap().extensions.with(Extension::Zicsr));
+    cpu.load_instructions(vec![
+        Instruction::Csrrs { rd: 1, rs1: 0, csr: csr::MISA },
+        Instruction::Addi { rd: 2, rs1: 0, imm: -1 },
+        Instruction::Csrrw { rd: 0, rs1: 2, csr: csr::MISA }, // WARL: ignored
+        Instruction::Csrrs { rd: 3, rs1: 0, csr: csr::MISA },
+    ]);
+    while cpu.execute_next().unwrap() {}
+    let letters = (1 << 0) | (1 << 2) | (1 << 8) | (1 << 12) | (1 << 18) | (1 << 20); // A, C, I, M, S, U
+    assert_eq!(cpu.regs[1], (1 << 30) | letters);
+    assert_eq!(cpu.regs[3], cpu.regs[1]);
+
+    let cpu = run_program_on::<Rv64>(vec![Instruction::Csrrs { rd: 1, rs1: 0, csr: csr::MISA }], 0);
+    assert_eq!(cpu.regs[1] as u64 >> 62, 2);
+    assert_eq!("rv64gc".parse::<Isa>().unwrap().misa() & 0x3FF_FFFF, 0x14_112D);
+}
+
+#[test]
+fn test_assembler_rejects_disabled_instructions() {
+    assert!(load_asm("tests/asm_files/basic.s").is_ok());
+
+    let err = load_asm_for("tests/asm_files/basic.s", &"rv32i".parse().unwrap()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "tests/asm_files/basic.s:3: `print x21` requires the Xprint extension, which rv32i does not enable"
+    );
+
+    let err = load_asm_for("tests/asm_files/compressed.s", &"rv32im_xprint".parse().unwrap()).err().unwrap();
+    assert!(err.to_string().contains("requires the C extension"));
+    assert!(load_asm_for("tests/asm_files/compressed.s", &"rv32imc_xprint".parse().unwrap()).is_ok());
+}
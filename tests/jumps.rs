@@ -10,7 +10,7 @@ fn test_non_leaf_function() {
 
         // int foo() { return bar() + 1; }
         Instruction::Addi { rd: 2, rs1: 2, imm: -4 },    // 2: sp -= 4
-        Instruction::Sw   { rs1: 1, rs2: 2, imm: 0 },    // 3: save ra
+        Instruction::Sw   { rs1: 2, rs2: 1, imm: 0 },    // 3: save ra
         Instruction::Jal  { rd: 1, offset: -4 },         // 4: call bar (→0)
         Instruction::Addi { rd: 10, rs1: 10, imm: 1 },   // 5: result += 1
         Instruction::Lw   { rd: 1, rs1: 2, imm: 0 },     // 6: restore ra
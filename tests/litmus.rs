@@ -0,0 +1,95 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::litmus::{Litmus, LitmusError, Observable};
+
+fn explore(path: &str) -> riscviz::litmus::Outcomes {
+    let source = std::fs::read_to_string(path).unwrap();
+    Litmus::parse(&source).unwrap().explore().unwrap()
+}
+
+fn reg(hart: usize, reg: usize) -> Observable {
+    Observable::Reg { hart, reg }
+}
+
+#[test]
+fn test_store_buffering() {
+    let outcomes = explore("tests/litmus/sb.litmus");
+    assert_eq!(outcomes.interleavings, 20);
+    assert_eq!(outcomes.states.len(), 3);
+    assert!(!outcomes.reachable());
+    assert!(outcomes.contains(&[(reg(0, 6), 1), (reg(1, 6), 1)]));
+    assert!(outcomes.contains(&[(reg(0, 6), 0), (reg(1, 6), 1)]));
+    assert!(!outcomes.contains(&[(reg(0, 6), 0), (reg(1, 6), 0)]));
+}
+
+#[test]
+fn test_message_passing() {
+    let outcomes = explore("tests/litmus/mp.litmus");
+    assert!(!outcomes.reachable());
+    assert!(outcomes.contains(&[(reg(1, 5), 1), (reg(1, 6), 42)]));
+    assert!(outcomes.contains(&[(Observable::Location("data".into()), 42)]));
+    let text = outcomes.to_string();
+    assert!(text.starts_with("Test MP under sequential consistency\nStates 3\n"));
+    assert!(text.ends_with("exists (1:x5=1 /\\ 1:x6=0): never"));
+}
+
+#[test]
+fn test_load_buffering() {
+    let outcomes = explore("tests/litmus/lb.litmus");
+    assert!(!outcomes.reachable());
+    assert_eq!(outcomes.states.values().sum::<usize>(), outcomes.interleavings);
+}
+
+#[test]
+fn test_atomics_make_increments_reachable_only_once() {
+    let racy = Litmus::parse(
+        "RISCV INC
+         { c=0; 0:x10=c; 1:x10=c }
+         P0:
+             lw x5, 0(x10)
+             addi x5, x5, 1
+             sw x5, 0(x10)
+         P1:
+             lw x5, 0(x10)
+             addi x5, x5, 1
+             sw x5, 0(x10)
+         exists (c=1)",
+    )
+    .unwrap();
+    assert!(racy.explore().unwrap().reachable());
+
+    let atomic = Litmus::parse(
+        "RISCV INC-AMO
+         { c=0; 0:x10=c; 0:x6=1; 1:x10=c; 1:x6=1 }
+         P0:
+             amoadd.w x5, x6, (x10)
+         P1:
+             amoadd.w x5, x6, (x10)
+         exists (c=1)",
+    )
+    .unwrap();
+    let outcomes = atomic.explore().unwrap();
+    assert!(!outcomes.reachable());
+    assert_eq!(outcomes.interleavings, 2);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(matches!(Litmus::parse("SB\n{}\nP0:\n"), Err(LitmusError::Syntax(1, _))));
+    assert!(matches!(Litmus::parse("RISCV T\n{ 0:x5=z }\nP0:\n"), Err(LitmusError::Syntax(2, _))));
+    assert!(matches!(Litmus::parse("RISCV T\n{ }\nP1:\n"), Err(LitmusError::Syntax(3, _))));
+    assert!(matches!(Litmus::parse("RISCV T\n{ }\nP0:\n  frob x1\n"), Err(LitmusError::Syntax(4, _))));
+    let spin = Litmus::parse("RISCV T\n{ }\nP0:\n  jal x0, 0\n").unwrap();
+    assert!(matches!(spin.explore(), Err(LitmusError::TooLong)));
+}
+
+#[test]
+fn test_fence() {
+    let fence = parse_instruction("fence rw, w").unwrap();
+    assert_eq!(fence, Instruction::Fence { pred: 0b0011, succ: 0b0001 });
+    assert_eq!(parse_instruction("fence"), Some(Instruction::Fence { pred: 0xF, succ: 0xF }));
+    assert_eq!(parse_instruction("fence wr, w"), None);
+    assert_eq!(encode(&fence), Some(0x0310_000F));
+    assert_eq!(decode(0x0ff0_000f), Some(Instruction::Fence { pred: 0xF, succ: 0xF }));
+}
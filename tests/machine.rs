@@ -0,0 +1,105 @@
+use riscviz::bus::Bus;
+use riscviz::clint::{Clint, VIRT_CLINT_BASE};
+use riscviz::csr;
+use riscviz::instruction::Instruction;
+use riscviz::machine::{Machine, Schedule};
+use riscviz::privilege;
+
+/// Each hart adds 1 to the word at 0x100 three times with a plain
+/// load/add/store, which loses updates when the harts interleave.
+fn racy_increment() -> Vec<Instruction> {
+    let mut program = vec![Instruction::Addi { rd: 1, rs1: 0, imm: 0x100 }];
+    for _ in 0..3 {
+        program.push(Instruction::Lw { rd: 5, rs1: 1, imm: 0 });
+        program.push(Instruction::Addi { rd: 5, rs1: 5, imm: 1 });
+        program.push(Instruction::Sw { rs1: 1, rs2: 5, imm: 0 });
+    }
+    program
+}
+
+#[test]
+fn test_harts_share_memory_and_know_their_id() {
+    let mut machine: Machine = Machine::new(Bus::new(0x1000), 3);
+    machine.load_instructions(vec![
+        Instruction::Csrrs { rd: 5, rs1: 0, csr: csr::MHARTID },
+        Instruction::Slli { rd: 6, rs1: 5, imm: 2 },
+        Instruction::Sw { rs1: 6, rs2: 5, imm: 0x200 },
+    ]);
+    let order = machine.run(100).unwrap();
+    assert_eq!(order, [0, 1, 2, 0, 1, 2, 0, 1, 2]);
+    for hart in 0..3 {
+        assert_eq!(machine.hart(hart).regs[5], hart as i32);
+        assert_eq!(machine.bus().ram().read_word(0x200 + 4 * hart as u64).unwrap(), hart as i32);
+    }
+}
+
+#[test]
+fn test_interleaving_loses_updates() {
+    let mut machine: Machine = Machine::new(Bus::new(0x1000), 2);
+    machine.load_instructions(racy_increment());
+    machine.run(100).unwrap();
+    assert_eq!(machine.bus().ram().read_word(0x100).unwrap(), 3);
+
+    let mut amo = vec![Instruction::Addi { rd: 1, rs1: 0, imm: 0x100 }, Instruction::Addi { rd: 6, rs1: 0, imm: 1 }];
+    amo.extend(vec![Instruction::AmoaddW { rd: 0, rs1: 1, rs2: 6, aq: false, rl: false }; 3]);
+    let mut machine: Machine = Machine::new(Bus::new(0x1000), 2);
+    machine.load_instructions(amo);
+    machine.run(100).unwrap();
+    assert_eq!(machine.bus().ram().read_word(0x100).unwrap(), 6);
+}
+
+#[test]
+fn test_seeded_schedule_is_reproducible() {
+    let run = |seed| {
+        let mut machine: Machine = Machine::new(Bus::new(0x1000), 2);
+        machine.set_schedule(Schedule::Random(seed));
+        machine.load_instructions(racy_increment());
+        let order = machine.run(100).unwrap();
+        (order, machine.bus().ram().read_word(0x100).unwrap())
+    };
+    let (order, count) = run(42);
+    assert_eq!(order.len(), 20);
+    assert_eq!(order.iter().filter(|&&h| h == 0).count(), 10);
+    assert_eq!(run(42), (order.clone(), count));
+    assert!((1..=42).any(|seed| run(seed).0 != order));
+}
+
+#[test]
+fn test_store_from_another_hart_breaks_reservation() {
+    let mut machine: Machine = Machine::new(Bus::new(0x1000), 2);
+    machine.load_instructions(vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 0x100 },
+        Instruction::Csrrs { rd: 5, rs1: 0, csr: csr::MHARTID },
+        Instruction::Bne { rs1: 5, rs2: 0, offset: 3 },
+        Instruction::LrW { rd: 6, rs1: 1, aq: false, rl: false },
+        Instruction::ScW { rd: 7, rs1: 1, rs2: 5, aq: false, rl: false },
+        Instruction::Sw { rs1: 1, rs2: 1, imm: 0 }, // hart 1 lands here between lr and sc
+    ]);
+    machine.run(100).unwrap();
+    assert_eq!(machine.hart(0).regs[7], 1);
+    assert_eq!(machine.bus().ram().read_word(0x100).unwrap(), 0x100);
+}
+
+#[test]
+fn test_software_interrupt_between_harts() {
+    let mut bus = Bus::new(0x1000);
+    bus.attach(VIRT_CLINT_BASE, None, Box::new(Clint::new(2, 1))).unwrap();
+    let mut machine: Machine = Machine::new(bus, 2);
+    machine.load_instructions(vec![
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 9 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Csrrsi { rd: 0, imm: privilege::MSIP as i32, csr: csr::MIE },
+        Instruction::Csrrsi { rd: 0, imm: privilege::MIE as i32, csr: csr::MSTATUS },
+        Instruction::Csrrs { rd: 5, rs1: 0, csr: csr::MHARTID },
+        Instruction::Bne { rs1: 5, rs2: 0, offset: 0 }, // hart 1 spins
+        Instruction::Lui { rd: 1, imm: 0x2000 },
+        Instruction::Addi { rd: 7, rs1: 0, imm: 1 },
+        Instruction::Sw { rs1: 1, rs2: 7, imm: 4 }, // msip of hart 1
+        // handler
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MCAUSE },
+    ]);
+    machine.run(100).unwrap();
+    assert_eq!(machine.hart(1).regs[10], 0x8000_0003_u32 as i32);
+    assert_eq!(machine.hart(1).trap_csrs().mepc, 4 * 5);
+    assert_eq!(machine.hart(0).regs[10], 0);
+}
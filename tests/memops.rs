@@ -6,7 +6,7 @@ fn test_word_load_store() {
     let cpu = run_program!(vec![
         Instruction::Addi { rd: 1, rs1: 0, imm: 100 },
         Instruction::Addi { rd: 2, rs1: 0, imm: 0 },
-        Instruction::Sw { rs1: 1, rs2: 2, imm: 0 },
+        Instruction::Sw { rs1: 2, rs2: 1, imm: 0 },
         Instruction::Lw { rd: 3, rs1: 2, imm: 0 },
     ]);
     assert_eq!(cpu.regs[3], 100);
@@ -16,7 +16,7 @@ fn test_halfword_load_store_signed_unsigned() {
     let cpu = run_program!(vec![
         Instruction::Addi { rd: 1, rs1: 0, imm: -300 }, // value to store (i16)
         Instruction::Addi { rd: 2, rs1: 0, imm: 4 },    // base addr
-        Instruction::Sh { rs1: 1, rs2: 2, imm: 0 },    // store halfword
+        Instruction::Sh { rs1: 2, rs2: 1, imm: 0 },    // store halfword
         Instruction::Lh { rd: 3, rs1: 2, imm: 0 },     // load signed halfword
         Instruction::Lhu { rd: 4, rs1: 2, imm: 0 },    // load unsigned halfword
     ]);
@@ -28,7 +28,7 @@ fn test_byte_load_store_signed_unsigned() {
     let cpu = run_program!(vec![
         Instruction::Addi { rd: 1, rs1: 0, imm: -50 }, // value to store (i8)
         Instruction::Addi { rd: 2, rs1: 0, imm: 10 },  // base addr
-        Instruction::Sb { rs1: 1, rs2: 2, imm: 0 },   // store byte
+        Instruction::Sb { rs1: 2, rs2: 1, imm: 0 },   // store byte
         Instruction::Lb { rd: 3, rs1: 2, imm: 0 },    // load signed byte
         Instruction::Lbu { rd: 4, rs1: 2, imm: 0 },   // load unsigned byte
     ]);
@@ -40,11 +40,11 @@ fn test_multiple_memory_ops() {
     let cpu = run_program!(vec![
         Instruction::Addi { rd: 1, rs1: 0, imm: 0x12345678 },
         Instruction::Addi { rd: 2, rs1: 0, imm: 20 },
-        Instruction::Sw { rs1: 1, rs2: 2, imm: 0 },
+        Instruction::Sw { rs1: 2, rs2: 1, imm: 0 },
         Instruction::Lw { rd: 3, rs1: 2, imm: 0 },
-        Instruction::Sh { rs1: 3, rs2: 2, imm: 4 },
+        Instruction::Sh { rs1: 2, rs2: 3, imm: 4 },
         Instruction::Lh { rd: 4, rs1: 2, imm: 4 },
-        Instruction::Sb { rs1: 3, rs2: 2, imm: 6 },
+        Instruction::Sb { rs1: 2, rs2: 3, imm: 6 },
         Instruction::Lb { rd: 5, rs1: 2, imm: 6 },
     ]);
 
@@ -0,0 +1,114 @@
+use riscviz::bus::Bus;
+use riscviz::cpu::Cpu;
+use riscviz::engine::Engine;
+use riscviz::instruction::Instruction;
+use riscviz::multicycle::{MultiCycle, State};
+
+mod common;
+use common::asm;
+
+fn cpu_with(program: Vec<Instruction>) -> Cpu {
+    let mut cpu: Cpu = Cpu::with_bus(Bus::new(0x1000));
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    cpu
+}
+
+/// Sums 5 + 4 + ... + 1 into x6 through memory at 0x80.
+fn sum_loop() -> Vec<Instruction> {
+    vec![
+        asm("addi x5, x0, 5"),
+        asm("addi x7, x0, 0x80"),
+        asm("lw x6, 0(x7)"),
+        asm("add x6, x6, x5"),
+        asm("sw x6, 0(x7)"),
+        asm("addi x5, x5, -1"),
+        Instruction::Bne { rs1: 5, rs2: 0, offset: -4 },
+    ]
+}
+
+#[test]
+fn test_paths() {
+    use State::*;
+    assert_eq!(State::path(&asm("add x1, x2, x3")), [Fetch, Decode, Execute, WriteBack]);
+    assert_eq!(State::path(&asm("lw x1, 0(x2)")), [Fetch, Decode, Execute, Memory, WriteBack]);
+    assert_eq!(State::path(&asm("sw x1, 0(x2)")), [Fetch, Decode, Execute, Memory]);
+    assert_eq!(State::path(&asm("beq x1, x2, 8")), [Fetch, Decode, Execute]);
+    assert_eq!(State::path(&asm("jal x1, 8")), [Fetch, Decode, Execute, WriteBack]);
+    assert_eq!(State::path(&asm("csrrw x1, mstatus, x2")), [Fetch, Decode, Execute, WriteBack]);
+    assert_eq!(State::path(&asm("ecall")), [Fetch, Decode, Execute]);
+}
+
+#[test]
+fn test_matches_interpreter() {
+    let mut reference = cpu_with(sum_loop());
+    let mut instructions = 0;
+    while reference.execute_next().unwrap() {
+        instructions += 1;
+    }
+
+    let mut cpu = cpu_with(sum_loop());
+    let mut multicycle = MultiCycle::new();
+    let cycles = multicycle.run(&mut cpu, 1000).unwrap();
+    assert_eq!(cpu.regs, reference.regs);
+    assert_eq!(cpu.pc, reference.pc);
+    assert_eq!(cpu.bus().ram().read_word(0x80).unwrap(), 15);
+    assert_eq!(multicycle.retired(), instructions);
+    // 2 addi, then 5 times lw, add, sw, addi, bne.
+    assert_eq!(cycles, 2 * 4 + 5 * (5 + 4 + 4 + 4 + 3));
+    assert_eq!(multicycle.cycles(), cycles);
+    assert!(!multicycle.step(&mut cpu).unwrap());
+}
+
+#[test]
+fn test_latches_per_state() {
+    let mut cpu = cpu_with(vec![asm("addi x5, x0, 0x40"), asm("addi x6, x0, 9"), asm("sw x6, 4(x5)"), asm("lw x7, 4(x5)")]);
+    let mut multicycle = MultiCycle::new();
+    multicycle.run(&mut cpu, 12).unwrap();
+    assert_eq!(multicycle.retired(), 3);
+    assert_eq!(multicycle.next_state(), None);
+
+    multicycle.step(&mut cpu).unwrap();
+    assert_eq!(multicycle.latches().ir, Some(asm("lw x7, 4(x5)")));
+    assert_eq!(multicycle.next_state(), Some(State::Decode));
+    multicycle.step(&mut cpu).unwrap();
+    assert_eq!(multicycle.latches().a, 0x40);
+    assert_eq!(cpu.regs[7], 0, "nothing executes before Execute");
+    multicycle.step(&mut cpu).unwrap();
+    assert_eq!(multicycle.latches().alu_out, 0x44);
+    assert_eq!(cpu.regs[7], 9);
+    multicycle.step(&mut cpu).unwrap();
+    assert_eq!(multicycle.latches().mdr, 9);
+    multicycle.step(&mut cpu).unwrap();
+    assert_eq!(multicycle.last().unwrap().transfer, "x7 <- MDR = 9");
+    assert_eq!(multicycle.retired(), 4);
+}
+
+#[test]
+fn test_display() {
+    let mut cpu = cpu_with(vec![asm("addi x5, x0, 3"), Instruction::Beq { rs1: 5, rs2: 5, offset: 2 }]);
+    let mut multicycle = MultiCycle::new();
+    multicycle.run(&mut cpu, 7).unwrap();
+    assert_eq!(
+        multicycle.to_string(),
+        "cycle 7: 1: Beq { rs1: 5, rs2: 5, offset: 2 }\n  Fetch > Decode > [Execute]\n  ALUOut <- ALU(3, 3) = 0; PC <- 12\n\
+         IR Beq { rs1: 5, rs2: 5, offset: 2 } | A 3 | B 3 | ALUOut 0 | MDR 0\n7 cycles, 2 instructions, CPI 3.50"
+    );
+}
+
+#[test]
+fn test_trap_ends_the_path() {
+    let program = ["addi x1, x0, 16", "csrw mtvec, x1", "lui x6, 0x10", "lw x5, 0(x6)", "addi x2, x0, 1"];
+    let mut cpu = cpu_with(program.into_iter().map(asm).collect());
+    let mut multicycle = MultiCycle::new();
+    while multicycle.last().is_none_or(|step| step.pc != 3 || step.state != State::Execute) {
+        multicycle.step(&mut cpu).unwrap();
+    }
+    let step = multicycle.last().unwrap();
+    assert_eq!(step.path, [State::Fetch, State::Decode, State::Execute]);
+    assert_eq!(step.transfer, "trap; PC <- mtvec = 16");
+    assert_eq!((multicycle.retired(), multicycle.next_state()), (3, None));
+
+    multicycle.run(&mut cpu, 100).unwrap();
+    assert_eq!((cpu.regs[2], cpu.regs[5], multicycle.retired()), (1, 0, 4));
+}
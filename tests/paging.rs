@@ -0,0 +1,163 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::csr;
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::mmu::{Access, Fault, PTE_A, PTE_D, PTE_R, PTE_U, PTE_V, PTE_W, SATP_SV32};
+use riscviz::privilege::{self, Privilege};
+
+/// Root table at 0x1000 and a level-0 table at 0x2000, mapping:
+///   0x0040_0000 -> 0x3000 (read/write)
+///   0x0040_1000 -> 0x4000 (read-only)
+///   0x0080_0000 -> 0x0 (4 MiB read/write superpage)
+fn paged_cpu() -> Cpu {
+    let mut cpu = Cpu::new(0x10000);
+    let mem = cpu.memory_mut();
+    mem.write_word(0x1004, ((2 << 10) | PTE_V) as i32).unwrap();
+    mem.write_word(0x1008, (PTE_V | PTE_R | PTE_W | PTE_U) as i32).unwrap();
+    mem.write_word(0x2000, ((3 << 10) | PTE_V | PTE_R | PTE_W | PTE_U) as i32).unwrap();
+    mem.write_word(0x2004, ((4 << 10) | PTE_V | PTE_R | PTE_U) as i32).unwrap();
+    cpu.write_csr(csr::SATP, (SATP_SV32 | 1) as i64).unwrap();
+    cpu.set_privilege(Privilege::User);
+    cpu
+}
+
+fn run(cpu: &mut Cpu, program: Vec<Instruction>) -> Result<(), CpuError> {
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    while cpu.execute_next()? {}
+    Ok(())
+}
+
+#[test]
+fn test_translation_sets_accessed_and_dirty() {
+    let mut cpu = paged_cpu();
+    run(&mut cpu, vec![
+        Instruction::Lui { rd: 1, imm: 0x400 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: 42 },
+        Instruction::Sw { rs1: 1, rs2: 2, imm: 8 },
+        Instruction::Lw { rd: 3, rs1: 1, imm: 8 },
+        Instruction::Lui { rd: 4, imm: 0x803 },
+        Instruction::Lw { rd: 5, rs1: 4, imm: 8 }, // same byte through the superpage
+    ])
+    .unwrap();
+    assert_eq!(cpu.regs[3], 42);
+    assert_eq!(cpu.regs[5], 42);
+    assert_eq!(cpu.memory().read_word(0x3008).unwrap(), 42);
+
+    let leaf = cpu.memory().read_word(0x2000).unwrap() as u32;
+    assert_eq!(leaf & (PTE_A | PTE_D), PTE_A | PTE_D);
+    let superpage = cpu.memory().read_word(0x1008).unwrap() as u32;
+    assert_eq!(superpage & (PTE_A | PTE_D), PTE_A);
+
+    let stats = cpu.mmu().tlb().stats();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+}
+
+#[test]
+fn test_page_faults() {
+    let mut cpu = paged_cpu();
+    cpu.load_instructions(vec![
+        Instruction::Lui { rd: 1, imm: 0x401 },
+        Instruction::Sw { rs1: 1, rs2: 0, imm: 0 },
+        Instruction::Lui { rd: 2, imm: 0xC00 },
+        Instruction::Lw { rd: 3, rs1: 2, imm: 0 },
+    ]);
+    assert!(cpu.execute_next().unwrap());
+    assert!(matches!(
+        cpu.execute_next(),
+        Err(CpuError::PageFault(0x40_1000, Access::Store, Fault::Permission(Access::Store)))
+    ));
+
+    // With a handler installed the fault traps instead.
+    cpu.write_csr(csr::MTVEC, 100).unwrap();
+    assert!(cpu.execute_next().unwrap());
+    assert_eq!(cpu.trap_csrs().mcause, 15);
+    assert_eq!(cpu.trap_csrs().mtval, 0x40_1000);
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+
+    cpu.set_privilege(Privilege::User);
+    cpu.pc = 2;
+    assert!(cpu.execute_next().unwrap());
+    assert!(cpu.execute_next().unwrap());
+    assert_eq!(cpu.trap_csrs().mcause, 13);
+    assert_eq!(cpu.trap_csrs().mtval, 0xC0_0000);
+}
+
+#[test]
+fn test_supervisor_needs_sum_for_user_pages() {
+    let mut cpu = paged_cpu();
+    cpu.set_privilege(Privilege::Supervisor);
+    let program = vec![Instruction::Lui { rd: 1, imm: 0x400 }, Instruction::Lw { rd: 2, rs1: 1, imm: 0 }];
+    assert!(matches!(run(&mut cpu, program.clone()), Err(CpuError::PageFault(_, Access::Load, Fault::UserPage))));
+
+    cpu.write_csr(csr::MSTATUS, privilege::SUM as i64).unwrap();
+    assert!(run(&mut cpu, program).is_ok());
+
+    // M-mode is never translated
+    cpu.set_privilege(Privilege::Machine);
+    assert_eq!(cpu.explain_translation(0x3000, Access::Load).result, Ok(0x3000));
+}
+
+#[test]
+fn test_tlb_capacity_and_sfence() {
+    let mut cpu = paged_cpu();
+    cpu.set_tlb_capacity(1);
+    cpu.write_csr(csr::MSTATUS, privilege::SUM as i64).unwrap();
+    cpu.set_privilege(Privilege::Supervisor);
+    run(&mut cpu, vec![
+        Instruction::Lui { rd: 1, imm: 0x400 },
+        Instruction::Lui { rd: 2, imm: 0x401 },
+        Instruction::Lw { rd: 3, rs1: 1, imm: 0 },
+        Instruction::Lw { rd: 3, rs1: 2, imm: 0 },
+        Instruction::Lw { rd: 3, rs1: 1, imm: 0 },
+        Instruction::Lw { rd: 3, rs1: 1, imm: 4 },
+    ])
+    .unwrap();
+    let stats = cpu.mmu().tlb().stats();
+    assert_eq!((stats.hits, stats.misses), (1, 3));
+
+    // A stale entry keeps working until sfence.vma drops it.
+    cpu.memory_mut().write_word(0x2000, 0).unwrap();
+    let program = vec![
+        Instruction::Lw { rd: 3, rs1: 1, imm: 0 },
+        Instruction::SfenceVma { rs1: 1, rs2: 0 },
+        Instruction::Lw { rd: 3, rs1: 1, imm: 0 },
+    ];
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    assert!(cpu.execute_next().unwrap());
+    assert!(cpu.execute_next().unwrap());
+    assert_eq!(cpu.mmu().tlb().stats().flushes, 1);
+    assert!(cpu.mmu().tlb().is_empty());
+    assert!(matches!(cpu.execute_next(), Err(CpuError::PageFault(0x40_0000, Access::Load, Fault::Invalid(0)))));
+
+    cpu.set_privilege(Privilege::User);
+    cpu.pc = 1;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::SfenceVma { .. }))));
+}
+
+#[test]
+fn test_explain_translation() {
+    let cpu = paged_cpu();
+    let walk = cpu.explain_translation(0x40_0008, Access::Load);
+    assert_eq!(walk.steps.len(), 2);
+    assert_eq!(walk.result, Ok(0x3008));
+    let text = walk.to_string();
+    assert!(text.contains("level 1: pte at 0x00001004 = 0x00000801 [V-------] -> next table at 0x00002000"));
+    assert!(text.ends_with("physical address 0x00003008"));
+
+    let walk = cpu.explain_translation(0x40_1000, Access::Store);
+    assert_eq!(walk.result, Err(Fault::Permission(Access::Store)));
+    assert!(walk.to_string().ends_with("page fault: page does not permit store"));
+}
+
+#[test]
+fn test_sfence_parsing_and_encoding() {
+    assert_eq!(parse_instruction("sfence.vma"), Some(Instruction::SfenceVma { rs1: 0, rs2: 0 }));
+    assert_eq!(parse_instruction("sfence.vma x1, x2"), Some(Instruction::SfenceVma { rs1: 1, rs2: 2 }));
+    assert_eq!(parse_instruction("csrw satp, x5"), Some(Instruction::Csrrw { rd: 0, rs1: 5, csr: csr::SATP }));
+    assert_eq!(encode(&Instruction::SfenceVma { rs1: 0, rs2: 0 }), Some(0x1200_0073));
+    let inst = Instruction::SfenceVma { rs1: 10, rs2: 11 };
+    assert_eq!(decode(encode(&inst).unwrap()), Some(inst));
+}
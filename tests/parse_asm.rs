@@ -1,4 +1,5 @@
 use riscviz::asm_parser::parse_instruction;
+use riscviz::fpu::RoundingMode;
 use riscviz::instruction::Instruction;
 
 #[test]
@@ -95,6 +96,56 @@ fn test_u_type_instructions() {
     assert!(matches!(parse_instruction("auipc x1, 0").unwrap(), Instruction::Auipc { rd: 1, imm: 0 }));
 }
 
+#[test]
+fn test_atomic_instructions() {
+    assert!(matches!(parse_instruction("lr.w x1, (x2)").unwrap(), Instruction::LrW { rd: 1, rs1: 2, aq: false, rl: false }));
+    assert!(matches!(parse_instruction("lr.w.aq x1, 0(x2)").unwrap(), Instruction::LrW { rd: 1, rs1: 2, aq: true, rl: false }));
+    assert!(matches!(parse_instruction("sc.w.rl x3, x4, (x5)").unwrap(), Instruction::ScW { rd: 3, rs1: 5, rs2: 4, aq: false, rl: true }));
+    assert!(matches!(parse_instruction("amoswap.w.aqrl x1, x2, (x3)").unwrap(), Instruction::AmoswapW { rd: 1, rs1: 3, rs2: 2, aq: true, rl: true }));
+    assert!(matches!(parse_instruction("amoadd.w x1, x2, (x3)").unwrap(), Instruction::AmoaddW { rd: 1, rs1: 3, rs2: 2, .. }));
+    assert!(matches!(parse_instruction("amoxor.w x1, x2, (x3)").unwrap(), Instruction::AmoxorW { .. }));
+    assert!(matches!(parse_instruction("amoand.w x1, x2, (x3)").unwrap(), Instruction::AmoandW { .. }));
+    assert!(matches!(parse_instruction("amoor.w x1, x2, (x3)").unwrap(), Instruction::AmoorW { .. }));
+    assert!(matches!(parse_instruction("amomin.w x1, x2, (x3)").unwrap(), Instruction::AmominW { .. }));
+    assert!(matches!(parse_instruction("amomax.w x1, x2, (x3)").unwrap(), Instruction::AmomaxW { .. }));
+    assert!(matches!(parse_instruction("amominu.w x1, x2, (x3)").unwrap(), Instruction::AmominuW { .. }));
+    assert!(matches!(parse_instruction("AMOMAXU.W.AQ x1, x2, (x3)").unwrap(), Instruction::AmomaxuW { aq: true, rl: false, .. }));
+
+    assert!(parse_instruction("amoadd.w x1, x2, 4(x3)").is_none());
+    assert!(parse_instruction("add.aq x1, x2, x3").is_none());
+}
+
+#[test]
+fn test_fp_instructions() {
+    assert!(matches!(parse_instruction("fadd.s f1, f2, f3").unwrap(), Instruction::FaddS { rd: 1, rs1: 2, rs2: 3, rm: RoundingMode::Dyn }));
+    assert!(matches!(parse_instruction("fsub.d fa0, fa1, ft0, rtz").unwrap(), Instruction::FsubD { rd: 10, rs1: 11, rs2: 0, rm: RoundingMode::Rtz }));
+    assert!(matches!(parse_instruction("fmadd.s fs0, fs1, fs2, ft11").unwrap(), Instruction::FmaddS { rd: 8, rs1: 9, rs2: 18, rs3: 31, .. }));
+    assert!(matches!(parse_instruction("flw ft1, 8(x2)").unwrap(), Instruction::Flw { rd: 1, rs1: 2, imm: 8 }));
+    assert!(matches!(parse_instruction("fsd fs11, -8(x2)").unwrap(), Instruction::Fsd { rs1: 2, rs2: 27, imm: -8 }));
+    assert!(matches!(parse_instruction("fcvt.w.s x5, fa7, rmm").unwrap(), Instruction::FcvtWS { rd: 5, rs1: 17, rm: RoundingMode::Rmm }));
+    assert!(matches!(parse_instruction("fcvt.d.wu f1, x5").unwrap(), Instruction::FcvtDWu { rd: 1, rs1: 5, .. }));
+    assert!(matches!(parse_instruction("feq.d x1, f2, f3").unwrap(), Instruction::FeqD { rd: 1, rs1: 2, rs2: 3 }));
+    assert!(matches!(parse_instruction("fclass.s x1, f2").unwrap(), Instruction::FclassS { rd: 1, rs1: 2 }));
+    assert!(matches!(parse_instruction("fmv.x.w x1, f2").unwrap(), Instruction::FmvXW { rd: 1, rs1: 2 }));
+    assert!(matches!(parse_instruction("fneg.d f1, f2").unwrap(), Instruction::FsgnjnD { rd: 1, rs1: 2, rs2: 2 }));
+
+    assert!(parse_instruction("fadd.s x1, f2, f3").is_none());
+    assert!(parse_instruction("fadd.s f1, f2, f3, up").is_none());
+    assert!(parse_instruction("fadd.s f32, f2, f3").is_none());
+    assert!(parse_instruction("fadd.s fa8, f2, f3").is_none());
+}
+
+#[test]
+fn test_csr_instructions() {
+    assert!(matches!(parse_instruction("csrrw x1, fcsr, x2").unwrap(), Instruction::Csrrw { rd: 1, rs1: 2, csr: 0x003 }));
+    assert!(matches!(parse_instruction("csrrsi x1, 0x001, 4").unwrap(), Instruction::Csrrsi { rd: 1, imm: 4, csr: 0x001 }));
+    assert!(matches!(parse_instruction("frrm x5").unwrap(), Instruction::Csrrs { rd: 5, rs1: 0, csr: 0x002 }));
+    assert!(matches!(parse_instruction("fsflags x5").unwrap(), Instruction::Csrrw { rd: 0, rs1: 5, csr: 0x001 }));
+    assert!(matches!(parse_instruction("fscsr x4, x5").unwrap(), Instruction::Csrrw { rd: 4, rs1: 5, csr: 0x003 }));
+    assert!(parse_instruction("csrrwi x1, frm, 32").is_none());
+    assert!(parse_instruction("csrrw x1, nosuchcsr, x2").is_none());
+}
+
 #[test]
 fn test_print_instruction() {
     assert!(matches!(parse_instruction("print x5").unwrap(), Instruction::Print { rs: 5 }));
@@ -0,0 +1,131 @@
+use riscviz::bus::Bus;
+use riscviz::cpu::Cpu;
+use riscviz::instruction::{Instruction, Reg};
+use riscviz::engine::Engine;
+use riscviz::pipeline::{Cell, Event, Pipeline, PipelineConfig, Stage};
+
+mod common;
+use common::asm;
+
+/// Runs `program` to the end, returning the pipeline and the CPU it drove.
+fn pipeline(program: Vec<Instruction>, forwarding: bool) -> (Pipeline, Cpu) {
+    let mut cpu = Cpu::with_bus(Bus::new(0x1000));
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    let mut pipeline = Pipeline::new(PipelineConfig { forwarding });
+    pipeline.run(&mut cpu, 1000).unwrap();
+    (pipeline, cpu)
+}
+
+/// The stages row `i` went through, with `None` for stall cycles.
+fn stages(pipeline: &Pipeline, i: usize) -> Vec<Option<Stage>> {
+    let cells = pipeline.rows()[i].cells.values();
+    cells.map(|cell| if let Cell::Stage(stage) = cell { Some(*stage) } else { None }).collect()
+}
+
+#[test]
+fn test_independent_instructions_overlap() {
+    let (p, _) = pipeline(vec![asm("addi x5, x0, 1"), asm("addi x6, x0, 2"), asm("addi x7, x0, 3"), asm("addi x8, x0, 4")], true);
+    assert_eq!(p.cycles(), 8);
+    assert_eq!(p.retired(), 4);
+    assert_eq!(p.cpi(), 2.0);
+    assert!(p.events().is_empty());
+    assert_eq!(p.rows()[3].cells.keys().copied().collect::<Vec<_>>(), [4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn test_forwarding_removes_alu_stalls() {
+    let program = vec![asm("addi x5, x0, 1"), asm("add x6, x5, x5"), asm("add x7, x6, x5")];
+    let (forwarded, forwarded_cpu) = pipeline(program.clone(), true);
+    assert_eq!(forwarded.cycles(), 7);
+    assert_eq!(forwarded.stalls(), 0);
+    assert!(forwarded.events().contains(&Event::Forward { cycle: 4, pc: 1, reg: Reg::X(5), producer: 0, from: "EX/MEM" }));
+    assert!(forwarded.events().contains(&Event::Forward { cycle: 5, pc: 2, reg: Reg::X(6), producer: 1, from: "EX/MEM" }));
+    assert!(forwarded.events().contains(&Event::Forward { cycle: 5, pc: 2, reg: Reg::X(5), producer: 0, from: "MEM/WB" }));
+
+    let (stalled, stalled_cpu) = pipeline(program, false);
+    assert_eq!(stalled.cycles(), 11);
+    assert_eq!(stalled.stalls(), 4);
+    use Stage::*;
+    assert_eq!(stages(&stalled, 1), [Some(If), Some(Id), None, None, Some(Ex), Some(Mem), Some(Wb)]);
+    assert_eq!(stalled_cpu.regs[7], 3);
+    assert_eq!(forwarded_cpu.regs[7], 3);
+}
+
+#[test]
+fn test_load_use_stalls_once_with_forwarding() {
+    let (p, _) = pipeline(vec![asm("lw x5, 0x100(x0)"), asm("add x6, x5, x5"), asm("sw x6, 0x104(x0)")], true);
+    assert_eq!(p.stalls(), 1);
+    assert_eq!(p.cycles(), 8);
+    assert!(matches!(p.events()[0], Event::Stall { cycle: 3, pc: 1, reg: Reg::X(5), load_use: true, .. }));
+    assert!(p.events().contains(&Event::Forward { cycle: 5, pc: 1, reg: Reg::X(5), producer: 0, from: "MEM/WB" }));
+}
+
+#[test]
+fn test_taken_branch_flushes_two_instructions() {
+    let (p, cpu) = pipeline(vec![
+        asm("addi x5, x0, 1"),
+        Instruction::Beq { rs1: 5, rs2: 5, offset: 3 },
+        asm("addi x6, x0, 1"),
+        asm("addi x7, x0, 1"),
+        asm("addi x8, x0, 1"),
+    ], true);
+    assert_eq!((cpu.regs[6], cpu.regs[7], cpu.regs[8]), (0, 0, 1));
+    assert_eq!(p.flushes(), 1);
+    assert!(p.events().contains(&Event::Flush { cycle: 4, pc: 1, target: 4, squashed: 2 }));
+    let flushed = p.rows().iter().filter(|row| row.flushed).map(|row| row.pc).collect::<Vec<_>>();
+    assert_eq!(flushed, [2, 3]);
+    assert_eq!(p.retired(), 3);
+    assert_eq!(p.cycles(), 9);
+}
+
+#[test]
+fn test_loop_matches_interpreter() {
+    let program = vec![
+        asm("addi x5, x0, 10"),
+        asm("add x6, x6, x5"),
+        asm("addi x5, x5, -1"),
+        Instruction::Bne { rs1: 5, rs2: 0, offset: -2 },
+        asm("sw x6, 0x100(x0)"),
+        asm("lw x7, 0x100(x0)"),
+    ];
+    let mut cpu: Cpu = Cpu::with_bus(Bus::new(0x1000));
+    cpu.load_instructions(program.clone());
+    cpu.pc = 0;
+    let mut executed = 0;
+    while cpu.execute_next().unwrap() {
+        executed += 1;
+    }
+
+    let (p, pipelined) = pipeline(program, true);
+    assert_eq!(pipelined.regs, cpu.regs);
+    assert_eq!(p.retired(), executed);
+    // Nine taken branches cost two cycles each.
+    assert_eq!(p.flushes(), 9);
+    assert_eq!(p.cycles(), executed + 4 + 2 * 9);
+}
+
+#[test]
+fn test_diagram() {
+    let (p, _) = pipeline(vec![asm("lw x1, 0(x0)"), asm("add x3, x1, x1")], true);
+    let text = p.to_string();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0].split_whitespace().collect::<Vec<_>>(), ["1", "2", "3", "4", "5", "6", "7"]);
+    assert!(lines[1].ends_with("IF  ID  EX MEM  WB"));
+    assert!(lines[2].ends_with("IF  ID  --  EX MEM  WB"));
+    assert_eq!(lines[3], "7 cycles, 2 instructions, CPI 3.50 (1 stalls, 0 flushes, 0 traps, forwarding on)");
+}
+
+#[test]
+fn test_trap_squashes_the_instruction() {
+    let program = ["addi x1, x0, 16", "csrw mtvec, x1", "ecall", "addi x3, x0, 1", "addi x2, x0, 1"];
+    let (p, cpu) = pipeline(program.into_iter().map(asm).collect(), true);
+    assert_eq!((cpu.regs[2], cpu.regs[3]), (1, 0));
+    assert!(p.events().contains(&Event::Trap { cycle: 5, pc: 2, target: 4, squashed: 2 }));
+    assert_eq!((p.traps(), p.flushes(), p.retired()), (1, 0, 3));
+
+    let ecall = &p.rows()[2];
+    assert!(ecall.flushed && ecall.trapped);
+    assert_eq!(stages(&p, 2), [Some(Stage::If), Some(Stage::Id), Some(Stage::Ex)]);
+    assert!(p.to_string().lines().nth(3).unwrap().ends_with("EX  (trapped)"));
+}
@@ -0,0 +1,90 @@
+use std::io;
+use std::sync::mpsc;
+use riscviz::bus::{Bus, Device};
+use riscviz::cpu::Cpu;
+use riscviz::csr;
+use riscviz::instruction::Instruction;
+use riscviz::plic::{Plic, VIRT_PLIC_BASE};
+use riscviz::privilege::{self, MEIP, SEIP};
+use riscviz::uart::{Uart, VIRT_UART_BASE, VIRT_UART_IRQ};
+
+const ENABLE: u64 = 0x2000;
+const THRESHOLD: u64 = 0x20_0000;
+const CLAIM: u64 = 0x20_0004;
+
+#[test]
+fn test_claim_and_complete() {
+    let mut plic = Plic::new(1);
+    plic.write(4 * 3, 4, 2).unwrap();
+    plic.write(4 * 5, 4, 6).unwrap();
+    plic.write(4 * 7, 4, 9).unwrap(); // clamped to 7
+    plic.write(ENABLE, 4, (1 << 3) | (1 << 5) | (1 << 7)).unwrap();
+    plic.interrupt_lines((1 << 3) | (1 << 5) | (1 << 7));
+    assert_eq!(plic.read(0x1000, 4).unwrap(), (1 << 3) | (1 << 5) | (1 << 7));
+    assert_eq!(plic.hart_interrupts(0), MEIP);
+
+    plic.write(THRESHOLD, 4, 6).unwrap();
+    assert_eq!(plic.read(CLAIM, 4).unwrap(), 7);
+    assert_eq!(plic.read(CLAIM, 4).unwrap(), 0); // 5 and 3 are at or below the threshold
+    assert_eq!(plic.hart_interrupts(0), 0);
+
+    plic.write(THRESHOLD, 4, 0).unwrap();
+    assert_eq!(plic.read(CLAIM, 4).unwrap(), 5);
+    assert_eq!(plic.read(CLAIM, 4).unwrap(), 3);
+    plic.interrupt_lines((1 << 3) | (1 << 5) | (1 << 7));
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 0); // all in service
+
+    plic.write(CLAIM, 4, 5).unwrap();
+    plic.interrupt_lines((1 << 3) | (1 << 5));
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 1 << 5);
+    plic.interrupt_lines(1 << 3);
+    assert_eq!(plic.read(0x1000, 4).unwrap(), 0); // the line dropped
+
+    // The supervisor context of hart 0.
+    plic.write(CLAIM, 4, 3).unwrap();
+    plic.interrupt_lines(1 << 3);
+    plic.write(ENABLE + 0x80, 4, 1 << 3).unwrap();
+    assert_eq!(plic.hart_interrupts(0), MEIP | SEIP);
+    assert_eq!(plic.read(THRESHOLD + 0x1000 + 4, 4).unwrap(), 3);
+    assert_eq!(plic.hart_interrupts(0), 0);
+}
+
+#[test]
+fn test_uart_receive_interrupt() {
+    let (tx, rx) = mpsc::channel();
+    tx.send(b'A').unwrap();
+    let mut bus = Bus::new(1024);
+    let uart = Uart::new(Box::new(io::sink()), Some(rx));
+    bus.attach(VIRT_UART_BASE, Some(VIRT_UART_IRQ), Box::new(uart)).unwrap();
+    bus.attach(VIRT_PLIC_BASE, None, Box::new(Plic::new(1))).unwrap();
+    let mut cpu: Cpu = Cpu::with_bus(bus);
+    cpu.load_instructions(vec![
+        Instruction::Lui { rd: 1, imm: 0xC000 }, // PLIC
+        Instruction::Addi { rd: 5, rs1: 0, imm: 1 },
+        Instruction::Sw { rs1: 1, rs2: 5, imm: 4 * VIRT_UART_IRQ as i32 },
+        Instruction::Lui { rd: 3, imm: 0xC002 }, // enables of context 0
+        Instruction::Addi { rd: 6, rs1: 0, imm: 1 << VIRT_UART_IRQ },
+        Instruction::Sw { rs1: 3, rs2: 6, imm: 0 },
+        Instruction::Lui { rd: 8, imm: 0x10000 }, // UART
+        Instruction::Sb { rs1: 8, rs2: 5, imm: 1 }, // IER = RX
+        Instruction::Addi { rd: 6, rs1: 0, imm: 4 * 14 },
+        Instruction::Csrrw { rd: 0, rs1: 6, csr: csr::MTVEC },
+        Instruction::Addi { rd: 6, rs1: 0, imm: MEIP as i32 },
+        Instruction::Csrrs { rd: 0, rs1: 6, csr: csr::MIE },
+        Instruction::Csrrsi { rd: 0, imm: privilege::MIE as i32, csr: csr::MSTATUS },
+        Instruction::Jal { rd: 0, offset: 0 },
+        // handler
+        Instruction::Csrrs { rd: 9, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Lui { rd: 4, imm: 0xC200 },
+        Instruction::Lw { rd: 10, rs1: 4, imm: 4 }, // claim
+        Instruction::Lbu { rd: 11, rs1: 8, imm: 0 },
+        Instruction::Sw { rs1: 4, rs2: 10, imm: 4 }, // complete
+        Instruction::Csrrs { rd: 12, rs1: 0, csr: csr::MIP },
+    ]);
+    cpu.pc = 0;
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(cpu.regs[9], 0x8000_000B_u32 as i32);
+    assert_eq!(cpu.regs[10], VIRT_UART_IRQ as i32);
+    assert_eq!(cpu.regs[11], b'A' as i32);
+    assert_eq!(cpu.regs[12], 0);
+}
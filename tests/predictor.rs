@@ -0,0 +1,92 @@
+use riscviz::cpu::Cpu;
+use riscviz::instruction::Instruction;
+use riscviz::predictor::{BranchPredictor, PredictorKind, UnknownPredictor};
+
+/// Outcomes of a branch at pc 8 jumping back to 4: `trips` iterations of
+/// a loop, `runs` times over.
+fn loop_outcomes(trips: usize, runs: usize) -> Vec<bool> {
+    (0..runs).flat_map(|_| (1..=trips).map(move |i| i < trips)).collect()
+}
+
+/// How many of `outcomes` `kind` predicts correctly.
+fn score(kind: PredictorKind, outcomes: &[bool]) -> usize {
+    let mut predictor = BranchPredictor::of_kind(kind);
+    outcomes.iter().filter(|&&taken| predictor.resolve(8, 4, taken)).count()
+}
+
+#[test]
+fn test_predictor_names() {
+    for kind in PredictorKind::ALL {
+        assert_eq!(kind.name().parse::<PredictorKind>(), Ok(kind));
+    }
+    assert_eq!("perceptron".parse::<PredictorKind>(), Err(UnknownPredictor("perceptron".to_string())));
+    assert_eq!(BranchPredictor::of_kind(PredictorKind::TwoBit).name(), "2-bit saturating");
+}
+
+#[test]
+fn test_static_predictors() {
+    let outcomes = loop_outcomes(4, 5);
+    assert_eq!(score(PredictorKind::NotTaken, &outcomes), 5);
+    assert_eq!(score(PredictorKind::Btfn, &outcomes), 15);
+
+    let mut btfn = BranchPredictor::of_kind(PredictorKind::Btfn);
+    assert!(btfn.resolve(3, 10, false), "forward branches are predicted not taken");
+}
+
+#[test]
+fn test_dynamic_predictors_on_a_loop() {
+    // The loop exit costs a 1-bit predictor twice per run: on the exit and
+    // on the next run's first iteration. A 2-bit counter only misses the
+    // exit once it has warmed up.
+    let outcomes = loop_outcomes(4, 5);
+    assert_eq!(score(PredictorKind::OneBit, &outcomes), 20 - 1 - 2 * 4 - 1);
+    assert_eq!(score(PredictorKind::TwoBit, &outcomes), 20 - 2 - 4);
+}
+
+#[test]
+fn test_history_learns_alternation() {
+    let outcomes = (0..40).map(|i| i % 2 == 0).collect::<Vec<_>>();
+    assert!(score(PredictorKind::TwoBit, &outcomes) <= 20);
+    let gshare = score(PredictorKind::Gshare, &outcomes);
+    assert!(gshare >= 30, "gshare got {gshare}/40");
+    let tournament = score(PredictorKind::Tournament, &outcomes);
+    assert!(tournament >= 30, "tournament got {tournament}/40");
+}
+
+#[test]
+fn test_cpu_scores_every_branch() {
+    // Counts x5 down from 3 and skips x7's increment once x6 reaches 2.
+    let program = vec![
+        Instruction::Addi { rd: 5, rs1: 0, imm: 3 },
+        Instruction::Addi { rd: 8, rs1: 0, imm: 2 },
+        Instruction::Addi { rd: 6, rs1: 6, imm: 1 },
+        Instruction::Bge { rs1: 6, rs2: 8, offset: 2 },
+        Instruction::Addi { rd: 7, rs1: 7, imm: 1 },
+        Instruction::Addi { rd: 5, rs1: 5, imm: -1 },
+        Instruction::Bne { rs1: 5, rs2: 0, offset: -4 },
+    ];
+    let mut cpu: Cpu = Cpu::new(1024);
+    cpu.add_predictor(BranchPredictor::of_kind(PredictorKind::NotTaken));
+    cpu.add_predictor(BranchPredictor::of_kind(PredictorKind::Btfn));
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    let mut executed = 0;
+    while cpu.execute_next().unwrap() {
+        executed += 1;
+    }
+    assert_eq!(cpu.regs[7], 1);
+
+    let [not_taken, btfn] = cpu.predictors() else { panic!("two predictors") };
+    let bge = not_taken.branches()[&3];
+    let bne = not_taken.branches()[&6];
+    assert_eq!((bge.executed, bge.taken, bge.correct), (3, 2, 1));
+    assert_eq!((bne.executed, bne.taken, bne.correct), (3, 2, 1));
+    assert_eq!(not_taken.predictions(), 6);
+    assert_eq!(not_taken.mispredictions(), 4);
+    assert_eq!(not_taken.adjusted_cycles(executed), executed + 4 * 2);
+    assert_eq!(btfn.mispredictions(), 3);
+    assert_eq!(
+        btfn.to_string(),
+        "BTFN: 3/6 correct (50.0%), 3 mispredictions x 2 cycles\n     3:     3 executed,     2 taken,     1 correct (33.3%)\n     6:     3 executed,     2 taken,     2 correct (66.7%)"
+    );
+}
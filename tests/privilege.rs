@@ -0,0 +1,154 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::csr;
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::privilege::{self, Privilege};
+use riscviz::run_program;
+
+#[test]
+fn test_ecall_traps_to_machine_mode() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 5 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MTVEC },
+        Instruction::Ecall,
+        Instruction::Addi { rd: 5, rs1: 0, imm: 1 },
+        Instruction::Jal { rd: 0, offset: 6 },
+        // handler: skip the ecall
+        Instruction::Csrrs { rd: 2, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Csrrs { rd: 3, rs1: 0, csr: csr::MEPC },
+        Instruction::Addi { rd: 3, rs1: 3, imm: 4 },
+        Instruction::Csrrw { rd: 0, rs1: 3, csr: csr::MEPC },
+        Instruction::Mret,
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[2], 11);
+    assert_eq!(cpu.regs[3], 4 * 3);
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+}
+
+#[test]
+fn test_user_mode_violations_trap() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 8 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MTVEC },
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 5 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MEPC },
+        Instruction::Mret, // MPP resets to U
+        // user program
+        Instruction::Csrrs { rd: 6, rs1: 0, csr: csr::MSTATUS },
+        Instruction::Ecall,
+        Instruction::Jal { rd: 0, offset: 9 },
+        // handler: collect the causes in x11 and skip the faulting instruction
+        Instruction::Csrrs { rd: 10, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Slli { rd: 11, rs1: 11, imm: 4 },
+        Instruction::Add { rd: 11, rs1: 11, rs2: 10 },
+        Instruction::Csrrs { rd: 3, rs1: 0, csr: csr::MEPC },
+        Instruction::Addi { rd: 3, rs1: 3, imm: 4 },
+        Instruction::Csrrw { rd: 0, rs1: 3, csr: csr::MEPC },
+        Instruction::Mret,
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[11], 0x28); // illegal instruction, then ecall from U
+    assert_eq!(cpu.regs[6], 0);
+    assert_eq!(cpu.privilege(), Privilege::User);
+}
+
+#[test]
+fn test_delegated_trap_goes_to_supervisor() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 10 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::STVEC },
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 << 8 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MEDELEG },
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 7 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MEPC },
+        Instruction::Mret,
+        // user program
+        Instruction::Ecall,
+        Instruction::Addi { rd: 5, rs1: 0, imm: 1 },
+        Instruction::Jal { rd: 0, offset: 7 },
+        // supervisor handler
+        Instruction::Csrrs { rd: 2, rs1: 0, csr: csr::SCAUSE },
+        Instruction::Csrrs { rd: 4, rs1: 0, csr: csr::SSTATUS },
+        Instruction::Csrrs { rd: 3, rs1: 0, csr: csr::SEPC },
+        Instruction::Addi { rd: 3, rs1: 3, imm: 4 },
+        Instruction::Csrrw { rd: 0, rs1: 3, csr: csr::SEPC },
+        Instruction::Sret,
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[2], 8);
+    assert_eq!(cpu.regs[4] as u64 & privilege::SPP, 0);
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.trap_csrs().mcause, 0);
+    assert_eq!(cpu.privilege(), Privilege::User);
+}
+
+#[test]
+fn test_memory_faults_trap_with_tval() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 4 * 4 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MTVEC },
+        Instruction::Lw { rd: 2, rs1: 0, imm: 1 },
+        Instruction::Jal { rd: 0, offset: 3 },
+        Instruction::Csrrs { rd: 3, rs1: 0, csr: csr::MCAUSE },
+        Instruction::Csrrs { rd: 4, rs1: 0, csr: csr::MTVAL },
+    ];
+    let cpu = run_program!(program);
+    assert_eq!(cpu.regs[3], 4);
+    assert_eq!(cpu.regs[4], 1);
+}
+
+#[test]
+fn test_violations_without_handler_are_errors() {
+    let mut cpu = Cpu::default();
+    cpu.set_privilege(Privilege::User);
+    cpu.load_instructions(vec![
+        Instruction::Mret,
+        Instruction::Csrrs { rd: 1, rs1: 0, csr: csr::SSTATUS },
+        Instruction::Wfi,
+        Instruction::Ecall,
+    ]);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Mret))));
+    cpu.pc = 1;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::CsrPrivilege(csr::SSTATUS, Privilege::User))));
+    cpu.pc = 2;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Wfi))));
+    cpu.pc = 3;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::EnvironmentCall(Privilege::User))));
+
+    let mut cpu = Cpu::default();
+    cpu.load_instructions(vec![Instruction::Wfi, Instruction::Csrrw { rd: 0, rs1: 0, csr: csr::MISA }]);
+    assert!(cpu.execute_next().unwrap());
+    assert!(cpu.execute_next().unwrap());
+
+    // mstatus.TW makes wfi illegal in S-mode, TSR does the same for sret.
+    let mut cpu = Cpu::default();
+    cpu.load_instructions(vec![
+        Instruction::Lui { rd: 1, imm: 0x600 },
+        Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MSTATUS },
+        Instruction::Wfi,
+        Instruction::Sret,
+    ]);
+    assert!(cpu.execute_next().unwrap());
+    assert!(cpu.execute_next().unwrap());
+    cpu.set_privilege(Privilege::Supervisor);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Wfi))));
+    cpu.pc = 3;
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Sret))));
+}
+
+#[test]
+fn test_privileged_parsing_and_encoding() {
+    assert_eq!(parse_instruction("ecall"), Some(Instruction::Ecall));
+    assert_eq!(parse_instruction("sret"), Some(Instruction::Sret));
+    assert_eq!(parse_instruction("csrw mtvec, x1"), Some(Instruction::Csrrw { rd: 0, rs1: 1, csr: csr::MTVEC }));
+    assert_eq!(parse_instruction("csrr x1, medeleg"), Some(Instruction::Csrrs { rd: 1, rs1: 0, csr: csr::MEDELEG }));
+
+    assert_eq!(encode(&Instruction::Ecall), Some(0x0000_0073));
+    assert_eq!(encode(&Instruction::Mret), Some(0x3020_0073));
+    for inst in [Instruction::Ecall, Instruction::Ebreak, Instruction::Mret, Instruction::Sret, Instruction::Wfi] {
+        assert_eq!(decode(encode(&inst).unwrap()), Some(inst));
+    }
+}
@@ -7,7 +7,7 @@ fn test_simple_recursion() {
 
         Instruction::Beq  { rs1: 10, rs2: 0, offset: 9 },  // 0: if n==0 goto base
         Instruction::Addi { rd: 2, rs1: 2, imm: -4 },      // 1: sp -= 4
-        Instruction::Sw   { rs1: 1, rs2: 2, imm: 0 },      // 2: save ra
+        Instruction::Sw   { rs1: 2, rs2: 1, imm: 0 },      // 2: save ra
         Instruction::Addi { rd: 10, rs1: 10, imm: -1 },    // 3: n -= 1
         Instruction::Jal  { rd: 1, offset: -4 },           // 4: call f(n-1)
         Instruction::Lw   { rd: 1, rs1: 2, imm: 0 },       // 5: restore ra
@@ -0,0 +1,158 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::run_program;
+use riscviz::utils::run_program_on;
+use riscviz::xlen::{Rv32, Rv64};
+
+#[test]
+fn test_same_program_on_both_widths() {
+    // sum 1..=10 into x3
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 10 },
+        Instruction::Add { rd: 3, rs1: 3, rs2: 1 },
+        Instruction::Addi { rd: 1, rs1: 1, imm: -1 },
+        Instruction::Bne { rs1: 1, rs2: 0, offset: -2 },
+        Instruction::Addi { rd: 4, rs1: 0, imm: -7 },
+        Instruction::Srai { rd: 5, rs1: 4, imm: 1 },
+    ];
+    let cpu32 = run_program!(program.clone());
+    let cpu64 = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu32.regs[3], 55);
+    assert_eq!(cpu64.regs[3], 55);
+    assert_eq!(cpu32.regs[5], -4);
+    assert_eq!(cpu64.regs[5], -4);
+    assert_eq!(cpu64.regs[2], 1024);
+}
+
+#[test]
+fn test_64_bit_registers() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: 1 },
+        Instruction::Slli { rd: 1, rs1: 1, imm: 63 },
+        Instruction::Srli { rd: 2, rs1: 1, imm: 60 },
+        Instruction::Srai { rd: 3, rs1: 1, imm: 60 },
+        Instruction::Lui { rd: 4, imm: 0x80000 },
+        Instruction::Addi { rd: 5, rs1: 0, imm: 40 },
+        Instruction::Sll { rd: 6, rs1: 2, rs2: 5 },
+        Instruction::Sltu { rd: 7, rs1: 0, rs2: 1 },
+        Instruction::Mulhu { rd: 8, rs1: 1, rs2: 2 },
+    ];
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[1], i64::MIN);
+    assert_eq!(cpu.regs[2], 8);
+    assert_eq!(cpu.regs[3], -8);
+    assert_eq!(cpu.regs[4], -0x8000_0000); // lui sign-extends
+    assert_eq!(cpu.regs[6], 8 << 40);
+    assert_eq!(cpu.regs[7], 1);
+    assert_eq!(cpu.regs[8], 4);
+}
+
+#[test]
+fn test_word_instructions_sign_extend() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: -1 },
+        Instruction::Srli { rd: 1, rs1: 1, imm: 33 },
+        Instruction::Addiw { rd: 2, rs1: 1, imm: 0x7FF },
+        Instruction::Addi { rd: 3, rs1: 1, imm: 0x7FF },
+        Instruction::Addw { rd: 4, rs1: 1, rs2: 1 },
+        Instruction::Subw { rd: 5, rs1: 0, rs2: 1 },
+        Instruction::Slliw { rd: 6, rs1: 1, imm: 1 },
+        Instruction::Addi { rd: 7, rs1: 0, imm: -16 },
+        Instruction::Srliw { rd: 8, rs1: 7, imm: 4 },
+        Instruction::Sraiw { rd: 9, rs1: 7, imm: 4 },
+        Instruction::Addi { rd: 10, rs1: 0, imm: 33 },
+        Instruction::Sllw { rd: 11, rs1: 10, rs2: 10 }, // shift amount is 33 & 31
+        Instruction::Mulw { rd: 12, rs1: 1, rs2: 10 },
+    ];
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[1], 0x7FFF_FFFF);
+    assert_eq!(cpu.regs[2], -0x7FFF_F802);
+    assert_eq!(cpu.regs[3], 0x8000_07FE);
+    assert_eq!(cpu.regs[4], -2);
+    assert_eq!(cpu.regs[5], -0x7FFF_FFFF);
+    assert_eq!(cpu.regs[6], -2);
+    assert_eq!(cpu.regs[8], 0x0FFF_FFFF);
+    assert_eq!(cpu.regs[9], -1);
+    assert_eq!(cpu.regs[11], 66);
+    assert_eq!(cpu.regs[12], 0x7FFF_FFDF);
+}
+
+#[test]
+fn test_word_division_corner_cases() {
+    let program = vec![
+        Instruction::Lui { rd: 1, imm: 0x80000 },
+        Instruction::Addi { rd: 2, rs1: 0, imm: -1 },
+        Instruction::Divw { rd: 3, rs1: 1, rs2: 2 },
+        Instruction::Remw { rd: 4, rs1: 1, rs2: 2 },
+        Instruction::Divw { rd: 5, rs1: 1, rs2: 0 },
+        Instruction::Divuw { rd: 6, rs1: 1, rs2: 0 },
+        Instruction::Remuw { rd: 7, rs1: 1, rs2: 0 },
+        Instruction::Divuw { rd: 8, rs1: 2, rs2: 1 },
+        Instruction::Div { rd: 9, rs1: 1, rs2: 2 },
+    ];
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[3], i32::MIN as i64);
+    assert_eq!(cpu.regs[4], 0);
+    assert_eq!(cpu.regs[5], -1);
+    assert_eq!(cpu.regs[6], -1);
+    assert_eq!(cpu.regs[7], i32::MIN as i64);
+    assert_eq!(cpu.regs[8], 1);
+    assert_eq!(cpu.regs[9], 0x8000_0000);
+}
+
+#[test]
+fn test_doubleword_memory() {
+    let program = vec![
+        Instruction::Addi { rd: 1, rs1: 0, imm: -3 },
+        Instruction::Sd { rs1: 2, rs2: 1, imm: -8 },
+        Instruction::Ld { rd: 3, rs1: 2, imm: -8 },
+        Instruction::Lw { rd: 4, rs1: 2, imm: -8 },
+        Instruction::Lwu { rd: 5, rs1: 2, imm: -8 },
+    ];
+    let cpu = run_program_on::<Rv64>(program, 0);
+    assert_eq!(cpu.regs[3], -3);
+    assert_eq!(cpu.regs[4], -3);
+    assert_eq!(cpu.regs[5], 0xFFFF_FFFD);
+}
+
+#[test]
+fn test_rv64_only_instructions_are_illegal_on_rv32() {
+    let mut cpu = Cpu::default();
+    cpu.load_instructions(vec![Instruction::Addiw { rd: 1, rs1: 0, imm: 1 }]);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(Instruction::Addiw { .. }))));
+
+    let mut cpu = Cpu::<Rv32>::with_memory(64);
+    cpu.load_instructions(vec![Instruction::Slli { rd: 1, rs1: 1, imm: 32 }]);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(_))));
+
+    let mut cpu = Cpu::<Rv64>::with_memory(64);
+    cpu.load_instructions(vec![Instruction::Slliw { rd: 1, rs1: 1, imm: 32 }]);
+    assert!(matches!(cpu.execute_next(), Err(CpuError::IllegalInstruction(_))));
+}
+
+#[test]
+fn test_rv64_parsing_and_encoding() {
+    assert_eq!(parse_instruction("addiw x1, x2, 1"), Some(Instruction::Addiw { rd: 1, rs1: 2, imm: 1 }));
+    assert_eq!(parse_instruction("ld x1, 8(x2)"), Some(Instruction::Ld { rd: 1, rs1: 2, imm: 8 }));
+    assert_eq!(parse_instruction("sd x1, 8(x2)"), Some(Instruction::Sd { rs1: 2, rs2: 1, imm: 8 }));
+    assert_eq!(parse_instruction("remuw x3, x4, x5"), Some(Instruction::Remuw { rd: 3, rs1: 4, rs2: 5 }));
+
+    assert_eq!(encode(&Instruction::Addiw { rd: 1, rs1: 2, imm: 1 }), Some(0x0011_009B));
+    assert_eq!(encode(&Instruction::Ld { rd: 1, rs1: 2, imm: 8 }), Some(0x0081_3083));
+    assert_eq!(encode(&Instruction::Sd { rs1: 2, rs2: 1, imm: 8 }), Some(0x0011_3423));
+    assert_eq!(encode(&Instruction::Slli { rd: 1, rs1: 1, imm: 63 }), Some(0x03F0_9093));
+    assert!(encode(&Instruction::Slliw { rd: 1, rs1: 1, imm: 32 }).is_none());
+
+    let insts = [
+        Instruction::Srai { rd: 1, rs1: 2, imm: 45 },
+        Instruction::Lwu { rd: 3, rs1: 4, imm: -4 },
+        Instruction::Sraiw { rd: 5, rs1: 6, imm: 31 },
+        Instruction::Sraw { rd: 7, rs1: 8, rs2: 9 },
+        Instruction::Divuw { rd: 10, rs1: 11, rs2: 12 },
+    ];
+    for inst in insts {
+        assert_eq!(decode(encode(&inst).unwrap()), Some(inst));
+    }
+}
@@ -0,0 +1,82 @@
+use riscviz::asm_parser::load_asm;
+use riscviz::cpu::Cpu;
+use riscviz::encoding::{self, Format};
+use riscviz::instruction::Instruction;
+use riscviz::stats::BranchCount;
+
+mod common;
+use common::asm;
+
+fn run_loop() -> Cpu {
+    let mut cpu: Cpu = Cpu::default();
+    cpu.load_program(load_asm("tests/asm_files/loop.s").unwrap());
+    while cpu.execute_next().unwrap() {}
+    cpu
+}
+
+#[test]
+fn test_mnemonics() {
+    let cases = [
+        (asm("addi x1, x0, 1"), "addi"),
+        (asm("sh1add.uw x1, x2, x3"), "sh1add.uw"),
+        (asm("amoadd.w x1, x2, (x3)"), "amoadd.w"),
+        (asm("fcvt.s.wu f1, x2"), "fcvt.s.wu"),
+        (Instruction::Ecall, "ecall"),
+    ];
+    for (inst, mnemonic) in cases {
+        assert_eq!(inst.mnemonic(), mnemonic);
+    }
+}
+
+#[test]
+fn test_formats() {
+    let cases = [
+        ("add x1, x2, x3", Format::R),
+        ("fmadd.s f1, f2, f3, f4", Format::R),
+        ("addi x1, x2, 3", Format::I),
+        ("lw x1, 0(x2)", Format::I),
+        ("jalr x1, x2, 0", Format::I),
+        ("sw x1, 0(x2)", Format::S),
+        ("bne x1, x2, 3", Format::B),
+        ("lui x1, 5", Format::U),
+        ("jal x1, -3", Format::J),
+    ];
+    for (line, format) in cases {
+        assert_eq!(encoding::format(&asm(line)), Some(format), "{line}");
+    }
+}
+
+#[test]
+fn test_loop_report() {
+    let cpu = run_loop();
+    assert_eq!((cpu.counters().executed(6), cpu.counters().taken(6)), (3, 2));
+
+    let stats = cpu.stats(2);
+    assert_eq!(stats.executed, 18);
+    assert_eq!((stats.loads, stats.stores), (1, 3));
+    let mnemonics = stats.mnemonics.iter().map(|(m, n)| (m.as_str(), *n)).collect::<Vec<_>>();
+    assert_eq!(mnemonics, [("addi", 8), ("bne", 3), ("mul", 3), ("sw", 3), ("lw", 1)]);
+    let formats = stats.formats.iter().map(|(&f, &n)| (f, n)).collect::<Vec<_>>();
+    assert_eq!(formats, [(Format::R, 3), (Format::I, 9), (Format::S, 3), (Format::B, 3)]);
+    assert_eq!(stats.branch_totals(), BranchCount { executed: 3, taken: 2 });
+    assert_eq!(stats.branch_totals().not_taken(), 1);
+
+    let hot = stats.hot_pcs.iter().map(|spot| (spot.pc, spot.count)).collect::<Vec<_>>();
+    assert_eq!(hot, [(2, 3), (3, 3)]);
+    assert_eq!(stats.hot_labels, [("loop".to_string(), 15), ("_start".to_string(), 2)]);
+    assert!(stats.to_string().starts_with("18 instructions executed, 1 loads, 3 stores\nmnemonics:\n  addi"));
+}
+
+#[test]
+fn test_counters_follow_the_program() {
+    let mut cpu = run_loop();
+    cpu.add_instruction(asm("addi x9, x0, 1"));
+    assert!(cpu.execute_next().unwrap());
+    assert_eq!((cpu.counters().executed(8), cpu.counters().total()), (1, 19));
+    assert_eq!(cpu.stats(1).hot_labels, [("loop".to_string(), 15)]);
+
+    cpu.reset_counters();
+    assert_eq!(cpu.stats(5).executed, 0);
+    cpu.load_instructions(vec![asm("addi x1, x0, 1")]);
+    assert_eq!(cpu.counters().total(), 0);
+}
@@ -0,0 +1,119 @@
+use riscviz::asm_parser::load_asm;
+use riscviz::cache::{CacheConfig, Caches};
+use riscviz::cpu::Cpu;
+use riscviz::timing::{Class, Latencies, Tally, TimingError, TimingModel};
+
+mod common;
+use common::asm;
+
+fn bram_core() -> Latencies {
+    std::fs::read_to_string("tests/latencies/bram_core.cfg").unwrap().parse().unwrap()
+}
+
+fn run(cpu: &mut Cpu, latencies: Latencies) -> TimingModel {
+    let mut timing = TimingModel::new(latencies);
+    while timing.step(cpu).unwrap() {}
+    timing
+}
+
+#[test]
+fn test_latency_file() {
+    let latencies = bram_core();
+    assert_eq!(
+        latencies,
+        Latencies { alu: 1, mul: 3, div: 34, load: 2, store: 2, taken: 3, not_taken: 1, memory: 0 }
+    );
+    assert_eq!(latencies.to_string().parse(), Ok(latencies));
+    assert_eq!("mul = 5".parse::<Latencies>().map(|l| (l.mul, l.div)), Ok((5, Latencies::default().div)));
+
+    assert_eq!("\n alu 1".parse::<Latencies>(), Err(TimingError::BadLine { line: 2 }));
+    assert_eq!("fpu = 4".parse::<Latencies>(), Err(TimingError::UnknownKey { line: 1, key: "fpu".to_string() }));
+    assert_eq!("load = two".parse::<Latencies>(), Err(TimingError::BadNumber { line: 1, value: "two".to_string() }));
+}
+
+#[test]
+fn test_classes() {
+    let cases = [
+        ("add x1, x2, x3", 0, Class::Alu),
+        ("mulhu x1, x2, x3", 0, Class::Mul),
+        ("remu x1, x2, x3", 0, Class::Div),
+        ("lbu x1, 0(x2)", 0, Class::Load),
+        ("sh x1, 0(x2)", 0, Class::Store),
+        ("jal x1, 0", 0, Class::Taken),
+        ("beq x1, x2, 0", 5, Class::Taken),
+        ("beq x1, x2, 0", 1, Class::NotTaken),
+    ];
+    for (line, next_pc, class) in cases {
+        assert_eq!(Class::of(&asm(line), 0, next_pc), class, "{line}");
+    }
+}
+
+#[test]
+fn test_label_breakdown() {
+    let mut cpu: Cpu = Cpu::default();
+    cpu.load_program(load_asm("tests/asm_files/loop.s").unwrap());
+    let timing = run(&mut cpu, bram_core());
+    assert_eq!(cpu.regs[8], 36);
+
+    // Three passes of addi, mul, sw, addi, bne; two of the bne are taken.
+    let labels = timing.labels().map(|l| (l.label.clone().unwrap(), l.time)).collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            ("_start".to_string(), Tally { instructions: 2, cycles: 2 }),
+            ("loop".to_string(), Tally { instructions: 15, cycles: 3 * (1 + 3 + 2 + 1) + 2 * 3 + 1 }),
+            ("done".to_string(), Tally { instructions: 1, cycles: 2 }),
+        ]
+    );
+    assert_eq!((timing.cycles(), timing.retired()), (32, 18));
+    assert_eq!(timing.classes()[&Class::Taken], Tally { instructions: 2, cycles: 6 });
+    assert!(timing.to_string().starts_with("32 cycles, 18 instructions, CPI 1.78"));
+}
+
+#[test]
+fn test_memory_latency() {
+    let program = vec![asm("sw x0, 0x100(x0)"), asm("lw x1, 0x100(x0)"), asm("lw x2, 0x104(x0)")];
+    let latencies = Latencies { memory: 10, ..bram_core() };
+
+    // Without caches, every load and store goes to memory.
+    let mut cpu: Cpu = Cpu::default();
+    cpu.load_instructions(program.clone());
+    let timing = run(&mut cpu, latencies);
+    assert_eq!((timing.memory_accesses(), timing.cycles()), (3, 2 + 2 + 2 + 3 * 10));
+
+    // A write-allocate D-cache misses once on the line; the fetches go
+    // through an I-cache that misses once too.
+    let mut cpu: Cpu = Cpu::default();
+    let config = CacheConfig::new(64, 16, 1).unwrap();
+    cpu.set_caches(Caches::new(Some(config), Some(config), None));
+    cpu.load_instructions(program);
+    let timing = run(&mut cpu, latencies);
+    assert_eq!((timing.memory_accesses(), timing.cycles()), (2, 2 + 2 + 2 + 2 * 10));
+}
+
+#[test]
+fn test_one_cycle_per_step() {
+    let mut cpu: Cpu = Cpu::default();
+    cpu.load_instructions(vec![asm("mul x1, x0, x0"), asm("addi x2, x0, 1")]);
+    let mut timing = TimingModel::new(bram_core());
+    for cycle in 1..=3 {
+        assert!(timing.step(&mut cpu).unwrap());
+        assert_eq!((timing.cycles(), cpu.pc), (cycle, 1));
+    }
+    assert!(timing.step(&mut cpu).unwrap());
+    assert!(!timing.step(&mut cpu).unwrap());
+    assert_eq!((timing.cycles(), timing.retired()), (4, 2));
+}
+
+#[test]
+fn test_trap_retires_nothing() {
+    let mut cpu: Cpu = Cpu::default();
+    let program = ["addi x1, x0, 12", "csrw mtvec, x1", "ecall", "addi x2, x0, 1"];
+    cpu.load_instructions(program.into_iter().map(asm).collect());
+    let timing = run(&mut cpu, bram_core());
+    assert_eq!(cpu.regs[2], 1);
+    // The ecall costs a cycle to redirect but is neither retired nor charged.
+    assert_eq!((timing.cycles(), timing.retired()), (4, 3));
+    assert_eq!(timing.classes()[&Class::Alu], Tally { instructions: 3, cycles: 3 });
+    assert_eq!(timing.labels().map(|l| l.time.instructions).sum::<usize>(), 3);
+}
@@ -0,0 +1,141 @@
+use riscviz::cpu::Cpu;
+use riscviz::instruction::Reg;
+use riscviz::tomasulo::{Event, Status, Timing, Tomasulo, TomasuloConfig, TomasuloError, UnitConfig};
+
+mod common;
+use common::asm;
+
+fn run(lines: &[&str], config: TomasuloConfig) -> (Tomasulo, Cpu) {
+    let mut cpu: Cpu = Cpu::new(1024);
+    cpu.load_instructions(lines.iter().map(|line| asm(line)).collect());
+    let mut tomasulo = Tomasulo::new(config);
+    while tomasulo.step(&mut cpu).unwrap() {}
+    (tomasulo, cpu)
+}
+
+fn timings(tomasulo: &Tomasulo) -> Vec<Timing> {
+    tomasulo.committed().iter().map(|entry| entry.timing).collect()
+}
+
+fn timing(issue: usize, execute: (usize, usize), write: usize, commit: usize) -> Timing {
+    Timing { issue, execute: Some(execute), write: Some(write), commit: Some(commit) }
+}
+
+const MUL_DIV: [&str; 6] = [
+    "addi x1, x0, 6",
+    "addi x2, x0, 7",
+    "mul x3, x1, x2",
+    "addi x4, x3, 1",
+    "div x5, x3, x2",
+    "addi x6, x0, 1",
+];
+
+#[test]
+fn test_out_of_order_execution() {
+    let (tomasulo, cpu) = run(&MUL_DIV, TomasuloConfig::default());
+    assert_eq!(&cpu.regs[1..7], &[6, 7, 42, 43, 6, 1]);
+    assert_eq!(
+        timings(&tomasulo),
+        [
+            timing(1, (2, 2), 3, 4),
+            timing(2, (3, 3), 4, 5),
+            // Waits for x2 on the CDB, then four cycles in Mul.
+            timing(3, (5, 8), 9, 10),
+            // Waits for x3.
+            timing(4, (10, 10), 11, 12),
+            timing(5, (10, 21), 22, 23),
+            // Independent, so it runs ahead but commits last.
+            timing(6, (7, 7), 8, 24),
+        ]
+    );
+    assert_eq!((tomasulo.cycles(), tomasulo.retired()), (24, 6));
+    assert!(tomasulo.rob().is_empty() && tomasulo.register_status().is_empty());
+}
+
+#[test]
+fn test_configured_latencies() {
+    let config = TomasuloConfig {
+        mul: "2:1:2".parse().unwrap(),
+        div: "2:1:3".parse().unwrap(),
+        ..TomasuloConfig::default()
+    };
+    let (tomasulo, _) = run(&MUL_DIV, config);
+    let executes = timings(&tomasulo).iter().map(|t| t.execute.unwrap()).collect::<Vec<_>>();
+    assert_eq!(executes[2], (5, 6));
+    assert_eq!(executes[4], (8, 10));
+}
+
+#[test]
+fn test_units_and_cdb_are_shared() {
+    // Two multiplies on one unit run back to back; with two units they
+    // overlap but still take turns on the CDB.
+    let lines = ["addi x1, x0, 3", "mul x2, x1, x1", "mul x3, x1, x1"];
+    let (tomasulo, _) = run(&lines, TomasuloConfig::default());
+    let t = timings(&tomasulo);
+    assert_eq!((t[1].execute, t[2].execute), (Some((4, 7)), Some((8, 11))));
+
+    let config = TomasuloConfig { mul: UnitConfig::new(2, 2, 4).unwrap(), ..TomasuloConfig::default() };
+    let (tomasulo, cpu) = run(&lines, config);
+    let t = timings(&tomasulo);
+    assert_eq!((t[1].execute, t[2].execute), (Some((4, 7)), Some((4, 7))));
+    assert_eq!((t[1].write, t[2].write), (Some(8), Some(9)));
+    assert_eq!(&cpu.regs[2..4], &[9, 9]);
+}
+
+#[test]
+fn test_full_rob_stalls_issue() {
+    let lines = ["div x1, x0, x0", "addi x2, x0, 1", "addi x3, x0, 2"];
+    let (tomasulo, _) = run(&lines, TomasuloConfig { rob: 2, ..TomasuloConfig::default() });
+    // The third waits for the divide to commit.
+    assert_eq!(timings(&tomasulo)[2].issue, timings(&tomasulo)[0].commit.unwrap());
+}
+
+#[test]
+fn test_stepped_tables() {
+    let mut cpu: Cpu = Cpu::new(1024);
+    cpu.load_instructions(MUL_DIV.iter().map(|line| asm(line)).collect());
+    let mut tomasulo = Tomasulo::default();
+    for _ in 0..4 {
+        assert!(tomasulo.step(&mut cpu).unwrap());
+    }
+    let events = tomasulo.events().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    assert_eq!(events, ["commit #1 (0)", "#2 broadcasts 7 on the CDB", "issue 3 as #4 to ALU1"]);
+
+    let rob = tomasulo.rob().iter().map(|entry| (entry.id, entry.status)).collect::<Vec<_>>();
+    assert_eq!(rob, [(2, Status::Written), (3, Status::Issued), (4, Status::Issued)]);
+    let renamed = tomasulo.register_status().iter().map(|(&reg, &id)| (reg, id)).collect::<Vec<_>>();
+    assert_eq!(renamed, [(Reg::X(2), 2), (Reg::X(3), 3), (Reg::X(4), 4)]);
+
+    let busy = tomasulo
+        .stations()
+        .iter()
+        .filter_map(|station| station.entry.map(|entry| (station.name(), entry.id)))
+        .collect::<Vec<_>>();
+    assert_eq!(busy, [("ALU1".to_string(), 4), ("Mul1".to_string(), 3)]);
+    let waiting = &tomasulo.rob()[2].sources;
+    assert_eq!(waiting.iter().map(|op| op.to_string()).collect::<Vec<_>>(), ["x3=#3"]);
+}
+
+#[test]
+fn test_unit_config_parsing() {
+    assert_eq!("2:1:4".parse(), Ok(UnitConfig { stations: 2, units: 1, latency: 4 }));
+    assert_eq!("2:1".parse::<UnitConfig>(), Err(TomasuloError::BadUnit("2:1".to_string())));
+    assert_eq!("2:x:4".parse::<UnitConfig>(), Err(TomasuloError::BadNumber("x".to_string())));
+    assert_eq!("2:1:0".parse::<UnitConfig>(), Err(TomasuloError::Zero("latency")));
+}
+
+#[test]
+fn test_trap_does_not_issue() {
+    let mut cpu: Cpu = Cpu::new(1024);
+    let program = ["addi x1, x0, 12", "csrw mtvec, x1", "ecall", "addi x2, x0, 1"];
+    cpu.load_instructions(program.into_iter().map(asm).collect());
+    let mut tomasulo = Tomasulo::default();
+    let mut flushes = vec![];
+    while tomasulo.step(&mut cpu).unwrap() {
+        flushes.extend(tomasulo.events().iter().filter(|e| matches!(e, Event::Flush { .. })).cloned());
+    }
+    assert_eq!(cpu.regs[2], 1);
+    assert_eq!(flushes, [Event::Flush { pc: 2, target: 3 }]);
+    assert_eq!(tomasulo.committed().iter().map(|entry| entry.pc).collect::<Vec<_>>(), [0, 1, 3]);
+    assert_eq!(tomasulo.retired(), 3);
+}
@@ -0,0 +1,92 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::cpu::Cpu;
+use riscviz::instruction::{Instruction, Reg};
+use riscviz::mmu::Access;
+use riscviz::privilege::{Exception, Privilege, Trap};
+use riscviz::trace::{MemAccess, RegRead, RegWrite, StepRecord, TrapRecord};
+
+fn cpu_with(lines: &[&str]) -> Cpu {
+    let mut cpu: Cpu = Cpu::new(1024);
+    let program = lines.iter().map(|line| parse_instruction(line).unwrap_or_else(|| panic!("bad: {line}")));
+    cpu.load_instructions(program.collect());
+    cpu
+}
+
+fn traced(cpu: &mut Cpu) -> StepRecord {
+    cpu.execute_traced().unwrap().expect("a step")
+}
+
+#[test]
+fn test_register_reads_and_write() {
+    let mut cpu = cpu_with(&["addi x1, x0, 5", "addi x3, x0, 2", "add x1, x1, x3"]);
+    let first = traced(&mut cpu);
+    assert_eq!((first.pc, first.next_pc), (0, 1));
+    assert_eq!(first.inst, Some(Instruction::Addi { rd: 1, rs1: 0, imm: 5 }));
+    assert_eq!(first.write, Some(RegWrite { reg: Reg::X(1), old: 0, new: 5 }));
+
+    traced(&mut cpu);
+    let second = traced(&mut cpu);
+    assert_eq!(second.reads, [RegRead { reg: Reg::X(1), value: 5 }, RegRead { reg: Reg::X(3), value: 2 }]);
+    assert_eq!(second.write, Some(RegWrite { reg: Reg::X(1), old: 5, new: 7 }));
+    assert!(second.memory.is_empty() && second.branch.is_none() && second.trap.is_none());
+    assert_eq!(cpu.execute_traced().unwrap(), None);
+}
+
+#[test]
+fn test_memory_accesses() {
+    let mut cpu = cpu_with(&["addi x1, x0, -1", "sh x1, 0x40(x0)", "lw x3, 0x40(x0)"]);
+    traced(&mut cpu);
+    let store = traced(&mut cpu);
+    let access = |access, old, new| MemAccess { vaddr: 0x40, paddr: 0x40, size: 0, access, old, new };
+    assert_eq!(store.memory, [MemAccess { size: 2, ..access(Access::Store, Some(0), Some(0xffff)) }]);
+    assert_eq!(store.write, None);
+
+    let load = traced(&mut cpu);
+    assert_eq!(load.memory, [MemAccess { size: 4, ..access(Access::Load, Some(0xffff), Some(0xffff)) }]);
+    assert_eq!(load.write, Some(RegWrite { reg: Reg::X(3), old: 0, new: 0xffff }));
+}
+
+#[test]
+fn test_branch_outcomes() {
+    // Branch offsets only come from labels, so build the branches directly.
+    let mut cpu: Cpu = Cpu::new(1024);
+    let nop = Instruction::Addi { rd: 0, rs1: 0, imm: 0 };
+    cpu.load_instructions(vec![
+        Instruction::Beq { rs1: 0, rs2: 0, offset: 2 },
+        nop,
+        Instruction::Bne { rs1: 0, rs2: 0, offset: 2 },
+        nop,
+    ]);
+    let taken = traced(&mut cpu);
+    assert_eq!((taken.next_pc, taken.branch), (2, Some(true)));
+    let not_taken = traced(&mut cpu);
+    assert_eq!((not_taken.next_pc, not_taken.branch), (3, Some(false)));
+    assert_eq!(traced(&mut cpu).branch, None);
+}
+
+#[test]
+fn test_trap_is_recorded() {
+    let mut cpu = cpu_with(&["addi x1, x0, 12", "csrw mtvec, x1", "ecall", "addi x0, x0, 0"]);
+    traced(&mut cpu);
+    traced(&mut cpu);
+    let ecall = traced(&mut cpu);
+    assert_eq!((ecall.pc, ecall.next_pc), (2, 3));
+    assert_eq!(
+        ecall.trap,
+        Some(TrapRecord { trap: Trap::Exception(Exception::MachineEcall), tval: 0, privilege: Privilege::Machine })
+    );
+    assert_eq!(ecall.write, None);
+}
+
+#[test]
+fn test_tracing_keeps_last_record() {
+    let mut cpu = cpu_with(&["addi x1, x0, 1", "addi x3, x0, 2"]);
+    cpu.execute_next().unwrap();
+    assert!(cpu.last_record().is_none());
+
+    cpu.set_tracing(true);
+    cpu.execute_next().unwrap();
+    let record = cpu.last_record().unwrap();
+    assert_eq!(record.write, Some(RegWrite { reg: Reg::X(3), old: 0, new: 2 }));
+    assert_eq!(record.to_string(), "1 -> 2: Addi { rd: 3, rs1: 0, imm: 2 }\n  x3: 0 -> 2");
+}
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::mpsc;
+use riscviz::asm_parser::load_asm;
+use riscviz::bus::Bus;
+use riscviz::cpu::Cpu;
+use riscviz::uart::{self, Uart, IER_RX, IER_THRE, LSR_DR, LSR_THRE, VIRT_UART_BASE, VIRT_UART_IRQ};
+
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn bus_with_uart(input: Option<mpsc::Receiver<u8>>) -> (Bus, SharedOutput) {
+    let output = SharedOutput::default();
+    let mut bus = Bus::new(1024);
+    let uart = Uart::new(Box::new(output.clone()), input);
+    bus.attach(VIRT_UART_BASE, Some(VIRT_UART_IRQ), Box::new(uart)).unwrap();
+    (bus, output)
+}
+
+#[test]
+fn test_program_prints_through_uart() {
+    let (bus, output) = bus_with_uart(None);
+    let mut cpu: Cpu = Cpu::with_bus(bus);
+    cpu.load_program(load_asm("tests/asm_files/uart.s").unwrap());
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(output.0.borrow().as_slice(), b"Hi\n");
+}
+
+#[test]
+fn test_program_echoes_input() {
+    let (tx, rx) = mpsc::channel();
+    for &byte in b"hey\n" {
+        tx.send(byte).unwrap();
+    }
+    let (bus, output) = bus_with_uart(Some(rx));
+    let mut cpu: Cpu = Cpu::with_bus(bus);
+    cpu.load_program(load_asm("tests/asm_files/echo.s").unwrap());
+    while cpu.execute_next().unwrap() {}
+    assert_eq!(output.0.borrow().as_slice(), b"hey\n");
+}
+
+#[test]
+fn test_receive_and_interrupts() {
+    let (tx, rx) = mpsc::channel();
+    let (mut bus, _) = bus_with_uart(Some(rx));
+    let reg = |offset: u64| VIRT_UART_BASE + offset;
+
+    assert_eq!(bus.read_byte(reg(5)).unwrap() as u8, LSR_THRE | 0x40);
+    assert_eq!(bus.read_byte(reg(2)).unwrap() as u8, 0x01); // no interrupt pending
+
+    tx.send(b'a').unwrap();
+    tx.send(b'b').unwrap();
+    bus.tick();
+    assert_eq!(bus.read_byte(reg(5)).unwrap() as u8 & LSR_DR, LSR_DR);
+    assert_eq!(bus.interrupts(), 0); // masked by IER
+
+    bus.write_byte(reg(1), IER_RX).unwrap();
+    assert_eq!(bus.interrupts(), 1 << VIRT_UART_IRQ);
+    assert_eq!(bus.read_byte(reg(2)).unwrap() as u8, 0x04);
+    assert_eq!(bus.read_byte(reg(0)).unwrap() as u8, b'a');
+    assert_eq!(bus.read_byte(reg(0)).unwrap() as u8, b'b');
+    assert_eq!(bus.read_byte(reg(5)).unwrap() as u8 & LSR_DR, 0);
+    assert_eq!(bus.interrupts(), 0);
+
+    // THR-empty fires when enabled and is acknowledged by reading IIR.
+    bus.write_byte(reg(1), IER_RX | IER_THRE).unwrap();
+    assert_ne!(bus.interrupts(), 0);
+    assert_eq!(bus.read_byte(reg(2)).unwrap() as u8, 0x02);
+    assert_eq!(bus.interrupts(), 0);
+}
+
+#[test]
+fn test_divisor_latch_and_scratch() {
+    let (mut bus, output) = bus_with_uart(None);
+    let reg = |offset: u64| VIRT_UART_BASE + offset;
+    bus.write_byte(reg(3), 0x83).unwrap(); // DLAB, 8N1
+    bus.write_byte(reg(0), 0x03).unwrap();
+    bus.write_byte(reg(1), 0x00).unwrap();
+    bus.write_byte(reg(3), 0x03).unwrap();
+    assert_eq!(bus.read_byte(reg(3)).unwrap(), 0x03);
+    assert_eq!(bus.read_byte(reg(1)).unwrap(), 0); // IER again, not the latch
+    bus.write_byte(reg(7), 0x5A).unwrap();
+    assert_eq!(bus.read_byte(reg(7)).unwrap(), 0x5A);
+    assert!(output.0.borrow().is_empty()); // the latch write did not transmit
+}
+
+#[test]
+fn test_input_from_reader() {
+    // The reader thread hangs up at end of input, so this blocks instead of polling.
+    let received = uart::input_from(&b"ok"[..]).iter().collect::<Vec<_>>();
+    assert_eq!(received, b"ok");
+
+    let (tx, rx) = mpsc::channel();
+    let (mut bus, _) = bus_with_uart(Some(rx));
+    received.into_iter().for_each(|byte| tx.send(byte).unwrap());
+    bus.tick();
+    assert_eq!(bus.read_byte(VIRT_UART_BASE).unwrap() as u8, b'o');
+    assert_eq!(bus.read_byte(VIRT_UART_BASE).unwrap() as u8, b'k');
+}
@@ -22,9 +22,10 @@ fn test_auipc() {
         Instruction::Auipc { rd: 3, imm: -1 },
     ]);
 
+    // auipc adds to its own byte address.
     assert_eq!(cpu.regs[1], 0);
-    assert_eq!(cpu.regs[2], 0x1000 + 1);
-    assert_eq!(cpu.regs[3], (0xFFFFF000u32 as i32) + 2);
+    assert_eq!(cpu.regs[2], 0x1000 + 4);
+    assert_eq!(cpu.regs[3], (0xFFFFF000u32 as i32) + 8);
 }
 
 #[test]
@@ -37,5 +38,5 @@ fn test_lui_auipc_combo() {
     ]);
 
     assert_eq!(cpu.regs[1], 0x10000234);
-    assert_eq!(cpu.regs[2], 2 + 8);
+    assert_eq!(cpu.regs[2], 8 + 8);
 }
\ No newline at end of file
@@ -0,0 +1,229 @@
+use riscviz::asm_parser::parse_instruction;
+use riscviz::bus::Bus;
+use riscviz::cpu::{Cpu, CpuError};
+use riscviz::csr;
+use riscviz::encoding::{decode, encode};
+use riscviz::instruction::Instruction;
+use riscviz::vector::Sew;
+
+mod common;
+use common::asm;
+
+/// A CPU whose RAM holds `words` from 0x100.
+fn cpu_with_words(words: &[i32]) -> Cpu {
+    let mut bus = Bus::new(0x1000);
+    for (i, word) in words.iter().enumerate() {
+        bus.write_word(0x100 + 4 * i as u64, *word).unwrap();
+    }
+    Cpu::with_bus(bus)
+}
+
+/// Runs `program` to the end and returns the number of instructions executed.
+fn run(cpu: &mut Cpu, program: Vec<Instruction>) -> Result<usize, CpuError> {
+    cpu.load_instructions(program);
+    cpu.pc = 0;
+    let mut steps = 0;
+    while cpu.execute_next()? {
+        steps += 1;
+    }
+    Ok(steps)
+}
+
+fn elements(cpu: &Cpu, r: usize, n: usize) -> Vec<i64> {
+    (0..n).map(|i| Sew::E32.sign_extend(cpu.vector().element(r, i, Sew::E32))).collect()
+}
+
+#[test]
+fn test_strip_mined_sum_matches_scalar_loop() {
+    let words = (1..=10).collect::<Vec<_>>();
+
+    let mut scalar = cpu_with_words(&words);
+    let scalar_steps = run(&mut scalar, vec![
+        asm("addi x10, x0, 0x100"),
+        asm("addi x11, x0, 10"),
+        asm("lw x6, 0(x10)"),
+        asm("add x12, x12, x6"),
+        asm("addi x10, x10, 4"),
+        asm("addi x11, x11, -1"),
+        Instruction::Bne { rs1: 11, rs2: 0, offset: -4 },
+    ]).unwrap();
+
+    let mut vector = cpu_with_words(&words);
+    let vector_steps = run(&mut vector, vec![
+        asm("addi x10, x0, 0x100"),
+        asm("addi x11, x0, 10"),
+        asm("vsetvli x5, x0, e32, m1, ta, ma"),
+        asm("vmv.v.i v8, 0"),
+        asm("vsetvli x5, x11, e32, m1, ta, ma"),
+        asm("vle32.v v1, (x10)"),
+        asm("vredsum.vs v8, v1, v8"),
+        asm("sub x11, x11, x5"),
+        asm("slli x6, x5, 2"),
+        asm("add x10, x10, x6"),
+        Instruction::Bne { rs1: 11, rs2: 0, offset: -6 },
+        asm("vmv.x.s x12, v8"),
+    ]).unwrap();
+
+    assert_eq!(scalar.regs[12], 55);
+    assert_eq!(vector.regs[12], 55);
+    assert_eq!(vector.regs[5], 2); // 4 + 4 + 2 elements
+    assert!(vector_steps < scalar_steps);
+}
+
+#[test]
+fn test_masked_add_leaves_inactive_elements() {
+    let mut cpu = cpu_with_words(&[1, 2, 3, 4]);
+    run(&mut cpu, vec![
+        asm("addi x10, x0, 0x100"),
+        asm("addi x11, x0, 3"),
+        asm("vsetivli x0, 4, e32, m1, tu, mu"),
+        asm("vle32.v v2, (x10)"),
+        asm("vmv.v.i v1, 5"),
+        asm("vmv.v.i v3, -1"),
+        asm("vmslt.vx v0, v2, x11"),
+        asm("vadd.vv v3, v2, v1, v0.t"),
+        asm("vcpop.m x5, v0"),
+        asm("vfirst.m x6, v0"),
+        asm("vmxor.mm v0, v0, v0"),
+        asm("vfirst.m x7, v0"),
+    ]).unwrap();
+    assert_eq!(elements(&cpu, 3, 4), [6, 7, -1, -1]);
+    assert_eq!((cpu.regs[5], cpu.regs[6], cpu.regs[7]), (2, 0, -1));
+}
+
+#[test]
+fn test_strided_load_and_unit_store() {
+    let mut cpu = cpu_with_words(&[0, 10, 20, 30, 40, 50, 60, 70]);
+    run(&mut cpu, vec![
+        asm("addi x10, x0, 0x100"),
+        asm("addi x11, x0, 8"),
+        asm("addi x12, x0, 0x200"),
+        asm("vsetivli x0, 4, e32, m1, ta, ma"),
+        asm("vlse32.v v1, (x10), x11"),
+        asm("vmul.vx v1, v1, x11"),
+        asm("vse32.v v1, (x12)"),
+        asm("vsetivli x0, 2, e16, m1, ta, ma"),
+        asm("vsse16.v v1, (x12), x11"),
+    ]).unwrap();
+    assert_eq!(elements(&cpu, 1, 4), [0, 160, 320, 480]);
+    let stored = (0..4).map(|i| cpu.bus().ram().read_word(0x200 + 4 * i).unwrap()).collect::<Vec<_>>();
+    // The e16 store rewrote the low halves of words 0 and 2 with elements 0 and 1.
+    assert_eq!(stored, [0, 160, 0, 480]);
+}
+
+#[test]
+fn test_reductions_are_signed() {
+    let mut cpu = cpu_with_words(&[6, -3, 12, 5]);
+    run(&mut cpu, vec![
+        asm("addi x10, x0, 0x100"),
+        asm("vsetivli x0, 4, e32, m1, ta, ma"),
+        asm("vle32.v v1, (x10)"),
+        asm("vmv.v.i v2, 0"),
+        asm("vredmin.vs v3, v1, v2"),
+        asm("vredmax.vs v4, v1, v2"),
+        asm("vredxor.vs v5, v1, v2"),
+        asm("vredsum.vs v6, v1, v2"),
+        asm("vmv.x.s x5, v3"),
+        asm("vmv.x.s x6, v4"),
+        asm("vmv.x.s x7, v5"),
+        asm("vmv.x.s x8, v6"),
+    ]).unwrap();
+    assert_eq!(cpu.regs[5..9], [-3, 12, 6 ^ -3 ^ 12 ^ 5, 20]);
+}
+
+#[test]
+fn test_vsetvli_clamps_to_vlmax() {
+    let mut cpu = Cpu::with_bus(Bus::new(0x1000));
+    run(&mut cpu, vec![
+        asm("addi x6, x0, 100"),
+        asm("vsetvli x5, x6, e8, m1, ta, ma"),
+        asm("vsetvli x7, x6, e64, m2, ta, ma"),
+        asm("vsetvli x8, x0, e16, mf2, ta, ma"),
+    ]).unwrap();
+    assert_eq!(cpu.regs[5..9], [16, 100, 4, 4]);
+    assert_eq!(cpu.read_csr(csr::VLENB).unwrap(), 16);
+
+    cpu.set_vlen(256);
+    run(&mut cpu, vec![asm("vsetvli x5, x0, e32, m1, ta, ma")]).unwrap();
+    assert_eq!(cpu.regs[5], 8);
+    assert_eq!(cpu.read_csr(csr::VL).unwrap(), 8);
+
+    // SEW=128 is reserved, which sets vill and makes vector arithmetic illegal.
+    run(&mut cpu, vec![Instruction::Vsetvli { rd: 5, rs1: 0, vtypei: 4 << 3 }]).unwrap();
+    assert_eq!(cpu.regs[5], 0);
+    assert_eq!(cpu.read_csr(csr::VTYPE).unwrap() as i32, i32::MIN);
+    assert!(matches!(run(&mut cpu, vec![asm("vadd.vi v1, v1, 1")]), Err(CpuError::IllegalInstruction(_))));
+}
+
+#[test]
+fn test_register_group_alignment() {
+    let mut cpu = Cpu::with_bus(Bus::new(0x1000));
+    run(&mut cpu, vec![asm("vsetvli x5, x0, e32, m2, ta, ma"), asm("vadd.vv v2, v4, v6")]).unwrap();
+    assert!(matches!(run(&mut cpu, vec![asm("vadd.vv v1, v2, v4")]), Err(CpuError::IllegalInstruction(_))));
+}
+
+#[test]
+fn test_parse_vector() {
+    assert_eq!(
+        asm("vadd.vv v1, v2, v3, v0.t"),
+        Instruction::VaddVv { vd: 1, vs2: 2, vs1: 3, vm: false }
+    );
+    assert_eq!(
+        asm("vlse16.v v4, (x10), x11"),
+        Instruction::VlseV { vd: 4, rs1: 10, rs2: 11, eew: Sew::E16, vm: true }
+    );
+    assert_eq!(asm("vsetvli x5, x10, e32, m1, ta, ma"), Instruction::Vsetvli { rd: 5, rs1: 10, vtypei: 0xD0 });
+    assert_eq!(parse_instruction("vle32.v v1, 4(x10)"), None);
+    assert_eq!(parse_instruction("vsetvli x5, x10, m1"), None);
+    assert_eq!(parse_instruction("vmv.v.v v1, v2, v0.t"), None);
+    assert_eq!(parse_instruction("vadd.vv v32, v2, v3"), None);
+}
+
+#[test]
+fn test_encode_decode_vector() {
+    assert_eq!(encode(&asm("vadd.vv v1, v2, v3")), Some(0x0221_80D7));
+    assert_eq!(encode(&asm("vsetvli x5, x10, e32, m1, ta, ma")), Some(0x0D05_72D7));
+    assert_eq!(encode(&asm("vle32.v v1, (x10)")), Some(0x0205_6087));
+    assert_eq!(encode(&asm("vadd.vi v1, v2, 16")), None);
+
+    for line in [
+        "vsetivli x5, 31, e8, mf8, tu, mu",
+        "vse64.v v8, (x11), v0.t",
+        "vsse8.v v8, (x11), x12",
+        "vsub.vx v1, v2, x3",
+        "vand.vi v1, v2, -16",
+        "vmul.vv v1, v2, v3, v0.t",
+        "vmul.vx v1, v2, x3",
+        "vmv.v.x v1, x5",
+        "vmv.v.i v1, -1",
+        "vmv.s.x v1, x5",
+        "vmv.x.s x5, v1",
+        "vredmax.vs v1, v2, v3",
+        "vmsle.vi v0, v2, 3",
+        "vmandn.mm v1, v2, v3",
+        "vcpop.m x5, v2, v0.t",
+        "vfirst.m x5, v2",
+    ] {
+        let inst = asm(line);
+        let word = encode(&inst).unwrap_or_else(|| panic!("cannot encode {line}"));
+        assert_eq!(decode(word), Some(inst), "{line}");
+    }
+    // The scalar FP loads still decode from the shared opcode.
+    assert_eq!(decode(encode(&asm("flw f1, 8(x10)")).unwrap()), Some(asm("flw f1, 8(x10)")));
+}
+
+#[test]
+fn test_visualizer() {
+    let mut cpu = Cpu::with_bus(Bus::new(0x1000));
+    run(&mut cpu, vec![
+        asm("vsetivli x0, 3, e32, m1, ta, mu"),
+        asm("vmv.v.i v1, 7"),
+        asm("vmseq.vi v0, v1, 7"),
+    ]).unwrap();
+    assert_eq!(cpu.vector().show(1), "[7, 7, 7 | 0]");
+    assert_eq!(cpu.vector().show_mask(0), "1110");
+    assert_eq!(
+        cpu.vector().to_string(),
+        "VLEN=128 vl=3 vtype=e32,m1,ta,mu\nv0  [7, 0, 0 | 0] mask 1110\nv1  [7, 7, 7 | 0]\n"
+    );
+}